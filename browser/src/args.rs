@@ -1,12 +1,122 @@
-use std::path::PathBuf;
+use std::{
+    convert::Infallible,
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use clap::Parser;
 
+/// Where the document is read from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    File(PathBuf),
+    /// `-`
+    Stdin,
+    /// `http://` or `https://`, requires the `http` feature
+    Url(String),
+}
+
+impl FromStr for Source {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "-" => Source::Stdin,
+            s if s.starts_with("http://") || s.starts_with("https://") => {
+                Source::Url(s.to_string())
+            }
+            s => Source::File(PathBuf::from(s)),
+        })
+    }
+}
+
+impl Source {
+    /// Where the document's relative image sources are resolved from. The working directory
+    /// unless it's a file
+    pub fn dir(&self) -> &Path {
+        match self {
+            Source::File(path) => path.parent().unwrap_or(Path::new("")),
+            Source::Stdin | Source::Url(_) => Path::new(""),
+        }
+    }
+}
+
+impl Default for Source {
+    fn default() -> Self {
+        Source::File(PathBuf::from(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../test_files/messages.smf"
+        )))
+    }
+}
+
+/// `WIDTHxHEIGHT` in pixels, e.g. `800x600`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewportSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for ViewportSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let size = s.split_once('x').and_then(|(width, height)| {
+            Some(ViewportSize {
+                width: width.parse().ok().filter(|width| *width > 0)?,
+                height: height.parse().ok().filter(|height| *height > 0)?,
+            })
+        });
+        size.ok_or_else(|| format!("expected WIDTHxHEIGHT, e.g. 800x600, found `{}`", s))
+    }
+}
+
+impl Display for ViewportSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
 #[derive(Parser, Debug)]
 pub struct BrowserArgs {
     #[arg(short, long, default_value_t = false)]
     pub debug_inspector: bool,
 
+    /// A file path, `-` for stdin, or an http(s) url
     #[arg(short, long)]
-    pub view: Option<PathBuf>,
-}
\ No newline at end of file
+    pub view: Option<Source>,
+
+    /// Show only the top level view with this `name`. Tab switches to the next one
+    #[arg(long, value_name = "NAME")]
+    pub view_name: Option<String>,
+
+    /// Print the diagnostics of a document without opening a window.
+    /// Exits with 1 if there are errors
+    #[arg(long, value_name = "SOURCE")]
+    pub check: Option<Source>,
+
+    /// Present frames as soon as they are ready instead of waiting for the display
+    #[arg(long, default_value_t = false)]
+    pub no_vsync: bool,
+
+    /// Render on the CPU instead of a GPU. Without this a software adapter is only
+    /// used when there is no GPU
+    #[arg(long, visible_alias = "no-gpu", default_value_t = false)]
+    pub software: bool,
+
+    /// Log the average time spent in layout and draw
+    #[arg(long, default_value_t = false)]
+    pub profile: bool,
+
+    /// Write log lines to this file instead of stderr. With `--debug-inspector` they go to
+    /// `browser.log` in the temp directory by default, so they don't draw over the inspector
+    #[arg(long, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// Open a window of this size that can't be resized, e.g. `800x600`, and lay out at exactly
+    /// this size whatever the display scale. Nodes that reach past it are printed as warnings,
+    /// also with `--check`
+    #[arg(long, value_name = "WIDTHxHEIGHT")]
+    pub size: Option<ViewportSize>,
+}
@@ -0,0 +1,83 @@
+use std::{
+    fs::OpenOptions,
+    io,
+    path::{Path, PathBuf},
+};
+
+use env_logger::{Env, Target};
+
+/// Where log lines are written
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogTarget {
+    Stderr,
+    File(PathBuf),
+}
+
+impl LogTarget {
+    /// `log_file` if there is one. Otherwise stderr, unless the inspector takes over the
+    /// terminal, since log lines would be printed over it. Then they go to `browser.log`
+    /// in the temp directory
+    pub fn choose(log_file: Option<&Path>, inspector: bool) -> LogTarget {
+        match log_file {
+            Some(path) => LogTarget::File(path.to_path_buf()),
+            None if inspector => LogTarget::File(std::env::temp_dir().join("browser.log")),
+            None => LogTarget::Stderr,
+        }
+    }
+}
+
+/// A logger writing to `target`, showing `default_filter` unless `RUST_LOG` says otherwise.
+/// A log file is appended to, so it can be followed with `tail -f` across runs
+pub fn build(target: &LogTarget, default_filter: &str) -> io::Result<env_logger::Logger> {
+    let mut builder =
+        env_logger::Builder::from_env(Env::default().default_filter_or(default_filter));
+
+    match target {
+        LogTarget::Stderr => builder.target(Target::Stderr),
+        LogTarget::File(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            builder.target(Target::Pipe(Box::new(file)))
+        }
+    };
+
+    Ok(builder.build())
+}
+
+/// Installs the logger from `build` as the global logger
+pub fn init(target: &LogTarget, default_filter: &str) -> io::Result<()> {
+    let logger = build(target, default_filter)?;
+    log::set_max_level(logger.filter());
+    log::set_boxed_logger(Box::new(logger)).map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use log::{Level, Log, Record};
+
+    use super::*;
+
+    #[test]
+    fn inspector_logs_go_to_a_file() {
+        assert_eq!(LogTarget::choose(None, false), LogTarget::Stderr);
+        assert!(matches!(LogTarget::choose(None, true), LogTarget::File(_)));
+
+        let path = std::env::temp_dir().join(format!("browser-log-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let target = LogTarget::choose(Some(&path), true);
+        assert_eq!(target, LogTarget::File(path.clone()));
+
+        let logger = build(&target, "info").unwrap();
+        logger.log(
+            &Record::builder()
+                .args(format_args!("laid out in 2ms"))
+                .level(Level::Info)
+                .build(),
+        );
+        logger.flush();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(written.contains("laid out in 2ms"));
+    }
+}
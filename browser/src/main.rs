@@ -1,18 +1,18 @@
 use std::{
+    cell::{Cell, RefCell},
     fs::File,
-    io::{BufReader, Stdout, Write},
+    io::{BufReader, Read, Stdout, Write},
     num::NonZeroU32,
-    path::PathBuf,
     process::exit,
     rc::Rc,
-    str::FromStr,
     sync::{
         mpsc::{self, Receiver, Sender},
         Arc, RwLock,
     },
+    time::Instant,
 };
 
-use args::BrowserArgs;
+use args::{BrowserArgs, Source, ViewportSize};
 use clap::Parser;
 use crossterm::{
     cursor::{Hide, MoveTo, Show},
@@ -21,46 +21,161 @@ use crossterm::{
     style::{Print, Stylize},
     terminal::{EnterAlternateScreen, LeaveAlternateScreen},
 };
+use logger::LogTarget;
 use neb_core::{
-    document::parse_from_stream,
+    document::{parse_from_stream_in, Document},
     gfx::vello::{
-        kurbo::{Affine, Line, Point},
+        kurbo::{Affine, Line, Point, Vec2},
         peniko::{Brush, Color, Stroke},
     },
+    gfx::winit::{event::VirtualKeyCode, window::CursorIcon},
+    gfx::{AdapterChoice, RenderOptions},
     ids::{get_id_mgr, ID},
+    node::NodeType,
 };
+use profile::Profiler;
 
+use neb_errors::{DocumentError, DocumentErrorType, ErrorKind};
 use neb_util::format::TreeDisplay;
 
 mod args;
+mod logger;
+mod profile;
 
 pub struct State {
     debug_id: Option<ID>,
     debug_line: Option<NonZeroU32>,
 }
 
-fn main() {
-    env_logger::init();
+fn open_source(source: &Source) -> std::io::Result<Box<dyn Read>> {
+    match source {
+        Source::File(path) => Ok(Box::new(File::open(path)?)),
+        Source::Stdin => Ok(Box::new(std::io::stdin())),
+        #[cfg(feature = "http")]
+        Source::Url(url) => {
+            let response = ureq::get(url)
+                .call()
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+            Ok(Box::new(response.into_reader()))
+        }
+        #[cfg(not(feature = "http"))]
+        Source::Url(_) => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "built without the `http` feature",
+        )),
+    }
+}
 
-    let args = BrowserArgs::parse();
+/// The whole source, read up front so diagnostics can quote it
+fn read_source(source: &Source) -> std::io::Result<String> {
+    let mut input = String::new();
+    open_source(source)?.read_to_string(&mut input)?;
+    Ok(input)
+}
+
+/// Prints a warning for each node that reaches past a `size` viewport, pointing into `input`
+fn report_overflow(document: &Document, size: ViewportSize, input: &str) {
+    for id in document.overflowing(size.width as f64, size.height as f64) {
+        let Some(node) = document.get_node(id) else {
+            continue;
+        };
+        let node = node.borrow();
+        let kind = match node.get_type() {
+            NodeType::Text(_) => "text",
+            ty => ty.as_str(),
+        };
+        let warning = DocumentError::new(
+            DocumentErrorType::Overflow {
+                node: kind.to_string(),
+                viewport: size.to_string(),
+                rect: get_id_mgr().get_layout(id).border_rect.to_string(),
+            },
+            ErrorKind::Warning,
+        );
+        match node.source_location() {
+            Some(location) => println!("{}", warning.at(location, input)),
+            None => println!("{}", warning),
+        }
+    }
+}
 
-    let file = File::open(
-        args.view
-            .unwrap_or(PathBuf::from_str("test_files/messages.smf").unwrap()),
-    )
-    .unwrap();
-    let file = BufReader::new(file);
+/// Prints every diagnostic, and the nodes overflowing `size` if there is one.
+/// Returns the exit code for `--check`, overflow doesn't count as an error
+fn check(source: &Source, size: Option<ViewportSize>) -> i32 {
+    let input = match read_source(source) {
+        Ok(input) => input,
+        Err(e) => {
+            println!("Unable to open {:?}: {}", source, e);
+            return 2;
+        }
+    };
 
-    let document = Arc::new(parse_from_stream(file));
+    let document = parse_from_stream_in(BufReader::new(input.as_bytes()), source.dir());
 
     let errors = document.get_errors();
-    if errors.len() > 0 {
-        for e in errors {
-            println!("{}", e)
-        }
+    for e in errors {
+        println!("{}", e)
+    }
+    if let Some(size) = size {
+        report_overflow(&document, size, &input);
+    }
+    if errors.iter().any(|e| e.kind() == ErrorKind::Error) {
+        1
+    } else {
+        0
+    }
+}
+
+fn main() {
+    let args = BrowserArgs::parse();
+
+    // Timings are logged at info, which is hidden by default
+    let filter = if args.profile { "info" } else { "error" };
+    let target = LogTarget::choose(args.log_file.as_deref(), args.debug_inspector);
+    if let Err(e) = logger::init(&target, filter) {
+        println!("Unable to log to {:?}: {}", target, e);
         return;
+    }
+
+    if let Some(source) = &args.check {
+        exit(check(source, args.size));
+    }
+
+    let source = args.view.clone().unwrap_or_default();
+    let input = match read_source(&source) {
+        Ok(input) => input,
+        Err(e) => {
+            println!("Unable to open {:?}: {}", source, e);
+            return;
+        }
     };
 
+    let document = Arc::new(parse_from_stream_in(
+        BufReader::new(input.as_bytes()),
+        source.dir(),
+    ));
+    println!("Parsed {}", document.get_body().borrow().format());
+
+    let errors = document.get_errors();
+    for e in errors {
+        println!("{}", e)
+    }
+    if errors.iter().any(|e| e.kind() == ErrorKind::Error) {
+        return;
+    }
+
+    if let Some(name) = &args.view_name {
+        if !document.set_active_view(Some(name)) {
+            let views: Vec<_> = document.view_names().collect();
+            println!("No view named `{}`, the views are {:?}", name, views);
+            return;
+        }
+    }
+
+    if let Some(size) = args.size {
+        report_overflow(&document, size, &input);
+    }
+
     let (tx, rx) = mpsc::channel();
 
     let io_doc = document.clone();
@@ -206,10 +321,71 @@ fn main() {
         debug_line: None,
     }));
 
-    pollster::block_on(neb_core::gfx::start_graphics_thread(move |builder| {
-        document.layout(builder.size.width, builder.size.height);
+    let profiler = args.profile.then(|| RefCell::new(Profiler::new()));
+    // F12 outlines every node and labels the one under the mouse
+    let debug_tree = Cell::new(false);
+
+    let options = RenderOptions {
+        vsync: !args.no_vsync,
+        adapter: if args.software {
+            AdapterChoice::Software
+        } else {
+            AdapterChoice::Auto
+        },
+        size: args.size.map(|size| (size.width, size.height)),
+    };
 
-        document.draw(builder);
+    let graphics = neb_core::gfx::start_graphics_thread(options, move |builder| {
+        if builder.keys.contains(&VirtualKeyCode::Tab) {
+            document.next_view();
+        }
+
+        if builder.keys.contains(&VirtualKeyCode::F12) {
+            debug_tree.set(!debug_tree.get());
+        }
+
+        if let Some(mouse) = builder.mouse {
+            if builder.scroll != Vec2::ZERO {
+                document.scroll_at(mouse, builder.scroll);
+            }
+        }
+
+        let (width, height) = match args.size {
+            Some(size) => (size.width as f64, size.height as f64),
+            None => (builder.size.width, builder.size.height),
+        };
+
+        // Hover follows the layout of the last frame, which is what is on screen
+        document.tick(Instant::now());
+        document.set_hovered(builder.mouse.and_then(|mouse| document.hit_test(mouse)));
+
+        if let Some(profiler) = &profiler {
+            let start = Instant::now();
+            document.layout(width, height);
+            let layout = start.elapsed();
+
+            let start = Instant::now();
+            let stats = document.draw(builder);
+            profiler
+                .borrow_mut()
+                .record(layout, start.elapsed(), document.node_count(), stats);
+        } else {
+            document.layout(width, height);
+
+            document.draw(builder);
+        }
+
+        if debug_tree.get() {
+            let hovered = builder.mouse.and_then(|mouse| document.hit_test(mouse));
+            document.draw_debug_overlay(builder, hovered);
+        }
+
+        if let Some(mouse) = builder.mouse {
+            builder.cursor = match document.hit_test(mouse) {
+                Some(id) => document.cursor_for(id),
+                None => Some(CursorIcon::Default),
+            };
+        }
 
         if args.debug_inspector {
             match rx.try_recv() {
@@ -257,20 +433,13 @@ fn main() {
         }
 
         if let (Some(val), Some(line)) = (&m.debug_id, &m.debug_line) {
-            let idmgr = get_id_mgr();
-            let layout = idmgr.get_layout(*val);
+            if let Some(node) = document.get_node(*val) {
+                let info = node.borrow().inspect();
 
-            let mut stdout = std::io::stdout();
-            execute!(
-                stdout,
-                MoveTo(1, 1 + line.get() as u16),
-                Print(format!(
-                    "Content {}, Padding {}, Border {}",
-                    layout.content_rect, layout.padding_rect, layout.border_rect
-                ))
-            )
-            .unwrap();
+                let mut stdout = std::io::stdout();
+                execute!(stdout, MoveTo(1, 1 + line.get() as u16), Print(info)).unwrap();
+            }
         }
-    }))
-    .unwrap();
+    });
+    pollster::block_on(graphics).unwrap();
 }
@@ -0,0 +1,121 @@
+use std::{collections::VecDeque, time::Duration};
+
+use neb_core::document::DrawStats;
+
+/// Frames averaged over before each report
+const WINDOW: usize = 60;
+
+/// Collects layout and draw timings for `--profile` and logs a rolling average
+pub struct Profiler {
+    frames: VecDeque<(Duration, Duration)>,
+    since_report: usize,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self {
+            frames: VecDeque::with_capacity(WINDOW),
+            since_report: 0,
+        }
+    }
+
+    /// Records one frame, logging the average over the last `WINDOW` frames once per window
+    pub fn record(&mut self, layout: Duration, draw: Duration, nodes: usize, stats: DrawStats) {
+        if self.frames.len() == WINDOW {
+            self.frames.pop_front();
+        }
+        self.frames.push_back((layout, draw));
+
+        self.since_report += 1;
+        if self.since_report < WINDOW {
+            return;
+        }
+        self.since_report = 0;
+
+        let (layout, draw) = self.average();
+        log::info!(
+            "{} nodes, layout {:.3}ms, draw {:.3}ms (average of {} frames), {} views reused and {} recorded",
+            nodes,
+            layout.as_secs_f64() * 1000.0,
+            draw.as_secs_f64() * 1000.0,
+            self.frames.len(),
+            stats.reused,
+            stats.recorded
+        );
+    }
+
+    pub fn average(&self) -> (Duration, Duration) {
+        if self.frames.is_empty() {
+            return (Duration::ZERO, Duration::ZERO);
+        }
+
+        let (layout, draw) = self.frames.iter().fold(
+            (Duration::ZERO, Duration::ZERO),
+            |(l, d), (layout, draw)| (l + *layout, d + *draw),
+        );
+        let count = self.frames.len() as u32;
+        (layout / count, draw / count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::BufReader, time::Instant};
+
+    use neb_core::{
+        document::parse_from_stream,
+        gfx::{
+            drawing_context::DrawingContext,
+            simple_text::SimpleText,
+            vello::{
+                kurbo::{Affine, Size, Vec2},
+                Scene, SceneBuilder,
+            },
+        },
+    };
+
+    use super::*;
+
+    /// Runs a window of frames of each fixture through the profiler, like `--profile` does
+    #[test]
+    fn fixtures_are_profiled() {
+        for source in [
+            include_str!("../../test_files/deep.smf"),
+            include_str!("../../test_files/messages.smf"),
+        ] {
+            let document = parse_from_stream(BufReader::new(source.as_bytes()));
+            let mut profiler = Profiler::new();
+            let mut stats = DrawStats::default();
+            for _ in 0..WINDOW {
+                let start = Instant::now();
+                document.layout(800.0, 600.0);
+                let layout = start.elapsed();
+
+                let mut scene = Scene::default();
+                let mut dctx = DrawingContext {
+                    builder: SceneBuilder::for_scene(&mut scene),
+                    text: SimpleText::new(),
+                    size: Size::new(800.0, 600.0),
+                    transform: Affine::IDENTITY,
+                    mouse: None,
+                    keys: Vec::new(),
+                    scroll: Vec2::ZERO,
+                    cursor: None,
+                };
+                let start = Instant::now();
+                stats = document.draw(&mut dctx);
+                profiler.record(layout, start.elapsed(), document.node_count(), stats);
+            }
+
+            // The window was reported and starts over
+            assert_eq!(profiler.frames.len(), WINDOW);
+            assert_eq!(profiler.since_report, 0);
+            let (layout, draw) = profiler.average();
+            assert!(layout > Duration::ZERO && draw > Duration::ZERO);
+
+            // Nothing changed after the first frame, so the last one was replayed
+            assert_eq!(stats.recorded, 0);
+            assert!(stats.reused > 0);
+        }
+    }
+}
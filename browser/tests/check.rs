@@ -0,0 +1,94 @@
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+fn check(source: &str) -> Output {
+    check_with(source, &[])
+}
+
+fn check_with(source: &str, args: &[&str]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_browser"))
+        .args(["--check", "-"])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(source.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn clean_document_passes() {
+    let output = check(
+        "setup {
+    style {
+        card {
+            padding: 4px
+        }
+    }
+}
+
+use setup.style
+
+view (class: card) {
+    :Hello
+}
+",
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn unresolved_class_fails() {
+    let output = check(
+        "view (class: missing) {
+    :Hello
+}
+",
+    );
+    assert_eq!(output.status.code(), Some(1));
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("missing"), "{}", stdout);
+}
+
+#[test]
+fn overflow_is_reported_at_a_fixed_size() {
+    let source = "setup {
+    style {
+        wide {
+            width: 900px
+            height: 20px
+        }
+    }
+}
+
+use setup.style
+
+view (class: wide) {
+}
+";
+
+    let output = check_with(source, &["--size", "800x600"]);
+    // Overflow is a warning, the document is still fine
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Warning: view overflows the 800x600 viewport"),
+        "{}",
+        stdout
+    );
+    // Formatted like other diagnostics, pointing at the view
+    assert!(stdout.contains("12 | view (class: wide) {"), "{}", stdout);
+
+    let output = check_with(source, &["--size", "1000x600"]);
+    assert!(output.stdout.is_empty());
+}
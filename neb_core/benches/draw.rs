@@ -0,0 +1,106 @@
+#![feature(test)]
+
+extern crate test;
+
+use std::io::BufReader;
+
+use neb_core::{
+    document::{parse_from_stream, Document},
+    gfx::{
+        drawing_context::DrawingContext,
+        simple_text::SimpleText,
+        vello::{
+            kurbo::{Affine, Size, Vec2},
+            Scene, SceneBuilder,
+        },
+    },
+    ids::ID,
+};
+use test::Bencher;
+
+const ITEMS: usize = 100;
+
+/// A list of items with a few lines of text each, like a chat log
+fn list_document() -> Document {
+    let source = format!(
+        "setup {{
+    style {{
+        item {{
+            padding: 8px
+            gap: 4px
+            radius: 6px
+            backgroundColor: rgb(59, 59, 61)
+            foregroundColor: rgb(255, 255, 255)
+        }}
+        selected {{
+            backgroundColor: rgb(11, 132, 255)
+        }}
+    }}
+}}
+
+use setup.style
+
+view (name: list) {{
+{}}}",
+        "    view (class: item) {
+        :A message that is long enough to wrap onto a second line in the list
+        :Sent at noon
+    }
+"
+        .repeat(ITEMS)
+    );
+    parse_from_stream(BufReader::new(source.as_bytes()))
+}
+
+fn draw(document: &Document) {
+    let mut scene = Scene::default();
+    let mut dctx = DrawingContext {
+        builder: SceneBuilder::for_scene(&mut scene),
+        text: SimpleText::new(),
+        size: Size::new(800.0, 600.0),
+        transform: Affine::IDENTITY,
+        mouse: None,
+        keys: Vec::new(),
+        scroll: Vec2::ZERO,
+        cursor: None,
+    };
+    document.draw(&mut dctx);
+}
+
+/// One item changes color every frame, so the rest of the list can be drawn as it was. Only the
+/// paint changes, so the layout from the first frame still holds
+#[bench]
+fn draw_one_changed(b: &mut Bencher) {
+    let document = list_document();
+    let items: Vec<ID> = document
+        .get_by_name("list")
+        .unwrap()
+        .borrow()
+        .children
+        .iter()
+        .map(|item| item.borrow().element.id())
+        .collect();
+    document.layout(800.0, 600.0);
+    draw(&document);
+
+    let mut next = 0;
+    b.iter(|| {
+        let item = items[next % items.len()];
+        if !document.add_class(item, "selected") {
+            document.remove_class(item, "selected");
+        }
+        next += 1;
+
+        draw(&document);
+    });
+}
+
+/// Nothing changes between frames
+#[bench]
+fn draw_static(b: &mut Bencher) {
+    let document = list_document();
+    document.layout(800.0, 600.0);
+    draw(&document);
+
+    b.iter(|| draw(&document));
+}
@@ -0,0 +1,38 @@
+#![feature(test)]
+
+extern crate test;
+
+use std::{fs::File, io::BufReader};
+
+use neb_core::document::{parse_from_stream, Document};
+use test::Bencher;
+
+fn deep_document() -> Document {
+    let file = File::open(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../test_files/deep.smf"
+    ))
+    .unwrap();
+    parse_from_stream(BufReader::new(file))
+}
+
+/// Every frame has new bounds, so every node is laid out again
+#[bench]
+fn layout_resized(b: &mut Bencher) {
+    let document = deep_document();
+    let mut width = 800.0;
+
+    b.iter(|| {
+        width = if width == 800.0 { 801.0 } else { 800.0 };
+        document.layout(width, 600.0);
+    });
+}
+
+/// Nothing changes between frames, so layout reuses the previous rects
+#[bench]
+fn layout_static(b: &mut Bencher) {
+    let document = deep_document();
+    document.layout(800.0, 600.0);
+
+    b.iter(|| document.layout(800.0, 600.0));
+}
@@ -9,6 +9,9 @@ pub const FOREGROUND_COLOR: Color = Color::BLACK;
 pub const DOCUMENT_PADDING: f32 = 8.0;
 pub const GAP: f64 = 4.0;
 pub const DIRECTION: Direction = Direction::Vertical;
+/// The size of the placeholder drawn for an image that couldn't be loaded
+pub const BROKEN_IMAGE_SIZE: f64 = 24.0;
+pub const BROKEN_IMAGE_COLOR: Color = Color::rgb8(200, 200, 200);
 
 #[macro_export]
 macro_rules! psize {
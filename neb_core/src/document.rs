@@ -1,11 +1,42 @@
-use std::io::{BufReader, Read};
+use std::{
+    collections::{HashMap, HashSet},
+    io::{BufReader, Read},
+    path::Path,
+    sync::RwLock,
+    time::Instant,
+};
 
-use neb_errors::DocumentError;
-use neb_graphics::{drawing_context::DrawingContext, vello::kurbo::Rect};
-use neb_smf::{Module, Symbol, SymbolKind};
-use neb_util::{format::TreeDisplay, Rf};
+use linked_hash_map::LinkedHashMap;
+use neb_errors::{sort_and_dedup, DocumentError, DocumentErrorType, ErrorKind, SourceLocation};
+use neb_graphics::{
+    drawing_context::DrawingContext,
+    input::CursorIcon,
+    vello::{
+        kurbo::{Point, Rect, Size, Vec2},
+        peniko::Color,
+        SceneFragment,
+    },
+};
+use neb_smf::{
+    ast::{AstNode, Value},
+    error::ParseErrorKind,
+    evaluate::substitute_constants,
+    token::{Range, Span, SpannedToken, Token},
+    Module, StyleVariant, Symbol, SymbolKind,
+};
+use neb_util::Rf;
 
-use crate::{node::{Node, NodeType}};
+use crate::{
+    ids::{get_id_mgr, layout_of, IDManager, Layout, ID},
+    images,
+    node::{Element, Node, NodeType},
+    scroll,
+    styling::{
+        color_from_value, expected_value, lerp_color, property_value, unitless_sizes, ColorError,
+        Cursor, CustomProperty, Overflow, StyleContext, StyleValue,
+    },
+    StyleValueAs,
+};
 
 pub fn indent(size: usize) -> String {
     const INDENT: &'static str = "    ";
@@ -14,10 +45,83 @@ pub fn indent(size: usize) -> String {
         .fold(String::with_capacity(size * INDENT.len()), |r, s| r + s)
 }
 
+/// Large enough that nothing wraps, but finite so centering still works
+const INTRINSIC_BOUNDS: f64 = 1_000_000.0;
+
 pub struct Document {
     errors: Vec<DocumentError>,
 
     body_root: Rf<Node>,
+
+    /// Every node in the body, by element id
+    nodes: HashMap<ID, Rf<Node>>,
+
+    /// Nodes given a `name` argument. The first node wins if a name is reused
+    names: HashMap<String, Rf<Node>>,
+
+    /// Top level views with a `name`, in document order. These are the screens that can be shown on their own
+    views: Vec<(String, Rf<Node>)>,
+
+    /// Index into `views` of the one being shown. With none, every top level node is shown
+    active_view: RwLock<Option<usize>>,
+
+    /// Width of the last layout, used to select responsive style variants
+    viewport_width: RwLock<f64>,
+
+    /// Area the body's children took up in the last layout
+    content_extent: RwLock<Rect>,
+
+    /// Style properties added by the embedder, in the order they were registered
+    custom_properties: Vec<(String, CustomProperty)>,
+
+    /// The node under the mouse and its ancestors
+    hovered: RwLock<HashSet<ID>>,
+
+    /// Time of the frame being drawn, see `tick`
+    frame_time: RwLock<Instant>,
+
+    /// Color transitions by node and property, kept after they finish to tell when the color changes
+    animations: RwLock<HashMap<(ID, String), Animation>>,
+
+    /// Paint hashes from the last `Element::hash_paint`, for the nodes that can be cached
+    paint_hashes: RwLock<HashMap<ID, u64>>,
+
+    /// The last fragment recorded for each view, with the paint hash it was recorded at
+    fragments: RwLock<HashMap<ID, (u64, SceneFragment)>>,
+
+    /// Counts for the frame being drawn
+    draw_stats: RwLock<DrawStats>,
+}
+
+/// How much of a frame came from recorded fragments
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DrawStats {
+    /// Views appended from the fragment recorded on an earlier frame
+    pub reused: usize,
+    /// Views that changed and were recorded again
+    pub recorded: usize,
+}
+
+/// A property on its way from one color to another
+struct Animation {
+    from: Color,
+    to: Color,
+    start: Instant,
+    /// In seconds
+    duration: f64,
+}
+
+impl Animation {
+    fn progress(&self, now: Instant) -> f64 {
+        if self.duration <= 0.0 {
+            return 1.0;
+        }
+        (now.saturating_duration_since(self.start).as_secs_f64() / self.duration).min(1.0)
+    }
+
+    fn color_at(&self, now: Instant) -> Color {
+        lerp_color(self.from, self.to, self.progress(now))
+    }
 }
 
 impl Document {
@@ -25,25 +129,660 @@ impl Document {
         &self.errors
     }
 
+    /// The root node, the parent of every top level element
+    pub fn root(&self) -> &Rf<Node> {
+        &self.body_root
+    }
+
     pub fn get_body(&self) -> &Rf<Node> {
         &self.body_root
     }
+
+    pub fn get_node(&self, id: ID) -> Option<&Rf<Node>> {
+        self.nodes.get(&id)
+    }
+
+    pub fn get_by_name(&self, name: &str) -> Option<Rf<Node>> {
+        self.names.get(name).cloned()
+    }
+
+    /// Names of the top level views, in document order
+    pub fn view_names(&self) -> impl Iterator<Item = &str> {
+        self.views.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// The name of the view being shown, if only one is
+    pub fn active_view(&self) -> Option<&str> {
+        let active = (*self.active_view.read().unwrap())?;
+        Some(self.views[active].0.as_str())
+    }
+
+    /// Shows only the top level view called `name`, or everything with `None`.
+    /// Returns `false` and leaves the active view alone if there is no such view
+    pub fn set_active_view(&self, name: Option<&str>) -> bool {
+        let index = match name {
+            Some(name) => match self.views.iter().position(|(view, _)| view == name) {
+                Some(index) => Some(index),
+                None => return false,
+            },
+            None => None,
+        };
+        *self.active_view.write().unwrap() = index;
+        true
+    }
+
+    /// Switches to the view after the active one, wrapping around to the first.
+    /// Returns the name of the view now shown
+    pub fn next_view(&self) -> Option<&str> {
+        if self.views.is_empty() {
+            return None;
+        }
+        {
+            let mut active = self.active_view.write().unwrap();
+            *active = Some(active.map_or(0, |index| (index + 1) % self.views.len()));
+        }
+        self.active_view()
+    }
+
+    /// The node that is laid out and drawn: the active view, or the body when there is none
+    pub(crate) fn shown_root(&self) -> Rf<Node> {
+        match *self.active_view.read().unwrap() {
+            Some(index) => self.views[index].1.clone(),
+            None => self.body_root.clone(),
+        }
+    }
+
+    /// Number of nodes in the body, including the body itself
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Adds `class` to a node. Styles are looked up from the classes every time they are read,
+    /// so the change shows on the next layout and draw. Returns `false` if nothing changed
+    pub fn add_class(&self, id: ID, class: &str) -> bool {
+        self.change_classes(id, |element| element.add_class(class))
+    }
+
+    /// Removes `class` from a node. Returns `false` if nothing changed
+    pub fn remove_class(&self, id: ID, class: &str) -> bool {
+        self.change_classes(id, |element| element.remove_class(class))
+    }
+
+    /// Adds `class` if the node doesn't have it, otherwise removes it.
+    /// Returns whether the node has the class now
+    pub fn toggle_class(&self, id: ID, class: &str) -> bool {
+        self.change_classes(id, |element| {
+            if !element.remove_class(class) {
+                element.add_class(class);
+            }
+            true
+        });
+        self.nodes
+            .get(&id)
+            .map_or(false, |node| node.borrow().element.has_class(class))
+    }
+
+    fn change_classes(&self, id: ID, change: impl FnOnce(&mut Element) -> bool) -> bool {
+        let Some(node) = self.nodes.get(&id) else {
+            return false;
+        };
+        if !change(&mut node.borrow_mut().element) {
+            return false;
+        }
+
+        // The subtree hash is refreshed by the next layout, but don't trust the stored rects until then
+        get_id_mgr().clear_layout_input(id);
+        true
+    }
+
+    pub fn viewport_width(&self) -> f64 {
+        *self.viewport_width.read().unwrap()
+    }
+
+    /// Size of the content from the last layout, measured from the top left of the window
+    pub fn dimensions(&self) -> Size {
+        let extent = *self.content_extent.read().unwrap();
+        Size::new(extent.x1.max(0.0), extent.y1.max(0.0))
+    }
+
+    pub(crate) fn set_content_extent(&self, extent: Rect) {
+        *self.content_extent.write().unwrap() = extent;
+    }
+
+    /// Adds a style property that the built in ones don't cover. Built in keys can't be overridden.
+    /// Custom properties draw in the order they were registered
+    pub fn register_property(&mut self, name: impl Into<String>, property: CustomProperty) {
+        let name = name.into();
+        self.custom_properties
+            .retain(|(existing, _)| *existing != name);
+        self.custom_properties.push((name, property));
+    }
+
+    pub fn custom_property(&self, name: &str) -> Option<&CustomProperty> {
+        self.custom_properties
+            .iter()
+            .find_map(|(key, property)| (key == name).then_some(property))
+    }
+
+    pub fn custom_properties(&self) -> impl Iterator<Item = (&str, &CustomProperty)> {
+        self.custom_properties
+            .iter()
+            .map(|(key, property)| (key.as_str(), property))
+    }
+
+    /// Marks `id` and its ancestors as hovered, for `state: Hover` style variants
+    pub fn set_hovered(&self, id: Option<ID>) {
+        let mut hovered = HashSet::new();
+        let mut node = id.and_then(|id| self.get_node(id)).cloned();
+        while let Some(current) = node {
+            let current = current.borrow();
+            hovered.insert(current.element.id());
+            node = current.get_parent().cloned();
+        }
+
+        *self.hovered.write().unwrap() = hovered;
+    }
+
+    pub fn is_hovered(&self, id: ID) -> bool {
+        self.hovered.read().unwrap().contains(&id)
+    }
+
+    /// What selects the style variants of `node`
+    pub fn style_context(&self, node: &Node) -> StyleContext {
+        StyleContext {
+            viewport_width: self.viewport_width(),
+            hovered: self.is_hovered(node.element.id()),
+        }
+    }
+
+    /// Sets the time transitions are drawn at. Called once per frame, before drawing
+    pub fn tick(&self, now: Instant) {
+        *self.frame_time.write().unwrap() = now;
+    }
+
+    /// Whether a transition of one of `id`'s properties is still running
+    pub(crate) fn is_node_animating(&self, id: ID) -> bool {
+        let now = *self.frame_time.read().unwrap();
+        self.animations
+            .read()
+            .unwrap()
+            .iter()
+            .any(|((node, _), animation)| *node == id && animation.progress(now) < 1.0)
+    }
+
+    pub(crate) fn paint_hash(&self, id: ID) -> Option<u64> {
+        self.paint_hashes.read().unwrap().get(&id).copied()
+    }
+
+    pub(crate) fn set_paint_hash(&self, id: ID, hash: Option<u64>) {
+        let mut hashes = self.paint_hashes.write().unwrap();
+        match hash {
+            Some(hash) => hashes.insert(id, hash),
+            None => hashes.remove(&id),
+        };
+    }
+
+    /// Appends the fragment recorded for `id` if it was recorded at `hash`
+    pub(crate) fn append_cached_fragment(
+        &self,
+        id: ID,
+        hash: u64,
+        dctx: &mut DrawingContext,
+    ) -> bool {
+        let fragments = self.fragments.read().unwrap();
+        match fragments.get(&id) {
+            Some((recorded, fragment)) if *recorded == hash => {
+                dctx.append_fragment(fragment, dctx.transform);
+                self.draw_stats.write().unwrap().reused += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub(crate) fn cache_fragment(&self, id: ID, hash: u64, fragment: SceneFragment) {
+        self.fragments.write().unwrap().insert(id, (hash, fragment));
+        self.draw_stats.write().unwrap().recorded += 1;
+    }
+
+    /// Whether a transition is still running, so the next frame will look different
+    pub fn is_animating(&self) -> bool {
+        let now = *self.frame_time.read().unwrap();
+        self.animations
+            .read()
+            .unwrap()
+            .values()
+            .any(|animation| animation.progress(now) < 1.0)
+    }
+
+    /// The color to draw `key` of `node` with this frame. When the node has a `transition`
+    /// for `key` and `target` changed, this moves from the color drawn so far to `target`
+    /// over the transition's duration. A missing color fades from or to transparent
+    pub fn transition_color(&self, node: &Node, key: &str, target: Option<Color>) -> Option<Color> {
+        let duration = StyleValueAs!(node.styles(self, "transition"), Transition)
+            .and_then(|transitions| transitions.into_iter().find(|t| t.property == key))
+            .map(|transition| transition.duration);
+
+        let id = (node.element.id(), key.to_string());
+        let mut animations = self.animations.write().unwrap();
+        let Some(duration) = duration else {
+            animations.remove(&id);
+            return target;
+        };
+
+        let now = *self.frame_time.read().unwrap();
+        let to = target.unwrap_or(Color::TRANSPARENT);
+        // The first color a node is drawn with doesn't animate
+        let animation = animations.entry(id).or_insert(Animation {
+            from: to,
+            to,
+            start: now,
+            duration: 0.0,
+        });
+
+        if animation.to != to {
+            *animation = Animation {
+                from: animation.color_at(now),
+                to,
+                start: now,
+                duration,
+            };
+        }
+
+        if animation.progress(now) < 1.0 {
+            Some(animation.color_at(now))
+        } else {
+            target
+        }
+    }
+
+    /// Resolves `key` from a style symbol, falling back to custom properties for unknown keys
+    pub fn style_from_symbol(&self, sym: &Node, key: &str, context: StyleContext) -> StyleValue {
+        match StyleValue::from_symbol(sym, key, context) {
+            StyleValue::Empty => (),
+            value => return value,
+        }
+
+        let (
+            Some(custom),
+            NodeType::Style {
+                properties,
+                variants,
+                ..
+            },
+        ) = (self.custom_property(key), &sym.ty)
+        else {
+            return StyleValue::Empty;
+        };
+
+        property_value(properties, variants, key, context)
+            .map(|value| custom.resolve(value))
+            .unwrap_or(StyleValue::Empty)
+    }
+
+    /// Resolves `key` from a value written as an element argument, like `style_from_symbol`
+    pub fn style_from_arg(&self, key: &str, value: &Value) -> StyleValue {
+        match StyleValue::from_value(key, value) {
+            StyleValue::Empty => self
+                .custom_property(key)
+                .map(|custom| custom.resolve(value))
+                .unwrap_or(StyleValue::Empty),
+            value => value,
+        }
+    }
+}
+
+/// How a node differs between two parses of a source, see `Document::diff`. Nodes are named by
+/// their path from the root: a named element by its name, otherwise by its kind and its index
+/// among unnamed siblings of that kind, e.g. `["main", "text[1]"]`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Added(Vec<String>),
+    Removed(Vec<String>),
+    /// The node's classes, or the styles they resolve to, changed
+    Restyled(Vec<String>),
+    /// The node's own content changed, like its text, source or arguments
+    Updated(Vec<String>),
 }
 
 impl Document {
-    pub fn draw(&self, dctx: &mut DrawingContext) {
-        let body = self.body_root.borrow();
-        body.draw(dctx, self);
+    /// What changed in the displayed tree from this document to `new`, a later parse of the
+    /// same source. Nodes are matched by path rather than ID, which differs between parses, so
+    /// state like scroll offsets can be carried over to the nodes that are still there
+    pub fn diff(&self, new: &Document) -> Vec<Change> {
+        let mut changes = Vec::new();
+        diff_children(
+            (self, &self.body_root),
+            (new, &new.body_root),
+            &mut Vec::new(),
+            &mut changes,
+        );
+        changes
+    }
+}
+
+/// The displayed children of `node` with their path segments
+fn keyed_children(node: &Rf<Node>) -> Vec<(String, Rf<Node>)> {
+    let mut unnamed: HashMap<String, usize> = HashMap::new();
+    let node = node.borrow();
+    node.children
+        .iter()
+        .filter(|child| child.borrow().is_displayed())
+        .map(|child| {
+            let node = child.borrow();
+            let key = match node.element.name() {
+                Some(name) => name.to_string(),
+                None => {
+                    let kind = match &node.ty {
+                        NodeType::Text(_) => "text",
+                        ty => ty.as_str(),
+                    };
+                    let index = unnamed.entry(kind.to_string()).or_default();
+                    *index += 1;
+                    format!("{}[{}]", kind, *index - 1)
+                }
+            };
+            (key, child.clone())
+        })
+        .collect()
+}
+
+fn diff_children(
+    (old_document, old): (&Document, &Rf<Node>),
+    (new_document, new): (&Document, &Rf<Node>),
+    path: &mut Vec<String>,
+    changes: &mut Vec<Change>,
+) {
+    let old_children = keyed_children(old);
+    let new_children = keyed_children(new);
+
+    for (key, old_child) in &old_children {
+        path.push(key.clone());
+        match new_children.iter().find(|(new_key, _)| new_key == key) {
+            Some((_, new_child)) => diff_node(
+                (old_document, old_child),
+                (new_document, new_child),
+                path,
+                changes,
+            ),
+            None => changes.push(Change::Removed(path.clone())),
+        }
+        path.pop();
+    }
+
+    for (key, _) in &new_children {
+        if !old_children.iter().any(|(old_key, _)| old_key == key) {
+            path.push(key.clone());
+            changes.push(Change::Added(path.clone()));
+            path.pop();
+        }
+    }
+}
+
+fn diff_node(
+    (old_document, old): (&Document, &Rf<Node>),
+    (new_document, new): (&Document, &Rf<Node>),
+    path: &mut Vec<String>,
+    changes: &mut Vec<Change>,
+) {
+    {
+        let (old, new) = (old.borrow(), new.borrow());
+        if old.ty != new.ty {
+            changes.push(Change::Updated(path.clone()));
+        }
+
+        let old_styles = old.class_styles(old_document);
+        let new_styles = new.class_styles(new_document);
+        let same_styles = old_styles.len() == new_styles.len()
+            && old_styles.iter().zip(&new_styles).all(|pair| match pair {
+                (Some(a), Some(b)) => a.borrow().ty == b.borrow().ty,
+                (None, None) => true,
+                _ => false,
+            });
+        if old.element.classes() != new.element.classes() || !same_styles {
+            changes.push(Change::Restyled(path.clone()));
+        }
+    }
+
+    diff_children((old_document, old), (new_document, new), path, changes);
+}
+
+/// A node's rects from a layout pass, with the children in the same order as in the node tree
+#[derive(Debug, Clone)]
+pub struct MeasuredNode {
+    pub id: ID,
+    pub layout: Layout,
+    pub children: Vec<MeasuredNode>,
+}
+
+impl MeasuredNode {
+    fn new(node: &Node, manager: &IDManager) -> MeasuredNode {
+        MeasuredNode {
+            id: node.element.id(),
+            layout: *manager.get_layout(node.element.id()),
+            children: node
+                .children
+                .iter()
+                .map(|child| MeasuredNode::new(&child.borrow(), manager))
+                .collect(),
+        }
+    }
+}
+
+impl Document {
+    /// Draws the document as it was last laid out. Views that look the same as on the last
+    /// frame are appended from the fragment recorded then, which the returned counts show
+    pub fn draw(&self, dctx: &mut DrawingContext) -> DrawStats {
+        *self.draw_stats.write().unwrap() = DrawStats::default();
+
+        let root = self.shown_root();
+        let root = root.borrow();
+        Element::hash_paint(&root, self);
+        root.draw(dctx, self);
+
+        *self.draw_stats.read().unwrap()
     }
 
     pub fn layout(&self, width: f64, height: f64) {
+        *self.viewport_width.write().unwrap() = width;
+
         let body = self.body_root.borrow();
-        body.get_element().layout(
-            &body,
-            Rect::from_origin_size((0.0, 0.0), (width, height)),
-            0,
+        Element::hash_subtree(&body, self);
+
+        let viewport = Rect::from_origin_size((0.0, 0.0), (width, height));
+        match *self.active_view.read().unwrap() {
+            // The view takes the place of the body, so it gets the whole viewport
+            Some(index) => {
+                let view = self.views[index].1.borrow();
+                let extent = view.get_element().layout(&view, viewport, 1, self);
+                self.set_content_extent(extent);
+            }
+            None => {
+                body.get_element().layout(&body, viewport, 0, self);
+            }
+        }
+    }
+
+    /// Lays the document out in a `width` by `height` viewport and collects the rects of every node shown
+    pub fn measure_tree(&self, width: f64, height: f64) -> MeasuredNode {
+        self.layout(width, height);
+
+        let root = self.shown_root();
+        let root = root.borrow();
+        MeasuredNode::new(&root, &get_id_mgr())
+    }
+
+    /// Lays the document out in a `width` by `height` viewport and returns the displayed nodes
+    /// whose border rect reaches past it, in tree order. What's inside an `overflow: Hidden` or
+    /// `Scroll` node is clipped to that node, so only the node itself is checked
+    pub fn overflowing(&self, width: f64, height: f64) -> Vec<ID> {
+        fn collect(document: &Document, node: &Rf<Node>, viewport: Rect, ids: &mut Vec<ID>) {
+            let node = node.borrow();
+            let id = node.element.id();
+
+            let rect = layout_of(id).border_rect;
+            if node.is_displayed() && rect.union(viewport) != viewport {
+                ids.push(id);
+            }
+
+            if let Overflow::Visible = node.overflow(document) {
+                for child in node.children.iter() {
+                    collect(document, child, viewport, ids);
+                }
+            }
+        }
+
+        self.layout(width, height);
+
+        let mut ids = Vec::new();
+        collect(
             self,
+            &self.shown_root(),
+            Rect::new(0.0, 0.0, width, height),
+            &mut ids,
         );
+        ids
+    }
+
+    /// The size the document wants to be, e.g. to size a window to its content.
+    /// Lays the document out in effectively unbounded space, so the next frame has to lay it out again
+    pub fn intrinsic_size(&self) -> Size {
+        self.layout(INTRINSIC_BOUNDS, INTRINSIC_BOUNDS);
+        self.dimensions()
+    }
+
+    /// Lays out a single node again in the bounds it was last given. If its outer rect
+    /// changed, the parent is laid out again too, and so on up the tree
+    pub fn relayout_subtree(&self, id: ID) {
+        let Some(node) = self.nodes.get(&id).cloned() else {
+            return;
+        };
+
+        // The node's inputs changed, so every ancestor's subtree hash is stale
+        Element::hash_subtree(&node.borrow(), self);
+        let mut parent = node.borrow().get_parent().cloned();
+        while let Some(p) = parent {
+            let p = p.borrow();
+            Element::hash_node(&p, self);
+            parent = p.get_parent().cloned();
+        }
+
+        let mut current = node;
+        loop {
+            let next = {
+                let node = current.borrow();
+                let id = node.element.id();
+
+                let (old, bounds) = {
+                    let manager = get_id_mgr();
+                    (
+                        manager.get_layout(id).border_rect,
+                        manager.layout_bounds(id),
+                    )
+                };
+                let Some(bounds) = bounds else {
+                    return;
+                };
+
+                let mut depth = 0;
+                let mut parent = node.get_parent().cloned();
+                while let Some(p) = parent {
+                    depth += 1;
+                    parent = p.borrow().get_parent().cloned();
+                }
+
+                if node.element.layout(&node, bounds, depth, self) == old {
+                    return;
+                }
+                node.get_parent().cloned()
+            };
+
+            let Some(next) = next else {
+                return;
+            };
+            current = next;
+        }
+    }
+
+    /// Finds the deepest displayed and visible node whose border rect contains `point`. A hidden
+    /// node lets the point through to what's behind it, though its visible children can be hit
+    pub fn hit_test(&self, point: Point) -> Option<ID> {
+        fn hit(document: &Document, node: &Rf<Node>, point: Point) -> Option<ID> {
+            let node = node.borrow();
+            let id = node.element.id();
+
+            // Clipped children can only be hit inside the clip, where they are drawn scrolled
+            let child_point = match node.overflow(document) {
+                Overflow::Visible => Some(point),
+                Overflow::Hidden => layout_of(id).padding_rect.contains(point).then_some(point),
+                Overflow::Scroll => layout_of(id)
+                    .padding_rect
+                    .contains(point)
+                    .then(|| point + node.scroll_offset()),
+            };
+
+            // Later children are drawn on top, so they are hit first
+            if let Some(point) = child_point {
+                if let Some(id) = node
+                    .children
+                    .iter()
+                    .rev()
+                    .find_map(|child| hit(document, child, point))
+                {
+                    return Some(id);
+                }
+            }
+
+            if node.is_displayed()
+                && node.is_visible(document)
+                && get_id_mgr().get_layout(id).border_rect.contains(point)
+            {
+                Some(id)
+            } else {
+                None
+            }
+        }
+
+        hit(self, &self.shown_root(), point)
+    }
+
+    /// Scrolls the innermost `overflow: Scroll` node under `point` that can still move by `delta`.
+    /// Returns `false` if nothing scrolled
+    pub fn scroll_at(&self, point: Point, delta: Vec2) -> bool {
+        let mut node = self
+            .hit_test(point)
+            .and_then(|id| self.nodes.get(&id).cloned());
+
+        while let Some(current) = node {
+            let current = current.borrow();
+            if let Overflow::Scroll = current.overflow(self) {
+                let (content, viewport) = current.scroll_size();
+                let offset = current.scroll_offset();
+                let scrolled = scroll::clamp_offset(offset + delta, content, viewport);
+                if scrolled != offset {
+                    get_id_mgr().set_scroll_offset(current.element.id(), scrolled);
+                    return true;
+                }
+            }
+            node = current.get_parent().cloned();
+        }
+
+        false
+    }
+
+    /// The cursor to show over a node. The `cursor` style is inherited from ancestors;
+    /// `None` means the cursor should be hidden
+    pub fn cursor_for(&self, id: ID) -> Option<CursorIcon> {
+        let mut node = self.nodes.get(&id).cloned();
+        while let Some(current) = node {
+            let current = current.borrow();
+            if let Some(cursor) = StyleValueAs!(current.styles(self, "cursor"), Cursor) {
+                return cursor.icon();
+            }
+            node = current.get_parent().cloned();
+        }
+
+        Cursor::Default.icon()
     }
 
     pub fn resolve_path<'a>(
@@ -53,9 +792,7 @@ impl Document {
     ) -> Option<Rf<Node>> {
         match &nodeb.ty {
             NodeType::Root | NodeType::View { .. } | NodeType::Setup | NodeType::StyleBlock => {
-                let Some(next) = path.next() else {
-                    return None
-                };
+                let Some(next) = path.next() else { return None };
                 if let Some(val) = nodeb
                     .children
                     .iter()
@@ -87,51 +824,440 @@ impl Document {
     }
 }
 
-pub fn parse_from_stream<R>(mut stream: BufReader<R>) -> Document
+pub fn parse_from_stream<R>(stream: BufReader<R>) -> Document
+where
+    R: Read,
+{
+    parse_from_stream_in(stream, Path::new(""))
+}
+
+/// Like `parse_from_stream`, with image sources relative to `dir` rather than the working
+/// directory. For a file that's the directory it's in
+pub fn parse_from_stream_in<R>(mut stream: BufReader<R>, dir: &Path) -> Document
 where
     R: Read,
 {
     let mut input = String::new();
     let _ = stream.read_to_string(&mut input).unwrap();
 
-    let (mods, _) = Module::parse_str(&input);
+    let (mods, parse_errors) = Module::parse_str(&input);
 
-    let root = Rf::new(Node::new_root(NodeType::Root));
+    let mut document = Document::from_module_in(mods, dir);
+    document.errors.extend(parse_errors.iter().map(|error| {
+        let ParseErrorKind::InvalidSyntax(msg) = &error.kind;
+        DocumentError::new(DocumentErrorType::Syntax(msg.clone()), ErrorKind::Error)
+            .at(range_location(&error.range), &input)
+    }));
+    sort_and_dedup(&mut document.errors);
 
-    let mod_tree = mods.symbol_tree.borrow();
+    document
+}
+
+impl Document {
+    /// Builds the node tree of an already parsed module and checks it. Errors from parsing
+    /// aren't part of the module, so only the checks on the tree are reported
+    pub fn from_module(mods: Module) -> Document {
+        Document::from_module_in(mods, Path::new(""))
+    }
+
+    /// `from_module` with image sources relative to `dir`, see `parse_from_stream_in`
+    pub fn from_module_in(mods: Module, dir: &Path) -> Document {
+        let input = &mods.content;
+        let root = Rf::new(Node::new_root(NodeType::Root));
+
+        let mut errors = Vec::new();
+        check_colors(&mods.symbol_tree, input, &mut errors);
+        check_properties(&mods.symbol_tree, input, &mut errors);
+
+        let mod_tree = mods.symbol_tree.borrow();
+
+        for symbol in mod_tree.children.values() {
+            let Some(p) = build_nodes(root.clone(), symbol, dir) else {
+                continue;
+            };
+            let mut root = root.borrow_mut();
+            root.add_child(p);
+        }
+
+        let mut nodes = HashMap::new();
+        let mut names = HashMap::new();
+        index_nodes(&root, &mut nodes, &mut names, input, &mut errors);
+
+        let mut views: Vec<(String, Rf<Node>)> = Vec::new();
+        for child in root.borrow().children.iter() {
+            let node = child.borrow();
+            let (NodeType::View { .. }, Some(name)) = (&node.ty, node.element.name()) else {
+                continue;
+            };
+            if !views.iter().any(|(view, _)| view == name) {
+                views.push((name.to_string(), child.clone()));
+            }
+        }
+
+        let mut document = Document {
+            errors,
+            body_root: root,
+            nodes,
+            names,
+            views,
+            active_view: RwLock::new(None),
+            viewport_width: RwLock::new(0.0),
+            content_extent: RwLock::new(Rect::ZERO),
+            custom_properties: Vec::new(),
+            hovered: RwLock::new(HashSet::new()),
+            frame_time: RwLock::new(Instant::now()),
+            animations: RwLock::new(HashMap::new()),
+            paint_hashes: RwLock::new(HashMap::new()),
+            fragments: RwLock::new(HashMap::new()),
+            draw_stats: RwLock::new(DrawStats::default()),
+            // styles: None,
+        };
+
+        let mut errors = Vec::new();
+        check_references(&document, &document.body_root, input, &mut errors);
+        document.errors.extend(errors);
+        sort_and_dedup(&mut document.errors);
+
+        document
+    }
+}
 
-    for symbol in mod_tree.children.values() {
-        let Some(p) = build_nodes(root.clone(), symbol) else {
+fn span_location(span: &Span) -> SourceLocation {
+    SourceLocation {
+        line: span.line_num,
+        column: span.position,
+        length: span.length,
+    }
+}
+
+/// Underlines the whole range when it fits on one line, otherwise just its first token
+pub(crate) fn range_location(range: &Range) -> SourceLocation {
+    let mut location = span_location(&range.start);
+    if range.start.line_num == range.end.line_num {
+        location.length =
+            (range.end.position + range.end.length).saturating_sub(range.start.position);
+    }
+    location
+}
+
+/// A short description of a value for type mismatch errors
+fn describe_value(value: &Value) -> String {
+    match value {
+        Value::Ident(SpannedToken(_, Token::Ident(id))) => format!("identifier `{}`", id),
+        Value::Integer(..) | Value::Float(..) => "a number".to_string(),
+        Value::Function {
+            ident: Some(SpannedToken(_, Token::Ident(func))),
+            ..
+        } => format!("function `{}`", func),
+        Value::Tuple(_) => "a tuple".to_string(),
+        Value::Array { .. } => "an array".to_string(),
+        _ => "a value".to_string(),
+    }
+}
+
+/// Reports classes and `use` paths that don't lead anywhere
+fn check_references(
+    document: &Document,
+    node: &Rf<Node>,
+    source: &str,
+    errors: &mut Vec<DocumentError>,
+) {
+    let node = node.borrow();
+
+    match &node.ty {
+        NodeType::View { args } => {
+            let classes = match args.get("class") {
+                Some(Value::Ident(token)) => vec![token],
+                Some(Value::Array { values, .. }) => values
+                    .iter_items()
+                    .filter_map(|value| match value {
+                        Value::Ident(token) => Some(token),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => Vec::new(),
+            };
+
+            if let Some(parent) = node.get_parent() {
+                let parent = parent.borrow();
+                for SpannedToken(span, token) in classes {
+                    let Token::Ident(class) = token else {
+                        continue;
+                    };
+                    if parent.symbol_in_scope(document, class).is_none() {
+                        errors.push(
+                            DocumentError::new(
+                                DocumentErrorType::UnresolvedReference(class.clone()),
+                                ErrorKind::Error,
+                            )
+                            .at(span_location(span), source),
+                        );
+                    }
+                }
+            }
+        }
+        NodeType::Use(path) => {
+            if document
+                .resolve_path(&document.body_root.borrow(), path.iter())
+                .is_none()
+            {
+                let mut error = DocumentError::new(
+                    DocumentErrorType::UnresolvedReference(path.join(".")),
+                    ErrorKind::Error,
+                );
+                if let Some(location) = node.source_location() {
+                    error = error.at(location, source);
+                }
+                errors.push(error);
+            }
+        }
+        _ => (),
+    }
+
+    for child in node.children.iter() {
+        check_references(document, child, source, errors);
+    }
+}
+
+fn index_nodes(
+    node: &Rf<Node>,
+    nodes: &mut HashMap<ID, Rf<Node>>,
+    names: &mut HashMap<String, Rf<Node>>,
+    source: &str,
+    errors: &mut Vec<DocumentError>,
+) {
+    nodes.insert(node.borrow().element.id(), node.clone());
+
+    let borrowed = node.borrow();
+    if let Some(name) = borrowed.element.name() {
+        if names.contains_key(name) {
+            let mut error = DocumentError::new(
+                DocumentErrorType::DuplicateName(name.to_string()),
+                ErrorKind::Warning,
+            );
+            // At the second `name` argument, or the node if it was named some other way
+            let location = match &borrowed.ty {
+                NodeType::View { args } => args
+                    .get("name")
+                    .map(|value| range_location(&value.get_range())),
+                _ => None,
+            };
+            if let Some(location) = location.or_else(|| borrowed.source_location()) {
+                error = error.at(location, source);
+            }
+            errors.push(error);
+        } else {
+            names.insert(name.to_string(), node.clone());
+        }
+    }
+
+    for child in borrowed.children.iter() {
+        index_nodes(child, nodes, names, source, errors);
+    }
+}
+
+/// The style properties `symbol` sets with their setup constants substituted, and what to call
+/// the symbol in messages. These are the properties of a style and its variants, or the
+/// arguments of a `view`, which style the element itself
+fn checked_properties(scope: &Rf<Symbol>) -> (String, Vec<(String, Value)>) {
+    let symbol = scope.borrow();
+    let (owner, properties): (_, Vec<_>) = match &symbol.kind {
+        SymbolKind::Style {
+            properties,
+            variants,
+            ..
+        } => (
+            format!("style `{}`", symbol.name),
+            properties
+                .iter()
+                .chain(variants.iter().flat_map(|v| v.properties.iter()))
+                .collect(),
+        ),
+        SymbolKind::Node { args } if symbol.name == "view" => {
+            ("`view`".to_string(), args.iter().collect())
+        }
+        _ => (String::new(), Vec::new()),
+    };
+
+    let properties = properties
+        .into_iter()
+        .map(|(key, value)| (key.clone(), constants_substituted(value, scope)))
+        .collect();
+    (owner, properties)
+}
+
+/// Reports color functions in styles and on views whose channels are malformed or out of range
+fn check_colors(scope: &Rf<Symbol>, source: &str, errors: &mut Vec<DocumentError>) {
+    let (owner, properties) = checked_properties(scope);
+
+    // A list holds a color for each side, e.g. `borderColor`
+    let values = properties.into_iter().flat_map(|(key, value)| match value {
+        Value::Array { values, .. } => values
+            .iter_items()
+            .map(|value| (key.clone(), value.clone()))
+            .collect(),
+        value => vec![(key, value)],
+    });
+
+    for (key, value) in values {
+        let Some(("rgb" | "rgba", _)) = value.as_function() else {
             continue;
         };
-        let mut root = root.borrow_mut();
-        root.add_child(p);
+
+        let reason = match color_from_value(&value) {
+            Ok(_) => continue,
+            Err(ColorError::OutOfRange(_)) => "channel out of range",
+            Err(ColorError::InvalidArguments) => {
+                "expected 3 unitless channels for `rgb` or 4 for `rgba`"
+            }
+        };
+
+        errors.push(
+            DocumentError::new(
+                DocumentErrorType::InvalidColor(format!("{} in `{}` of {}", reason, key, owner)),
+                ErrorKind::Error,
+            )
+            .at(range_location(&value.get_range()), source),
+        );
+    }
+
+    for child in scope.borrow().children.values() {
+        check_colors(child, source, errors);
+    }
+}
+
+/// Reports built in style properties in styles and on views whose value doesn't fit the property
+fn check_properties(scope: &Rf<Symbol>, source: &str, errors: &mut Vec<DocumentError>) {
+    let (_, properties) = checked_properties(scope);
+
+    for (key, value) in properties {
+        let Some(expected) = expected_value(&key) else {
+            continue;
+        };
+        for unitless in unitless_sizes(&key, &value) {
+            errors.push(
+                DocumentError::new(
+                    DocumentErrorType::MissingUnit(key.clone()),
+                    ErrorKind::Warning,
+                )
+                .at(range_location(&unitless.get_range()), source),
+            );
+        }
+        // Bad colors are reported by `check_colors`
+        if let Some(("rgb" | "rgba", _)) = value.as_function() {
+            continue;
+        }
+        if !matches!(StyleValue::from_value(&key, &value), StyleValue::Empty) {
+            continue;
+        }
+
+        errors.push(
+            DocumentError::new(
+                DocumentErrorType::TypeMismatch {
+                    expected: format!("{} for `{}`", expected, key),
+                    found: describe_value(&value),
+                },
+                ErrorKind::Error,
+            )
+            .at(range_location(&value.get_range()), source),
+        );
     }
 
-    println!("Parsed {}", root.borrow().format());
+    for child in scope.borrow().children.values() {
+        check_properties(child, source, errors);
+    }
+}
 
-    Document {
-        errors: Vec::new(),
-        body_root: root,
-        // styles: None,
+/// `text` with `\n` turned into a line break and `\\` into a backslash. Any other backslash
+/// is kept as written
+fn unescape_text(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match (ch, chars.peek()) {
+            ('\\', Some('n')) => {
+                chars.next();
+                unescaped.push('\n');
+            }
+            ('\\', Some('\\')) => {
+                chars.next();
+                unescaped.push('\\');
+            }
+            (ch, _) => unescaped.push(ch),
+        }
     }
+    unescaped
+}
+
+/// `values` with the setup constants they use substituted, see `constants_substituted`
+fn with_constants(
+    values: &LinkedHashMap<String, Value>,
+    scope: &Rf<Symbol>,
+) -> LinkedHashMap<String, Value> {
+    values
+        .iter()
+        .map(|(key, value)| (key.clone(), constants_substituted(value, scope)))
+        .collect()
 }
 
-fn build_nodes(parent: Rf<Node>, symbol: &Rf<Symbol>) -> Option<Rf<Node>> {
-    let symbol = symbol.borrow();
+/// `value` with the setup constants it uses substituted, see
+/// `neb_smf::evaluate::substitute_constants`. A constant defined in terms of itself is left as
+/// written, so it's reported as a value that doesn't fit
+fn constants_substituted(value: &Value, scope: &Rf<Symbol>) -> Value {
+    substitute_constants(value, scope).unwrap_or_else(|_| value.clone())
+}
+
+fn build_nodes(parent: Rf<Node>, scope: &Rf<Symbol>, dir: &Path) -> Option<Rf<Node>> {
+    let symbol = scope.borrow();
     match &symbol.kind {
         SymbolKind::Node { args } => {
             let ty = if symbol.name == "view" {
-                NodeType::View { args: args.clone() }
+                NodeType::View {
+                    args: with_constants(args, scope),
+                }
+            } else if symbol.name == "spacer" {
+                NodeType::Spacer
+            } else if symbol.name == "image" {
+                let src = match args.get("src") {
+                    Some(Value::Ident(SpannedToken(_, Token::Ident(src)))) => src.clone(),
+                    _ => String::new(),
+                };
+                NodeType::Image {
+                    image: images::load(&dir.join(&src)),
+                    src,
+                }
             } else if symbol.name == "style" {
                 NodeType::StyleBlock
             } else {
                 NodeType::Setup
             };
-            let node = Rf::new(Node::new(ty, parent));
+            let name = match args.get("name") {
+                Some(Value::Ident(SpannedToken(_, Token::Ident(name)))) => Some(name.clone()),
+                _ => None,
+            };
+            let classes: Vec<String> = match args.get("class") {
+                Some(value @ Value::Ident(_)) => vec![value],
+                Some(Value::Array { values, .. }) => values.iter_items().collect(),
+                _ => Vec::new(),
+            }
+            .into_iter()
+            .filter_map(|value| match value {
+                Value::Ident(SpannedToken(_, Token::Ident(class))) => Some(class.clone()),
+                _ => None,
+            })
+            .collect();
+
+            let mut node = Node::new(ty, parent).with_classes(classes);
+            if let Some(name) = name {
+                node = node.with_name(name);
+            }
+            if let Some(range) = symbol.range {
+                node = node.with_source_range(range);
+            }
+            let node = Rf::new(node);
 
             for (_name, val) in symbol.children.iter() {
-                let Some(child) = build_nodes(node.clone(), val) else {
+                let Some(child) = build_nodes(node.clone(), val, dir) else {
                     continue;
                 };
 
@@ -141,15 +1267,39 @@ fn build_nodes(parent: Rf<Node>, symbol: &Rf<Symbol>) -> Option<Rf<Node>> {
 
             Some(node)
         }
-        SymbolKind::Use(path) => Some(Rf::new(Node::new(NodeType::Use(path.clone()), parent))),
-        SymbolKind::Style { properties } => Some(Rf::new(Node::new(
+        SymbolKind::Use(path) => {
+            let mut node = Node::new(NodeType::Use(path.clone()), parent);
+            if let Some(range) = symbol.range {
+                node = node.with_source_range(range);
+            }
+            Some(Rf::new(node))
+        }
+        SymbolKind::Style {
+            properties,
+            important,
+            variants,
+        } => Some(Rf::new(Node::new(
             NodeType::Style {
                 name: symbol.name.clone(),
-                properties: properties.clone(),
+                properties: with_constants(properties, scope),
+                important: important.clone(),
+                variants: variants
+                    .iter()
+                    .map(|variant| StyleVariant {
+                        properties: with_constants(&variant.properties, scope),
+                        ..variant.clone()
+                    })
+                    .collect(),
             },
             parent,
         ))),
-        SymbolKind::Text(s) => Some(Rf::new(Node::new(NodeType::Text(s.clone()), parent))),
+        SymbolKind::Text(s) => {
+            let mut node = Node::new(NodeType::Text(unescape_text(s)), parent);
+            if let Some(range) = symbol.range {
+                node = node.with_source_range(range);
+            }
+            Some(Rf::new(node))
+        }
         _ => None,
     }
 }
@@ -0,0 +1,204 @@
+//! Writes a laid out document as SVG, without a GPU
+
+use std::fmt::Write;
+
+use neb_graphics::{
+    simple_text::SimpleText,
+    vello::{
+        kurbo::{Rect, RoundedRect, RoundedRectRadii, Shape},
+        peniko::Color,
+    },
+};
+
+use crate::{
+    defaults,
+    document::Document,
+    ids::layout_of,
+    node::{inner_radii, Node, NodeType},
+    psize,
+    styling::StyleValue,
+    StyleValueAs,
+};
+
+impl Document {
+    /// Lays the document out in a `width` by `height` viewport and writes it as an SVG document.
+    ///
+    /// Backgrounds and borders become `<rect>`s, or `<path>`s when the corners differ, and text
+    /// becomes `<text>`. Images, shadows and custom properties are left out, and since SVG text
+    /// doesn't wrap, a text node only starts a new `<text>` at its line breaks
+    pub fn to_svg(&self, width: f64, height: f64) -> String {
+        self.layout(width, height);
+
+        let mut svg = String::new();
+        writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+        )
+        .unwrap();
+
+        let root = self.shown_root();
+        write_node(&root.borrow(), self, &mut svg);
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+fn write_node(node: &Node, document: &Document, svg: &mut String) {
+    let transform = node.get_element().transform(node, document);
+    if let Some(transform) = transform {
+        let [a, b, c, d, e, f] = transform.as_coeffs();
+        writeln!(svg, r#"<g transform="matrix({a} {b} {c} {d} {e} {f})">"#).unwrap();
+    }
+
+    // The body and other containers aren't painted themselves, but their children are
+    let layout = layout_of(node.element.id());
+    match &node.ty {
+        _ if !node.is_displayed() || !node.is_visible(document) => (),
+        NodeType::View { .. } => {
+            let background_color =
+                StyleValueAs!(node.styles(document, "backgroundColor"), BackgroundColor);
+            let border_segments = node.border_segments(document);
+            let border_color = match node.styles(document, "borderColor") {
+                _ if !border_segments.is_empty() => None,
+                StyleValue::BorderColor { color } => Some(color),
+                StyleValue::BorderColors { colors } => Some(colors[0]),
+                _ => None,
+            };
+            let border_width = StyleValueAs!(node.styles(document, "borderWidth"), BorderWidth);
+
+            let radius: Option<RoundedRectRadii> =
+                StyleValueAs!(node.styles(document, "radius"), Radius)
+                    .and_then(|radius| radius.try_into().ok());
+            // The stroke is centered on its path, so the path runs through the middle of the border
+            let border: Option<Rect> = border_width.and_then(|width| width.try_into().ok());
+            if let (Some(color), Some(border)) = (border_color, border) {
+                let inset = border.x0 / 2.0;
+                write_shape(
+                    svg,
+                    layout.border_rect.inset(-inset),
+                    radius,
+                    &format!(
+                        r#"fill="none" {} stroke-width="{}""#,
+                        paint("stroke", color),
+                        border.x0
+                    ),
+                );
+            }
+
+            for (segment, color) in &border_segments {
+                writeln!(
+                    svg,
+                    r#"<path d="{}" {}/>"#,
+                    segment.to_svg(),
+                    paint("fill", *color)
+                )
+                .unwrap();
+            }
+
+            // The background sits inside the border, so its corners are tighter
+            if let Some(color) = background_color {
+                let radius = radius.map(|radius| inner_radii(radius, border_width));
+                write_shape(svg, layout.padding_rect, radius, &paint("fill", color));
+            }
+        }
+        NodeType::Text(text) => {
+            // Text is painted in the color of the view it sits in
+            let color = node
+                .get_parent()
+                .and_then(|parent| {
+                    StyleValueAs!(
+                        parent.borrow().styles(document, "foregroundColor"),
+                        ForegroundColor
+                    )
+                })
+                .unwrap_or(defaults::FOREGROUND_COLOR);
+
+            // Line breaks in the text start a new line a line height down
+            let size = psize!(defaults::TEXT_SIZE);
+            let (ascent, descent, gap) = SimpleText::new().get_adg(None, size);
+            let line_height = (ascent + descent + gap).ceil();
+
+            let (x, y) = (layout.padding_rect.x0, layout.padding_rect.y0);
+            for (index, line) in node
+                .text_transform(document)
+                .apply(text)
+                .split('\n')
+                .enumerate()
+            {
+                writeln!(
+                    svg,
+                    r#"<text x="{x}" y="{}" font-family="Roboto Mono, monospace" font-size="{size}" dominant-baseline="text-before-edge" {}>{}</text>"#,
+                    y + line_height * index as f64,
+                    paint("fill", color),
+                    escape(line),
+                )
+                .unwrap();
+            }
+        }
+        _ => (),
+    }
+
+    for child in node.children.iter() {
+        write_node(&child.borrow(), document, svg);
+    }
+
+    if transform.is_some() {
+        svg.push_str("</g>\n");
+    }
+}
+
+/// A `<rect>` when every corner has the same radius, otherwise a `<path>` around the rounded rect
+fn write_shape(svg: &mut String, rect: Rect, radius: Option<RoundedRectRadii>, attributes: &str) {
+    let radius = radius.unwrap_or_default();
+    let corners = [
+        radius.top_left,
+        radius.top_right,
+        radius.bottom_right,
+        radius.bottom_left,
+    ];
+
+    if corners.iter().all(|corner| *corner == radius.top_left) {
+        let rx = if radius.top_left > 0.0 {
+            format!(r#" rx="{}""#, radius.top_left)
+        } else {
+            String::new()
+        };
+        writeln!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{}" height="{}"{rx} {attributes}/>"#,
+            rect.x0,
+            rect.y0,
+            rect.width(),
+            rect.height(),
+        )
+        .unwrap();
+    } else {
+        let path = RoundedRect::from_rect(rect, radius).to_path(0.1);
+        writeln!(svg, r#"<path d="{}" {attributes}/>"#, path.to_svg()).unwrap();
+    }
+}
+
+/// `color` as an SVG paint attribute, e.g. `fill="rgb(255, 0, 0)"`. The alpha goes in a separate
+/// opacity attribute, which is the reverse of what `svg::modify_opacity` reads
+pub fn paint(attribute: &str, color: Color) -> String {
+    let mut paint = format!(
+        r#"{attribute}="rgb({}, {}, {})""#,
+        color.r, color.g, color.b
+    );
+    if color.a != 255 {
+        write!(
+            paint,
+            r#" {attribute}-opacity="{}""#,
+            color.a as f64 / 255.0
+        )
+        .unwrap();
+    }
+    paint
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
@@ -1,29 +1,62 @@
 use std::{
     collections::HashMap,
+    hash::{Hash, Hasher},
     sync::{Mutex, MutexGuard},
 };
 
-use neb_graphics::vello::kurbo::Rect;
+use neb_graphics::vello::kurbo::{Rect, Vec2};
 
 lazy_static::lazy_static! {
     pub(crate) static ref ID_MANAGER: Mutex<IDManager> = {
-        Mutex::new(IDManager { id_mappings: HashMap::new(), next_id: rand::random() })
+        Mutex::new(IDManager {
+            id_mappings: HashMap::new(),
+            subtree_hashes: HashMap::new(),
+            layout_inputs: HashMap::new(),
+            layout_bounds: HashMap::new(),
+            scroll_offsets: HashMap::new(),
+            next_id: rand::random(),
+        })
     };
 }
 
+/// Locks the manager. The lock isn't reentrant, so the guard must be dropped before
+/// anything that can lock it again, e.g. laying out a child node
 pub fn get_id_mgr() -> MutexGuard<'static, IDManager> {
     ID_MANAGER.lock().unwrap()
 }
 
+/// A copy of a node's layout, taken without keeping the manager locked
+pub fn layout_of(id: ID) -> Layout {
+    *get_id_mgr().get_layout(id)
+}
+
+/// Changes a node's layout in place. The manager is only locked while `f` runs, so `f` must not lock it again
+pub fn update_layout(id: ID, f: impl FnOnce(&mut Layout)) {
+    let mut manager = get_id_mgr();
+    let layout = manager.id_mappings.entry(id).or_default();
+    f(layout)
+}
+
 pub type ID = u64;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Layout {
     pub padding_rect: Rect,
     pub content_rect: Rect,
     pub border_rect: Rect,
 }
 
+impl Hash for Layout {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for rect in [self.border_rect, self.padding_rect, self.content_rect] {
+            for v in [rect.x0, rect.y0, rect.x1, rect.y1] {
+                // Adding zero turns `-0.0` into `0.0`, so layouts that are equal hash the same
+                (v + 0.0).to_bits().hash(state);
+            }
+        }
+    }
+}
+
 pub const LAYOUT_ZERO: Layout = Layout {
     padding_rect: Rect::ZERO,
     content_rect: Rect::ZERO,
@@ -43,6 +76,14 @@ impl Default for Layout {
 #[derive(Debug)]
 pub struct IDManager {
     pub(crate) id_mappings: HashMap<ID, Layout>,
+    /// Hash of everything under a node that layout reads, refreshed every layout pass
+    pub(crate) subtree_hashes: HashMap<ID, u64>,
+    /// Inputs (bounds + subtree hash) that the stored layout was computed from
+    pub(crate) layout_inputs: HashMap<ID, u64>,
+    /// The bounds a node was last laid out in, so it can be laid out again on its own
+    pub(crate) layout_bounds: HashMap<ID, Rect>,
+    /// How far the content of scrollable nodes is scrolled, from the top left
+    pub(crate) scroll_offsets: HashMap<ID, Vec2>,
     next_id: ID,
 }
 
@@ -111,6 +152,43 @@ impl IDManager {
     pub fn get_layout(&self, id: ID) -> &Layout {
         self.id_mappings.get(&id).unwrap_or(&LAYOUT_ZERO)
     }
+
+    pub fn subtree_hash(&self, id: ID) -> Option<u64> {
+        self.subtree_hashes.get(&id).copied()
+    }
+
+    pub fn set_subtree_hash(&mut self, id: ID, hash: u64) {
+        self.subtree_hashes.insert(id, hash);
+    }
+
+    pub fn layout_input(&self, id: ID) -> Option<u64> {
+        self.layout_inputs.get(&id).copied()
+    }
+
+    pub fn set_layout_input(&mut self, id: ID, hash: u64) {
+        self.layout_inputs.insert(id, hash);
+    }
+
+    /// Forces the node to be laid out again next time, even if its inputs are unchanged
+    pub fn clear_layout_input(&mut self, id: ID) {
+        self.layout_inputs.remove(&id);
+    }
+
+    pub fn layout_bounds(&self, id: ID) -> Option<Rect> {
+        self.layout_bounds.get(&id).copied()
+    }
+
+    pub fn set_layout_bounds(&mut self, id: ID, bounds: Rect) {
+        self.layout_bounds.insert(id, bounds);
+    }
+
+    pub fn scroll_offset(&self, id: ID) -> Vec2 {
+        self.scroll_offsets.get(&id).copied().unwrap_or(Vec2::ZERO)
+    }
+
+    pub fn set_scroll_offset(&mut self, id: ID, offset: Vec2) {
+        self.scroll_offsets.insert(id, offset);
+    }
 }
 
 // pub fn fd() {
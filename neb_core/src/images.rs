@@ -0,0 +1,35 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use neb_graphics::vello::peniko::{Blob, Format, Image};
+
+lazy_static::lazy_static! {
+    /// Decoded images by path, so nodes showing the same file share one bitmap.
+    /// Failures are kept too, to not read a broken file every time it's used
+    static ref IMAGES: Mutex<HashMap<PathBuf, Option<Image>>> = Mutex::new(HashMap::new());
+}
+
+/// The decoded image at `path`. `None` if the file can't be read or decoded
+pub fn load(path: &Path) -> Option<Image> {
+    IMAGES
+        .lock()
+        .unwrap()
+        .entry(path.to_path_buf())
+        .or_insert_with(|| decode(path))
+        .clone()
+}
+
+fn decode(path: &Path) -> Option<Image> {
+    let decoded = image::open(path).ok()?.into_rgba8();
+    let (width, height) = decoded.dimensions();
+
+    Some(Image::new(
+        Blob::new(Arc::new(decoded.into_raw())),
+        Format::Rgba8,
+        width,
+        height,
+    ))
+}
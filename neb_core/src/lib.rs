@@ -12,13 +12,2997 @@ pub mod ids;
 
 pub mod styling;
 
+pub mod scroll;
+
+pub mod images;
+
+pub mod export;
+
+pub mod overlay;
+
 mod rectr;
 
 // mod svg;
 
+use std::io::BufReader;
+
+use document::Document;
+
+/// Parses `text` and lays it out in a `width` by `height` viewport. Nothing here needs a window
+/// or event loop, so this works without the `native` feature, e.g. for a WASM build that draws
+/// the geometry from `Document::measure_tree` itself
+pub fn parse_and_layout(text: &str, width: f64, height: f64) -> Document {
+    let document = document::parse_from_stream(BufReader::new(text.as_bytes()));
+    document.layout(width, height);
+    document
+}
+
 #[cfg(test)]
 mod tests {
+    use neb_smf::{
+        ast::Value,
+        token::{Span, SpannedToken, Token, Unit},
+        Module, SymbolKind,
+    };
+
+    use std::{
+        collections::hash_map::DefaultHasher,
+        hash::{Hash, Hasher},
+        io::{BufReader, Cursor},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant},
+    };
+
+    use neb_errors::{ErrorKind, SourceLocation};
+    use neb_graphics::{
+        drawing_context::DrawingContext,
+        input::CursorIcon,
+        simple_text::{SimpleText, TextWrap},
+        vello::{
+            kurbo::{Affine, Point, Rect, RoundedRectRadii, Shape, Size, Vec2},
+            peniko::{BlendMode, Color, Mix},
+            Scene, SceneBuilder,
+        },
+    };
+    use neb_util::Rf;
+
+    use crate::{
+        defaults,
+        document::{
+            parse_from_stream, parse_from_stream_in, Change, Document, DrawStats, MeasuredNode,
+        },
+        ids::{get_id_mgr, Layout},
+        node::{Node, NodeType},
+        psize,
+        scroll::{self, MIN_THUMB_LENGTH},
+        styling::{
+            color_from_iter, color_from_value, lerp_color, rect_shorthand_from_iter, ColorError,
+            CustomProperty, Direction, StyleValue, TextTransform, UnitRect, UnitValue,
+        },
+        StyleValueAs,
+    };
+
+    fn px(v: i64) -> Value {
+        let token = SpannedToken::new(Token::Integer(v, Some(Unit::Pixel)), Span::default());
+        Value::Integer(v, Some(Unit::Pixel), token)
+    }
+
+    fn int(v: i64) -> Value {
+        let token = SpannedToken::new(Token::Integer(v, None), Span::default());
+        Value::Integer(v, None, token)
+    }
+
+    fn float(v: f64) -> Value {
+        let token = SpannedToken::new(Token::Float(v, None), Span::default());
+        Value::Float(v, None, token)
+    }
+
+    fn rect(x0: f64, y0: f64, x1: f64, y1: f64) -> UnitRect {
+        use UnitValue::Pixels;
+        UnitRect::new(Pixels(x0), Pixels(y0), Pixels(x1), Pixels(y1))
+    }
 
     #[test]
     fn it_works() {}
-}
\ No newline at end of file
+
+    #[test]
+    fn shorthand_rects() {
+        assert_eq!(
+            rect_shorthand_from_iter([px(4)].iter()),
+            Some(rect(4.0, 4.0, 4.0, 4.0))
+        );
+        assert_eq!(
+            rect_shorthand_from_iter([px(4), px(8)].iter()),
+            Some(rect(8.0, 4.0, 8.0, 4.0))
+        );
+        assert_eq!(
+            rect_shorthand_from_iter([px(4), px(8), px(12), px(16)].iter()),
+            Some(rect(16.0, 4.0, 8.0, 12.0))
+        );
+    }
+
+    #[test]
+    fn breakpoint_variants() {
+        let source = "setup {
+    style {
+        spaced {
+            gap: 40px
+        }
+        spaced@(maxWidth: 600px) {
+            gap: 10px
+        }
+    }
+}
+
+use setup.style
+
+view (class: spaced) {
+    :Hello
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        let view = document
+            .get_body()
+            .borrow()
+            .children
+            .iter()
+            .find(|child| matches!(child.borrow().ty, NodeType::View { .. }))
+            .cloned()
+            .unwrap();
+
+        let gap = |width| {
+            document.layout(width, 600.0);
+            match view.borrow().styles(&document, "gap") {
+                StyleValue::Gap { amount } => Some(amount),
+                _ => None,
+            }
+        };
+
+        assert_eq!(gap(800.0), Some(UnitValue::Pixels(40.0)));
+        assert_eq!(gap(500.0), Some(UnitValue::Pixels(10.0)));
+        assert_eq!(gap(800.0), Some(UnitValue::Pixels(40.0)));
+    }
+
+    #[test]
+    fn color_channels() {
+        assert_eq!(
+            color_from_iter([int(255), int(128), int(0)].iter()),
+            Ok(Color::rgba8(255, 128, 0, 255))
+        );
+        assert_eq!(
+            color_from_iter([float(1.0), float(0.5), float(0.0), float(0.5)].iter()),
+            Ok(Color::rgba8(255, 128, 0, 128))
+        );
+        assert_eq!(
+            color_from_iter([int(300), int(0), float(2.0)].iter()),
+            Err(ColorError::OutOfRange(Color::rgba8(255, 0, 255, 255)))
+        );
+        assert_eq!(
+            color_from_iter([int(0), int(0)].iter()),
+            Err(ColorError::InvalidArguments)
+        );
+        assert_eq!(
+            color_from_iter([int(0), int(0), px(4)].iter()),
+            Err(ColorError::InvalidArguments)
+        );
+    }
+
+    #[test]
+    fn colors_agree_across_representations() {
+        let source = "style {
+    tint {
+        backgroundColor: rgba(255, 128, 0, 128)
+    }
+}";
+        let (module, _) = neb_smf::Module::parse_str(source);
+        let style = module
+            .resolve_symbol_chain_string(["style".to_string(), "tint".to_string()].iter())
+            .unwrap();
+        let SymbolKind::Style { properties, .. } = &style.borrow().kind else {
+            panic!("`tint` isn't a style");
+        };
+        let function = &properties["backgroundColor"];
+
+        // What the `rgba` symbol evaluates the same arguments to
+        let rgba = module.resolve_symbol(&module.symbol_tree, "rgba").unwrap();
+        let SymbolKind::Function { func, .. } = &rgba.borrow().kind else {
+            panic!("`rgba` isn't a function");
+        };
+        let (_, args) = function.as_function().unwrap();
+        let tuple = func(args.iter_values().cloned().collect()).unwrap();
+
+        let expected = Color::rgba8(255, 128, 0, 128);
+        assert_eq!(color_from_value(function), Ok(expected));
+        assert_eq!(color_from_value(&tuple), Ok(expected));
+        assert_eq!(
+            color_from_iter([int(255), int(128), int(0), int(128)].iter()),
+            Ok(expected)
+        );
+        assert_eq!(
+            color_from_value(&int(255)),
+            Err(ColorError::InvalidArguments)
+        );
+
+        // `rgb` fills in an opaque alpha, so its tuple is read the same way
+        let rgb = module.resolve_symbol(&module.symbol_tree, "rgb").unwrap();
+        let SymbolKind::Function { func, .. } = &rgb.borrow().kind else {
+            panic!("`rgb` isn't a function");
+        };
+        let tuple = func(vec![int(255), int(128), int(0)]).unwrap();
+        assert_eq!(color_from_value(&tuple), Ok(Color::rgba8(255, 128, 0, 255)));
+    }
+
+    #[test]
+    fn hex_and_named_colors_are_drawn() {
+        let source = "setup {
+    style {
+        card {
+            backgroundColor: \"#ff8000\"
+            foregroundColor: white
+            borderColor: [red, \"#00f\"]
+        }
+    }
+}
+
+use setup.style
+
+view (name: card, class: card) {
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        assert!(document.get_errors().is_empty());
+
+        let card = document.get_by_name("card").unwrap();
+        let style = |key| card.borrow().styles(&document, key);
+        assert_eq!(
+            StyleValueAs!(style("backgroundColor"), BackgroundColor),
+            Some(Color::rgba8(255, 128, 0, 255))
+        );
+        assert_eq!(
+            StyleValueAs!(style("foregroundColor"), ForegroundColor),
+            Some(Color::WHITE)
+        );
+        let red = Color::rgba8(255, 0, 0, 255);
+        let blue = Color::rgba8(0, 0, 255, 255);
+        assert_eq!(
+            StyleValueAs!(style("borderColor"), BorderColors),
+            Some([red, blue, red, blue])
+        );
+    }
+
+    #[test]
+    fn setup_constants_are_drawn() {
+        let source = "setup (accent: rgb(255, 0, 0), highlight: accent) {
+    style {
+        card {
+            borderColor: highlight
+        }
+    }
+}
+
+use setup.style
+
+view (name: card, class: card, foregroundColor: accent) {
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        assert!(document.get_errors().is_empty());
+
+        // The same constants `Module::evaluate_value` resolves
+        let card = document.get_by_name("card").unwrap();
+        let style = |key| card.borrow().styles(&document, key);
+        let red = Color::rgba8(255, 0, 0, 255);
+        assert_eq!(StyleValueAs!(style("borderColor"), BorderColor), Some(red));
+        assert_eq!(
+            StyleValueAs!(style("foregroundColor"), ForegroundColor),
+            Some(red)
+        );
+    }
+
+    #[test]
+    fn out_of_range_color_is_reported() {
+        let source = "setup {
+    style {
+        loud {
+            backgroundColor: rgb(300, -1, 0)
+        }
+    }
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        let errors: Vec<_> = document
+            .get_errors()
+            .iter()
+            .map(|e| e.get_message())
+            .collect();
+
+        assert_eq!(
+            errors,
+            vec!["Invalid color: channel out of range in `backgroundColor` of style `loud`"]
+        );
+    }
+
+    #[test]
+    fn view_arguments_are_checked_like_styles() {
+        let source = "view (backgroundColor: notacolor) {
+}
+view (foregroundColor: rgb(300, 0, 0), gap: 4) {
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        let errors: Vec<_> = document
+            .get_errors()
+            .iter()
+            .map(|e| (e.kind(), e.get_message()))
+            .collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    ErrorKind::Error,
+                    "Expected a color like `rgb(0, 0, 0)` or `black` for `backgroundColor`, found identifier `notacolor`".to_string()
+                ),
+                (
+                    ErrorKind::Error,
+                    "Invalid color: channel out of range in `foregroundColor` of `view`".to_string()
+                ),
+                (
+                    ErrorKind::Warning,
+                    "Missing a unit for `gap`, it is read as `px`".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn relayout_subtree_grows_ancestors() {
+        let source = "view {
+    view {
+        :Unrelated
+    }
+    view {
+        :Hi
+    }
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        document.layout(200.0, 600.0);
+
+        let outer = document.get_body().borrow().children[0].clone();
+        let unrelated = outer.borrow().children[0].clone();
+        let target = outer.borrow().children[1].clone();
+        let text = target.borrow().children[0].clone();
+
+        let rect = |node: &Rf<Node>| {
+            get_id_mgr()
+                .get_layout(node.borrow().element.id())
+                .border_rect
+        };
+        let (outer_before, unrelated_before, target_before) =
+            (rect(&outer), rect(&unrelated), rect(&target));
+        let unrelated_input = get_id_mgr().layout_input(unrelated.borrow().element.id());
+
+        text.borrow_mut().ty = NodeType::Text("a much longer message that has to wrap".into());
+        document.relayout_subtree(text.borrow().element.id());
+
+        let grown = rect(&target).height() - target_before.height();
+        assert!(grown > 0.0);
+        assert_eq!(rect(&outer).height(), outer_before.height() + grown);
+        assert_eq!(rect(&unrelated), unrelated_before);
+        assert_eq!(
+            get_id_mgr().layout_input(unrelated.borrow().element.id()),
+            unrelated_input
+        );
+    }
+
+    #[test]
+    fn cursor_style_resolution() {
+        let source = "setup {
+    style {
+        button {
+            cursor: Pointer
+        }
+        secret {
+            cursor: Hidden
+        }
+    }
+}
+
+use setup.style
+
+view {
+    view (class: button) {
+        :Click
+    }
+    view (class: secret) {
+    }
+    view {
+    }
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        document.layout(400.0, 400.0);
+
+        let outer = document
+            .get_body()
+            .borrow()
+            .children
+            .iter()
+            .find(|child| matches!(child.borrow().ty, NodeType::View { .. }))
+            .cloned()
+            .unwrap();
+        let id = |node: &Rf<Node>| node.borrow().element.id();
+        let button = outer.borrow().children[0].clone();
+        let text = button.borrow().children[0].clone();
+
+        // Hovering the text inherits the button's cursor
+        let center = get_id_mgr().get_layout(id(&text)).border_rect.center();
+        assert_eq!(document.hit_test(center), Some(id(&text)));
+        assert_eq!(document.cursor_for(id(&text)), Some(CursorIcon::Hand));
+
+        assert_eq!(document.cursor_for(id(&outer.borrow().children[1])), None);
+        assert_eq!(
+            document.cursor_for(id(&outer.borrow().children[2])),
+            Some(CursorIcon::Default)
+        );
+    }
+
+    #[test]
+    fn parse_from_in_memory_reader() {
+        let source: &[u8] = b"view {\n    :Hello\n}";
+        let document = parse_from_stream(BufReader::new(Cursor::new(source)));
+
+        let body = document.get_body().borrow();
+        let view = body.children[0].borrow();
+        assert!(matches!(view.ty, NodeType::View { .. }));
+        assert!(matches!(&view.children[0].borrow().ty, NodeType::Text(t) if t == "Hello"));
+    }
+
+    fn border_rects(document: &Document) -> Vec<Rect> {
+        fn collect(node: &Rf<Node>, rects: &mut Vec<Rect>) {
+            let node = node.borrow();
+            rects.push(get_id_mgr().get_layout(node.element.id()).border_rect);
+            node.children.iter().for_each(|child| collect(child, rects));
+        }
+
+        let mut rects = Vec::new();
+        collect(document.get_body(), &mut rects);
+        rects
+    }
+
+    #[test]
+    fn cached_layout_matches_fresh_layout() {
+        let source = "setup {
+    style {
+        spaced {
+            padding: 8px
+            gap: 40px
+        }
+        spaced@(maxWidth: 600px) {
+            gap: 10px
+        }
+    }
+}
+
+use setup.style
+
+view (class: spaced) {
+    view (class: spaced) {
+    }
+    view (class: spaced) {
+    }
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+
+        document.layout(800.0, 600.0);
+        let first = border_rects(&document);
+        document.layout(800.0, 600.0);
+        assert_eq!(border_rects(&document), first);
+
+        // The breakpoint changes the gap, which must invalidate the cached rects
+        document.layout(500.0, 600.0);
+        let fresh = parse_from_stream(BufReader::new(source.as_bytes()));
+        fresh.layout(500.0, 600.0);
+        assert_eq!(border_rects(&document), border_rects(&fresh));
+        assert_ne!(border_rects(&document), first);
+    }
+
+    #[test]
+    fn percentage_gap_is_of_the_main_axis() {
+        let source = "setup {
+    style {
+        row {
+            direction: Horizontal
+            width: 200px
+            gap: 10%
+        }
+        cell {
+            width: 20px
+            height: 20px
+        }
+    }
+}
+
+use setup.style
+
+view (class: row) {
+    view (class: cell) {
+    }
+    view (class: cell) {
+    }
+    view (class: cell) {
+    }
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        document.layout(800.0, 600.0);
+
+        let body = document.get_body().borrow();
+        let row = body
+            .children
+            .iter()
+            .find(|child| matches!(child.borrow().ty, NodeType::View { .. }))
+            .unwrap()
+            .borrow();
+        let cells: Vec<_> = row
+            .children
+            .iter()
+            .map(|cell| {
+                get_id_mgr()
+                    .get_layout(cell.borrow().element.id())
+                    .border_rect
+            })
+            .collect();
+
+        assert_eq!(cells.len(), 3);
+        for pair in cells.windows(2) {
+            assert_eq!(pair[1].x0 - pair[0].x1, 20.0);
+        }
+    }
+
+    #[test]
+    fn horizontal_text_shares_a_baseline() {
+        let source = "setup {
+    style {
+        row {
+            direction: Horizontal
+        }
+        padded {
+            padding: 12px
+            borderWidth: 2px
+        }
+    }
+}
+
+use setup.style
+
+view (class: row) {
+    view (name: plain) {
+        :Plain
+    }
+    view (name: padded, class: padded) {
+        view {
+            :Padded
+        }
+    }
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        document.layout(800.0, 600.0);
+
+        let baseline = |name: &str| {
+            // The padded text sits in a view of its own
+            let mut text = document.get_by_name(name).unwrap();
+            while !matches!(text.borrow().ty, NodeType::Text(_)) {
+                let first = text.borrow().children[0].clone();
+                text = first;
+            }
+            let text = text.borrow();
+            let top = get_id_mgr().get_layout(text.element.id()).border_rect.y0;
+            top + text.first_baseline(&document).unwrap()
+        };
+        let top = |name: &str| {
+            let id = document.get_by_name(name).unwrap().borrow().element.id();
+            get_id_mgr().get_layout(id).border_rect.y0
+        };
+
+        assert_eq!(baseline("plain"), baseline("padded"));
+        // The plain text moved down to meet the padded one
+        assert_eq!(top("plain"), top("padded") + 14.0);
+    }
+
+    #[test]
+    fn inline_text_runs_share_a_line() {
+        let source = "setup {
+    style {
+        row {
+            display: Inline
+        }
+    }
+}
+
+use setup.style
+
+view (class: row) {
+    :Hello
+    :World
+}
+view {
+    :Hello
+    :World
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        document.layout(800.0, 600.0);
+
+        let text_rects = |view: usize| {
+            let body = document.get_body().borrow();
+            let views: Vec<_> = body
+                .children
+                .iter()
+                .filter(|child| matches!(child.borrow().ty, NodeType::View { .. }))
+                .cloned()
+                .collect();
+            let view = views[view].borrow();
+            view.children
+                .iter()
+                .map(|text| {
+                    get_id_mgr()
+                        .get_layout(text.borrow().element.id())
+                        .border_rect
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let inline = text_rects(0);
+        assert_eq!(inline[0].y0, inline[1].y0);
+        assert!(inline[1].x0 > inline[0].x1);
+
+        let stacked = text_rects(1);
+        assert!(stacked[1].y0 >= stacked[0].y1);
+    }
+
+    #[test]
+    fn rotation_pivots_around_transform_origin() {
+        let source = "setup {
+    style {
+        spin {
+            padding: rect_xy(40px, 10px)
+            transform: rotate(180)
+        }
+        corner {
+            padding: 10px
+            transform: rotate(180)
+            transformOrigin: TopLeft
+        }
+    }
+}
+
+use setup.style
+
+view (class: spin) {
+}
+view (class: corner) {
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        document.layout(800.0, 600.0);
+
+        let body = document.get_body().borrow();
+        let views: Vec<_> = body
+            .children
+            .iter()
+            .filter(|child| matches!(child.borrow().ty, NodeType::View { .. }))
+            .cloned()
+            .collect();
+
+        let close = |a: Point, b: Point| (a - b).hypot() < 1e-9;
+
+        // Half a turn about the center swaps opposite corners
+        let spin = views[0].borrow();
+        let rect = get_id_mgr().get_layout(spin.element.id()).border_rect;
+        let transform = spin.element.transform(&spin, &document).unwrap();
+        assert!(close(
+            transform * Point::new(rect.x0, rect.y0),
+            Point::new(rect.x1, rect.y1)
+        ));
+
+        // About the top left corner, that corner stays put
+        let corner = views[1].borrow();
+        let rect = get_id_mgr().get_layout(corner.element.id()).border_rect;
+        let transform = corner.element.transform(&corner, &document).unwrap();
+        assert!(close(
+            transform * Point::new(rect.x0, rect.y0),
+            Point::new(rect.x0, rect.y0)
+        ));
+    }
+
+    #[test]
+    fn intrinsic_size_fits_content() {
+        let source = "setup {
+    style {
+        outer {
+            padding: rect_xy(20px, 5px)
+            gap: 0px
+        }
+        square {
+            padding: 10px
+        }
+    }
+}
+
+use setup.style
+
+view (class: outer) {
+    view (class: square) {
+    }
+    view (class: square) {
+    }
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        let size = document.intrinsic_size();
+
+        // The body's only child needs no gap
+        assert_eq!(size.width, 60.0);
+        assert_eq!(size.height, 50.0);
+
+        // Laying out in a window afterwards leaves the content where it was
+        document.layout(800.0, 600.0);
+        assert_eq!(document.dimensions(), size);
+    }
+
+    #[test]
+    fn custom_property_resolves_and_draws() {
+        let source = "setup {
+    style {
+        shadowed {
+            shadowColor: rgb(0, 0, 255)
+        }
+    }
+}
+
+use setup.style
+
+view (class: shadowed) {
+    :Text
+}";
+        let mut document = parse_from_stream(BufReader::new(source.as_bytes()));
+
+        let drawn = Arc::new(AtomicUsize::new(0));
+        let counter = drawn.clone();
+        document.register_property(
+            "shadowColor",
+            CustomProperty::new(|value| match value.as_function() {
+                Some(("rgb", args)) => match color_from_iter(args.iter_values()) {
+                    Ok(color) => StyleValue::BackgroundColor { color },
+                    Err(_) => StyleValue::Empty,
+                },
+                _ => StyleValue::Custom {
+                    value: value.clone(),
+                },
+            })
+            .inherited()
+            .with_draw(move |value, _, _| {
+                assert!(matches!(value, StyleValue::BackgroundColor { .. }));
+                counter.fetch_add(1, Ordering::SeqCst);
+            }),
+        );
+        document.layout(800.0, 600.0);
+
+        let body = document.get_body().borrow();
+        let view = body
+            .children
+            .iter()
+            .find(|child| matches!(child.borrow().ty, NodeType::View { .. }))
+            .unwrap()
+            .borrow();
+        let text = view.children[0].borrow();
+
+        let blue = Color::rgb8(0, 0, 255);
+        for node in [&view, &text] {
+            match node.styles(&document, "shadowColor") {
+                StyleValue::BackgroundColor { color } => assert_eq!(color, blue),
+                other => panic!("expected the resolved color, got {:?}", other),
+            }
+        }
+
+        let mut scene = Scene::default();
+        let mut dctx = DrawingContext {
+            builder: SceneBuilder::for_scene(&mut scene),
+            text: SimpleText::new(),
+            size: Size::new(800.0, 600.0),
+            transform: Affine::IDENTITY,
+            mouse: None,
+            keys: Vec::new(),
+            scroll: Vec2::ZERO,
+            cursor: None,
+        };
+        document.draw(&mut dctx);
+
+        // Drawn for the view and, through inheritance, its text
+        assert_eq!(drawn.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn box_shadow_is_offset_from_the_border() {
+        let source = "setup {
+    style {
+        card {
+            radius: 4px
+            boxShadow: shadow(0, 2px, 8px, rgba(0, 0, 0, 128))
+        }
+        flat {
+            boxShadow: shadow(3px, 3px, 0, rgb(0, 0, 0))
+        }
+    }
+}
+
+use setup.style
+
+view (class: card) {
+    :Card
+}
+view (class: flat) {
+    :Flat
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        document.layout(800.0, 600.0);
+
+        let body = document.get_body().borrow();
+        let views: Vec<_> = body
+            .children
+            .iter()
+            .filter(|child| matches!(child.borrow().ty, NodeType::View { .. }))
+            .cloned()
+            .collect();
+
+        let card = views[0].borrow();
+        let rect = get_id_mgr().get_layout(card.element.id()).border_rect;
+        let shadow = StyleValueAs!(card.styles(&document, "boxShadow"), BoxShadow).unwrap()[0];
+        assert_eq!(shadow.blur, 8.0);
+
+        let layers = shadow.layers(rect, RoundedRectRadii::from_single_radius(4.0));
+        assert_eq!(layers.len(), 8);
+        for (shape, color) in &layers {
+            let center = shape.rect().center();
+            assert!((center - (rect.center() + Vec2::new(0.0, 2.0))).hypot() < 1e-9);
+            assert_eq!((color.r, color.g, color.b), (0, 0, 0));
+        }
+
+        // The blur spreads evenly either side of the offset border
+        let (inner, _) = &layers[0];
+        let (outer, _) = &layers[layers.len() - 1];
+        assert!(inner.rect().width() < rect.width() && outer.rect().width() > rect.width());
+        assert!(outer.radii().top_left > 4.0);
+
+        let flat = views[1].borrow();
+        let rect = get_id_mgr().get_layout(flat.element.id()).border_rect;
+        let shadow = StyleValueAs!(flat.styles(&document, "boxShadow"), BoxShadow).unwrap()[0];
+        let layers = shadow.layers(rect, RoundedRectRadii::default());
+        assert_eq!(layers.len(), 1);
+        assert_eq!(layers[0].0.rect(), rect + Vec2::new(3.0, 3.0));
+        assert_eq!(layers[0].1, Color::rgb8(0, 0, 0));
+    }
+
+    #[test]
+    fn box_shadow_takes_a_list() {
+        let source = "setup {
+    style {
+        card {
+            boxShadow: [shadow(0, 1px, 2px, rgb(0, 0, 0)), shadow(0, 8px, 16px, rgba(0, 0, 0, 64))]
+        }
+    }
+}
+
+use setup.style
+
+view (class: card) {
+    :Card
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        assert!(document.get_errors().is_empty());
+
+        let body = document.get_body().borrow();
+        let card = body
+            .children
+            .iter()
+            .find(|child| matches!(child.borrow().ty, NodeType::View { .. }))
+            .unwrap()
+            .borrow();
+
+        let shadows = StyleValueAs!(card.styles(&document, "boxShadow"), BoxShadow).unwrap();
+        let blurs: Vec<_> = shadows.iter().map(|shadow| shadow.blur).collect();
+        assert_eq!(blurs, vec![2.0, 16.0]);
+    }
+
+    #[test]
+    fn elements_are_found_by_name() {
+        let source = "view (name: header) {
+    :Title
+}
+view (name: footer) {
+}
+view (name: header) {
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+
+        let header = document.get_by_name("header").unwrap();
+        let header = header.borrow();
+        assert_eq!(header.element.name(), Some("header"));
+        assert!(matches!(&header.children[0].borrow().ty, NodeType::Text(text) if text == "Title"));
+
+        assert!(document.get_by_name("footer").is_some());
+        assert!(document.get_by_name("missing").is_none());
+
+        // The second `header` is reported at its name and the first one is kept
+        let duplicates: Vec<_> = document
+            .get_errors()
+            .iter()
+            .filter(|error| error.get_message().contains("`header`"))
+            .map(|error| error.location())
+            .collect();
+        assert_eq!(
+            duplicates,
+            vec![Some(SourceLocation {
+                line: 5,
+                column: 12,
+                length: 6
+            })]
+        );
+    }
+
+    #[test]
+    fn unresolved_use_points_at_the_statement() {
+        let source = "view {
+    use nowhere.style
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        let errors: Vec<_> = document
+            .get_errors()
+            .iter()
+            .map(|error| (error.get_message(), error.location().map(|l| l.line)))
+            .collect();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].0.contains("nowhere.style"), "{:?}", errors);
+        assert_eq!(errors[0].1, Some(1));
+    }
+
+    #[test]
+    fn view_text_and_elements_keep_their_order() {
+        let source = "view {
+    text here view { :Inner } more text
+    Hello, world!
+    view (name: last) {
+    }
+    :tail
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+
+        let body = document.get_body().borrow();
+        let view = body.children[0].borrow();
+        let children: Vec<_> = view
+            .children
+            .iter()
+            .map(|child| child.borrow().ty.as_str().to_string())
+            .collect();
+        assert_eq!(
+            children,
+            [
+                "text here",
+                "view",
+                "more text",
+                "Hello, world!",
+                "view",
+                "tail"
+            ]
+        );
+
+        let inner = view.children[1].borrow();
+        assert_eq!(inner.children[0].borrow().ty.as_str(), "Inner");
+    }
+
+    #[test]
+    fn vertical_align_moves_the_child_group() {
+        let source = "setup {
+    style {
+        bottom {
+            verticalAlign: Bottom
+            gap: 10px
+        }
+    }
+}
+
+use setup.style
+
+view (class: bottom) {
+    :First
+    :Second
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        document.layout(800.0, 300.0);
+
+        let body = document.get_body().borrow();
+        let view = body
+            .children
+            .iter()
+            .find(|child| matches!(child.borrow().ty, NodeType::View { .. }))
+            .unwrap()
+            .borrow();
+
+        let rects: Vec<_> = view
+            .children
+            .iter()
+            .map(|child| {
+                get_id_mgr()
+                    .get_layout(child.borrow().element.id())
+                    .border_rect
+            })
+            .collect();
+
+        // The group sits on the bottom of the 300px view
+        let content = get_id_mgr().get_layout(view.element.id()).content_rect;
+        assert_eq!(content.y1, 300.0);
+        assert_eq!(rects[1].y1, 300.0);
+        assert_eq!(rects[1].y0 - rects[0].y1, 10.0);
+        assert!(rects[0].y0 > 0.0);
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_source() {
+        let source = "setup {
+    style {
+        header {
+            padding: Red
+        }
+    }
+}
+
+use setup.style
+
+view (class: header) {
+}
+view (class: footer) {
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        let errors = document.get_errors();
+        assert_eq!(errors.len(), 2);
+
+        assert!(errors[0].get_message().contains("found identifier `Red`"));
+        assert_eq!(
+            errors[0].snippet().unwrap(),
+            "  --> 4:22\n  |\n4 |             padding: Red\n  |                      ^^^"
+        );
+
+        assert_eq!(errors[1].get_message(), "Cannot find `footer` in scope");
+        let location = errors[1].location().unwrap();
+        assert_eq!(
+            (location.line, location.column, location.length),
+            (12, 13, 6)
+        );
+        assert_eq!(errors[1].kind(), ErrorKind::Error);
+    }
+
+    #[test]
+    fn calc_mixes_percentages_and_pixels() {
+        let source = "setup {
+    style {
+        outer {
+            width: 300px
+        }
+        inner {
+            width: calc(100% - 20px)
+        }
+    }
+}
+
+use setup.style
+
+view (class: outer) {
+    view (class: inner) {
+        :Text
+    }
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        assert!(document.get_errors().is_empty());
+        document.layout(800.0, 600.0);
+
+        let body = document.get_body().borrow();
+        let outer = body
+            .children
+            .iter()
+            .find(|child| matches!(child.borrow().ty, NodeType::View { .. }))
+            .unwrap()
+            .borrow();
+        let inner = outer.children[0].borrow();
+
+        let outer = get_id_mgr().get_layout(outer.element.id()).content_rect;
+        let inner = get_id_mgr().get_layout(inner.element.id()).content_rect;
+        assert_eq!(outer.width(), 300.0);
+        assert_eq!(inner.width(), 280.0);
+    }
+
+    /// Pairs every node with its measurement, checking that the shapes match on the way
+    fn zip_measured<'a>(
+        node: &Rf<Node>,
+        measured: &'a MeasuredNode,
+        out: &mut Vec<(Rf<Node>, &'a MeasuredNode)>,
+    ) {
+        let children = node.borrow().children.clone();
+        assert_eq!(node.borrow().element.id(), measured.id);
+        assert_eq!(children.len(), measured.children.len());
+
+        out.push((node.clone(), measured));
+        for (child, measured) in children.iter().zip(measured.children.iter()) {
+            zip_measured(child, measured, out);
+        }
+    }
+
+    #[test]
+    fn measured_tree_mirrors_the_nested_fixture() {
+        let source = include_str!("../../test_files/deep.smf");
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        let measured = document.measure_tree(800.0, 600.0);
+
+        let mut nodes = Vec::new();
+        zip_measured(document.get_body(), &measured, &mut nodes);
+
+        let views: Vec<_> = nodes
+            .iter()
+            .filter(|(node, _)| matches!(node.borrow().ty, NodeType::View { .. }))
+            .map(|(_, measured)| measured.layout)
+            .collect();
+        assert!(views.len() > 10);
+
+        for (depth, layout) in views.iter().enumerate() {
+            assert!(layout.border_rect.width() > 0.0 && layout.border_rect.height() > 0.0);
+
+            // Each level is inset by the parent's 4px padding
+            assert_eq!(layout.border_rect.x0, 4.0 * depth as f64);
+            assert_eq!(layout.content_rect.x0 - layout.border_rect.x0, 4.0);
+            assert_eq!(layout.content_rect.y0 - layout.padding_rect.y0, 4.0);
+        }
+
+        // The level's text comes first, then the next level 2px below it
+        for pair in nodes.windows(2) {
+            let [(text, text_rects), (view, view_rects)] = pair else {
+                continue;
+            };
+            if matches!(text.borrow().ty, NodeType::Text(_))
+                && matches!(view.borrow().ty, NodeType::View { .. })
+            {
+                let text_rect = text_rects.layout.border_rect;
+                assert!(text_rect.width() > 0.0 && text_rect.height() > 0.0);
+
+                let gap = view_rects.layout.border_rect.y0 - text_rect.y1;
+                assert!((gap - 2.0).abs() < 1.0);
+            }
+        }
+    }
+
+    #[test]
+    fn measured_messages_fixture() {
+        let source = include_str!("../../test_files/messages.smf");
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        let measured = document.measure_tree(800.0, 600.0);
+
+        let mut nodes = Vec::new();
+        zip_measured(document.get_body(), &measured, &mut nodes);
+
+        // The body's only view holds the messages, stacked vertically inside 40px of padding
+        let (_, container) = nodes
+            .iter()
+            .find(|(node, _)| matches!(node.borrow().ty, NodeType::View { .. }))
+            .unwrap();
+        let content = container.layout.content_rect;
+        assert_eq!(content.x0 - container.layout.border_rect.x0, 40.0);
+        assert_eq!(content.y0 - container.layout.border_rect.y0, 40.0);
+
+        let items = &container.children;
+        let centered = [false, true, false, true, false, true, true];
+        assert_eq!(items.len(), centered.len());
+
+        for (item, centered) in items.iter().zip(centered) {
+            let layout = item.layout;
+            assert!(layout.border_rect.width() > 0.0 && layout.border_rect.height() > 0.0);
+            assert_eq!(layout.content_rect.x0 - layout.padding_rect.x0, 20.0);
+            assert_eq!(layout.content_rect.y0 - layout.padding_rect.y0, 4.0);
+
+            // Messages from the left start at the edge, the others are centered
+            if centered {
+                let middle = (layout.border_rect.x0 + layout.border_rect.x1) / 2.0;
+                assert!((middle - content.center().x).abs() <= 1.0);
+            } else {
+                assert_eq!(layout.border_rect.x0, content.x0);
+            }
+        }
+
+        for pair in items.windows(2) {
+            let gap = pair[1].layout.border_rect.y0 - pair[0].layout.border_rect.y1;
+            assert!((gap - 40.0).abs() < 1.0);
+        }
+    }
+
+    #[test]
+    fn color_lerp_halfway() {
+        let from = Color::rgba8(0, 100, 200, 255);
+        let to = Color::rgba8(100, 200, 0, 55);
+
+        assert_eq!(lerp_color(from, to, 0.0), from);
+        assert_eq!(lerp_color(from, to, 0.5), Color::rgba8(50, 150, 100, 155));
+        assert_eq!(lerp_color(from, to, 1.0), to);
+        assert_eq!(
+            UnitValue::Pixels(10.0).lerp(UnitValue::Pixels(20.0), 0.5),
+            UnitValue::Pixels(15.0)
+        );
+    }
+
+    #[test]
+    fn hover_transitions_background_color() {
+        let source = "setup {
+    style {
+        button {
+            backgroundColor: rgb(0, 0, 0)
+            transition: backgroundColor(1.0)
+        }
+        button@(state: Hover) {
+            backgroundColor: rgb(200, 100, 0)
+        }
+    }
+}
+
+use setup.style
+
+view (class: button) {
+    :Press
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        assert!(document.get_errors().is_empty());
+
+        let button = document
+            .get_body()
+            .borrow()
+            .children
+            .iter()
+            .find(|child| matches!(child.borrow().ty, NodeType::View { .. }))
+            .cloned()
+            .unwrap();
+        let button = button.borrow();
+        let id = button.element.id();
+
+        let start = Instant::now();
+        let frame = |at: f64, hovered: bool| {
+            document.tick(start + Duration::from_secs_f64(at));
+            document.set_hovered(hovered.then_some(id));
+            let target =
+                StyleValueAs!(button.styles(&document, "backgroundColor"), BackgroundColor);
+            document.transition_color(&button, "backgroundColor", target)
+        };
+
+        assert_eq!(frame(0.0, false), Some(Color::rgb8(0, 0, 0)));
+        assert!(!document.is_animating());
+
+        // The hover color is only reached after the whole second
+        assert_eq!(frame(1.0, true), Some(Color::rgb8(0, 0, 0)));
+        assert_eq!(frame(1.5, true), Some(Color::rgb8(100, 50, 0)));
+        assert!(document.is_animating());
+        assert_eq!(frame(2.0, true), Some(Color::rgb8(200, 100, 0)));
+        assert!(!document.is_animating());
+
+        // Leaving part way through heads back from wherever the color got to
+        assert_eq!(frame(3.0, false), Some(Color::rgb8(200, 100, 0)));
+        assert_eq!(frame(3.5, false), Some(Color::rgb8(100, 50, 0)));
+        assert_eq!(frame(3.5, true), Some(Color::rgb8(100, 50, 0)));
+        assert_eq!(frame(4.0, true), Some(Color::rgb8(150, 75, 0)));
+    }
+
+    #[test]
+    fn matched_child_sizing_lays_out_deep_trees() {
+        const DEPTH: usize = 150;
+
+        let mut source = String::from(
+            "setup {
+    style {
+        matched {
+            childSizing: Match
+        }
+    }
+}
+
+use setup.style
+",
+        );
+        for _ in 0..DEPTH {
+            source.push_str("view (class: matched) {\n:Hi\n");
+        }
+        source.push_str(":Hello there\n");
+        for _ in 0..DEPTH {
+            source.push_str("}\n");
+        }
+
+        // A lock held across a nested layout hangs instead of failing, so give up after a while
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::Builder::new()
+            .stack_size(256 * 1024 * 1024)
+            .spawn(move || {
+                let document = parse_from_stream(BufReader::new(source.as_bytes()));
+                document.layout(800.0, 600.0);
+                document.layout(800.0, 600.0);
+
+                let mut node = document.get_body().clone();
+                for _ in 0..DEPTH {
+                    let next = node
+                        .borrow()
+                        .children
+                        .iter()
+                        .find(|child| matches!(child.borrow().ty, NodeType::View { .. }))
+                        .cloned()
+                        .unwrap();
+                    node = next;
+                }
+
+                let widths = node
+                    .borrow()
+                    .children
+                    .iter()
+                    .map(|child| {
+                        get_id_mgr()
+                            .get_layout(child.borrow().element.id())
+                            .border_rect
+                    })
+                    .collect::<Vec<_>>();
+                tx.send(widths).unwrap();
+            })
+            .unwrap();
+
+        let rects = rx.recv_timeout(Duration::from_secs(60)).unwrap();
+        assert_eq!(rects.len(), 2);
+        assert_eq!(rects[0].x1, rects[1].x1);
+        assert!(rects[0].y1 <= rects[1].y0);
+    }
+
+    #[test]
+    fn toggled_class_restyles_the_node() {
+        let source = "setup {
+    style {
+        base {
+            padding: 4px
+        }
+        selected {
+            backgroundColor: rgb(255, 0, 0)
+            padding: 10px
+        }
+    }
+}
+
+use setup.style
+
+view (class: base) {
+    :Item
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        assert!(document.get_errors().is_empty());
+
+        let view = document
+            .get_body()
+            .borrow()
+            .children
+            .iter()
+            .find(|child| matches!(child.borrow().ty, NodeType::View { .. }))
+            .cloned()
+            .unwrap();
+        let id = view.borrow().element.id();
+        let background = || {
+            StyleValueAs!(
+                view.borrow().styles(&document, "backgroundColor"),
+                BackgroundColor
+            )
+        };
+        let content = || get_id_mgr().get_layout(id).content_rect;
+
+        document.layout(800.0, 600.0);
+        assert_eq!(background(), None);
+        assert_eq!(content().x0, 4.0);
+
+        // Classes added later come after the existing ones, so `base` still sets the padding
+        assert!(document.toggle_class(id, "selected"));
+        assert!(!document.add_class(id, "selected"));
+        document.layout(800.0, 600.0);
+        assert_eq!(background(), Some(Color::rgb8(255, 0, 0)));
+        assert_eq!(content().x0, 4.0);
+
+        assert!(document.remove_class(id, "base"));
+        document.layout(800.0, 600.0);
+        assert_eq!(content().x0, 10.0);
+
+        assert!(!document.toggle_class(id, "selected"));
+        document.layout(800.0, 600.0);
+        assert_eq!(background(), None);
+        assert_eq!(view.borrow().element.classes(), &[] as &[String]);
+    }
+
+    #[test]
+    fn empty_documents_are_valid() {
+        for source in ["", "\n\n", "// only a comment\n"] {
+            let document = parse_from_stream(BufReader::new(source.as_bytes()));
+            assert!(document.get_errors().is_empty(), "{:?}", source);
+            assert!(
+                document.get_body().borrow().children.is_empty(),
+                "{:?}",
+                source
+            );
+            assert_eq!(document.node_count(), 1);
+
+            document.layout(800.0, 600.0);
+            assert_eq!(document.dimensions(), Size::ZERO);
+            assert_eq!(document.intrinsic_size(), Size::ZERO);
+            assert_eq!(document.hit_test(Point::new(10.0, 10.0)), None);
+        }
+    }
+
+    #[test]
+    fn direction_names_ignore_case_and_separators() {
+        for name in ["vertical", "Vertical", "VERTICAL"] {
+            assert!(matches!(
+                Direction::from_name(name),
+                Some(Direction::Vertical)
+            ));
+        }
+        for name in [
+            "HorizontalReverse",
+            "horizontalReverse",
+            "horizontal_reverse",
+        ] {
+            assert!(matches!(
+                Direction::from_name(name),
+                Some(Direction::HorizontalReverse)
+            ));
+        }
+        assert!(Direction::from_name("diagonal").is_none());
+
+        let source = "setup {
+    style {
+        row {
+            direction: horizontal-reverse
+        }
+        bad {
+            direction: diagonal
+        }
+    }
+}
+
+use setup.style
+
+view (class: row) {
+    :Hello
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        let errors = document.get_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].location().unwrap().line, 6);
+
+        let view = document
+            .get_body()
+            .borrow()
+            .children
+            .iter()
+            .find(|child| matches!(child.borrow().ty, NodeType::View { .. }))
+            .cloned()
+            .unwrap();
+        assert!(matches!(
+            StyleValueAs!(view.borrow().styles(&document, "direction"), Direction),
+            Some(Direction::HorizontalReverse)
+        ));
+    }
+
+    #[test]
+    fn isolated_render_keeps_relative_geometry() {
+        let source = "setup {
+    style {
+        card {
+            padding: 6px
+            gap: 4px
+            marked: 1
+        }
+    }
+}
+
+use setup.style
+
+view {
+    :Above
+    view (class: card, name: card) {
+        :Title
+        view (class: card) {
+            :Body
+        }
+    }
+}";
+        let mut document = parse_from_stream(BufReader::new(source.as_bytes()));
+
+        let drawn = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = drawn.clone();
+        document.register_property(
+            "marked",
+            CustomProperty::new(|value| StyleValue::Custom {
+                value: value.clone(),
+            })
+            .inherited()
+            .with_draw(move |_, layout, _| recorded.lock().unwrap().push(layout.border_rect)),
+        );
+        document.layout(800.0, 600.0);
+        let before = border_rects(&document);
+
+        let card = document.get_by_name("card").unwrap();
+        let card = card.borrow();
+        let (origin, bounds) = {
+            let manager = get_id_mgr();
+            (
+                manager.get_layout(card.element.id()).border_rect.origin(),
+                manager.layout_bounds(card.element.id()).unwrap(),
+            )
+        };
+
+        fn collect(node: &Node, rects: &mut Vec<Rect>) {
+            rects.push(get_id_mgr().get_layout(node.element.id()).border_rect);
+            for child in node.children.iter() {
+                collect(&child.borrow(), rects);
+            }
+        }
+        let mut in_document = Vec::new();
+        collect(&card, &mut in_document);
+
+        let mut scene = Scene::default();
+        let mut dctx = DrawingContext {
+            builder: SceneBuilder::for_scene(&mut scene),
+            text: SimpleText::new(),
+            size: Size::new(800.0, 600.0),
+            transform: Affine::IDENTITY,
+            mouse: None,
+            keys: Vec::new(),
+            scroll: Vec2::ZERO,
+            cursor: None,
+        };
+        let offset = Vec2::new(1000.0, 500.0);
+        let rect = card.render_isolated(&mut dctx, &document, bounds + offset);
+        assert_eq!(rect.origin(), origin + offset);
+
+        let relative = |rects: &[Rect], origin: Point| {
+            rects
+                .iter()
+                .map(|rect| *rect - origin.to_vec2())
+                .collect::<Vec<_>>()
+        };
+        let drawn = drawn.lock().unwrap();
+        assert_eq!(drawn.len(), in_document.len());
+        assert_eq!(
+            relative(&drawn, rect.origin()),
+            relative(&in_document, origin)
+        );
+
+        // The document's own layout is untouched
+        assert_eq!(border_rects(&document), before);
+    }
+
+    #[test]
+    fn only_the_active_view_is_laid_out_and_drawn() {
+        let source = "setup {
+    style {
+        screen {
+            marked: 1
+        }
+    }
+}
+
+use setup.style
+
+view (class: screen, name: home) {
+    :Home
+}
+view (class: screen, name: settings) {
+    :Settings
+}";
+        let mut document = parse_from_stream(BufReader::new(source.as_bytes()));
+
+        let drawn = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = drawn.clone();
+        document.register_property(
+            "marked",
+            CustomProperty::new(|value| StyleValue::Custom {
+                value: value.clone(),
+            })
+            .with_draw(move |_, layout, _| recorded.lock().unwrap().push(layout.border_rect)),
+        );
+        assert_eq!(
+            document.view_names().collect::<Vec<_>>(),
+            vec!["home", "settings"]
+        );
+
+        let id_of = |name: &str| document.get_by_name(name).unwrap().borrow().element.id();
+        let border_rect = |name: &str| get_id_mgr().get_layout(id_of(name)).border_rect;
+
+        let draw = |document: &Document| {
+            drawn.lock().unwrap().clear();
+            let mut scene = Scene::default();
+            let mut dctx = DrawingContext {
+                builder: SceneBuilder::for_scene(&mut scene),
+                text: SimpleText::new(),
+                size: Size::new(800.0, 600.0),
+                transform: Affine::IDENTITY,
+                mouse: None,
+                keys: Vec::new(),
+                scroll: Vec2::ZERO,
+                cursor: None,
+            };
+            document.draw(&mut dctx);
+            drawn.lock().unwrap().clone()
+        };
+
+        // Both are stacked until one is picked
+        document.layout(800.0, 600.0);
+        let home = border_rect("home");
+        assert!(border_rect("settings").y0 >= home.y1);
+        assert_eq!(draw(&document).len(), 2);
+
+        assert!(document.set_active_view(Some("settings")));
+        assert_eq!(document.active_view(), Some("settings"));
+        document.layout(800.0, 600.0);
+        let settings = border_rect("settings");
+        assert_eq!(settings.origin(), home.origin());
+        assert_eq!(draw(&document), vec![settings]);
+        // Home was drawn here before, now only the settings view can be hit
+        let hit = document.hit_test(settings.center()).unwrap();
+        let hit = document.get_node(hit).unwrap().borrow();
+        let parent = hit.get_parent().unwrap().borrow().element.id();
+        assert_eq!(parent, id_of("settings"));
+
+        assert!(!document.set_active_view(Some("missing")));
+        assert_eq!(document.active_view(), Some("settings"));
+
+        assert_eq!(document.next_view(), Some("home"));
+        document.layout(800.0, 600.0);
+        assert_eq!(draw(&document), vec![border_rect("home")]);
+
+        assert!(document.set_active_view(None));
+        assert_eq!(draw(&document).len(), 2);
+    }
+
+    #[test]
+    fn scroll_offsets_stay_within_the_overflow() {
+        let content = Size::new(300.0, 1000.0);
+        let viewport = Size::new(200.0, 400.0);
+        let clamp = |x, y| scroll::clamp_offset(Vec2::new(x, y), content, viewport);
+
+        assert_eq!(clamp(50.0, 250.0), Vec2::new(50.0, 250.0));
+        assert_eq!(clamp(-10.0, -10.0), Vec2::ZERO);
+        assert_eq!(clamp(500.0, 5000.0), Vec2::new(100.0, 600.0));
+
+        // Content that fits doesn't move
+        let fits = scroll::clamp_offset(Vec2::new(20.0, 20.0), Size::new(100.0, 100.0), viewport);
+        assert_eq!(fits, Vec2::ZERO);
+    }
+
+    #[test]
+    fn thumb_is_sized_by_the_visible_fraction() {
+        // A quarter of the content is visible, so the thumb is a quarter of the track
+        assert_eq!(scroll::thumb(400.0, 1600.0, 400.0, 0.0), Some((0.0, 100.0)));
+        assert_eq!(
+            scroll::thumb(400.0, 1600.0, 400.0, 1200.0),
+            Some((300.0, 100.0))
+        );
+        assert_eq!(
+            scroll::thumb(400.0, 1600.0, 400.0, 600.0),
+            Some((150.0, 100.0))
+        );
+
+        // Very long content keeps a usable thumb
+        let (_, length) = scroll::thumb(400.0, 1_000_000.0, 400.0, 0.0).unwrap();
+        assert_eq!(length, MIN_THUMB_LENGTH);
+
+        assert_eq!(scroll::thumb(400.0, 300.0, 400.0, 0.0), None);
+
+        let bars = scroll::scrollbars(
+            Rect::new(0.0, 0.0, 200.0, 400.0),
+            Size::new(200.0, 1600.0),
+            Size::new(200.0, 400.0),
+            Vec2::ZERO,
+        );
+        assert_eq!(bars.len(), 1);
+        let (track, thumb) = bars[0];
+        assert_eq!(track, Rect::new(192.0, 0.0, 200.0, 400.0));
+        assert_eq!(thumb, Rect::new(192.0, 0.0, 200.0, 100.0));
+    }
+
+    #[test]
+    fn important_properties_win_over_other_classes() {
+        let source = "setup {
+    style {
+        first {
+            backgroundColor: rgb(255, 0, 0)
+            padding: 4px
+        }
+        strong {
+            backgroundColor: rgb(0, 0, 255) !important
+            padding: 10px !
+        }
+        weak {
+            backgroundColor: rgb(0, 255, 0)
+        }
+    }
+}
+
+use setup.style
+
+view (name: later, class: [first, strong]) {
+}
+view (name: earlier, class: [strong, weak]) {
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        assert!(document.get_errors().is_empty());
+
+        let style = |name: &str, key: &str| {
+            let node = document.get_by_name(name).unwrap();
+            let node = node.borrow();
+            node.styles(&document, key)
+        };
+
+        // Without the marker `first` would win, being the first class to set both
+        let blue = Color::rgb8(0, 0, 255);
+        assert_eq!(
+            StyleValueAs!(style("later", "backgroundColor"), BackgroundColor),
+            Some(blue)
+        );
+        assert_eq!(
+            StyleValueAs!(style("later", "padding"), Padding),
+            Some(UnitRect::new(
+                UnitValue::Pixels(10.0),
+                UnitValue::Pixels(10.0),
+                UnitValue::Pixels(10.0),
+                UnitValue::Pixels(10.0)
+            ))
+        );
+
+        assert_eq!(
+            StyleValueAs!(style("earlier", "backgroundColor"), BackgroundColor),
+            Some(blue)
+        );
+    }
+
+    #[test]
+    fn unchanged_views_reuse_their_fragments() {
+        const ITEMS: usize = 50;
+        let source = format!(
+            "setup {{
+    style {{
+        selected {{
+            backgroundColor: rgb(255, 0, 0)
+        }}
+    }}
+}}
+
+use setup.style
+
+view (name: list) {{
+{}}}",
+            "    view {\n        :Item\n    }\n".repeat(ITEMS)
+        );
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        assert!(document.get_errors().is_empty());
+
+        let frame = || {
+            document.layout(800.0, 600.0);
+
+            let mut scene = Scene::default();
+            let mut dctx = DrawingContext {
+                builder: SceneBuilder::for_scene(&mut scene),
+                text: SimpleText::new(),
+                size: Size::new(800.0, 600.0),
+                transform: Affine::IDENTITY,
+                mouse: None,
+                keys: Vec::new(),
+                scroll: Vec2::ZERO,
+                cursor: None,
+            };
+            document.draw(&mut dctx)
+        };
+
+        assert_eq!(
+            frame(),
+            DrawStats {
+                reused: 0,
+                recorded: ITEMS + 1
+            }
+        );
+
+        // Nothing changed, so the whole list is one fragment
+        assert_eq!(
+            frame(),
+            DrawStats {
+                reused: 1,
+                recorded: 0
+            }
+        );
+
+        // Only the changed item and the list around it are drawn again
+        let list = document.get_by_name("list").unwrap();
+        let item = list.borrow().children[3].borrow().element.id();
+        assert!(document.add_class(item, "selected"));
+        assert_eq!(
+            frame(),
+            DrawStats {
+                reused: ITEMS - 1,
+                recorded: 2
+            }
+        );
+    }
+
+    #[test]
+    fn hiding_a_view_records_its_children_again() {
+        let source = "setup {
+    style {
+        hidden {
+            visibility: Hidden
+        }
+    }
+}
+
+use setup.style
+
+view (name: outer) {
+    view (name: inner) {
+        :Hi
+    }
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        assert!(document.get_errors().is_empty());
+
+        let frame = || {
+            document.layout(800.0, 600.0);
+
+            let mut scene = Scene::default();
+            let mut dctx = DrawingContext {
+                builder: SceneBuilder::for_scene(&mut scene),
+                text: SimpleText::new(),
+                size: Size::new(800.0, 600.0),
+                transform: Affine::IDENTITY,
+                mouse: None,
+                keys: Vec::new(),
+                scroll: Vec2::ZERO,
+                cursor: None,
+            };
+            document.draw(&mut dctx)
+        };
+
+        assert_eq!(
+            frame(),
+            DrawStats {
+                reused: 0,
+                recorded: 2
+            }
+        );
+
+        // The inner view inherits the visibility, so its old fragment can't be replayed
+        let outer = document.get_by_name("outer").unwrap().borrow().element.id();
+        assert!(document.add_class(outer, "hidden"));
+        assert_eq!(
+            frame(),
+            DrawStats {
+                reused: 0,
+                recorded: 2
+            }
+        );
+    }
+
+    #[test]
+    fn transitioning_views_are_not_replayed() {
+        let source = "setup {
+    style {
+        box {
+            transition: backgroundColor(1.0)
+        }
+        selected {
+            backgroundColor: rgb(200, 100, 0)
+        }
+    }
+}
+
+use setup.style
+
+view (name: box, class: box) {
+    :Hi
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        assert!(document.get_errors().is_empty());
+
+        let start = Instant::now();
+        let frame = |at: f64| {
+            document.tick(start + Duration::from_secs_f64(at));
+            document.layout(800.0, 600.0);
+
+            let mut scene = Scene::default();
+            let mut dctx = DrawingContext {
+                builder: SceneBuilder::for_scene(&mut scene),
+                text: SimpleText::new(),
+                size: Size::new(800.0, 600.0),
+                transform: Affine::IDENTITY,
+                mouse: None,
+                keys: Vec::new(),
+                scroll: Vec2::ZERO,
+                cursor: None,
+            };
+            document.draw(&mut dctx)
+        };
+
+        assert_eq!(
+            frame(0.0),
+            DrawStats {
+                reused: 0,
+                recorded: 1
+            }
+        );
+
+        // The color starts moving on the frame it changes, so nothing is recorded until it stops
+        let id = document.get_by_name("box").unwrap().borrow().element.id();
+        assert!(document.add_class(id, "selected"));
+        assert_eq!(
+            frame(1.0),
+            DrawStats {
+                reused: 0,
+                recorded: 0
+            }
+        );
+        assert_eq!(
+            frame(1.5),
+            DrawStats {
+                reused: 0,
+                recorded: 0
+            }
+        );
+
+        // A fragment from the start of the transition would still show the old color
+        assert_eq!(
+            frame(2.5),
+            DrawStats {
+                reused: 0,
+                recorded: 1
+            }
+        );
+        assert_eq!(
+            frame(3.0),
+            DrawStats {
+                reused: 1,
+                recorded: 0
+            }
+        );
+    }
+
+    #[test]
+    fn uppercase_text_is_measured_after_the_transform() {
+        let source = "setup {
+    style {
+        loud {
+            textTransform: Uppercase
+        }
+    }
+}
+
+use setup.style
+
+view (name: quiet) {
+    :straße
+}
+view (name: loud, class: loud) {
+    :straße
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        assert!(document.get_errors().is_empty());
+        document.layout(800.0, 600.0);
+
+        let width = |name: &str| {
+            let view = document.get_by_name(name).unwrap();
+            let text = view.borrow().children[0].clone();
+            let id = text.borrow().element.id();
+            get_id_mgr().get_layout(id).border_rect.width()
+        };
+
+        // `ß` has no single uppercase letter, so the loud text is one character longer
+        assert_eq!(TextTransform::Uppercase.apply("straße"), "STRASSE");
+        assert!(width("loud") > width("quiet"));
+    }
+
+    #[test]
+    fn images_take_their_decoded_size() {
+        let path = std::env::temp_dir().join("neb_core_image_size.png");
+        image::RgbaImage::new(30, 20).save(&path).unwrap();
+
+        let source = format!(
+            "view {{
+    image (name: photo, src: \"{}\")
+    image (name: missing, src: \"does/not/exist.png\")
+}}",
+            path.display()
+        );
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        assert!(document.get_errors().is_empty());
+        document.layout(800.0, 600.0);
+
+        let size = |name: &str| {
+            let node = document.get_by_name(name).unwrap();
+            let id = node.borrow().element.id();
+            get_id_mgr().get_layout(id).content_rect.size()
+        };
+
+        assert_eq!(size("photo"), Size::new(30.0, 20.0));
+        // Shown as a placeholder rather than left out
+        assert_eq!(
+            size("missing"),
+            Size::new(defaults::BROKEN_IMAGE_SIZE, defaults::BROKEN_IMAGE_SIZE)
+        );
+    }
+
+    #[test]
+    fn images_load_relative_to_the_document() {
+        let dir = std::env::temp_dir().join("neb_core_image_dir");
+        std::fs::create_dir_all(&dir).unwrap();
+        image::RgbaImage::new(12, 8)
+            .save(dir.join("photo.png"))
+            .unwrap();
+
+        let source = "view {
+    image (name: photo, src: \"photo.png\")
+}";
+        let document = parse_from_stream_in(BufReader::new(source.as_bytes()), &dir);
+        let node = document.get_by_name("photo").unwrap();
+        let node = node.borrow();
+        assert!(matches!(
+            node.get_type(),
+            NodeType::Image { image: Some(image), .. } if (image.width, image.height) == (12, 8)
+        ));
+    }
+
+    #[test]
+    fn node_types_compare_without_source_positions() {
+        let types = |source: &str| {
+            let document = parse_from_stream(BufReader::new(source.as_bytes()));
+            let body = document.get_body().borrow();
+            let view = body.children[0].borrow();
+            let text = view.children[0].borrow();
+            (view.ty.clone(), text.ty.clone())
+        };
+
+        let (view, text) = types("view (class: [a, b], width: 10px) {\n    :Hi\n}");
+        // Same content further down and spaced out differently
+        let (moved_view, moved_text) =
+            types("\n\nview (class: [a,  b],  width: 10px) {\n\n    :Hi\n}");
+        assert!(view == moved_view);
+        assert!(text == moved_text);
+
+        let (other_view, other_text) = types("view (class: [a, b], width: 12px) {\n    :Hey\n}");
+        assert!(view != other_view);
+        assert!(text != other_text);
+        assert!(view != text);
+    }
+
+    #[test]
+    fn layouts_compare_by_their_rects() {
+        let hash = |layout: &Layout| {
+            let mut hasher = DefaultHasher::new();
+            layout.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let layout = Layout {
+            padding_rect: Rect::new(0.0, 0.0, 20.0, 10.0),
+            content_rect: Rect::new(2.0, 2.0, 18.0, 8.0),
+            border_rect: Rect::new(-0.0, 0.0, 20.0, 10.0),
+        };
+        let same = Layout {
+            border_rect: Rect::new(0.0, 0.0, 20.0, 10.0),
+            ..layout
+        };
+        assert_eq!(layout, same);
+        assert_eq!(hash(&layout), hash(&same));
+
+        let moved = Layout {
+            content_rect: Rect::new(2.0, 3.0, 18.0, 8.0),
+            ..layout
+        };
+        assert_ne!(layout, moved);
+        assert_ne!(hash(&layout), hash(&moved));
+    }
+
+    #[test]
+    fn parse_and_layout_measures_without_a_window() {
+        let document = crate::parse_and_layout("view {\n    :Hello\n}", 400.0, 300.0);
+        assert!(document.get_errors().is_empty());
+
+        let view = document.get_body().borrow().children[0].clone();
+        let rect = get_id_mgr()
+            .get_layout(view.borrow().element.id())
+            .border_rect;
+        assert!(rect.width() > 0.0 && rect.height() > 0.0);
+        assert!(rect.width() <= 400.0);
+    }
+
+    #[test]
+    fn spacer_pushes_siblings_to_the_edges() {
+        let source = "setup {
+    style {
+        bar {
+            direction: Horizontal
+            width: 400px
+        }
+    }
+}
+
+use setup.style
+
+view (name: bar, class: bar) {
+    view (name: left) {
+        :a
+    }
+    spacer {}
+    view (name: right) {
+        :b
+    }
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        assert!(document.get_errors().is_empty());
+        document.layout(800.0, 600.0);
+
+        let rect = |name: &str| {
+            let node = document.get_by_name(name).unwrap();
+            let id = node.borrow().element.id();
+            get_id_mgr().get_layout(id).border_rect
+        };
+
+        let bar = rect("bar");
+        assert_eq!(bar.width(), 400.0);
+        assert_eq!(rect("left").x0, bar.x0);
+        assert_eq!(rect("right").x1, bar.x1);
+        // The spacer takes what's left besides the gaps in between the children
+        let spacer = document.get_by_name("bar").unwrap().borrow().children[1].clone();
+        assert!(matches!(spacer.borrow().ty, NodeType::Spacer));
+        let spacer = get_id_mgr()
+            .get_layout(spacer.borrow().element.id())
+            .border_rect;
+        assert_eq!(
+            spacer.width(),
+            bar.width() - rect("left").width() - rect("right").width() - 2.0 * defaults::GAP
+        );
+    }
+
+    #[test]
+    fn inline_styles_win_over_classes() {
+        let source = "setup {
+    style {
+        card {
+            backgroundColor: rgb(1, 2, 3)
+            foregroundColor: rgb(1, 2, 3) !important
+        }
+    }
+}
+
+use setup.style
+
+view (name: main, class: card, backgroundColor: rgb(10, 20, 30), foregroundColor: rgb(10, 20, 30)) {
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        assert!(document.get_errors().is_empty());
+
+        let node = document.get_by_name("main").unwrap();
+        let node = node.borrow();
+        assert_eq!(
+            StyleValueAs!(node.styles(&document, "backgroundColor"), BackgroundColor),
+            Some(Color::rgb8(10, 20, 30))
+        );
+        // An `!important` class property still wins
+        assert_eq!(
+            StyleValueAs!(node.styles(&document, "foregroundColor"), ForegroundColor),
+            Some(Color::rgb8(1, 2, 3))
+        );
+    }
+
+    #[test]
+    fn blend_modes_push_a_layer() {
+        let source = "setup {
+    style {
+        overlay {
+            blendMode: Multiply
+        }
+        plain {
+            blendMode: Normal
+        }
+    }
+}
+
+use setup.style
+
+view (name: multiplied, class: overlay) {
+}
+view (name: screened, blendMode: Screen) {
+}
+view (name: normal, class: plain) {
+}
+view (name: unset) {
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        assert!(document.get_errors().is_empty());
+
+        let blend_mode = |name: &str| {
+            let node = document.get_by_name(name).unwrap();
+            let node = node.borrow();
+            node.blend_mode(&document)
+        };
+        assert_eq!(
+            blend_mode("multiplied"),
+            Some(BlendMode::from(Mix::Multiply))
+        );
+        assert_eq!(blend_mode("screened"), Some(BlendMode::from(Mix::Screen)));
+        // Normal blending doesn't need a layer of its own
+        assert_eq!(blend_mode("normal"), None);
+        assert_eq!(blend_mode("unset"), None);
+
+        let document = parse_from_stream(BufReader::new(
+            "style {\n    odd {\n        blendMode: Sideways\n    }\n}".as_bytes(),
+        ));
+        assert_eq!(document.get_errors().len(), 1);
+    }
+
+    #[test]
+    fn blend_layers_cover_shadows_and_overflowing_children() {
+        let source = "setup {
+    style {
+        card {
+            blendMode: Multiply
+            width: 100px
+            height: 50px
+            boxShadow: shadow(4px, 4px, 10px, rgb(0, 0, 0))
+        }
+        wide {
+            width: 300px
+            height: 10px
+        }
+    }
+}
+
+use setup.style
+
+view (name: card, class: card) {
+    view (name: wide, class: wide) {
+    }
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        assert!(document.get_errors().is_empty());
+        document.layout(800.0, 600.0);
+
+        let rect = |name: &str| {
+            let id = document.get_by_name(name).unwrap().borrow().element.id();
+            get_id_mgr().get_layout(id).border_rect
+        };
+        let (card, wide) = (rect("card"), rect("wide"));
+        let bounds = document
+            .get_by_name("card")
+            .unwrap()
+            .borrow()
+            .paint_bounds(&document);
+
+        // Half the blur around the offset shadow, and the child sticking out to the right
+        assert_eq!(
+            bounds,
+            Rect::new(card.x0 - 1.0, card.y0 - 1.0, wide.x1, card.y1 + 9.0)
+        );
+    }
+
+    #[test]
+    fn inspector_shows_where_a_node_was_written() {
+        let source = "view {
+    :Header
+
+    view (name: card) {
+        :Body
+    }
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        document.layout(800.0, 600.0);
+
+        let card = document.get_by_name("card").unwrap();
+        let card = card.borrow();
+        let info = card.inspect();
+        assert!(info.starts_with("Line 4, column 5: "), "{}", info);
+
+        let layout = *get_id_mgr().get_layout(card.element.id());
+        assert!(info.contains(&format!("Border {}", layout.border_rect)));
+
+        // Text starts after its `:`
+        let text = card.children[0].borrow();
+        assert!(text.inspect().starts_with("Line 5, column 10: "));
+    }
+
+    #[test]
+    fn unitless_sizes_are_pixels_with_a_warning() {
+        let source = "setup {
+    style {
+        card {
+            gap: 4
+            padding: 2px
+        }
+    }
+}
+
+use setup.style
+
+view (name: card, class: card) {
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        let node = document.get_by_name("card").unwrap();
+        let node = node.borrow();
+        assert_eq!(
+            StyleValueAs!(node.styles(&document, "gap"), Gap),
+            Some(UnitValue::Pixels(4.0))
+        );
+
+        let errors = document.get_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind(), ErrorKind::Warning);
+        assert_eq!(
+            errors[0].get_message(),
+            "Missing a unit for `gap`, it is read as `px`"
+        );
+        let location = errors[0].location().unwrap();
+        assert_eq!(
+            (location.line, location.column, location.length),
+            (3, 17, 1)
+        );
+    }
+
+    #[test]
+    fn rounded_views_clip_their_children() {
+        let source = "setup {
+    style {
+        card {
+            radius: 20px
+            backgroundColor: rgb(255, 255, 255)
+        }
+        filler {
+            width: 100px
+            height: 100px
+            backgroundColor: rgb(255, 0, 0)
+        }
+    }
+}
+
+use setup.style
+
+view (name: card, class: card) {
+    view (class: filler) {
+    }
+}
+view (name: square) {
+    view (class: filler) {
+    }
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        document.layout(800.0, 600.0);
+
+        let card = document.get_by_name("card").unwrap();
+        let card = card.borrow();
+        let clip = card.rounded_clip(&document).unwrap();
+
+        let child = card.children[0].borrow();
+        let child = *get_id_mgr().get_layout(child.element.id());
+        let corner = Point::new(child.border_rect.x0 + 1.0, child.border_rect.y0 + 1.0);
+        assert!(!clip.contains(corner));
+        assert!(clip.contains(child.border_rect.center()));
+
+        let square = document.get_by_name("square").unwrap();
+        assert!(square.borrow().rounded_clip(&document).is_none());
+    }
+
+    #[test]
+    fn text_in_an_aligned_fit_container_wraps_at_the_offered_width() {
+        // The padding makes the fitted width fractional, which rounding used to cut short
+        let text_width = |align: &str| {
+            let source = format!(
+                "view ({align}) {{
+    view (name: padded, padding: 0.6px) {{
+        :Hello there world
+    }}
+}}"
+            );
+            let document = parse_from_stream(BufReader::new(source.as_bytes()));
+            document.layout(800.0, 600.0);
+
+            let padded = document.get_by_name("padded").unwrap();
+            let padded = padded.borrow();
+            let text = padded.children[0].borrow();
+            let layout = *get_id_mgr().get_layout(text.element.id());
+            layout.content_rect.width()
+        };
+
+        assert_eq!(text_width("align: Center"), text_width("align: Left"));
+        assert_eq!(
+            text_width("verticalAlign: Center"),
+            text_width("align: Left")
+        );
+    }
+
+    #[test]
+    fn root_style_fills_the_canvas() {
+        let top_level = "style {
+    root {
+        backgroundColor: rgb(10, 20, 30)
+        padding: 8px
+    }
+}
+
+view {
+    :Content
+}";
+        let used = "setup {
+    style {
+        body {
+            backgroundColor: rgb(10, 20, 30)
+            padding: 8px
+        }
+    }
+}
+
+use setup.style
+
+view {
+    :Content
+}";
+        for source in [top_level, used] {
+            let document = parse_from_stream(BufReader::new(source.as_bytes()));
+            document.layout(800.0, 600.0);
+
+            let root = document.get_body().borrow();
+            assert_eq!(
+                StyleValueAs!(root.styles(&document, "backgroundColor"), BackgroundColor),
+                Some(Color::rgb8(10, 20, 30))
+            );
+
+            // The background is drawn over the padding rect, which covers the whole canvas
+            let layout = *get_id_mgr().get_layout(root.element.id());
+            assert_eq!(layout.padding_rect, Rect::new(0.0, 0.0, 800.0, 600.0));
+
+            let view = root
+                .children
+                .iter()
+                .find(|child| matches!(child.borrow().ty, NodeType::View { .. }))
+                .unwrap();
+            let view = get_id_mgr()
+                .get_layout(view.borrow().element.id())
+                .border_rect;
+            assert_eq!((view.x0, view.y0), (8.0, 8.0));
+        }
+    }
+
+    #[test]
+    fn from_module_builds_the_same_tree_as_parsing() {
+        let source = "setup {
+    style {
+        card {
+            backgroundColor: notacolor
+        }
+    }
+}
+
+use setup.style
+
+view (name: main, class: card) {
+    :Title
+    view (name: inner) {
+        spacer {}
+        :Body
+    }
+}";
+        let parsed = parse_from_stream(BufReader::new(source.as_bytes()));
+        let (module, parse_errors) = Module::parse_str(source);
+        assert!(parse_errors.is_empty());
+        let built = Document::from_module(module);
+
+        fn same_tree(a: &Rf<Node>, b: &Rf<Node>) -> bool {
+            let (a, b) = (a.borrow(), b.borrow());
+            a.ty == b.ty
+                && a.element.name() == b.element.name()
+                && a.children.len() == b.children.len()
+                && a.children
+                    .iter()
+                    .zip(&b.children)
+                    .all(|(a, b)| same_tree(a, b))
+        }
+        assert!(same_tree(parsed.root(), built.root()));
+        assert!(!built.root().borrow().children.is_empty());
+
+        let messages = |document: &Document| -> Vec<_> {
+            document
+                .get_errors()
+                .iter()
+                .map(|e| e.get_message())
+                .collect()
+        };
+        assert_eq!(messages(&parsed), messages(&built));
+        assert_eq!(messages(&built).len(), 1);
+    }
+
+    #[test]
+    fn min_max_and_clamp_pick_a_length() {
+        let source = "setup {
+    style {
+        box {
+            width: 200px
+        }
+        narrow {
+            width: min(100%, 300px)
+            height: clamp(10px, 5px, 20px)
+        }
+    }
+}
+
+use setup.style
+
+view (name: outer, class: box) {
+    view (name: inner, class: narrow) {
+        :Hi
+    }
+}";
+        let (module, _) = Module::parse_str(source);
+        let call = |name: &str, args: Vec<Value>| {
+            let symbol = module.resolve_symbol(&module.symbol_tree, name).unwrap();
+            let SymbolKind::Function { func, .. } = &symbol.borrow().kind else {
+                panic!("`{}` isn't a function", name);
+            };
+            func(args)
+        };
+        assert_eq!(call("clamp", vec![px(10), px(5), px(20)]), Some(px(10)));
+        assert_eq!(call("max", vec![px(3), px(7)]), Some(px(7)));
+        // Numbers without a unit are pixels, like the renderer reads them
+        assert_eq!(call("min", vec![px(3), int(7)]), Some(px(3)));
+        assert_eq!(call("max", vec![px(3), int(9), px(7)]), Some(int(9)));
+
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        assert!(document.get_errors().is_empty());
+        document.layout(800.0, 600.0);
+
+        let inner = document.get_by_name("inner").unwrap();
+        let rect = get_id_mgr()
+            .get_layout(inner.borrow().element.id())
+            .border_rect;
+        assert_eq!(rect.width(), 200.0);
+        assert_eq!(rect.height(), 10.0);
+    }
+
+    #[test]
+    fn unitless_lengths_in_min_and_max_are_pixels() {
+        let source = "view (width: 400px) {
+    view (name: inner, width: min(100%, 150), height: max(5, 10px)) {
+    }
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        let errors: Vec<_> = document
+            .get_errors()
+            .iter()
+            .map(|e| (e.kind(), e.get_message()))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (
+                    ErrorKind::Warning,
+                    "Missing a unit for `width`, it is read as `px`".to_string()
+                ),
+                (
+                    ErrorKind::Warning,
+                    "Missing a unit for `height`, it is read as `px`".to_string()
+                ),
+            ]
+        );
+
+        document.layout(800.0, 600.0);
+        let inner = document.get_by_name("inner").unwrap();
+        let rect = get_id_mgr()
+            .get_layout(inner.borrow().element.id())
+            .border_rect;
+        assert_eq!(rect.width(), 150.0);
+        assert_eq!(rect.height(), 10.0);
+    }
+
+    #[test]
+    fn diff_reports_the_node_a_style_edit_reaches() {
+        let source = |color: &str, extra: &str| {
+            format!(
+                "setup {{
+    style {{
+        card {{
+            backgroundColor: {}
+        }}
+        plain {{
+            padding: 4px
+        }}
+    }}
+}}
+
+use setup.style
+
+view (name: main) {{
+    view (name: first, class: card) {{
+        :One
+    }}
+    view (class: plain) {{
+        :Two
+    }}{}
+}}",
+                color, extra
+            )
+        };
+        let parse = |text: String| parse_from_stream(BufReader::new(text.as_bytes()));
+
+        let old = parse(source("rgb(255, 0, 0)", ""));
+        assert!(old.diff(&parse(source("rgb(255, 0, 0)", ""))).is_empty());
+
+        let restyled = parse(source("rgb(0, 0, 255)", ""));
+        assert_eq!(
+            old.diff(&restyled),
+            vec![Change::Restyled(vec!["main".into(), "first".into()])]
+        );
+
+        let added = parse(source("rgb(255, 0, 0)", "\n    spacer {}"));
+        assert_eq!(
+            old.diff(&added),
+            vec![Change::Added(vec!["main".into(), "spacer[0]".into()])]
+        );
+        assert_eq!(
+            added.diff(&old),
+            vec![Change::Removed(vec!["main".into(), "spacer[0]".into()])]
+        );
+    }
+
+    #[test]
+    fn escaped_line_breaks_split_text() {
+        let source =
+            "view {\n    :First line\\nSecond line\n    :First line\n    :Back\\\\slash\n}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        document.layout(800.0, 600.0);
+
+        let body = document.get_body().borrow();
+        let view = body.children[0].borrow();
+        let heights: Vec<_> = view
+            .children
+            .iter()
+            .map(|text| {
+                let text = text.borrow();
+                get_id_mgr()
+                    .get_layout(text.element.id())
+                    .content_rect
+                    .height()
+            })
+            .collect();
+        assert_eq!(heights[0], 2.0 * heights[1]);
+
+        let texts: Vec<_> = view
+            .children
+            .iter()
+            .map(|text| text.borrow().ty.to_string())
+            .collect();
+        assert_eq!(texts[0], "First line\nSecond line");
+        assert_eq!(texts[2], "Back\\slash");
+    }
+
+    #[test]
+    fn bottom_border_is_drawn_along_the_bottom_edge() {
+        let source = "setup {
+    style {
+        underline {
+            width: 100px
+            height: 20px
+            borderWidth: 0px 0px 2px 0px
+            borderColor: [rgb(0, 0, 0), rgb(0, 0, 0), rgb(255, 0, 0), rgb(0, 0, 0)]
+        }
+        outline {
+            borderWidth: 2px
+            borderColor: [rgb(0, 0, 255)]
+        }
+    }
+}
+
+use setup.style
+
+view (name: underline, class: underline) {
+}
+view (name: outline, class: outline) {
+    :Boxed
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        assert!(document.get_errors().is_empty());
+        document.layout(800.0, 600.0);
+
+        let underline = document.get_by_name("underline").unwrap();
+        let underline = underline.borrow();
+        let segments = underline.border_segments(&document);
+        assert_eq!(segments.len(), 1);
+
+        let (segment, color) = &segments[0];
+        let border = get_id_mgr().get_layout(underline.element.id()).border_rect;
+        assert_eq!(*color, Color::rgb8(255, 0, 0));
+        assert_eq!(
+            segment.bounding_box(),
+            Rect::new(border.x0, border.y1 - 2.0, border.x1, border.y1)
+        );
+
+        // The same color and width all around is drawn as one shape
+        let outline = document.get_by_name("outline").unwrap();
+        assert!(outline.borrow().border_segments(&document).is_empty());
+    }
+
+    #[test]
+    fn nodes_past_the_viewport_overflow() {
+        let source = "setup {
+    style {
+        wide {
+            width: 900px
+            height: 20px
+        }
+        clip {
+            width: 200px
+            height: 20px
+            overflow: Hidden
+        }
+    }
+}
+
+use setup.style
+
+view (name: wide, class: wide) {
+}
+view (name: clip, class: clip) {
+    view (name: clipped, class: wide) {
+    }
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        let id = |name: &str| document.get_by_name(name).unwrap().borrow().element.id();
+
+        // What's clipped can't be seen past the viewport
+        assert_eq!(document.overflowing(800.0, 600.0), [id("wide")]);
+        assert!(document.overflowing(1000.0, 600.0).is_empty());
+    }
+
+    #[test]
+    fn grid_columns_share_the_leftover_width() {
+        let source = "setup {
+    style {
+        grid {
+            width: 500px
+            gap: 0px
+            columns: [100px, 1fr, 1fr]
+        }
+        cell {
+            height: 20px
+        }
+    }
+}
+
+use setup.style
+
+view (name: grid, class: grid) {
+    view (name: a, class: cell) {
+    }
+    view (name: b, class: cell) {
+    }
+    view (name: c, class: cell) {
+    }
+    view (name: d, class: cell) {
+    }
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        document.layout(800.0, 600.0);
+        let rect = |name: &str| {
+            let node = document.get_by_name(name).unwrap();
+            let id = node.borrow().element.id();
+            get_id_mgr().get_layout(id).border_rect
+        };
+
+        let x0 = rect("grid").x0;
+        let columns: Vec<_> = ["a", "b", "c"]
+            .iter()
+            .map(|name| (rect(name).x0 - x0, rect(name).width()))
+            .collect();
+        assert_eq!(columns, [(0.0, 100.0), (100.0, 200.0), (300.0, 200.0)]);
+
+        // The fourth child starts the next row in the first column
+        assert_eq!(rect("d").x0, x0);
+        assert_eq!(rect("d").y0, rect("a").y1);
+        assert_eq!(rect("grid").height(), 40.0);
+    }
+
+    #[test]
+    fn auto_columns_are_rejected() {
+        let source = "view (columns: [auto, 1fr]) {
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        let errors: Vec<_> = document
+            .get_errors()
+            .iter()
+            .map(|e| e.get_message())
+            .collect();
+
+        assert_eq!(
+            errors,
+            vec!["Expected a list of sizes, percentages or fractions like `1fr` for `columns`, found an array"]
+        );
+    }
+
+    #[test]
+    fn clicks_pass_through_hidden_nodes() {
+        let source = "setup {
+    style {
+        back {
+            width: 200px
+            height: 100px
+        }
+        front {
+            width: 100px
+            height: 50px
+        }
+        hidden {
+            visibility: Hidden
+        }
+        shown {
+            width: 20px
+            height: 20px
+            visibility: Visible
+        }
+    }
+}
+
+use setup.style
+
+view (name: back, class: back) {
+    view (name: front, class: front) {
+        view (name: shown, class: shown) {
+        }
+    }
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        document.layout(800.0, 600.0);
+        let node = |name: &str| document.get_by_name(name).unwrap();
+        let id = |name: &str| node(name).borrow().element.id();
+        let rect = |name: &str| get_id_mgr().get_layout(id(name)).border_rect;
+
+        let over_front = Point::new(rect("front").x1 - 5.0, rect("front").y1 - 5.0);
+        assert_eq!(document.hit_test(over_front), Some(id("front")));
+
+        assert!(document.add_class(id("front"), "hidden"));
+        document.layout(800.0, 600.0);
+
+        // The hidden node keeps its place, but the click lands on the node behind it
+        assert_eq!(rect("front").width(), 100.0);
+        assert!(!node("front").borrow().is_visible(&document));
+        assert_eq!(document.hit_test(over_front), Some(id("back")));
+
+        // A child can show itself again inside a hidden parent
+        assert_eq!(document.hit_test(rect("shown").center()), Some(id("shown")));
+    }
+
+    #[test]
+    fn min_content_width_is_the_longest_word() {
+        let source = "setup {
+    style {
+        row {
+            direction: Horizontal
+            gap: 10px
+            padding: 5px
+        }
+    }
+}
+
+use setup.style
+
+view (name: row, class: row) {
+    :a longest word
+    :tiny
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        let row = document.get_by_name("row").unwrap();
+        let text = row.borrow().children.iter().next().unwrap().clone();
+
+        let mut simple = SimpleText::new();
+        let size = psize!(defaults::TEXT_SIZE);
+        let bounds = Rect::new(0.0, 0.0, f64::INFINITY, f64::INFINITY);
+        let width = |word: &str| {
+            SimpleText::new()
+                .layout(None, size, word, &bounds, TextWrap::NoWrap)
+                .width()
+        };
+
+        let longest = text.borrow().min_content_width(&document);
+        assert_eq!(longest, width("longest"));
+        assert_eq!(
+            simple.min_content_width(None, size, "a longest word", TextWrap::NoWrap),
+            width("a longest word")
+        );
+
+        // Side by side with the gap in between, inside the padding
+        let expected = width("longest") + 10.0 + width("tiny") + 10.0;
+        assert_eq!(row.borrow().min_content_width(&document), expected);
+    }
+
+    #[test]
+    fn debug_overlay_outlines_and_labels_every_box() {
+        let source = "setup {
+    style {
+        card {
+            width: 100px
+            height: 50px
+        }
+    }
+}
+
+use setup.style
+
+view (name: card, class: card) {
+    :Hi
+}
+view {
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        document.layout(800.0, 600.0);
+
+        let card = document.get_by_name("card").unwrap();
+        let card = card.borrow().element.id();
+        let boxes = document.debug_overlay(Some(card));
+
+        // Both views and the text, but not the body they are in or the setup
+        assert_eq!(boxes.len(), 3);
+        for overlay in &boxes {
+            assert_eq!(
+                overlay.rect,
+                get_id_mgr().get_layout(overlay.id).border_rect
+            );
+            assert_eq!(overlay.hovered, overlay.id == card);
+        }
+
+        let hovered = boxes.iter().find(|overlay| overlay.hovered).unwrap();
+        assert_eq!(hovered.label, format!("#{} view 100x50", card));
+        let text = &boxes[1];
+        assert_eq!(text.label, format!("#{}", text.id));
+    }
+
+    #[test]
+    fn svg_export_writes_a_rect_per_box() {
+        let source = "setup {
+    style {
+        first {
+            width: 100px
+            height: 50px
+            backgroundColor: rgb(255, 0, 0)
+        }
+        second {
+            width: 40px
+            height: 30px
+            backgroundColor: rgba(0, 0, 255, 51)
+        }
+    }
+}
+
+use setup.style
+
+view (name: first, class: first) {
+}
+view (name: second, class: second) {
+}";
+        let document = parse_from_stream(BufReader::new(source.as_bytes()));
+        let svg = document.to_svg(800.0, 600.0);
+
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"800\""));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<rect").count(), 2);
+
+        let second = document.get_by_name("second").unwrap();
+        let second = get_id_mgr()
+            .get_layout(second.borrow().element.id())
+            .padding_rect;
+        assert!(second.y0 >= 50.0);
+
+        assert!(svg.contains(
+            "<rect x=\"0\" y=\"0\" width=\"100\" height=\"50\" fill=\"rgb(255, 0, 0)\"/>"
+        ));
+        assert!(svg.contains(&format!(
+            "<rect x=\"0\" y=\"{}\" width=\"40\" height=\"30\" fill=\"rgb(0, 0, 255)\" fill-opacity=\"0.2\"/>",
+            second.y0
+        )));
+    }
+}
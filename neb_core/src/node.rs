@@ -1,30 +1,40 @@
-use std::{collections::HashMap, fmt::Display, slice::Iter, sync::RwLockReadGuard};
+use std::{
+    cell::Cell,
+    collections::{hash_map::DefaultHasher, HashSet},
+    fmt::Display,
+    hash::{Hash, Hasher},
+    slice::Iter,
+    sync::RwLockReadGuard,
+};
 
+use linked_hash_map::LinkedHashMap;
+use neb_errors::SourceLocation;
 use neb_graphics::{
     drawing_context::DrawingContext,
-    simple_text,
+    simple_text::{self, TextWrap},
     vello::{
-        kurbo::{Affine, Rect, RoundedRect, RoundedRectRadii},
-        peniko::{Brush, Stroke},
+        kurbo::{Affine, BezPath, Line, Rect, RoundedRect, RoundedRectRadii, Size, Vec2},
+        peniko::{BlendMode, Brush, Color, Fill, Image, Mix, Stroke},
+        SceneFragment,
     },
 };
-use neb_smf::{
-    ast::Value,
-    token::{SpannedToken, Token},
-};
+use neb_smf::{ast::Value, token::Range, StyleVariant};
 
 use crate::{
     // rectr::RoundedRect,
-    styling::{Align, ChildSizing, Direction},
+    styling::{
+        Align, ChildSizing, Direction, LengthExpr, Overflow, TextOverflow, TextTransform, Track,
+        TransformOrigin, Visibility, WhiteSpace, WordBreak,
+    },
     StyleValueAs,
 };
 
 use crate::{
     defaults,
-    document::Document,
-    ids::{get_id_mgr, ID},
-    psize,
-    styling::{StyleValue, UnitValue},
+    document::{range_location, Document},
+    ids::{get_id_mgr, layout_of, update_layout, ID},
+    psize, scroll,
+    styling::{hash_color, resolve_unit, StyleValue, UnitRect, UnitValue},
 };
 use neb_util::{
     format::{NodeDisplay, TreeDisplay},
@@ -40,13 +50,23 @@ pub enum NodeType {
     StyleBlock,
     Setup,
     View {
-        args: HashMap<String, Value>,
+        args: LinkedHashMap<String, Value>,
     },
     Style {
         name: String,
-        properties: HashMap<String, Value>,
+        properties: LinkedHashMap<String, Value>,
+        /// Properties marked `!important`
+        important: HashSet<String>,
+        variants: Vec<StyleVariant>,
     },
     Text(String),
+    /// A bitmap decoded from `src`, or `None` if it couldn't be loaded
+    Image {
+        src: String,
+        image: Option<Image>,
+    },
+    /// Empty space that grows to fill what its siblings leave on the main axis
+    Spacer,
     Root,
 }
 
@@ -59,6 +79,8 @@ impl NodeType {
             StyleBlock => "style",
             Text(s) => s.as_str(),
             View { .. } => "view",
+            Image { .. } => "image",
+            Spacer => "spacer",
             Root => "root",
             Style { name, .. } => name.as_str(),
         }
@@ -75,6 +97,41 @@ macro_rules! is_node {
     }};
 }
 
+/// Structural equality, ignoring where argument and property values are in the source.
+/// Images are the same if they come from the same source
+impl PartialEq for NodeType {
+    fn eq(&self, other: &Self) -> bool {
+        use NodeType::*;
+        match (self, other) {
+            (Use(a), Use(b)) => a == b,
+            (StyleBlock, StyleBlock) | (Setup, Setup) | (Spacer, Spacer) | (Root, Root) => true,
+            (View { args: a }, View { args: b }) => a == b,
+            (
+                Style {
+                    name,
+                    properties,
+                    important,
+                    variants,
+                },
+                Style {
+                    name: other_name,
+                    properties: other_properties,
+                    important: other_important,
+                    variants: other_variants,
+                },
+            ) => {
+                name == other_name
+                    && properties == other_properties
+                    && important == other_important
+                    && variants == other_variants
+            }
+            (Text(a), Text(b)) => a == b,
+            (Image { src: a, .. }, Image { src: b, .. }) => a == b,
+            _ => false,
+        }
+    }
+}
+
 impl Display for NodeType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.as_str())
@@ -125,6 +182,16 @@ impl Node {
         self
     }
 
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.element = self.element.with_name(name);
+        self
+    }
+
+    pub fn with_source_range(mut self, range: Range) -> Self {
+        self.element = self.element.with_source_range(range);
+        self
+    }
+
     pub fn add_child(&mut self, node: impl Into<Rf<Node>>) {
         self.children.push(node.into())
     }
@@ -160,99 +227,552 @@ impl Node {
         &mut self.element
     }
 
+    /// Draws the node and its children. A view whose paint hash from the last
+    /// `Element::hash_paint` is unchanged appends the fragment it recorded before instead
     pub fn draw(&self, dctx: &mut DrawingContext, document: &Document) {
+        let id = self.element.id;
+        let hash = match self.ty {
+            NodeType::View { .. } => document.paint_hash(id),
+            _ => None,
+        };
+        let Some(hash) = hash else {
+            return self.draw_uncached(dctx, document);
+        };
+
+        if document.append_cached_fragment(id, hash, dctx) {
+            return;
+        }
+
+        let mut fragment = SceneFragment::new();
+        dctx.record(&mut fragment, |dctx| self.draw_uncached(dctx, document));
+        dctx.append_fragment(&fragment, dctx.transform);
+        document.cache_fragment(id, hash, fragment);
+    }
+
+    fn draw_uncached(&self, dctx: &mut DrawingContext, document: &Document) {
+        let parent_transform = dctx.transform;
+        if let Some(transform) = self.element.transform(self, document) {
+            dctx.transform = parent_transform * transform;
+        }
+
+        // Blended as a whole, so the node and its children share one layer
+        let blend_mode = self.blend_mode(document);
+        if let Some(blend_mode) = blend_mode {
+            let bounds = self.paint_bounds(document);
+            dctx.builder
+                .push_layer(blend_mode, 1.0, parent_transform, &bounds);
+        }
+
         self.element.draw(self, dctx, document);
 
-        self.children
-            .iter()
-            .for_each(|child| child.borrow().draw(dctx, document));
+        let overflow = self.overflow(document);
+        let rounded_clip = self.rounded_clip(document);
+        if let (Overflow::Visible, None) = (overflow, rounded_clip) {
+            self.children
+                .iter()
+                .for_each(|child| child.borrow().draw(dctx, document));
+        } else {
+            let layout = layout_of(self.element.id);
+            let offset = match overflow {
+                Overflow::Scroll => self.scroll_offset(),
+                _ => Vec2::ZERO,
+            };
+
+            let transform = dctx.transform;
+            match rounded_clip {
+                Some(shape) => dctx.builder.push_layer(Mix::Clip, 1.0, transform, &shape),
+                None => dctx
+                    .builder
+                    .push_layer(Mix::Clip, 1.0, transform, &layout.padding_rect),
+            }
+            dctx.transform = transform * Affine::translate(-offset);
+            self.children
+                .iter()
+                .for_each(|child| child.borrow().draw(dctx, document));
+            dctx.transform = transform;
+            dctx.builder.pop_layer();
+
+            // The bars stay put while the children move under them
+            if let Overflow::Scroll = overflow {
+                let (content, viewport) = self.scroll_size();
+                for (track, thumb) in
+                    scroll::scrollbars(layout.padding_rect, content, viewport, offset)
+                {
+                    dctx.builder
+                        .fill(Fill::NonZero, transform, scroll::TRACK_COLOR, None, &track);
+                    dctx.builder
+                        .fill(Fill::NonZero, transform, scroll::THUMB_COLOR, None, &thumb);
+                }
+            }
+        }
+
+        if blend_mode.is_some() {
+            dctx.builder.pop_layer();
+        }
+        dctx.transform = parent_transform;
+    }
+
+    /// Lays out and draws the node and its children into `bounds`, wherever the node sits in
+    /// the document, e.g. for a thumbnail drawn with `render_to_texture`. The node's ancestors
+    /// and their transforms are ignored. The layout from the document is put back afterwards.
+    ///
+    /// Returns the border rect the node took up
+    pub fn render_isolated(
+        &self,
+        dctx: &mut DrawingContext,
+        document: &Document,
+        bounds: Rect,
+    ) -> Rect {
+        let previous = get_id_mgr().layout_bounds(self.element.id);
+
+        Element::hash_subtree(self, document);
+        let rect = self.element.layout(self, bounds, 0, document);
+        Element::hash_paint(self, document);
+        self.draw(dctx, document);
+
+        if let Some(previous) = previous {
+            self.element.layout(self, previous, 0, document);
+        }
+
+        rect
     }
 
     pub fn parent(&self) -> Rf<Node> {
         self.parent.as_ref().expect("Expected parent!").clone()
     }
 
-    fn symbol_in_scope(&self, document: &Document, name: &str) -> Option<Rf<Node>> {
-        let sty = self.children.iter().find_map(|f| {
+    pub(crate) fn symbol_in_scope(&self, document: &Document, name: &str) -> Option<Rf<Node>> {
+        if let Some(sty) = self.child_in_scope(document, name) {
+            return Some(sty);
+        }
+
+        let p = self.parent.as_ref()?.borrow();
+        p.symbol_in_scope(document, name)
+    }
+
+    /// The style each of the node's classes resolves to, in class order
+    pub(crate) fn class_styles(&self, document: &Document) -> Vec<Option<Rf<Node>>> {
+        let Some(parent) = &self.parent else {
+            return Vec::new();
+        };
+        let parent = parent.borrow();
+        self.element
+            .classes
+            .iter()
+            .map(|class| parent.symbol_in_scope(document, class))
+            .collect()
+    }
+
+    /// The style the root node is styled with, named `root` or `body`. It's written in a top
+    /// level `style` block or brought in by a top level `use`
+    fn root_style(&self, document: &Document) -> Option<Rf<Node>> {
+        ["root", "body"].into_iter().find_map(|name| {
+            self.child_in_scope(document, name).or_else(|| {
+                self.children.iter().find_map(|child| {
+                    let block = child.borrow();
+                    match block.ty {
+                        NodeType::StyleBlock => block.child_in_scope(document, name),
+                        _ => None,
+                    }
+                })
+            })
+        })
+    }
+
+    /// Looks through the node's children and whatever its `use` statements bring in, without
+    /// walking up to the parents. The target of a `use` is only searched this way, otherwise an
+    /// unknown name would lead back to the same `use` forever
+    fn child_in_scope(&self, document: &Document, name: &str) -> Option<Rf<Node>> {
+        self.children.iter().find_map(|f| {
             let node = f.borrow();
             match &node.ty {
                 NodeType::Use(p) => {
-                    if let Some(nd) = document.resolve_path(&document.get_body().borrow(), p.iter())
-                    {
-                        let b = {
-                            let n = nd.borrow();
-                            if n.ty.as_str() == name {
-                                true
-                            } else {
-                                return n.symbol_in_scope(document, name);
-                            }
-                        };
-                        if b {
-                            return Some(nd);
-                        }
+                    let nd = document.resolve_path(&document.get_body().borrow(), p.iter())?;
+                    let n = nd.borrow();
+                    if n.ty.as_str() == name {
+                        Some(nd.clone())
+                    } else {
+                        n.child_in_scope(document, name)
                     }
-                    None
                 }
                 _ => {
                     if node.ty.as_str() == name {
-                        return Some(f.clone());
+                        Some(f.clone())
                     } else {
                         None
                     }
                 }
             }
-        });
+        })
+    }
 
-        if sty.is_none() {
-            if let Some(prent) = &self.parent {
-                let p = prent.borrow();
-                p.symbol_in_scope(document, name)
-            } else {
-                return None;
+    pub fn styles(&self, document: &Document, key: &str) -> StyleValue {
+        match self.own_styles(document, key) {
+            StyleValue::Empty => (),
+            value => return value,
+        }
+
+        // Built in inherited properties are looked up by whatever reads them;
+        // inherited custom properties resolve through the parent here
+        match (document.custom_property(key), &self.parent) {
+            (Some(custom), Some(parent)) if custom.inherited => {
+                parent.borrow().styles(document, key)
             }
-        } else {
-            return sty;
+            _ => StyleValue::Empty,
         }
     }
 
-    pub fn styles(&self, document: &Document, key: &str) -> StyleValue {
-        let class = match &self.ty {
-            NodeType::View { args } => args.get("class"),
-            _ => None,
+    /// The value of `key` from the node's own arguments and classes. An argument on the element
+    /// wins over its classes, unless a class marks it `!important`. Otherwise the first class
+    /// that sets it wins
+    fn own_styles(&self, document: &Document, key: &str) -> StyleValue {
+        if let NodeType::Root = self.ty {
+            let Some(style) = self.root_style(document) else {
+                return StyleValue::Empty;
+            };
+            let context = document.style_context(self);
+            return document.style_from_symbol(&style.borrow(), key, context);
+        }
+
+        // Set on the element itself, e.g. `view (backgroundColor: rgb(0, 0, 0))`
+        let inline = match &self.ty {
+            NodeType::View { args } => args.get(key).map_or(StyleValue::Empty, |value| {
+                document.style_from_arg(key, value)
+            }),
+            _ => StyleValue::Empty,
         };
 
-        match class {
-            Some(Value::Ident(SpannedToken(_, Token::Ident(s)))) => {
-                let parent = self.parent.as_ref().unwrap().borrow();
-                let Some(symbol) = parent.symbol_in_scope(document, s) else {
-                    return StyleValue::Empty
-                };
+        let Some(parent) = &self.parent else {
+            return inline;
+        };
+        if self.element.classes.is_empty() {
+            return inline;
+        }
 
-                let sym = symbol.borrow();
+        let context = document.style_context(self);
+        let parent = parent.borrow();
+        let mut first = inline;
+        for class in self.element.classes.iter() {
+            let Some(symbol) = parent.symbol_in_scope(document, class) else {
+                continue;
+            };
+
+            let sym = symbol.borrow();
 
-                return StyleValue::from_symbol(&sym, key);
+            match document.style_from_symbol(&sym, key, context) {
+                StyleValue::Empty => continue,
+                val if StyleValue::is_important(&sym, key, context) => return val,
+                val => {
+                    if let StyleValue::Empty = first {
+                        first = val;
+                    }
+                }
             }
-            Some(Value::Array { values, .. }) => {
-                for val in values.iter_items() {
-                    if let Value::Ident(SpannedToken(_, Token::Ident(s))) = val {
-                        let parent = self.parent.as_ref().unwrap().borrow();
-                        let Some(symbol) = parent.symbol_in_scope(document, s) else {
-                            return StyleValue::Empty
-                        };
+        }
+
+        first
+    }
+
+    /// Text takes `whiteSpace`, `textOverflow` and `wordBreak` from the element containing it
+    pub fn text_wrap(&self, document: &Document) -> TextWrap {
+        let Some(parent) = &self.parent else {
+            return TextWrap::Wrap;
+        };
+        let parent = parent.borrow();
 
-                        let sym = symbol.borrow();
+        let white_space = StyleValueAs!(parent.styles(document, "whiteSpace"), WhiteSpace);
+        let overflow = StyleValueAs!(parent.styles(document, "textOverflow"), TextOverflow);
+        let word_break = StyleValueAs!(parent.styles(document, "wordBreak"), WordBreak);
 
-                        match StyleValue::from_symbol(&sym, key) {
-                            StyleValue::Empty => continue,
-                            val => return val,
+        match (white_space, overflow, word_break) {
+            (Some(WhiteSpace::NoWrap), Some(TextOverflow::Ellipsis), _) => TextWrap::Ellipsis,
+            (Some(WhiteSpace::NoWrap), _, _) => TextWrap::NoWrap,
+            (_, _, Some(WordBreak::BreakAll)) => TextWrap::BreakAll,
+            _ => TextWrap::Wrap,
+        }
+    }
+
+    /// Text takes `textTransform` from the element containing it. Measuring and drawing
+    /// both use the transformed string, since the transform can change its width
+    pub fn text_transform(&self, document: &Document) -> TextTransform {
+        self.parent
+            .as_ref()
+            .and_then(|parent| {
+                StyleValueAs!(
+                    parent.borrow().styles(document, "textTransform"),
+                    TextTransform
+                )
+            })
+            .unwrap_or(TextTransform::None)
+    }
+
+    /// Whether the node is painted and can be hit. Takes `visibility` from the closest element
+    /// that sets one, starting with the node itself
+    pub fn is_visible(&self, document: &Document) -> bool {
+        let mut visibility = StyleValueAs!(self.styles(document, "visibility"), Visibility);
+        let mut node = self.parent.clone();
+        while let (None, Some(current)) = (visibility, node) {
+            let current = current.borrow();
+            visibility = StyleValueAs!(current.styles(document, "visibility"), Visibility);
+            node = current.parent.clone();
+        }
+
+        visibility != Some(Visibility::Hidden)
+    }
+
+    /// Distance from the top of the node to the baseline of its first line of text,
+    /// following the first displayed child down. `None` if there is no text to align by
+    pub fn first_baseline(&self, document: &Document) -> Option<f64> {
+        match &self.ty {
+            NodeType::Text(_) => {
+                let (ascent, _, _) =
+                    simple_text::SimpleText::new().get_adg(None, psize!(defaults::TEXT_SIZE));
+                Some(ascent.ceil())
+            }
+            NodeType::View { .. } => {
+                let child = self.children.iter().find(|c| c.borrow().is_displayed())?;
+                let baseline = child.borrow().first_baseline(document)?;
+
+                let padding = StyleValueAs!(self.styles(document, "padding"), Padding)
+                    .map_or(0.0, |r| TryInto::<Rect>::try_into(r).unwrap().y0);
+                let border = StyleValueAs!(self.styles(document, "borderWidth"), BorderWidth)
+                    .map_or(0.0, |r| TryInto::<Rect>::try_into(r).unwrap().y0);
+                Some(padding + border + baseline)
+            }
+            _ => None,
+        }
+    }
+
+    /// The narrowest the node can be without its content running past it, e.g. so layout can keep
+    /// a child from shrinking below its longest word. Text goes by `SimpleText::min_content_width`.
+    /// A view's content is its set width in pixels, or else its children's, side by side when it
+    /// stacks them horizontally. Its padding and border go around that
+    pub fn min_content_width(&self, document: &Document) -> f64 {
+        match &self.ty {
+            NodeType::Text(t) => simple_text::SimpleText::new().min_content_width(
+                None,
+                psize!(defaults::TEXT_SIZE),
+                &self.text_transform(document).apply(t),
+                self.text_wrap(document),
+            ),
+            NodeType::View { .. } => {
+                let direction = StyleValueAs!(self.styles(document, "direction"), Direction)
+                    .unwrap_or(defaults::DIRECTION);
+                let gap = StyleValueAs!(self.styles(document, "gap"), Gap)
+                    .map_or(defaults::GAP, |gap| resolve_unit(gap, 0.0));
+
+                let content = match StyleValueAs!(self.styles(document, "width"), Width) {
+                    Some(LengthExpr::Length(length)) if length.percent == 0.0 => length.pixels,
+                    _ => {
+                        let widths: Vec<f64> = self
+                            .children
+                            .iter()
+                            .filter(|child| child.borrow().is_displayed())
+                            .map(|child| child.borrow().min_content_width(document))
+                            .collect();
+                        match direction {
+                            Direction::Horizontal | Direction::HorizontalReverse => {
+                                widths.iter().sum::<f64>()
+                                    + gap * widths.len().saturating_sub(1) as f64
+                            }
+                            _ => widths.iter().copied().fold(0.0, f64::max),
                         }
                     }
+                };
+
+                let sides = |rect: Option<UnitRect>| {
+                    rect.and_then(|rect| TryInto::<Rect>::try_into(rect).ok())
+                        .map_or(0.0, |rect| rect.x0 + rect.x1)
+                };
+                content
+                    + sides(StyleValueAs!(self.styles(document, "padding"), Padding))
+                    + sides(StyleValueAs!(
+                        self.styles(document, "borderWidth"),
+                        BorderWidth
+                    ))
+            }
+            // Spacers give way entirely, images are as wide as layout made them
+            NodeType::Spacer => 0.0,
+            _ => layout_of(self.element.id).border_rect.width(),
+        }
+    }
+
+    /// What happens to children that don't fit. Only views clip
+    pub fn overflow(&self, document: &Document) -> Overflow {
+        match &self.ty {
+            NodeType::View { .. } => StyleValueAs!(self.styles(document, "overflow"), Overflow)
+                .unwrap_or(Overflow::Visible),
+            _ => Overflow::Visible,
+        }
+    }
+
+    /// What drawing the node and its children covers, in the space the node is drawn in before
+    /// its own transform: the border box grown by its shadows and the children that aren't
+    /// clipped to it
+    pub fn paint_bounds(&self, document: &Document) -> Rect {
+        let border_rect = layout_of(self.element.id).border_rect;
+        let mut bounds = border_rect;
+        if let Some(shadows) = StyleValueAs!(self.styles(document, "boxShadow"), BoxShadow) {
+            for shadow in shadows {
+                bounds = bounds.union(shadow.bounds(border_rect));
+            }
+        }
+
+        if let (Overflow::Visible, None) = (self.overflow(document), self.rounded_clip(document)) {
+            for child in self.children.iter() {
+                let child = child.borrow();
+                if child.is_displayed() {
+                    bounds = bounds.union(child.paint_bounds(document));
                 }
             }
-            _ => (),
         }
 
-        StyleValue::Empty
+        match self.element.transform(self, document) {
+            Some(transform) => transform.transform_rect_bbox(bounds),
+            None => bounds,
+        }
+    }
+
+    /// The shape children are clipped to in a view with a `radius`: the padding rect with the
+    /// corners of the background, so children don't draw into the rounded off corners
+    pub fn rounded_clip(&self, document: &Document) -> Option<RoundedRect> {
+        let NodeType::View { .. } = self.ty else {
+            return None;
+        };
+        let radius = StyleValueAs!(self.styles(document, "radius"), Radius)?;
+        let radius: RoundedRectRadii = radius.try_into().ok()?;
+        let border_width = StyleValueAs!(self.styles(document, "borderWidth"), BorderWidth);
+
+        let radii = inner_radii(radius, border_width);
+        if radii.as_single_radius() == Some(0.0) {
+            return None;
+        }
+        Some(RoundedRect::from_rect(
+            layout_of(self.element.id).padding_rect,
+            radii,
+        ))
+    }
+
+    /// The sides of the border and their colors, when the sides differ in color or width and
+    /// can't be drawn as one shape. Each side is filled between the border and padding rects
+    /// with mitred corners, leaving out sides without a width. The `radius` isn't followed.
+    ///
+    /// Empty when there is no border or it is the same all around
+    pub fn border_segments(&self, document: &Document) -> Vec<(BezPath, Color)> {
+        let colors = match self.styles(document, "borderColor") {
+            StyleValue::BorderColor { color } => [color; 4],
+            StyleValue::BorderColors { colors } => colors,
+            _ => return Vec::new(),
+        };
+        let widths: Option<Rect> = StyleValueAs!(self.styles(document, "borderWidth"), BorderWidth)
+            .and_then(|widths| widths.try_into().ok());
+        let Some(widths) = widths else {
+            return Vec::new();
+        };
+
+        let same_color = colors.iter().all(|color| *color == colors[0]);
+        let same_width = [widths.y0, widths.x1, widths.y1]
+            .iter()
+            .all(|width| *width == widths.x0);
+        if same_color && same_width {
+            return Vec::new();
+        }
+
+        let layout = layout_of(self.element.id);
+        let (o, i) = (layout.border_rect, layout.padding_rect);
+
+        // Each side runs along the outside of the border and back along the inside
+        let sides = [
+            [(o.x0, o.y0), (o.x1, o.y0), (i.x1, i.y0), (i.x0, i.y0)],
+            [(o.x1, o.y0), (o.x1, o.y1), (i.x1, i.y1), (i.x1, i.y0)],
+            [(o.x1, o.y1), (o.x0, o.y1), (i.x0, i.y1), (i.x1, i.y1)],
+            [(o.x0, o.y1), (o.x0, o.y0), (i.x0, i.y0), (i.x0, i.y1)],
+        ];
+        let widths = [widths.y0, widths.x1, widths.y1, widths.x0];
+
+        sides
+            .into_iter()
+            .zip(widths)
+            .zip(colors)
+            .filter(|((_, width), _)| *width > 0.0)
+            .map(|((corners, _), color)| {
+                let mut path = BezPath::new();
+                path.move_to(corners[0]);
+                for corner in &corners[1..] {
+                    path.line_to(*corner);
+                }
+                path.close_path();
+                (path, color)
+            })
+            .collect()
+    }
+
+    /// The blend mode of the layer the node draws into. `None` when it draws straight onto what is
+    /// under it, which `Normal` does too
+    pub fn blend_mode(&self, document: &Document) -> Option<BlendMode> {
+        match &self.ty {
+            NodeType::View { .. } | NodeType::Image { .. } => {
+                StyleValueAs!(self.styles(document, "blendMode"), BlendMode)
+                    .filter(|mode| mode.mix != Mix::Normal)
+            }
+            _ => None,
+        }
+    }
+
+    /// The size of the children, measured from the top left of the content rect,
+    /// and the size of the content rect they scroll through
+    pub fn scroll_size(&self) -> (Size, Size) {
+        let viewport = layout_of(self.element.id).content_rect;
+
+        let mut content = Size::ZERO;
+        for child in self.children.iter() {
+            let child = child.borrow();
+            if !child.is_displayed() {
+                continue;
+            }
+            let rect = layout_of(child.element.id).border_rect;
+            content.width = content.width.max(rect.x1 - viewport.x0);
+            content.height = content.height.max(rect.y1 - viewport.y0);
+        }
+
+        (content, viewport.size())
+    }
+
+    /// How far the children are scrolled. The stored offset is kept within the content
+    /// as it is laid out now, which may have shrunk since it was scrolled
+    pub fn scroll_offset(&self) -> Vec2 {
+        let (content, viewport) = self.scroll_size();
+        let offset = get_id_mgr().scroll_offset(self.element.id);
+        scroll::clamp_offset(offset, content, viewport)
+    }
+
+    /// What the debug inspector shows for the node: where it was written and its layout.
+    /// Lines and columns count from 1, like in diagnostics
+    pub fn inspect(&self) -> String {
+        let layout = layout_of(self.element.id);
+        let rects = format!(
+            "Content {}, Padding {}, Border {}",
+            layout.content_rect, layout.padding_rect, layout.border_rect
+        );
+
+        match self.element.source_range {
+            Some(range) => format!(
+                "Line {}, column {}: {}",
+                range.start.line_num + 1,
+                range.start.position + 1,
+                rects
+            ),
+            None => rects,
+        }
+    }
+
+    /// Where the node was written, for pointing errors at it
+    pub fn source_location(&self) -> Option<SourceLocation> {
+        self.element.source_range.as_ref().map(range_location)
+    }
+
+    pub fn get_parent(&self) -> Option<&Rf<Node>> {
+        self.parent.as_ref()
     }
 
     pub fn bparent(&self) -> RwLockReadGuard<'_, Node> {
@@ -261,7 +781,10 @@ impl Node {
 
     pub fn is_displayed(&self) -> bool {
         match &self.ty {
-            NodeType::View { .. } | NodeType::Text { .. } => true,
+            NodeType::View { .. }
+            | NodeType::Text { .. }
+            | NodeType::Image { .. }
+            | NodeType::Spacer => true,
             _ => false,
         }
     }
@@ -303,6 +826,12 @@ pub struct Element {
     id: ID,
 
     classes: Vec<String>,
+
+    /// Unique name given with the `name` argument
+    name: Option<String>,
+
+    /// Where the node was written in the source
+    source_range: Option<Range>,
 }
 
 impl std::fmt::Debug for Element {
@@ -310,6 +839,7 @@ impl std::fmt::Debug for Element {
         f.debug_struct("Element")
             .field("id", &self.id)
             .field("classes", &self.classes)
+            .field("name", &self.name)
             .finish()
     }
 }
@@ -319,6 +849,8 @@ impl Element {
         Element {
             id: get_id_mgr().gen_insert_zero(),
             classes: Vec::with_capacity(0),
+            name: None,
+            source_range: None,
         }
     }
 
@@ -326,6 +858,52 @@ impl Element {
         self.classes = classes.into();
         self
     }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn with_source_range(mut self, range: Range) -> Self {
+        self.source_range = Some(range);
+        self
+    }
+
+    pub fn id(&self) -> ID {
+        self.id
+    }
+
+    pub fn classes(&self) -> &[String] {
+        &self.classes
+    }
+
+    pub fn has_class(&self, class: &str) -> bool {
+        self.classes.iter().any(|c| c == class)
+    }
+
+    /// Adds `class` after the existing ones. Returns `false` if it was already there
+    pub fn add_class(&mut self, class: &str) -> bool {
+        if self.has_class(class) {
+            return false;
+        }
+        self.classes.push(class.to_string());
+        true
+    }
+
+    /// Returns `false` if the element didn't have `class`
+    pub fn remove_class(&mut self, class: &str) -> bool {
+        let len = self.classes.len();
+        self.classes.retain(|c| c != class);
+        self.classes.len() != len
+    }
+
+    pub fn source_range(&self) -> Option<Range> {
+        self.source_range
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
 }
 
 impl Default for Element {
@@ -333,12 +911,242 @@ impl Default for Element {
         Self {
             id: get_id_mgr().gen_insert_zero(),
             classes: Vec::with_capacity(0),
+            name: None,
+            source_range: None,
         }
     }
 }
 
+/// The corners of the padding rect, inside a border of `border_width` around corners of `radius`
+pub(crate) fn inner_radii(
+    radius: RoundedRectRadii,
+    border_width: Option<UnitRect>,
+) -> RoundedRectRadii {
+    let Some(w) = border_width else {
+        return radius;
+    };
+    let w: Rect = w.try_into().unwrap();
+
+    // Only allow the content to have a radius if the radius is larger than the border width
+    RoundedRectRadii::new(
+        if radius.top_left > w.x0 && radius.top_left > w.y0 {
+            radius.top_left
+        } else {
+            0.0
+        },
+        if radius.top_right > w.x1 && radius.top_right > w.y0 {
+            radius.top_right
+        } else {
+            0.0
+        },
+        if radius.bottom_right > w.x1 && radius.bottom_right > w.y1 {
+            radius.bottom_right
+        } else {
+            0.0
+        },
+        if radius.bottom_left > w.x0 && radius.bottom_left > w.y0 {
+            radius.bottom_left
+        } else {
+            0.0
+        },
+    )
+}
+
+/// The part of `area` a spacer takes up: `size` along `direction` from where the stack
+/// continues, and nothing across it
+fn spacer_area(area: Rect, direction: Direction, size: f64) -> Rect {
+    match direction {
+        Direction::Vertical => Rect::new(area.x0, area.y0, area.x0, area.y0 + size),
+        Direction::VerticalReverse => Rect::new(area.x0, area.y1 - size, area.x0, area.y1),
+        Direction::Horizontal => Rect::new(area.x0, area.y0, area.x0 + size, area.y0),
+        Direction::HorizontalReverse => Rect::new(area.x1 - size, area.y0, area.x1, area.y0),
+    }
+}
+
+/// The lowest first baseline among the displayed children, measured from the top of the row
+fn baseline_of_children(node: &Node, document: &Document) -> Option<f64> {
+    node.children
+        .iter()
+        .filter(|child| child.borrow().is_displayed())
+        .filter_map(|child| child.borrow().first_baseline(document))
+        .reduce(f64::max)
+}
+
+/// Style properties that are read by `Element::layout`
+const LAYOUT_PROPERTIES: [&str; 12] = [
+    "padding",
+    "borderWidth",
+    "width",
+    "height",
+    "aspectRatio",
+    "childSizing",
+    "columns",
+    "gap",
+    "direction",
+    "align",
+    "verticalAlign",
+    "display",
+];
+
+/// Style properties that change how a view is painted
+const PAINT_PROPERTIES: &[&str] = &[
+    "backgroundColor",
+    "foregroundColor",
+    "borderColor",
+    "borderWidth",
+    "radius",
+    "boxShadow",
+    "transform",
+    "transformOrigin",
+    "overflow",
+    "transition",
+    "blendMode",
+    "visibility",
+];
+
 impl Element {
+    /// Hashes everything that changes how `node` and its displayed descendants are painted,
+    /// keeping the hash of each node for `Node::draw` to tell whether a recorded fragment is current.
+    /// `None` if the subtree has to be drawn every frame, e.g. because a custom property draws in it
+    pub fn hash_paint(node: &Node, document: &Document) -> Option<u64> {
+        let mut hasher = DefaultHasher::new();
+        let mut cacheable = true;
+
+        for child in node.children.iter() {
+            let child = child.borrow();
+            if child.is_displayed() {
+                match Element::hash_paint(&child, document) {
+                    Some(hash) => hash.hash(&mut hasher),
+                    None => cacheable = false,
+                }
+            }
+        }
+
+        std::mem::discriminant(&node.ty).hash(&mut hasher);
+        layout_of(node.element.id).hash(&mut hasher);
+        // Inherited, so hiding an ancestor changes what this node draws too
+        node.is_visible(document).hash(&mut hasher);
+
+        // Text is drawn in its parent's foreground color, which is part of the parent's hash
+        if let NodeType::Text(t) = &node.ty {
+            node.text_transform(document).apply(t).hash(&mut hasher);
+            node.text_wrap(document).hash(&mut hasher);
+        } else {
+            if let NodeType::Image { src, .. } = &node.ty {
+                src.hash(&mut hasher);
+            }
+            for key in PAINT_PROPERTIES {
+                node.styles(document, key).hash(&mut hasher);
+            }
+            // Resolved here rather than when drawing, so a transition that starts this frame
+            // already keeps the node out of the cache below
+            if node.is_visible(document) {
+                let target =
+                    StyleValueAs!(node.styles(document, "backgroundColor"), BackgroundColor);
+                if let Some(color) = document.transition_color(node, "backgroundColor", target) {
+                    hash_color(color, &mut hasher);
+                }
+            }
+            if let Overflow::Scroll = node.overflow(document) {
+                let offset = node.scroll_offset();
+                offset.x.to_bits().hash(&mut hasher);
+                offset.y.to_bits().hash(&mut hasher);
+            }
+        }
+
+        let custom_draw = document.custom_properties().any(|(name, property)| {
+            property.has_draw() && !matches!(node.styles(document, name), StyleValue::Empty)
+        });
+        if custom_draw || document.is_node_animating(node.element.id) {
+            cacheable = false;
+        }
+
+        let hash = cacheable.then(|| hasher.finish());
+        document.set_paint_hash(node.element.id, hash);
+        hash
+    }
+
+    /// Hashes the layout inputs of `node` and every displayed descendant,
+    /// caching each subtree's hash in the id manager for the next layout pass
+    pub fn hash_subtree(node: &Node, document: &Document) -> u64 {
+        for child in node.children.iter() {
+            let child = child.borrow();
+            if child.is_displayed() {
+                Element::hash_subtree(&child, document);
+            }
+        }
+
+        Element::hash_node(node, document)
+    }
+
+    /// Like `hash_subtree`, but reuses the cached hashes of the children
+    pub fn hash_node(node: &Node, document: &Document) -> u64 {
+        let mut hasher = DefaultHasher::new();
+
+        std::mem::discriminant(&node.ty).hash(&mut hasher);
+        match &node.ty {
+            NodeType::Text(t) => {
+                node.text_transform(document).apply(t).hash(&mut hasher);
+                node.text_wrap(document).hash(&mut hasher);
+            }
+            NodeType::Image { src, .. } => src.hash(&mut hasher),
+            _ => (),
+        }
+
+        for key in LAYOUT_PROPERTIES {
+            node.styles(document, key).hash(&mut hasher);
+        }
+
+        for child in node.children.iter() {
+            let child = child.borrow();
+            if child.is_displayed() {
+                get_id_mgr()
+                    .subtree_hash(child.element.id)
+                    .hash(&mut hasher);
+            }
+        }
+
+        let hash = hasher.finish();
+        get_id_mgr().set_subtree_hash(node.element.id, hash);
+        hash
+    }
+
     pub fn layout(&self, node: &Node, bounds: Rect, depth: usize, document: &Document) -> Rect {
+        get_id_mgr().set_layout_bounds(node.element.id, bounds);
+
+        // Skip the subtree entirely if neither the bounds nor anything below changed since the last frame
+        let inputs = get_id_mgr().subtree_hash(node.element.id).map(|subtree| {
+            let mut hasher = DefaultHasher::new();
+            subtree.hash(&mut hasher);
+            for v in [bounds.x0, bounds.y0, bounds.x1, bounds.y1] {
+                v.to_bits().hash(&mut hasher);
+            }
+            hasher.finish()
+        });
+
+        if let Some(inputs) = inputs {
+            let manager = get_id_mgr();
+            if manager.layout_input(node.element.id) == Some(inputs) {
+                return manager.get_layout(node.element.id).border_rect;
+            }
+        }
+
+        let bounds = self.layout_uncached(node, bounds, depth, document);
+
+        if let Some(inputs) = inputs {
+            get_id_mgr().set_layout_input(node.element.id, inputs);
+        }
+
+        bounds
+    }
+
+    fn layout_uncached(
+        &self,
+        node: &Node,
+        bounds: Rect,
+        depth: usize,
+        document: &Document,
+    ) -> Rect {
         let padding: Option<Rect> =
             StyleValueAs!(node.styles(document, "padding"), Padding).map(|r| r.try_into().unwrap());
         let border_width: Option<Rect> =
@@ -348,6 +1156,12 @@ impl Element {
         let child_sizing = StyleValueAs!(node.styles(document, "childSizing"), ChildSizing)
             .unwrap_or(ChildSizing::Individual);
 
+        // Percentages are of the space the parent offers
+        let width = StyleValueAs!(node.styles(document, "width"), Width)
+            .map(|width| width.resolve(bounds.width()).max(0.0));
+        let height = StyleValueAs!(node.styles(document, "height"), Height)
+            .map(|height| height.resolve(bounds.height()).max(0.0));
+
         /*
             The padding and border take up space,
             therefore we have to subtract them from the bounds so that
@@ -375,6 +1189,18 @@ impl Element {
             bounds
         };
 
+        // A set `width` or `height` is the size of the content box
+        let bounds = Rect::new(
+            bounds.x0,
+            bounds.y0,
+            width.map_or(bounds.x1, |width| bounds.x0 + width),
+            height.map_or(bounds.y1, |height| bounds.y0 + height),
+        );
+
+        // How long each spacer child is along the stacking direction. Stays zero until the other
+        // children have been measured
+        let spacer_size = Cell::new(0.0);
+
         // Lays out child nodes in a stack
         let layout_children_vertically = |bounds: &Rect, gap: UnitValue, fit: bool| {
             // Start the bounds from top up (bounds.y0)
@@ -385,24 +1211,36 @@ impl Element {
                 bounds.y0,
             );
 
-            let gap_pixels = match gap {
-                UnitValue::Pixels(p) => p,
-            };
+            let gap_pixels = resolve_unit(gap, bounds.height());
+            // Whether a child was placed yet. The gap only goes in between children
+            let mut placed = false;
 
-            let mut max_width = 0;
+            let mut max_x1 = bounds.x0;
             // Layout each child and add it's requested size to the total area
             for child in node.children.iter() {
                 let node = child.borrow();
                 if !node.is_displayed() {
                     continue;
                 }
+                if placed {
+                    rect.y1 += gap_pixels as f64;
+                }
+
+                // A cached layout may already be widened to match old siblings
+                if let ChildSizing::Match = child_sizing {
+                    get_id_mgr().clear_layout_input(node.element.id);
+                }
 
                 // The bounds of the space that has not been taken up yet
                 let area = Rect::new(bounds.x0, bounds.y0 + rect.height(), bounds.x1, bounds.y1);
+                let area = match node.ty {
+                    NodeType::Spacer => spacer_area(area, Direction::Vertical, spacer_size.get()),
+                    _ => area,
+                };
 
                 let area = node.element.layout(&node, area, depth + 1, document);
-                if area.x1 as i32 > max_width {
-                    max_width = area.x1 as i32;
+                if area.x1 > max_x1 {
+                    max_x1 = area.x1;
                 }
                 if fit {
                     if area.width() > rect.width() {
@@ -413,28 +1251,25 @@ impl Element {
                 }
 
                 // We round height for that pixel perfection 🤤
-                rect.y1 += area.height().round() + gap_pixels as f64
+                rect.y1 += area.height().round();
+                placed = true;
             }
             if let ChildSizing::Match = child_sizing {
-                // set layout for all children with max width
+                // Every child is laid out by now, so widening them never holds the manager across a layout
                 for child in node.children.iter() {
                     let node = child.borrow();
                     if !node.is_displayed() {
                         continue;
                     }
 
-                    node.element.layout(&node, rect, depth + 1, document);
-
-                    // let mut manager = get_id_mgr();
-                    // let mut layout = *manager.get_layout(node.element.id);
-                    // if max_width > layout.content_rect.width() as i32 {
-                    //     layout.content_rect.x1 +=
-                    //         (max_width - layout.content_rect.width() as i32) as f64;
-                    //     layout.border_rect.x1 +=
-                    //         (max_width - layout.border_rect.width() as i32) as f64;
-                    // }
-                    // manager.set_layout_content(node.element.id, layout.content_rect);
-                    // manager.set_layout_border(node.element.id, layout.border_rect);
+                    update_layout(node.element.id, |layout| {
+                        let grow = max_x1 - layout.border_rect.x1;
+                        if grow > 0.0 {
+                            layout.content_rect.x1 += grow;
+                            layout.padding_rect.x1 += grow;
+                            layout.border_rect.x1 += grow;
+                        }
+                    });
                 }
             }
 
@@ -442,7 +1277,7 @@ impl Element {
         };
 
         // Lays out child nodes in a stack
-        let layout_children_vertically_rev = |gap: UnitValue, fit: bool| {
+        let layout_children_vertically_rev = |bounds: &Rect, gap: UnitValue, fit: bool| {
             // Start the bounds from top up (bounds.y0)
             let mut rect = Rect::new(
                 bounds.x0,
@@ -451,9 +1286,9 @@ impl Element {
                 bounds.y1,
             );
 
-            let gap_pixels = match gap {
-                UnitValue::Pixels(p) => p,
-            };
+            let gap_pixels = resolve_unit(gap, bounds.height());
+            // Whether a child was placed yet. The gap only goes in between children
+            let mut placed = false;
 
             // Layout each child and add it's requested size to the total area
             for child in node.children.iter() {
@@ -461,9 +1296,18 @@ impl Element {
                 if !node.is_displayed() {
                     continue;
                 }
+                if placed {
+                    rect.y0 -= gap_pixels as f64;
+                }
 
                 // The bounds of the space that has not been taken up yet
                 let area = Rect::new(bounds.x0, bounds.y0, bounds.x1, bounds.y1 - rect.height());
+                let area = match node.ty {
+                    NodeType::Spacer => {
+                        spacer_area(area, Direction::VerticalReverse, spacer_size.get())
+                    }
+                    _ => area,
+                };
 
                 let area = self.layout(&node, area, depth + 1, document);
                 if fit {
@@ -473,13 +1317,14 @@ impl Element {
                 }
 
                 // We round height for that pixel perfection 🤤
-                rect.y0 -= area.height().round() + gap_pixels as f64
+                rect.y0 -= area.height().round();
+                placed = true;
             }
             rect
         };
 
         // Lays out child nodes in a stack
-        let layout_children_horizontally = |gap: UnitValue, fit: bool| {
+        let layout_children_horizontally = |bounds: &Rect, gap: UnitValue, fit: bool| {
             // Start the bounds from top up (bounds.y0)
             let mut rect = Rect::new(
                 bounds.x0,
@@ -489,9 +1334,12 @@ impl Element {
             );
 
             // The gap is the space in between child nodes
-            let gap_pixels = match gap {
-                UnitValue::Pixels(p) => p,
-            };
+            let gap_pixels = resolve_unit(gap, bounds.width());
+            // Whether a child was placed yet
+            let mut placed = false;
+
+            // Children with text sit on the lowest baseline among them, instead of at the top
+            let baseline = baseline_of_children(node, document);
 
             // Layout each child and add it's requested size to the total area
             for child in node.children.iter() {
@@ -499,25 +1347,42 @@ impl Element {
                 if !node.is_displayed() {
                     continue;
                 }
+                if placed {
+                    rect.x1 += gap_pixels as f64;
+                }
 
                 // The bounds of the space that has not been taken up yet
-                let area = Rect::new(bounds.x0 + rect.width(), bounds.y0, bounds.x1, bounds.y1);
+                let drop = match (baseline, node.first_baseline(document)) {
+                    (Some(baseline), Some(own)) => baseline - own,
+                    _ => 0.0,
+                };
+                let area = Rect::new(
+                    bounds.x0 + rect.width(),
+                    bounds.y0 + drop,
+                    bounds.x1,
+                    bounds.y1,
+                );
+                let area = match node.ty {
+                    NodeType::Spacer => spacer_area(area, Direction::Horizontal, spacer_size.get()),
+                    _ => area,
+                };
 
                 let area = self.layout(&node, area, depth + 1, document);
                 if fit {
-                    if area.height() > rect.height() {
-                        rect.y1 = rect.y0 + area.height();
+                    if area.y1 > rect.y1 {
+                        rect.y1 = area.y1;
                     }
                 }
 
                 // We round height for that pixel perfection 🤤
-                rect.x1 += area.width().round() + gap_pixels as f64
+                rect.x1 += area.width().round();
+                placed = true;
             }
             rect
         };
 
         // Lays out child nodes in a stack
-        let layout_children_horizontally_rev = |gap: UnitValue, fit: bool| {
+        let layout_children_horizontally_rev = |bounds: &Rect, gap: UnitValue, fit: bool| {
             // Start the bounds from top up (bounds.y0)
             let mut rect = Rect::new(
                 bounds.x1,
@@ -527,9 +1392,12 @@ impl Element {
             );
 
             // The gap is the space in between child nodes
-            let gap_pixels = match gap {
-                UnitValue::Pixels(p) => p,
-            };
+            let gap_pixels = resolve_unit(gap, bounds.width());
+            // Whether a child was placed yet
+            let mut placed = false;
+
+            // Children with text sit on the lowest baseline among them, instead of at the top
+            let baseline = baseline_of_children(node, document);
 
             // Layout each child and add it's requested size to the total area
             for child in node.children.iter() {
@@ -537,20 +1405,179 @@ impl Element {
                 if !node.is_displayed() {
                     continue;
                 }
+                if placed {
+                    rect.x0 -= gap_pixels as f64;
+                }
 
                 // The bounds of the space that has not been taken up yet
-                let area = Rect::new(bounds.x0, bounds.y0, bounds.x1 - rect.width(), bounds.y1);
+                let drop = match (baseline, node.first_baseline(document)) {
+                    (Some(baseline), Some(own)) => baseline - own,
+                    _ => 0.0,
+                };
+                let area = Rect::new(
+                    bounds.x0,
+                    bounds.y0 + drop,
+                    bounds.x1 - rect.width(),
+                    bounds.y1,
+                );
+                let area = match node.ty {
+                    NodeType::Spacer => {
+                        spacer_area(area, Direction::HorizontalReverse, spacer_size.get())
+                    }
+                    _ => area,
+                };
 
                 let area = self.layout(&node, area, depth + 1, document);
                 if fit {
-                    if area.height() > rect.height() {
-                        rect.y1 = rect.y0 + area.height();
+                    if area.y1 > rect.y1 {
+                        rect.y1 = area.y1;
                     }
                 }
 
                 // We round height for that pixel perfection 🤤
-                rect.x0 -= area.width().round() + gap_pixels as f64
+                rect.x0 -= area.width().round();
+                placed = true;
+            }
+            rect
+        };
+
+        // Flows text runs into line boxes, left to right, moving a run onto the next line when
+        // it doesn't fit. Any other child ends the current line and takes up a line of its own
+        let layout_children_inline = |bounds: &Rect, gap: UnitValue| {
+            let mut rect = Rect::new(bounds.x0, bounds.y0, bounds.x0, bounds.y0);
+
+            let gap_pixels = resolve_unit(gap, bounds.width());
+
+            // Where the next run starts, and the top and height of the current line box
+            let mut pen_x = bounds.x0;
+            let mut line_y = bounds.y0;
+            let mut line_height = 0.0f64;
+
+            for child in node.children.iter() {
+                let node = child.borrow();
+                if !node.is_displayed() {
+                    continue;
+                }
+
+                // Runs are measured on a single line so that they wrap as a unit
+                let run_width = match &node.ty {
+                    NodeType::Text(t) => Some(
+                        simple_text::SimpleText::new()
+                            .layout(
+                                None,
+                                psize!(defaults::TEXT_SIZE),
+                                &node.text_transform(document).apply(t),
+                                &Rect::new(0.0, 0.0, f64::INFINITY, f64::INFINITY),
+                                TextWrap::NoWrap,
+                            )
+                            .width(),
+                    ),
+                    _ => None,
+                };
+
+                let fits = run_width.map_or(false, |width| pen_x + width <= bounds.x1);
+                if !fits && pen_x > bounds.x0 {
+                    line_y += line_height + gap_pixels;
+                    pen_x = bounds.x0;
+                    line_height = 0.0;
+                }
+
+                // Every run is set in the default text size, so aligning the tops of the runs
+                // also puts them on a shared baseline
+                let area = Rect::new(pen_x, line_y, bounds.x1, bounds.y1);
+                let area = node.element.layout(&node, area, depth + 1, document);
+
+                rect.x1 = rect.x1.max(area.x1);
+                rect.y1 = rect.y1.max(area.y1);
+                line_height = line_height.max(area.height().round());
+
+                if run_width.is_some() {
+                    pen_x = area.x1.round() + gap_pixels;
+                } else {
+                    line_y += line_height + gap_pixels;
+                    pen_x = bounds.x0;
+                    line_height = 0.0;
+                }
+            }
+
+            rect
+        };
+
+        // Lays out child nodes in rows of cells, a cell for each track. Fixed tracks are
+        // sized first, then fraction tracks share what is left of the width. Children are
+        // stretched to the width of their cell and a row is as tall as its tallest child
+        let layout_children_grid = |bounds: &Rect, gap: UnitValue, tracks: &[Track]| {
+            let gap_pixels = resolve_unit(gap, bounds.width());
+
+            let children: Vec<_> = node
+                .children
+                .iter()
+                .filter(|child| child.borrow().is_displayed())
+                .collect();
+
+            let mut widths: Vec<f64> = tracks
+                .iter()
+                .map(|track| match track {
+                    Track::Fixed(size) => resolve_unit(*size, bounds.width()),
+                    Track::Fraction(_) => 0.0,
+                })
+                .collect();
+
+            let fractions: f64 = tracks
+                .iter()
+                .map(|track| match track {
+                    Track::Fraction(fraction) => *fraction,
+                    _ => 0.0,
+                })
+                .sum();
+            if fractions > 0.0 {
+                let used = widths.iter().sum::<f64>() + gap_pixels * (tracks.len() - 1) as f64;
+                let share = (bounds.width() - used).max(0.0) / fractions;
+                for (width, track) in widths.iter_mut().zip(tracks) {
+                    if let Track::Fraction(fraction) = track {
+                        *width = share * fraction;
+                    }
+                }
+            }
+
+            let grid_width = widths.iter().sum::<f64>() + gap_pixels * (tracks.len() - 1) as f64;
+            let mut rect = Rect::new(bounds.x0, bounds.y0, bounds.x0 + grid_width, bounds.y0);
+
+            for row in children.chunks(tracks.len()) {
+                let mut x = bounds.x0;
+                let mut row_height = 0.0f64;
+
+                for (child, width) in row.iter().zip(&widths) {
+                    let node = child.borrow();
+                    let area = Rect::new(x, rect.y1, x + width, bounds.y1);
+                    let area = match node.ty {
+                        NodeType::Spacer => spacer_area(area, Direction::Vertical, 0.0),
+                        _ => area,
+                    };
+
+                    let area = node.element.layout(&node, area, depth + 1, document);
+                    row_height = row_height.max(area.height().round());
+
+                    let cell_x1 = x + width;
+                    update_layout(node.element.id, |layout| {
+                        let grow = cell_x1 - layout.border_rect.x1;
+                        if grow > 0.0 {
+                            layout.content_rect.x1 += grow;
+                            layout.padding_rect.x1 += grow;
+                            layout.border_rect.x1 += grow;
+                        }
+                    });
+
+                    x += width + gap_pixels;
+                }
+
+                rect.y1 += row_height + gap_pixels;
+            }
+            // Only the gaps in between rows count
+            if !children.is_empty() {
+                rect.y1 -= gap_pixels;
             }
+
             rect
         };
 
@@ -565,15 +1592,52 @@ impl Element {
                 let fit = true;
 
                 let align = StyleValueAs!(node.styles(document, "align"), Align);
+                let vertical_align =
+                    StyleValueAs!(node.styles(document, "verticalAlign"), VerticalAlign);
+
+                let inline = matches!(
+                    StyleValueAs!(node.styles(document, "display"), Display),
+                    Some(crate::styling::Display::Inline)
+                );
+
+                let tracks =
+                    StyleValueAs!(node.styles(document, "columns"), Columns).unwrap_or_default();
+
+                let layout_children = |bounds: &Rect| match direction {
+                    _ if inline => layout_children_inline(bounds, gap),
+                    _ if !tracks.is_empty() => layout_children_grid(bounds, gap, &tracks),
+                    Direction::Vertical => layout_children_vertically(bounds, gap, fit),
+                    Direction::VerticalReverse => layout_children_vertically_rev(bounds, gap, fit),
+                    Direction::Horizontal => layout_children_horizontally(bounds, gap, fit),
+                    Direction::HorizontalReverse => {
+                        layout_children_horizontally_rev(bounds, gap, fit)
+                    }
+                };
 
-                let area = match (direction, align) {
-                    (Direction::Vertical, _) => layout_children_vertically(&bounds, gap, fit),
-                    (Direction::VerticalReverse, _) => layout_children_vertically_rev(gap, fit),
-                    (Direction::Horizontal, _) => layout_children_horizontally(gap, fit),
-                    (Direction::HorizontalReverse, _) => layout_children_horizontally_rev(gap, fit),
+                // Measure the children where they would go, then move the group within the bounds
+                let area = layout_children(&bounds);
+
+                // Spacers share the space the other children leave on the main axis
+                let spacers = node
+                    .children
+                    .iter()
+                    .filter(|child| matches!(child.borrow().ty, NodeType::Spacer))
+                    .count();
+                let area = if spacers > 0 && !inline && tracks.is_empty() {
+                    let (offered, used) = match direction {
+                        Direction::Vertical | Direction::VerticalReverse => {
+                            (bounds.height(), area.height())
+                        }
+                        _ => (bounds.width(), area.width()),
+                    };
+                    let free = offered - used;
+                    spacer_size.set((free / spacers as f64).max(0.0));
+                    layout_children(&bounds)
+                } else {
+                    area
                 };
 
-                let (area, recalc) = match StyleValueAs!(node.styles(document, "align"), Align) {
+                let (area, recalc) = match align {
                     Some(Align::Right) => (
                         Rect::new(bounds.x1 - area.width(), area.y0, bounds.x1, area.y1),
                         true,
@@ -590,19 +1654,54 @@ impl Element {
                     _ => (area, false),
                 };
 
-                let area = if recalc {
-                    match (direction, align) {
-                        (Direction::Vertical, _) => layout_children_vertically(&area, gap, fit),
-                        (Direction::VerticalReverse, _) => layout_children_vertically_rev(gap, fit),
-                        (Direction::Horizontal, _) => layout_children_horizontally(gap, fit),
-                        (Direction::HorizontalReverse, _) => {
-                            layout_children_horizontally_rev(gap, fit)
-                        }
-                    }
+                let (area, recalc) = match vertical_align {
+                    Some(Align::Bottom) => (
+                        Rect::new(area.x0, bounds.y1 - area.height(), area.x1, bounds.y1),
+                        true,
+                    ),
+                    Some(Align::Center) => (
+                        Rect::new(
+                            area.x0,
+                            (bounds.height() / 2.0 - area.height() / 2.0 + bounds.y0).round(),
+                            area.x1,
+                            (bounds.height() / 2.0 + area.height() / 2.0 + bounds.y0).round(),
+                        ),
+                        true,
+                    ),
+                    _ => (area, recalc),
+                };
+
+                // The children are laid out again where the group moved to, but in the size they
+                // were first offered. The fitted area is rounded and can come out narrower than
+                // what the children measured, which would wrap text against its own extent
+                let mut area = if recalc {
+                    let x0 = match direction {
+                        Direction::HorizontalReverse if !inline => area.x1 - bounds.width(),
+                        _ => area.x0,
+                    };
+                    let y0 = match direction {
+                        Direction::VerticalReverse if !inline => area.y1 - bounds.height(),
+                        _ => area.y0,
+                    };
+                    layout_children(&Rect::new(
+                        x0,
+                        y0,
+                        x0 + bounds.width(),
+                        y0 + bounds.height(),
+                    ))
                 } else {
                     area
                 };
 
+                // The children don't shrink or grow an element with a set size
+                if width.is_some() {
+                    area.x0 = bounds.x0;
+                    area.x1 = bounds.x1;
+                }
+                if height.is_some() {
+                    area.y0 = bounds.y0;
+                    area.y1 = bounds.y1;
+                }
                 area
             }
             // SymbolKind::Node { args }
@@ -615,13 +1714,38 @@ impl Element {
             // }
             NodeType::Text(t) => {
                 let mut simple_text = simple_text::SimpleText::new();
-                let tl = simple_text.layout(None, psize!(defaults::TEXT_SIZE), t, &bounds);
+                let tl = simple_text.layout(
+                    None,
+                    psize!(defaults::TEXT_SIZE),
+                    &node.text_transform(document).apply(t),
+                    &bounds,
+                    node.text_wrap(document),
+                );
 
                 let area =
                     Rect::from_origin_size((bounds.x0, bounds.y0), (tl.width(), tl.height()));
 
                 area
             }
+            NodeType::Image { image, .. } => {
+                let intrinsic = image.as_ref().map_or(
+                    Size::new(defaults::BROKEN_IMAGE_SIZE, defaults::BROKEN_IMAGE_SIZE),
+                    |image| Size::new(image.width as f64, image.height as f64),
+                );
+
+                // A single set side keeps the image's proportions, unless `aspectRatio` overrides them
+                let ratio = StyleValueAs!(node.styles(document, "aspectRatio"), AspectRatio)
+                    .unwrap_or(intrinsic.width / intrinsic.height);
+                let size = match (width, height) {
+                    (Some(width), Some(height)) => Size::new(width, height),
+                    (Some(width), None) => Size::new(width, width / ratio),
+                    (None, Some(height)) => Size::new(height * ratio, height),
+                    (None, None) => Size::new(intrinsic.width, intrinsic.width / ratio),
+                };
+
+                Rect::from_origin_size((bounds.x0, bounds.y0), size)
+            }
+            NodeType::Spacer => bounds,
             NodeType::Root => {
                 let gap = StyleValueAs!(node.styles(document, "gap"), Gap)
                     .unwrap_or(UnitValue::Pixels(defaults::GAP));
@@ -629,13 +1753,17 @@ impl Element {
                 let direction = StyleValueAs!(node.styles(document, "direction"), Direction)
                     .unwrap_or(defaults::DIRECTION);
 
-                let fit = false;
-                match direction {
+                // Fitting only shrinks the returned rect, which is kept as the content extent
+                let fit = true;
+                let content = match direction {
                     Direction::Vertical => layout_children_vertically(&bounds, gap, fit),
-                    Direction::VerticalReverse => layout_children_vertically_rev(gap, fit),
-                    Direction::Horizontal => layout_children_horizontally(gap, fit),
-                    Direction::HorizontalReverse => layout_children_horizontally_rev(gap, fit),
+                    Direction::VerticalReverse => layout_children_vertically_rev(&bounds, gap, fit),
+                    Direction::Horizontal => layout_children_horizontally(&bounds, gap, fit),
+                    Direction::HorizontalReverse => {
+                        layout_children_horizontally_rev(&bounds, gap, fit)
+                    }
                 };
+                document.set_content_extent(content);
 
                 /* Only difference in body is in keeps the max size */
                 bounds
@@ -677,16 +1805,43 @@ impl Element {
         bounds
     }
 
+    /// The node's `transform`, pivoting around its `transformOrigin` (the center by default)
+    pub fn transform(&self, node: &Node, document: &Document) -> Option<Affine> {
+        let transform = StyleValueAs!(node.styles(document, "transform"), Transform)?;
+        let origin = StyleValueAs!(node.styles(document, "transformOrigin"), TransformOrigin)
+            .unwrap_or(TransformOrigin::Center)
+            .point(&get_id_mgr().get_layout(self.id).border_rect);
+
+        Some(Affine::translate(origin.to_vec2()) * transform * Affine::translate(-origin.to_vec2()))
+    }
+
     pub fn draw(&self, node: &Node, dctx: &mut DrawingContext, document: &Document) {
-        if !node.is_displayed() {
+        if !node.is_displayed() || !node.is_visible(document) {
             return;
         }
-        let binding = get_id_mgr();
-        let layout = binding.get_layout(self.id);
+        let layout = *get_id_mgr().get_layout(self.id);
+
+        // Custom properties go first so their effects sit under the node's own painting
+        for (name, property) in document.custom_properties() {
+            match node.styles(document, name) {
+                StyleValue::Empty => (),
+                value => property.draw(&value, &layout, dctx),
+            }
+        }
 
-        let background_color =
-            StyleValueAs!(node.styles(document, "backgroundColor"), BackgroundColor);
-        let border_color = StyleValueAs!(node.styles(document, "borderColor"), BorderColor);
+        let background_color = document.transition_color(
+            node,
+            "backgroundColor",
+            StyleValueAs!(node.styles(document, "backgroundColor"), BackgroundColor),
+        );
+        // A border that differs between its sides is filled a side at a time
+        let border_segments = node.border_segments(document);
+        let border_color = match node.styles(document, "borderColor") {
+            _ if !border_segments.is_empty() => None,
+            StyleValue::BorderColor { color } => Some(color),
+            StyleValue::BorderColors { colors } => Some(colors[0]),
+            _ => None,
+        };
         let border_width = StyleValueAs!(node.styles(document, "borderWidth"), BorderWidth);
 
         let foreground_color =
@@ -703,38 +1858,28 @@ impl Element {
 
         let radius: Option<RoundedRectRadii> = radius.map(|rad| rad.try_into().unwrap());
 
-        let radius = if let Some(radius) = radius {
-            // Only allow the content to have a radius if the radius is larger than the border width
-            Some(if let Some(w) = border_width {
-                let w: Rect = w.try_into().unwrap();
-                RoundedRectRadii::new(
-                    if radius.top_left > w.x0 && radius.top_left > w.y0 {
-                        radius.top_left
-                    } else {
-                        0.0
-                    },
-                    if radius.top_right > w.x1 && radius.top_right > w.y0 {
-                        radius.top_right
-                    } else {
-                        0.0
-                    },
-                    if radius.bottom_right > w.x1 && radius.bottom_right > w.y1 {
-                        radius.bottom_right
-                    } else {
-                        0.0
-                    },
-                    if radius.bottom_left > w.x0 && radius.bottom_left > w.y0 {
-                        radius.bottom_left
-                    } else {
-                        0.0
-                    },
-                )
-            } else {
-                radius
-            })
-        } else {
-            None
-        };
+        // The shadow follows the outside of the border, so it takes the radius as written
+        if let Some(shadows) = StyleValueAs!(node.styles(document, "boxShadow"), BoxShadow) {
+            for shadow in shadows.iter().rev() {
+                for (shape, color) in shadow.layers(layout.border_rect, radius.unwrap_or_default())
+                {
+                    dctx.builder.fill(
+                        neb_graphics::vello::peniko::Fill::NonZero,
+                        dctx.transform,
+                        color,
+                        None,
+                        &shape,
+                    );
+                }
+            }
+        }
+
+        let radius = radius.map(|radius| inner_radii(radius, border_width));
+
+        for (segment, color) in &border_segments {
+            dctx.builder
+                .fill(Fill::NonZero, dctx.transform, *color, None, segment);
+        }
 
         match (border_color, background_color) {
             // If we have a background color, then we can draw border as rectangle
@@ -745,7 +1890,7 @@ impl Element {
 
                     dctx.builder.fill(
                         neb_graphics::vello::peniko::Fill::NonZero,
-                        Affine::IDENTITY,
+                        dctx.transform,
                         color,
                         None,
                         &rounded,
@@ -755,7 +1900,7 @@ impl Element {
                         // No radius
                         dctx.builder.fill(
                             neb_graphics::vello::peniko::Fill::NonZero,
-                            Affine::IDENTITY,
+                            dctx.transform,
                             color,
                             None,
                             &layout.border_rect,
@@ -773,7 +1918,7 @@ impl Element {
 
                         dctx.builder.stroke(
                             &Stroke::new(w.x0 as _),
-                            Affine::IDENTITY,
+                            dctx.transform,
                             color,
                             None,
                             &rounded,
@@ -782,7 +1927,7 @@ impl Element {
                         // No radius
                         dctx.builder.stroke(
                             &Stroke::new(w.x0 as _),
-                            Affine::IDENTITY,
+                            dctx.transform,
                             color,
                             None,
                             &layout.border_rect,
@@ -818,16 +1963,15 @@ impl Element {
 
                 dctx.builder.fill(
                     neb_graphics::vello::peniko::Fill::EvenOdd,
-                    Affine::IDENTITY,
+                    dctx.transform,
                     color,
                     None,
                     &rounded,
                 );
             } else {
-
                 dctx.builder.fill(
                     neb_graphics::vello::peniko::Fill::EvenOdd,
-                    Affine::IDENTITY,
+                    dctx.transform,
                     color,
                     None,
                     &layout.padding_rect,
@@ -892,11 +2036,51 @@ impl Element {
                     None,
                     psize!(defaults::TEXT_SIZE),
                     Some(&Brush::Solid(parent_foreground_color)),
-                    Affine::translate((layout.padding_rect.x0, layout.padding_rect.y0)),
-                    t,
+                    dctx.transform
+                        * Affine::translate((layout.padding_rect.x0, layout.padding_rect.y0)),
+                    &node.text_transform(document).apply(t),
                     &layout.padding_rect,
+                    node.text_wrap(document),
+                );
+            }
+            // The bitmap is stretched over the content box
+            NodeType::Image {
+                image: Some(image), ..
+            } => {
+                let content = layout.content_rect;
+                dctx.builder.draw_image(
+                    image,
+                    dctx.transform
+                        * Affine::translate((content.x0, content.y0))
+                        * Affine::scale_non_uniform(
+                            content.width() / image.width as f64,
+                            content.height() / image.height as f64,
+                        ),
                 );
             }
+            // A crossed out box where an image couldn't be loaded
+            NodeType::Image { image: None, .. } => {
+                let content = layout.content_rect;
+                dctx.builder.fill(
+                    Fill::NonZero,
+                    dctx.transform,
+                    defaults::BROKEN_IMAGE_COLOR,
+                    None,
+                    &content,
+                );
+                for line in [
+                    Line::new((content.x0, content.y0), (content.x1, content.y1)),
+                    Line::new((content.x1, content.y0), (content.x0, content.y1)),
+                ] {
+                    dctx.builder.stroke(
+                        &Stroke::new(1.0),
+                        dctx.transform,
+                        foreground_color,
+                        None,
+                        &line,
+                    );
+                }
+            }
             _ => (),
         }
     }
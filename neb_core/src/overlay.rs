@@ -0,0 +1,123 @@
+//! Outlines every node's box over the document, to check layout in the window itself
+
+use neb_graphics::{
+    drawing_context::DrawingContext,
+    simple_text::TextWrap,
+    vello::{
+        kurbo::{Affine, Rect, Vec2},
+        peniko::{Brush, Color, Stroke},
+    },
+};
+
+use crate::{
+    document::Document,
+    ids::{layout_of, ID},
+    node::{Node, NodeType},
+    styling::Overflow,
+};
+
+const LABEL_SIZE: f32 = 10.0;
+const OUTLINE_COLOR: Color = Color::rgba8(255, 0, 255, 160);
+const HOVERED_COLOR: Color = Color::rgba8(255, 0, 0, 255);
+
+/// A box the debug overlay outlines, in window coordinates
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverlayBox {
+    pub id: ID,
+    pub rect: Rect,
+    pub label: String,
+    pub hovered: bool,
+}
+
+impl Document {
+    /// The border rect of every displayed node as last laid out, parents before children, with
+    /// its id as the label. The `hovered` node's label also gives its kind and size.
+    ///
+    /// Boxes in scrolled containers are moved with the scroll like they are drawn. Transforms
+    /// aren't applied, so a transformed node is outlined where layout put it
+    pub fn debug_overlay(&self, hovered: Option<ID>) -> Vec<OverlayBox> {
+        let mut boxes = Vec::new();
+        overlay_boxes(
+            &self.shown_root().borrow(),
+            self,
+            hovered,
+            Vec2::ZERO,
+            &mut boxes,
+        );
+        boxes
+    }
+
+    /// Draws the boxes from `debug_overlay` over what's been drawn this frame. The hovered box
+    /// is drawn last, so its outline and label aren't covered by its children's
+    pub fn draw_debug_overlay(&self, dctx: &mut DrawingContext, hovered: Option<ID>) {
+        let mut boxes = self.debug_overlay(hovered);
+        boxes.sort_by_key(|overlay| overlay.hovered);
+
+        for overlay in &boxes {
+            let (width, color) = match overlay.hovered {
+                true => (2.0, HOVERED_COLOR),
+                false => (1.0, OUTLINE_COLOR),
+            };
+            dctx.builder.stroke(
+                &Stroke::new(width),
+                dctx.transform,
+                &Brush::Solid(color),
+                None,
+                &overlay.rect,
+            );
+
+            // Labels sit just inside the top left corner and may run past the box
+            let bounds = Rect::new(0.0, 0.0, dctx.size.width, LABEL_SIZE as f64 * 2.0);
+            dctx.text.add(
+                &mut dctx.builder,
+                None,
+                LABEL_SIZE,
+                Some(&Brush::Solid(color)),
+                dctx.transform * Affine::translate((overlay.rect.x0 + 2.0, overlay.rect.y0 + 1.0)),
+                &overlay.label,
+                &bounds,
+                TextWrap::NoWrap,
+            );
+        }
+    }
+}
+
+fn overlay_boxes(
+    node: &Node,
+    document: &Document,
+    hovered: Option<ID>,
+    offset: Vec2,
+    boxes: &mut Vec<OverlayBox>,
+) {
+    let id = node.element.id();
+    let layout = layout_of(id);
+
+    if node.is_displayed() {
+        let rect = layout.border_rect - offset;
+        let hovered = hovered == Some(id);
+        let label = if hovered {
+            let kind = match &node.ty {
+                NodeType::Text(_) => "text",
+                ty => ty.as_str(),
+            };
+            format!("#{} {} {}x{}", id, kind, rect.width(), rect.height())
+        } else {
+            format!("#{}", id)
+        };
+
+        boxes.push(OverlayBox {
+            id,
+            rect,
+            label,
+            hovered,
+        });
+    }
+
+    let offset = match node.overflow(document) {
+        Overflow::Scroll => offset + node.scroll_offset(),
+        _ => offset,
+    };
+    for child in node.children.iter() {
+        overlay_boxes(&child.borrow(), document, hovered, offset, boxes);
+    }
+}
@@ -0,0 +1,87 @@
+use neb_graphics::vello::{
+    kurbo::{Rect, Size, Vec2},
+    peniko::Color,
+};
+
+/// Width of a scrollbar track, across the direction it scrolls in
+pub const SCROLLBAR_WIDTH: f64 = 8.0;
+
+/// Shortest a thumb gets, so it stays easy to see with a lot of content
+pub const MIN_THUMB_LENGTH: f64 = 16.0;
+
+pub const TRACK_COLOR: Color = Color::rgba8(255, 255, 255, 24);
+pub const THUMB_COLOR: Color = Color::rgba8(255, 255, 255, 96);
+
+/// Keeps `offset` within `[0, content - viewport]` on each axis.
+/// Content that fits in the viewport can't be scrolled at all
+pub fn clamp_offset(offset: Vec2, content: Size, viewport: Size) -> Vec2 {
+    let max = Vec2::new(
+        (content.width - viewport.width).max(0.0),
+        (content.height - viewport.height).max(0.0),
+    );
+    Vec2::new(offset.x.clamp(0.0, max.x), offset.y.clamp(0.0, max.y))
+}
+
+/// Start and length of the thumb along a track `track` pixels long, for `viewport` pixels of
+/// `content` scrolled by `offset`. `None` if the content fits and there is nothing to scroll
+pub fn thumb(track: f64, content: f64, viewport: f64, offset: f64) -> Option<(f64, f64)> {
+    if content <= viewport || track <= 0.0 {
+        return None;
+    }
+
+    let length = (track * viewport / content)
+        .max(MIN_THUMB_LENGTH)
+        .min(track);
+    let progress = (offset / (content - viewport)).clamp(0.0, 1.0);
+    Some(((track - length) * progress, length))
+}
+
+/// The tracks and thumbs for `viewport` pixels of `content` shown in `rect`: a vertical bar on
+/// the right and a horizontal one along the bottom, for each axis that overflows
+pub fn scrollbars(rect: Rect, content: Size, viewport: Size, offset: Vec2) -> Vec<(Rect, Rect)> {
+    let vertical = content.height > viewport.height;
+    let horizontal = content.width > viewport.width;
+
+    // The bars don't overlap in the corner they share
+    let corner = |other: bool| if other { SCROLLBAR_WIDTH } else { 0.0 };
+
+    let mut bars = Vec::new();
+    if vertical {
+        let track = Rect::new(
+            rect.x1 - SCROLLBAR_WIDTH,
+            rect.y0,
+            rect.x1,
+            rect.y1 - corner(horizontal),
+        );
+        if let Some((start, length)) =
+            thumb(track.height(), content.height, viewport.height, offset.y)
+        {
+            let thumb = Rect::new(
+                track.x0,
+                track.y0 + start,
+                track.x1,
+                track.y0 + start + length,
+            );
+            bars.push((track, thumb));
+        }
+    }
+    if horizontal {
+        let track = Rect::new(
+            rect.x0,
+            rect.y1 - SCROLLBAR_WIDTH,
+            rect.x1 - corner(vertical),
+            rect.y1,
+        );
+        if let Some((start, length)) = thumb(track.width(), content.width, viewport.width, offset.x)
+        {
+            let thumb = Rect::new(
+                track.x0 + start,
+                track.y0,
+                track.x0 + start + length,
+                track.y1,
+            );
+            bars.push((track, thumb));
+        }
+    }
+    bars
+}
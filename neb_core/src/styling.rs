@@ -1,43 +1,433 @@
+use std::borrow::Cow;
 use std::collections::HashSet;
-use std::fmt::{Debug, Display};
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
 
-use neb_graphics::vello::kurbo::{Rect, RoundedRectRadii};
-use neb_graphics::vello::peniko::Color;
+use linked_hash_map::LinkedHashMap;
+use neb_graphics::drawing_context::DrawingContext;
+use neb_graphics::input::CursorIcon;
+use neb_graphics::vello::kurbo::{Affine, Point, Rect, RoundedRect, RoundedRectRadii, Vec2};
+use neb_graphics::vello::peniko::{BlendMode, Color, Mix};
 use neb_macros::EnumHash;
 use neb_smf::ast::{ElementArgs, Value};
-use neb_smf::token::{SpannedToken, Token, Unit};
+use neb_smf::color as smf_color;
+pub use neb_smf::direction::Direction;
+use neb_smf::token::{Operator, SpannedToken, Token, Unit};
+use neb_smf::StyleVariant;
 
+use crate::ids::Layout;
 use crate::node::{Node, NodeType};
 
-#[derive(Debug, Clone, Copy)]
-pub enum Direction {
-    Vertical,
-    Horizontal,
-    VerticalReverse,
-    HorizontalReverse,
-}
-
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Hash)]
 pub enum Align {
     Center,
     Left,
     Right,
+    Top,
+    Bottom,
+}
+
+#[derive(Debug, Clone, Copy, Hash)]
+pub enum Display {
+    Block,
+    Inline,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// A hidden node keeps its place in the layout, but isn't painted and can't be clicked.
+/// Children take it from their parent unless they set their own
+#[derive(Debug, Clone, Copy, PartialEq, Hash)]
+pub enum Visibility {
+    Visible,
+    Hidden,
+}
+
+#[derive(Debug, Clone, Copy, Hash)]
 pub enum ChildSizing {
     Match,
     Individual,
 }
 
+#[derive(Debug, Clone, Copy, Hash)]
+pub enum WhiteSpace {
+    Normal,
+    NoWrap,
+}
+
+/// Where wrapping text may break a line
+#[derive(Debug, Clone, Copy, Hash)]
+pub enum WordBreak {
+    /// Only at spaces
+    Normal,
+    /// Also inside a word too long for a line of its own, such as a URL
+    BreakAll,
+}
+
+#[derive(Debug, Clone, Copy, Hash)]
+pub enum TextOverflow {
+    Clip,
+    Ellipsis,
+}
+
+/// Case changes applied to text when it is shown, leaving the source as written
+#[derive(Debug, Clone, Copy, PartialEq, Hash)]
+pub enum TextTransform {
+    None,
+    Uppercase,
+    Lowercase,
+    /// Uppercases the first letter of every word
+    Capitalize,
+}
+
+impl TextTransform {
+    /// The text as it is shown. Case mapping can change the length, e.g. `ß` uppercases to `SS`
+    pub fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self {
+            TextTransform::None => text.into(),
+            TextTransform::Uppercase => text.to_uppercase().into(),
+            TextTransform::Lowercase => text.to_lowercase().into(),
+            TextTransform::Capitalize => {
+                let mut word_start = true;
+                let mut shown = String::with_capacity(text.len());
+                for c in text.chars() {
+                    if word_start {
+                        shown.extend(c.to_uppercase());
+                    } else {
+                        shown.push(c);
+                    }
+                    word_start = c.is_whitespace();
+                }
+                shown.into()
+            }
+        }
+    }
+}
+
+/// What happens to children that don't fit in a view
+#[derive(Debug, Clone, Copy, PartialEq, Hash)]
+pub enum Overflow {
+    Visible,
+    /// Clipped to the padding rect
+    Hidden,
+    /// Clipped, with scrollbars and wheel scrolling
+    Scroll,
+}
+
+/// The point a `transform` pivots around
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransformOrigin {
+    Center,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    /// Offset from the top left of the border rect
+    Offset(UnitValue, UnitValue),
+}
+
+impl Hash for TransformOrigin {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        if let TransformOrigin::Offset(x, y) = self {
+            x.hash(state);
+            y.hash(state);
+        }
+    }
+}
+
+impl TransformOrigin {
+    pub fn point(&self, rect: &Rect) -> Point {
+        match self {
+            TransformOrigin::Center => rect.center(),
+            TransformOrigin::TopLeft => Point::new(rect.x0, rect.y0),
+            TransformOrigin::TopRight => Point::new(rect.x1, rect.y0),
+            TransformOrigin::BottomLeft => Point::new(rect.x0, rect.y1),
+            TransformOrigin::BottomRight => Point::new(rect.x1, rect.y1),
+            TransformOrigin::Offset(x, y) => Point::new(
+                rect.x0 + resolve_unit(*x, rect.width()),
+                rect.y0 + resolve_unit(*y, rect.height()),
+            ),
+        }
+    }
+}
+
+/// The names `blendMode` takes, each mixing a node into what is drawn under it
+pub const BLEND_MODES: [(&str, Mix); 16] = [
+    ("Normal", Mix::Normal),
+    ("Multiply", Mix::Multiply),
+    ("Screen", Mix::Screen),
+    ("Overlay", Mix::Overlay),
+    ("Darken", Mix::Darken),
+    ("Lighten", Mix::Lighten),
+    ("ColorDodge", Mix::ColorDodge),
+    ("ColorBurn", Mix::ColorBurn),
+    ("HardLight", Mix::HardLight),
+    ("SoftLight", Mix::SoftLight),
+    ("Difference", Mix::Difference),
+    ("Exclusion", Mix::Exclusion),
+    ("Hue", Mix::Hue),
+    ("Saturation", Mix::Saturation),
+    ("Color", Mix::Color),
+    ("Luminosity", Mix::Luminosity),
+];
+
+fn blend_mode(value: &Value) -> Option<BlendMode> {
+    let Value::Ident(SpannedToken(_, Token::Ident(name))) = value else {
+        return None;
+    };
+    BLEND_MODES
+        .iter()
+        .find(|(mode, _)| mode == name)
+        .map(|(_, mix)| BlendMode::from(*mix))
+}
+
+/// Most layers a shadow's blur is approximated with
+const SHADOW_STEPS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoxShadow {
+    pub offset: Vec2,
+    pub blur: f64,
+    pub color: Color,
+}
+
+impl Hash for BoxShadow {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for v in [self.offset.x, self.offset.y, self.blur] {
+            hash_f64(v, state);
+        }
+        hash_color(self.color, state);
+    }
+}
+
+impl BoxShadow {
+    /// What the layers of the shadow of `rect` cover
+    pub fn bounds(&self, rect: Rect) -> Rect {
+        (rect + self.offset).inflate(self.blur / 2.0, self.blur / 2.0)
+    }
+
+    /// The shapes to fill, in order, to draw the shadow of `rect`.
+    ///
+    /// The pinned vello can't blur, so a blurred shadow is a stack of translucent rounded rects
+    /// growing from half the blur inside the shape to half the blur outside it
+    pub fn layers(&self, rect: Rect, radii: RoundedRectRadii) -> Vec<(RoundedRect, Color)> {
+        let rect = rect + self.offset;
+        let steps = (self.blur.ceil().max(0.0) as usize).min(SHADOW_STEPS);
+        if steps <= 1 {
+            return vec![(RoundedRect::from_rect(rect, radii), self.color)];
+        }
+
+        // Where every layer overlaps the stack should add up to the shadow's own alpha
+        let alpha = 1.0 - (1.0 - self.color.a as f64 / 255.0).powf(1.0 / steps as f64);
+        let color = Color {
+            a: (alpha * 255.0).round() as u8,
+            ..self.color
+        };
+
+        (0..steps)
+            .map(|i| {
+                let grow = self.blur * ((i as f64 + 0.5) / steps as f64 - 0.5);
+                let radii = RoundedRectRadii::new(
+                    (radii.top_left + grow).max(0.0),
+                    (radii.top_right + grow).max(0.0),
+                    (radii.bottom_right + grow).max(0.0),
+                    (radii.bottom_left + grow).max(0.0),
+                );
+                (
+                    RoundedRect::from_rect(rect.inflate(grow, grow), radii),
+                    color,
+                )
+            })
+            .collect()
+    }
+}
+
+/// Animates changes of one property, e.g. `transition: backgroundColor(0.25)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub property: String,
+    /// In seconds
+    pub duration: f64,
+}
+
+impl Hash for Transition {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.property.hash(state);
+        hash_f64(self.duration, state);
+    }
+}
+
+/// `property(seconds)`
+fn transition_from_function<'a>(
+    property: &str,
+    mut iter: impl Iterator<Item = &'a Value>,
+) -> Option<Transition> {
+    let duration = value_number(iter.next()?)?;
+    if iter.next().is_some() || duration < 0.0 {
+        return None;
+    }
+
+    Some(Transition {
+        property: property.to_string(),
+        duration,
+    })
+}
+
+/// The width of a grid column, see `columns`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Track {
+    /// A size, or a percentage of the grid's width
+    Fixed(UnitValue),
+    /// A share of the width the other columns and the gaps leave, e.g. `1fr`
+    Fraction(f64),
+}
+
+impl Hash for Track {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Track::Fixed(value) => value.hash(state),
+            Track::Fraction(v) => hash_f64(*v, state),
+        }
+    }
+}
+
+/// A size, a percentage or a fraction like `1fr`
+fn track_from_value(val: &Value) -> Option<Track> {
+    match val {
+        Value::Integer(v, Some(Unit::Fraction), _) => Some(Track::Fraction(*v as _)),
+        Value::Float(v, Some(Unit::Fraction), _) => Some(Track::Fraction(*v)),
+        val => value_unit(val)
+            .or_else(|| value_percent(val))
+            .map(Track::Fixed),
+    }
+}
+
+/// Blends each channel from `from` at `t = 0` to `to` at `t = 1`
+pub fn lerp_color(from: Color, to: Color, t: f64) -> Color {
+    let t = t.clamp(0.0, 1.0);
+    let channel = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * t).round() as u8;
+
+    Color::rgba8(
+        channel(from.r, to.r),
+        channel(from.g, to.g),
+        channel(from.b, to.b),
+        channel(from.a, to.a),
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Hash)]
+pub enum Cursor {
+    Default,
+    Pointer,
+    Text,
+    Hidden,
+}
+
+impl Cursor {
+    /// The OS cursor to show, or `None` if the cursor should be hidden
+    pub fn icon(&self) -> Option<CursorIcon> {
+        match self {
+            Cursor::Default => Some(CursorIcon::Default),
+            Cursor::Pointer => Some(CursorIcon::Hand),
+            Cursor::Text => Some(CursorIcon::Text),
+            Cursor::Hidden => None,
+        }
+    }
+}
+
 lazy_static::lazy_static! {
-    static ref INHERITED: HashSet<&'static str> = HashSet::from(["textAlign"]);
+    static ref INHERITED: HashSet<&'static str> =
+        HashSet::from(["textAlign", "cursor", "visibility"]);
 }
 
 pub fn is_inherited(key: &str) -> bool {
     INHERITED.contains(key)
 }
 
+/// What the built in property `key` takes, for error messages. `None` for unknown properties
+pub fn expected_value(key: &str) -> Option<&'static str> {
+    let expected = match key {
+        "backgroundColor" | "foregroundColor" => "a color like `rgb(0, 0, 0)` or `black`",
+        "borderColor" => "a color like `rgb(0, 0, 0)` or `black`, or one for each side",
+        "padding" | "borderWidth" => "a size or rect",
+        "radius" => "a radius or rect",
+        "gap" => "a size or percentage",
+        "width" | "height" => {
+            "a size, percentage, `calc(...)`, `min(...)`, `max(...)` or `clamp(...)`"
+        }
+        "aspectRatio" => "a positive number",
+        "direction" => "`Vertical`, `Horizontal`, `VerticalReverse` or `HorizontalReverse`",
+        "align" | "textAlign" => "`Left`, `Center` or `Right`",
+        "verticalAlign" => "`Top`, `Center` or `Bottom`",
+        "childSizing" => "`Match` or `Individual`",
+        "columns" => "a list of sizes, percentages or fractions like `1fr`",
+        "display" => "`Block` or `Inline`",
+        "visibility" => "`Visible` or `Hidden`",
+        "cursor" => "`Default`, `Pointer`, `Text` or `Hidden`",
+        "whiteSpace" => "`Normal` or `NoWrap`",
+        "wordBreak" => "`Normal` or `BreakAll`",
+        "textOverflow" => "`Clip` or `Ellipsis`",
+        "overflow" => "`Visible`, `Hidden` or `Scroll`",
+        "textTransform" => "`None`, `Uppercase`, `Lowercase` or `Capitalize`",
+        "transform" => "`rotate`, `scale`, `flipX` or `flipY`",
+        "transformOrigin" => "a corner, `Center` or an offset",
+        "boxShadow" => "`shadow(x, y, blur, color)` or a list of them",
+        "transition" => "`property(seconds)` or a list of them",
+        "blendMode" => "a blend mode like `Multiply` or `Screen`",
+        _ => return None,
+    };
+    Some(expected)
+}
+
+/// A style property added by an embedder, see `Document::register_property`
+pub struct CustomProperty {
+    /// Nodes that don't set the property take it from their parent
+    pub inherited: bool,
+    resolve: Box<dyn Fn(&Value) -> StyleValue + Send + Sync>,
+    draw: Option<Box<dyn Fn(&StyleValue, &Layout, &mut DrawingContext) + Send + Sync>>,
+}
+
+impl CustomProperty {
+    /// `resolve` converts the value written in a style, e.g. with `color_from_iter`.
+    /// Returning `StyleValue::Custom` keeps the value as it was written
+    pub fn new(resolve: impl Fn(&Value) -> StyleValue + Send + Sync + 'static) -> Self {
+        Self {
+            inherited: false,
+            resolve: Box::new(resolve),
+            draw: None,
+        }
+    }
+
+    pub fn inherited(mut self) -> Self {
+        self.inherited = true;
+        self
+    }
+
+    /// Called for every node with the property set, before the node paints its own background
+    pub fn with_draw(
+        mut self,
+        draw: impl Fn(&StyleValue, &Layout, &mut DrawingContext) + Send + Sync + 'static,
+    ) -> Self {
+        self.draw = Some(Box::new(draw));
+        self
+    }
+
+    pub fn resolve(&self, value: &Value) -> StyleValue {
+        (self.resolve)(value)
+    }
+
+    /// Whether the property draws anything, see `with_draw`
+    pub fn has_draw(&self) -> bool {
+        self.draw.is_some()
+    }
+
+    pub fn draw(&self, value: &StyleValue, layout: &Layout, dctx: &mut DrawingContext) {
+        if let Some(draw) = &self.draw {
+            draw(value, layout, dctx)
+        }
+    }
+}
+
 #[derive(EnumHash, Debug, Clone)]
 pub enum StyleValue {
     /* Colors */
@@ -46,60 +436,447 @@ pub enum StyleValue {
 
     BorderWidth { rect: UnitRect },
     BorderColor { color: Color },
+    // A color for each side, top, right, bottom and left
+    BorderColors { colors: [Color; 4] },
 
     /* Sizing */
     Gap { amount: UnitValue },
     Padding { rect: UnitRect },
     Radius { rect: UnitRect },
     Direction { direction: Direction },
+    Width { width: LengthExpr },
+    Height { height: LengthExpr },
+    // Width over height, for elements with proportions of their own like images
+    AspectRatio { ratio: f64 },
 
     TextAlign { horizontal: Align },
     Align { horizontal: Align },
+    VerticalAlign { vertical: Align },
     ChildSizing { sizing: ChildSizing },
+    // Lays the children out in a grid with a column for each track, filling rows in order
+    Columns { tracks: Vec<Track> },
+    Display { display: Display },
+    Visibility { visibility: Visibility },
+    Cursor { cursor: Cursor },
+    WhiteSpace { white_space: WhiteSpace },
+    WordBreak { word_break: WordBreak },
+    TextOverflow { overflow: TextOverflow },
+    Overflow { overflow: Overflow },
+    TextTransform { transform: TextTransform },
+    Transform { transform: Affine },
+    TransformOrigin { origin: TransformOrigin },
+    // Listed shadows are painted over the ones after them
+    BoxShadow { shadows: Vec<BoxShadow> },
+    Transition { transitions: Vec<Transition> },
+    // How the node and its children mix with what is under them
+    BlendMode { mode: BlendMode },
+
+    // The unconverted value of a custom property
+    Custom { value: Value },
 
     Empty,
 }
 
-pub fn color_from_iter<'a>(mut iter: impl Iterator<Item = &'a Value>) -> Option<Color> {
-    let r = iter.next()?;
-    let g = iter.next()?;
-    let b = iter.next()?;
-    let a = iter.next();
-    match (r, g, b, a) {
-        (
-            Value::Integer(r, None, _),
-            Value::Integer(g, None, _),
-            Value::Integer(b, None, _),
-            None,
-        ) => Some(Color {
-            r: *r as _,
-            g: *g as _,
-            b: *b as _,
-            a: 255,
+/// Lets a node hash the properties it is laid out and painted with, see `Element::hash_node`
+impl Hash for StyleValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            StyleValue::BackgroundColor { color }
+            | StyleValue::ForegroundColor { color }
+            | StyleValue::BorderColor { color } => hash_color(*color, state),
+            StyleValue::BorderColors { colors } => {
+                for color in colors {
+                    hash_color(*color, state);
+                }
+            }
+            StyleValue::BorderWidth { rect }
+            | StyleValue::Padding { rect }
+            | StyleValue::Radius { rect } => rect.hash(state),
+            StyleValue::Gap { amount } => amount.hash(state),
+            StyleValue::Direction { direction } => direction.hash(state),
+            StyleValue::Width { width: length } | StyleValue::Height { height: length } => {
+                length.hash(state)
+            }
+            StyleValue::AspectRatio { ratio } => hash_f64(*ratio, state),
+            StyleValue::TextAlign { horizontal } | StyleValue::Align { horizontal } => {
+                horizontal.hash(state)
+            }
+            StyleValue::VerticalAlign { vertical } => vertical.hash(state),
+            StyleValue::ChildSizing { sizing } => sizing.hash(state),
+            StyleValue::Columns { tracks } => tracks.hash(state),
+            StyleValue::Display { display } => display.hash(state),
+            StyleValue::Visibility { visibility } => visibility.hash(state),
+            StyleValue::Cursor { cursor } => cursor.hash(state),
+            StyleValue::WhiteSpace { white_space } => white_space.hash(state),
+            StyleValue::WordBreak { word_break } => word_break.hash(state),
+            StyleValue::TextOverflow { overflow } => overflow.hash(state),
+            StyleValue::Overflow { overflow } => overflow.hash(state),
+            StyleValue::TextTransform { transform } => transform.hash(state),
+            StyleValue::Transform { transform } => {
+                for v in transform.as_coeffs() {
+                    hash_f64(v, state);
+                }
+            }
+            StyleValue::TransformOrigin { origin } => origin.hash(state),
+            StyleValue::BoxShadow { shadows } => shadows.hash(state),
+            StyleValue::Transition { transitions } => transitions.hash(state),
+            StyleValue::BlendMode { mode } => {
+                (mode.mix as u8).hash(state);
+                (mode.compose as u8).hash(state);
+            }
+            // Only custom properties hold these, and they aren't layout or paint inputs
+            StyleValue::Custom { .. } | StyleValue::Empty => (),
+        }
+    }
+}
+
+pub(crate) fn hash_color<H: Hasher>(color: Color, state: &mut H) {
+    [color.r, color.g, color.b, color.a].hash(state);
+}
+
+/// Adding zero turns `-0.0` into `0.0`, so values that are equal hash the same
+fn hash_f64<H: Hasher>(v: f64, state: &mut H) {
+    (v + 0.0).to_bits().hash(state);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorError {
+    /// Wrong number of channels or a channel that isn't a unitless number
+    InvalidArguments,
+    /// A channel was outside of 0-255 (or 0.0-1.0); carries the clamped color
+    OutOfRange(Color),
+}
+
+impl From<smf_color::ColorError> for ColorError {
+    fn from(error: smf_color::ColorError) -> Self {
+        match error {
+            smf_color::ColorError::InvalidArguments => ColorError::InvalidArguments,
+            smf_color::ColorError::OutOfRange(color) => ColorError::OutOfRange(peniko_color(color)),
+        }
+    }
+}
+
+fn peniko_color(color: smf_color::Color) -> Color {
+    Color::rgba8(color.r, color.g, color.b, color.a)
+}
+
+/// Integer channels are 0-255 and float channels are 0.0-1.0, see `neb_smf::color::Color`
+pub fn color_from_iter<'a>(iter: impl Iterator<Item = &'a Value>) -> Result<Color, ColorError> {
+    Ok(peniko_color(smf_color::Color::from_channels(iter)?))
+}
+
+/// The color `value` spells out, parsed by `neb_smf::color::Color::from_value` so the renderer
+/// agrees with the validator and tooling: `rgb(...)`, `rgba(...)`, the channel tuple those
+/// functions evaluate to in the symbol tree, a hex string like `"#ff8000"` or a name
+pub fn color_from_value(value: &Value) -> Result<Color, ColorError> {
+    Ok(peniko_color(smf_color::Color::from_value(value)?))
+}
+
+/// What decides which style variants apply to an element
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StyleContext {
+    /// For `minWidth` and `maxWidth` conditions
+    pub viewport_width: f64,
+    /// Whether the mouse is over the element or one of its children, for `state: Hover`
+    pub hovered: bool,
+}
+
+/// The raw value of a style property, taken from the narrowest matching variant first
+pub(crate) fn property_value<'a>(
+    properties: &'a LinkedHashMap<String, Value>,
+    variants: &'a [StyleVariant],
+    prop_key: &str,
+    context: StyleContext,
+) -> Option<&'a Value> {
+    select_variant(variants, prop_key, context)
+        .and_then(|variant| variant.properties.get(prop_key))
+        .or_else(|| properties.get(prop_key))
+}
+
+/// Whether the value `property_value` picks was marked `!important`
+pub(crate) fn property_is_important(
+    properties: &LinkedHashMap<String, Value>,
+    important: &HashSet<String>,
+    variants: &[StyleVariant],
+    prop_key: &str,
+    context: StyleContext,
+) -> bool {
+    match select_variant(variants, prop_key, context) {
+        Some(variant) => variant.important.contains(prop_key),
+        None => properties.contains_key(prop_key) && important.contains(prop_key),
+    }
+}
+
+fn value_number(val: &Value) -> Option<f64> {
+    match val {
+        Value::Integer(v, None, _) => Some(*v as _),
+        Value::Float(v, None, _) => Some(*v),
+        _ => None,
+    }
+}
+
+/// `rotate(degrees)`, `scale(s)`, `scale(x, y)`, `flipX()` and `flipY()`
+fn transform_from_function<'a>(
+    func: &str,
+    iter: impl Iterator<Item = &'a Value>,
+) -> Option<Affine> {
+    let args: Vec<_> = iter.map(value_number).collect::<Option<_>>()?;
+
+    match (func, args.as_slice()) {
+        ("rotate", [degrees]) => Some(Affine::rotate(degrees.to_radians())),
+        ("scale", [s]) => Some(Affine::scale(*s)),
+        ("scale", [x, y]) => Some(Affine::scale_non_uniform(*x, *y)),
+        ("flipX", []) => Some(Affine::FLIP_X),
+        ("flipY", []) => Some(Affine::FLIP_Y),
+        _ => None,
+    }
+}
+
+/// A length in pixels; a unitless number counts as pixels so `0` works without a unit
+fn value_length(val: &Value) -> Option<f64> {
+    match val {
+        Value::Integer(v, None | Some(Unit::Pixel), _) => Some(*v as _),
+        Value::Float(v, None | Some(Unit::Pixel), _) => Some(*v),
+        _ => None,
+    }
+}
+
+/// A single pixel or percentage literal
+fn length_from_value(val: &Value) -> Option<Length> {
+    match val {
+        Value::Integer(v, unit, _) => Length::of_unit(*v as f64, Unit::of_size(*unit)),
+        Value::Float(v, unit, _) => Length::of_unit(*v, Unit::of_size(*unit)),
+        _ => None,
+    }
+}
+
+/// An operand of a `calc(...)` expression
+#[derive(Clone, Copy)]
+enum CalcTerm {
+    Number(f64),
+    Size(Length),
+}
+
+/// Evaluates the arithmetic in a `calc(...)` argument. Sizes can be added to and subtracted
+/// from each other and scaled by unitless numbers; anything else is `None`
+fn calc_term(val: &Value) -> Option<CalcTerm> {
+    use CalcTerm::{Number, Size};
+
+    let (left, op, right) = match val {
+        Value::Integer(v, None, _) => return Some(Number(*v as f64)),
+        Value::Float(v, None, _) => return Some(Number(*v)),
+        Value::Binary {
+            left,
+            op: SpannedToken(_, Token::Operator(op)),
+            right,
+        } => (calc_term(left)?, op, calc_term(right)?),
+        val => return length_from_value(val).map(Size),
+    };
+
+    let scale = |l: Length, by: f64| Length {
+        pixels: l.pixels * by,
+        percent: l.percent * by,
+    };
+
+    let term = match (op, left, right) {
+        (Operator::Plus, Number(a), Number(b)) => Number(a + b),
+        (Operator::Minus, Number(a), Number(b)) => Number(a - b),
+        (Operator::Star, Number(a), Number(b)) => Number(a * b),
+        (Operator::Slash, Number(a), Number(b)) if b != 0.0 => Number(a / b),
+        (Operator::Plus, Size(a), Size(b)) => Size(Length {
+            pixels: a.pixels + b.pixels,
+            percent: a.percent + b.percent,
         }),
-        (
-            Value::Integer(r, None, _),
-            Value::Integer(g, None, _),
-            Value::Integer(b, None, _),
-            Some(Value::Integer(a, None, _)),
-        ) => Some(Color {
-            r: *r as _,
-            g: *g as _,
-            b: *b as _,
-            a: *a as _,
+        (Operator::Minus, Size(a), Size(b)) => Size(Length {
+            pixels: a.pixels - b.pixels,
+            percent: a.percent - b.percent,
         }),
+        (Operator::Star, Size(l), Number(n)) | (Operator::Star, Number(n), Size(l)) => {
+            Size(scale(l, n))
+        }
+        (Operator::Slash, Size(l), Number(n)) if n != 0.0 => Size(scale(l, 1.0 / n)),
+        _ => return None,
+    };
+    Some(term)
+}
+
+/// `calc(100% - 20px)`
+fn calc_from_iter<'a>(mut iter: impl Iterator<Item = &'a Value>) -> Option<Length> {
+    let value = iter.next()?;
+    if iter.next().is_some() {
+        return None;
+    }
+
+    match calc_term(value)? {
+        CalcTerm::Size(length) => Some(length),
+        CalcTerm::Number(_) => None,
+    }
+}
+
+/// A `calc(...)` operand or a `min`, `max` or `clamp` of them. Sizes may mix pixels and
+/// percentages, since those are compared once the containing dimension is known. A number
+/// without a unit is a size too, see `Unit::of_size`
+fn length_expr_from_value(val: &Value) -> Option<LengthExpr> {
+    match val {
+        Value::Function {
+            ident: Some(SpannedToken(_, Token::Ident(func))),
+            args,
+        } => length_expr_from_function(func, args),
+        val => match calc_term(val)? {
+            CalcTerm::Size(length) => Some(LengthExpr::Length(length)),
+            CalcTerm::Number(v) => Length::of_unit(v, Unit::of_size(None)).map(LengthExpr::Length),
+        },
+    }
+}
+
+/// `calc(...)`, `min(a, b, ...)`, `max(a, b, ...)` and `clamp(lo, value, hi)`
+fn length_expr_from_function(func: &str, args: &ElementArgs) -> Option<LengthExpr> {
+    if func == "calc" {
+        return calc_from_iter(args.iter_values()).map(LengthExpr::Length);
+    }
+
+    let values: Vec<_> = args
+        .iter_values()
+        .map(length_expr_from_value)
+        .collect::<Option<_>>()?;
+
+    match (func, values.len()) {
+        ("min", 1..) => Some(LengthExpr::Min(values)),
+        ("max", 1..) => Some(LengthExpr::Max(values)),
+        ("clamp", 3) => {
+            let [lo, value, hi] = <[LengthExpr; 3]>::try_from(values).ok()?;
+            Some(LengthExpr::Clamp(Box::new([lo, value, hi])))
+        }
         _ => None,
     }
 }
 
+/// `shadow(x, y, blur, rgba(...))`
+fn shadow_from_iter<'a>(mut iter: impl Iterator<Item = &'a Value>) -> Option<BoxShadow> {
+    let x = value_length(iter.next()?)?;
+    let y = value_length(iter.next()?)?;
+    let blur = value_length(iter.next()?)?;
+
+    let color = match color_from_value(iter.next()?) {
+        Ok(color) | Err(ColorError::OutOfRange(color)) => color,
+        Err(ColorError::InvalidArguments) => return None,
+    };
+
+    if iter.next().is_some() {
+        return None;
+    }
+
+    Some(BoxShadow {
+        offset: Vec2::new(x, y),
+        blur: blur.max(0.0),
+        color,
+    })
+}
+
+/// A size in pixels. Numbers without a unit are pixels too, see `unitless_sizes`
 fn value_unit(val: &Value) -> Option<UnitValue> {
     match val {
-        Value::Integer(u, Some(Unit::Pixel), _) => Some(UnitValue::Pixels(*u as _)),
-        Value::Float(u, Some(Unit::Pixel), _) => Some(UnitValue::Pixels(*u)),
+        Value::Integer(u, Some(Unit::Pixel) | None, _) => Some(UnitValue::Pixels(*u as _)),
+        Value::Float(u, Some(Unit::Pixel) | None, _) => Some(UnitValue::Pixels(*u)),
         _ => None,
     }
 }
 
+/// Properties that take sizes in pixels, where a number without a unit is read as pixels
+const SIZE_PROPERTIES: [&str; 7] = [
+    "gap",
+    "padding",
+    "borderWidth",
+    "radius",
+    "width",
+    "height",
+    "transformOrigin",
+];
+
+/// The numbers in `value` that are missing a unit where `key` takes sizes, e.g. the `4` in
+/// `gap: 4` or the `600` in `min(100%, 600)`. They are read as pixels, but a unit may have
+/// been forgotten
+pub fn unitless_sizes<'a>(key: &str, value: &'a Value) -> Vec<&'a Value> {
+    if !SIZE_PROPERTIES.contains(&key) {
+        return Vec::new();
+    }
+
+    let unitless =
+        |value: &&Value| matches!(value, Value::Integer(_, None, _) | Value::Float(_, None, _));
+    match value {
+        Value::Tuple(values) => values.iter().filter(unitless).collect(),
+        Value::Function {
+            ident: Some(SpannedToken(_, Token::Ident(func))),
+            args,
+        } if matches!(func.as_str(), "min" | "max" | "clamp") => args
+            .iter_values()
+            .flat_map(|arg| unitless_sizes(key, arg))
+            .collect(),
+        value => Some(value).filter(unitless).into_iter().collect(),
+    }
+}
+
+fn value_percent(val: &Value) -> Option<UnitValue> {
+    match val {
+        Value::Integer(u, Some(Unit::Percent), _) => Some(UnitValue::Percent(*u as _)),
+        Value::Float(u, Some(Unit::Percent), _) => Some(UnitValue::Percent(*u)),
+        _ => None,
+    }
+}
+
+/// Bounds of a variant's `minWidth`/`maxWidth` condition
+fn variant_bounds(variant: &StyleVariant) -> (f64, f64) {
+    let bound = |key| {
+        variant
+            .condition
+            .get(key)
+            .and_then(value_unit)
+            .map(|uv| resolve_unit(uv, 0.0))
+    };
+
+    (
+        bound("minWidth").unwrap_or(0.0),
+        bound("maxWidth").unwrap_or(f64::INFINITY),
+    )
+}
+
+/// Whether a variant's `state` condition holds. Variants without one always match
+fn variant_state_matches(variant: &StyleVariant, context: StyleContext) -> bool {
+    match variant.condition.get("state") {
+        None => true,
+        Some(Value::Ident(SpannedToken(_, Token::Ident(state)))) if state == "Hover" => {
+            context.hovered
+        }
+        Some(_) => false,
+    }
+}
+
+/// Picks the variant defining `key` that matches the context. State variants win over
+/// the others, then the narrowest viewport range wins
+fn select_variant<'a>(
+    variants: &'a [StyleVariant],
+    key: &str,
+    context: StyleContext,
+) -> Option<&'a StyleVariant> {
+    let width = context.viewport_width;
+    variants
+        .iter()
+        .filter(|variant| variant.properties.contains_key(key))
+        .filter(|variant| variant_state_matches(variant, context))
+        .map(|variant| {
+            let stateful = variant.condition.contains_key("state");
+            (variant, variant_bounds(variant), stateful)
+        })
+        .filter(|(_, (min, max), _)| *min <= width && width <= *max)
+        .min_by(|(_, (amin, amax), astate), (_, (bmin, bmax), bstate)| {
+            bstate
+                .cmp(astate)
+                .then((amax - amin).total_cmp(&(bmax - bmin)))
+        })
+        .map(|(variant, _, _)| variant)
+}
+
 fn rect_form_iter<'a>(mut iter: impl Iterator<Item = &'a Value>) -> Option<UnitRect> {
     let a = value_unit(iter.next()?)?;
     let b = value_unit(iter.next()?)?;
@@ -120,23 +897,88 @@ fn rect_all_form_iter<'a>(mut iter: impl Iterator<Item = &'a Value>) -> Option<U
     Some(UnitRect::new(a, a, a, a))
 }
 
+/// Builds a rect from a CSS like shorthand list of one to four values.
+///
+/// One value applies to all sides, two are `vertical horizontal`,
+/// three are `top horizontal bottom` and four are `top right bottom left`
+pub(crate) fn rect_shorthand_from_iter<'a>(
+    iter: impl Iterator<Item = &'a Value>,
+) -> Option<UnitRect> {
+    let values: Option<Vec<_>> = iter.map(value_unit).collect();
+
+    match values?[..] {
+        [a] => Some(UnitRect::new(a, a, a, a)),
+        [y, x] => Some(UnitRect::new(x, y, x, y)),
+        [top, x, bottom] => Some(UnitRect::new(x, top, x, bottom)),
+        [top, right, bottom, left] => Some(UnitRect::new(left, top, right, bottom)),
+        _ => None,
+    }
+}
+
+/// Spreads a CSS like shorthand list of one to four values over the sides, in the same
+/// order as `rect_shorthand_from_iter`. Returns them as top, right, bottom and left
+pub(crate) fn sides_shorthand<T: Copy>(values: &[T]) -> Option<[T; 4]> {
+    match *values {
+        [a] => Some([a, a, a, a]),
+        [y, x] => Some([y, x, y, x]),
+        [top, x, bottom] => Some([top, x, bottom, x]),
+        [top, right, bottom, left] => Some([top, right, bottom, left]),
+        _ => None,
+    }
+}
+
+/// Builds corner radii from a CSS like shorthand list of one to four values.
+///
+/// Corners go clockwise from the top left, the same order `RoundedRectRadii` takes
+pub(crate) fn radii_shorthand_from_iter<'a>(
+    iter: impl Iterator<Item = &'a Value>,
+) -> Option<UnitRect> {
+    let values: Option<Vec<_>> = iter.map(value_unit).collect();
+
+    match values?[..] {
+        [a] => Some(UnitRect::new(a, a, a, a)),
+        [a, b] => Some(UnitRect::new(a, b, a, b)),
+        [a, b, c] => Some(UnitRect::new(a, b, c, b)),
+        [a, b, c, d] => Some(UnitRect::new(a, b, c, d)),
+        _ => None,
+    }
+}
+
 // fn verify_enum()
 
 impl StyleValue {
     fn build_function(key: &str, func: &str, args: &ElementArgs) -> StyleValue {
         match func {
-            "rgb" | "rgba" => {
-                let Some(color) = color_from_iter(args.iter_values()) else {
-                    return StyleValue::Empty
+            property if key == "transition" => {
+                if let Some(transition) = transition_from_function(property, args.iter_values()) {
+                    return StyleValue::Transition {
+                        transitions: vec![transition],
+                    };
+                }
+            }
+            "rotate" | "scale" | "flipX" | "flipY" if key == "transform" => {
+                if let Some(transform) = transform_from_function(func, args.iter_values()) {
+                    return StyleValue::Transform { transform };
+                }
+            }
+            "calc" | "min" | "max" | "clamp" => {
+                let Some(length) = length_expr_from_function(func, args) else {
+                    return StyleValue::Empty;
                 };
 
                 match key {
-                    "foregroundColor" => return StyleValue::ForegroundColor { color },
-                    "backgroundColor" => return StyleValue::BackgroundColor { color },
-                    "borderColor" => return StyleValue::BorderColor { color },
+                    "width" => return StyleValue::Width { width: length },
+                    "height" => return StyleValue::Height { height: length },
                     _ => (),
                 }
             }
+            "shadow" if key == "boxShadow" => {
+                if let Some(shadow) = shadow_from_iter(args.iter_values()) {
+                    return StyleValue::BoxShadow {
+                        shadows: vec![shadow],
+                    };
+                }
+            }
             "rect_xy" => {
                 let Some(rect) = rect_xy_form_iter(args.iter_values()) else {
                     return StyleValue::Empty;
@@ -178,93 +1020,379 @@ impl StyleValue {
         StyleValue::Empty
     }
 
-    pub fn from_symbol(sym: &Node, prop_key: &str) -> StyleValue {
+    pub fn from_symbol(sym: &Node, prop_key: &str, context: StyleContext) -> StyleValue {
         match &sym.ty {
-            NodeType::Style { properties, .. } => {
-                if let Some(prop) = properties.get(prop_key) {
-                    match prop {
-                        Value::Function {
-                            ident: Some(SpannedToken(_, Token::Ident(i))),
-                            args,
-                        } => return StyleValue::build_function(prop_key, i, args),
-                        Value::Float(_, _, _) | Value::Integer(_, _, _) => {
-                            let Some(uv) = value_unit(prop) else {
-                                return StyleValue::Empty
-                            };
-                            match prop_key {
-                                "gap" => return StyleValue::Gap { amount: uv },
-                                _ => (),
-                            }
+            NodeType::Style {
+                properties,
+                variants,
+                ..
+            } => property_value(properties, variants, prop_key, context)
+                .map(|prop| StyleValue::from_value(prop_key, prop))
+                .unwrap_or(StyleValue::Empty),
+            _ => StyleValue::Empty,
+        }
+    }
+
+    /// Whether the style block `sym` marks the value it has for `prop_key` as `!important`
+    pub fn is_important(sym: &Node, prop_key: &str, context: StyleContext) -> bool {
+        match &sym.ty {
+            NodeType::Style {
+                properties,
+                important,
+                variants,
+                ..
+            } => property_is_important(properties, important, variants, prop_key, context),
+            _ => false,
+        }
+    }
+
+    /// Builds the style value for `prop_key` out of a parsed property value, or `Empty` when the
+    /// value doesn't fit the property
+    pub fn from_value(prop_key: &str, prop: &Value) -> StyleValue {
+        if prop_key == "direction" {
+            let direction = match prop {
+                Value::Ident(SpannedToken(_, Token::Ident(name))) => Direction::from_name(name),
+                _ => None,
+            };
+            return match direction {
+                Some(direction) => StyleValue::Direction { direction },
+                None => StyleValue::Empty,
+            };
+        }
+        if prop_key == "blendMode" {
+            return match blend_mode(prop) {
+                Some(mode) => StyleValue::BlendMode { mode },
+                None => StyleValue::Empty,
+            };
+        }
+
+        // Out of range channels are reported when the document is built; draw the clamped color
+        let color = match color_from_value(prop) {
+            Ok(color) | Err(ColorError::OutOfRange(color)) => Some(color),
+            Err(ColorError::InvalidArguments) => None,
+        };
+        match (prop_key, color) {
+            ("foregroundColor", Some(color)) => return StyleValue::ForegroundColor { color },
+            ("backgroundColor", Some(color)) => return StyleValue::BackgroundColor { color },
+            ("borderColor", Some(color)) => return StyleValue::BorderColor { color },
+            _ => (),
+        }
+
+        match prop {
+            Value::Function {
+                ident: Some(SpannedToken(_, Token::Ident(i))),
+                args,
+            } => return StyleValue::build_function(prop_key, i, args),
+            Value::Float(_, _, _) | Value::Integer(_, _, _) => {
+                match (prop_key, length_from_value(prop).map(LengthExpr::Length)) {
+                    ("width", Some(width)) => return StyleValue::Width { width },
+                    ("height", Some(height)) => return StyleValue::Height { height },
+                    _ => (),
+                }
+
+                if prop_key == "aspectRatio" {
+                    return match value_number(prop) {
+                        Some(ratio) if ratio > 0.0 => StyleValue::AspectRatio { ratio },
+                        _ => StyleValue::Empty,
+                    };
+                }
+
+                // A gap can be a percentage of the parent's length along the stacking direction
+                if let ("gap", Some(amount)) = (prop_key, value_percent(prop)) {
+                    return StyleValue::Gap { amount };
+                }
+
+                let Some(uv) = value_unit(prop) else {
+                    return StyleValue::Empty;
+                };
+                match prop_key {
+                    "gap" => return StyleValue::Gap { amount: uv },
+                    "padding" => {
+                        return StyleValue::Padding {
+                            rect: UnitRect::new(uv, uv, uv, uv),
+                        }
+                    }
+                    "radius" => {
+                        return StyleValue::Radius {
+                            rect: UnitRect::new(uv, uv, uv, uv),
                         }
-                        Value::Ident(SpannedToken(_, Token::Ident(id))) => {
-                            match (prop_key, id.as_str()) {
-                                ("childSizing", "Match") => {
-                                    return StyleValue::ChildSizing {
-                                        sizing: ChildSizing::Match,
-                                    }
-                                }
-                                ("childSizing", "Individual") => {
-                                    return StyleValue::ChildSizing {
-                                        sizing: ChildSizing::Individual,
-                                    }
-                                }
-                                ("align", "Center") => {
-                                    return StyleValue::Align {
-                                        horizontal: Align::Center,
-                                    }
-                                }
-                                ("align", "Left") => {
-                                    return StyleValue::Align {
-                                        horizontal: Align::Left,
-                                    }
-                                }
-                                ("align", "Right") => {
-                                    return StyleValue::Align {
-                                        horizontal: Align::Right,
-                                    }
-                                }
-                                ("textAlign", "Center") => {
-                                    return StyleValue::TextAlign {
-                                        horizontal: Align::Center,
-                                    }
-                                }
-                                ("textAlign", "Left") => {
-                                    return StyleValue::TextAlign {
-                                        horizontal: Align::Left,
-                                    }
-                                }
-                                ("textAlign", "Right") => {
-                                    return StyleValue::TextAlign {
-                                        horizontal: Align::Right,
-                                    }
-                                }
-                                ("direction", "Vertical") => {
-                                    return StyleValue::Direction {
-                                        direction: Direction::Vertical,
-                                    }
-                                }
-                                ("direction", "Horizontal") => {
-                                    return StyleValue::Direction {
-                                        direction: Direction::Horizontal,
-                                    }
-                                }
-                                ("direction", "VerticalReverse") => {
-                                    return StyleValue::Direction {
-                                        direction: Direction::VerticalReverse,
-                                    }
-                                }
-                                ("direction", "HorizontalReverse") => {
-                                    return StyleValue::Direction {
-                                        direction: Direction::HorizontalReverse,
-                                    }
-                                }
-                                _ => (),
-                            }
+                    }
+                    "borderWidth" => {
+                        return StyleValue::BorderWidth {
+                            rect: UnitRect::new(uv, uv, uv, uv),
                         }
-                        _ => (),
                     }
+                    _ => (),
                 }
             }
+            Value::Array { values, .. } => match prop_key {
+                "transition" => {
+                    let transitions = values
+                        .iter_items()
+                        .map(|value| {
+                            let (property, args) = value.as_function()?;
+                            transition_from_function(property, args.iter_values())
+                        })
+                        .collect::<Option<Vec<_>>>();
+
+                    if let Some(transitions) = transitions {
+                        return StyleValue::Transition { transitions };
+                    }
+                }
+                "borderColor" => {
+                    // Out of range channels are reported when the document is built
+                    let colors = values
+                        .iter_items()
+                        .map(|value| match color_from_value(value) {
+                            Ok(color) | Err(ColorError::OutOfRange(color)) => Some(color),
+                            Err(ColorError::InvalidArguments) => None,
+                        })
+                        .collect::<Option<Vec<_>>>();
+
+                    if let Some(colors) = colors.as_deref().and_then(sides_shorthand) {
+                        return StyleValue::BorderColors { colors };
+                    }
+                }
+                "columns" => {
+                    let tracks = values
+                        .iter_items()
+                        .map(track_from_value)
+                        .collect::<Option<Vec<_>>>();
+
+                    if let Some(tracks) = tracks.filter(|tracks| !tracks.is_empty()) {
+                        return StyleValue::Columns { tracks };
+                    }
+                }
+                "boxShadow" => {
+                    let shadows = values
+                        .iter_items()
+                        .map(|value| match value.as_function() {
+                            Some(("shadow", args)) => shadow_from_iter(args.iter_values()),
+                            _ => None,
+                        })
+                        .collect::<Option<Vec<_>>>();
+
+                    if let Some(shadows) = shadows {
+                        return StyleValue::BoxShadow { shadows };
+                    }
+                }
+                _ => (),
+            },
+            Value::Tuple(values) => match prop_key {
+                "transformOrigin" => {
+                    if let [x, y] = values.as_slice() {
+                        if let (Some(x), Some(y)) = (value_unit(x), value_unit(y)) {
+                            return StyleValue::TransformOrigin {
+                                origin: TransformOrigin::Offset(x, y),
+                            };
+                        }
+                    }
+                }
+                "padding" => {
+                    if let Some(rect) = rect_shorthand_from_iter(values.iter()) {
+                        return StyleValue::Padding { rect };
+                    }
+                }
+                "borderWidth" => {
+                    if let Some(rect) = rect_shorthand_from_iter(values.iter()) {
+                        return StyleValue::BorderWidth { rect };
+                    }
+                }
+                "radius" => {
+                    if let Some(rect) = radii_shorthand_from_iter(values.iter()) {
+                        return StyleValue::Radius { rect };
+                    }
+                }
+                _ => (),
+            },
+            Value::Ident(SpannedToken(_, Token::Ident(id))) => match (prop_key, id.as_str()) {
+                ("childSizing", "Match") => {
+                    return StyleValue::ChildSizing {
+                        sizing: ChildSizing::Match,
+                    }
+                }
+                ("childSizing", "Individual") => {
+                    return StyleValue::ChildSizing {
+                        sizing: ChildSizing::Individual,
+                    }
+                }
+                ("align", "Center") => {
+                    return StyleValue::Align {
+                        horizontal: Align::Center,
+                    }
+                }
+                ("align", "Left") => {
+                    return StyleValue::Align {
+                        horizontal: Align::Left,
+                    }
+                }
+                ("align", "Right") => {
+                    return StyleValue::Align {
+                        horizontal: Align::Right,
+                    }
+                }
+                ("verticalAlign", "Top") => {
+                    return StyleValue::VerticalAlign {
+                        vertical: Align::Top,
+                    }
+                }
+                ("verticalAlign", "Center") => {
+                    return StyleValue::VerticalAlign {
+                        vertical: Align::Center,
+                    }
+                }
+                ("verticalAlign", "Bottom") => {
+                    return StyleValue::VerticalAlign {
+                        vertical: Align::Bottom,
+                    }
+                }
+                ("textAlign", "Center") => {
+                    return StyleValue::TextAlign {
+                        horizontal: Align::Center,
+                    }
+                }
+                ("textAlign", "Left") => {
+                    return StyleValue::TextAlign {
+                        horizontal: Align::Left,
+                    }
+                }
+                ("textAlign", "Right") => {
+                    return StyleValue::TextAlign {
+                        horizontal: Align::Right,
+                    }
+                }
+                ("transformOrigin", "Center") => {
+                    return StyleValue::TransformOrigin {
+                        origin: TransformOrigin::Center,
+                    }
+                }
+                ("transformOrigin", "TopLeft") => {
+                    return StyleValue::TransformOrigin {
+                        origin: TransformOrigin::TopLeft,
+                    }
+                }
+                ("transformOrigin", "TopRight") => {
+                    return StyleValue::TransformOrigin {
+                        origin: TransformOrigin::TopRight,
+                    }
+                }
+                ("transformOrigin", "BottomLeft") => {
+                    return StyleValue::TransformOrigin {
+                        origin: TransformOrigin::BottomLeft,
+                    }
+                }
+                ("transformOrigin", "BottomRight") => {
+                    return StyleValue::TransformOrigin {
+                        origin: TransformOrigin::BottomRight,
+                    }
+                }
+                ("display", "Block") => {
+                    return StyleValue::Display {
+                        display: Display::Block,
+                    }
+                }
+                ("display", "Inline") => {
+                    return StyleValue::Display {
+                        display: Display::Inline,
+                    }
+                }
+                ("visibility", "Visible") => {
+                    return StyleValue::Visibility {
+                        visibility: Visibility::Visible,
+                    }
+                }
+                ("visibility", "Hidden") => {
+                    return StyleValue::Visibility {
+                        visibility: Visibility::Hidden,
+                    }
+                }
+                ("cursor", "Default") => {
+                    return StyleValue::Cursor {
+                        cursor: Cursor::Default,
+                    }
+                }
+                ("cursor", "Pointer") => {
+                    return StyleValue::Cursor {
+                        cursor: Cursor::Pointer,
+                    }
+                }
+                ("cursor", "Text") => {
+                    return StyleValue::Cursor {
+                        cursor: Cursor::Text,
+                    }
+                }
+                ("cursor", "Hidden") => {
+                    return StyleValue::Cursor {
+                        cursor: Cursor::Hidden,
+                    }
+                }
+                ("whiteSpace", "Normal") => {
+                    return StyleValue::WhiteSpace {
+                        white_space: WhiteSpace::Normal,
+                    }
+                }
+                ("whiteSpace", "NoWrap") => {
+                    return StyleValue::WhiteSpace {
+                        white_space: WhiteSpace::NoWrap,
+                    }
+                }
+                ("wordBreak", "Normal") => {
+                    return StyleValue::WordBreak {
+                        word_break: WordBreak::Normal,
+                    }
+                }
+                ("wordBreak", "BreakAll") => {
+                    return StyleValue::WordBreak {
+                        word_break: WordBreak::BreakAll,
+                    }
+                }
+                ("textOverflow", "Clip") => {
+                    return StyleValue::TextOverflow {
+                        overflow: TextOverflow::Clip,
+                    }
+                }
+                ("textOverflow", "Ellipsis") => {
+                    return StyleValue::TextOverflow {
+                        overflow: TextOverflow::Ellipsis,
+                    }
+                }
+                ("textTransform", "None") => {
+                    return StyleValue::TextTransform {
+                        transform: TextTransform::None,
+                    }
+                }
+                ("textTransform", "Uppercase") => {
+                    return StyleValue::TextTransform {
+                        transform: TextTransform::Uppercase,
+                    }
+                }
+                ("textTransform", "Lowercase") => {
+                    return StyleValue::TextTransform {
+                        transform: TextTransform::Lowercase,
+                    }
+                }
+                ("textTransform", "Capitalize") => {
+                    return StyleValue::TextTransform {
+                        transform: TextTransform::Capitalize,
+                    }
+                }
+                ("overflow", "Visible") => {
+                    return StyleValue::Overflow {
+                        overflow: Overflow::Visible,
+                    }
+                }
+                ("overflow", "Hidden") => {
+                    return StyleValue::Overflow {
+                        overflow: Overflow::Hidden,
+                    }
+                }
+                ("overflow", "Scroll") => {
+                    return StyleValue::Overflow {
+                        overflow: Overflow::Scroll,
+                    }
+                }
+                _ => (),
+            },
             _ => (),
         }
         StyleValue::Empty
@@ -321,6 +1449,27 @@ macro_rules! StyleValueAs {
         rect
       } => Some((rect)),_ => None,
     }
+  };
+    ($e:expr,Width) => {
+    match$e {
+      StyleValue::Width {
+        width
+      } => Some((width)),_ => None,
+    }
+  };
+    ($e:expr,Height) => {
+    match$e {
+      StyleValue::Height {
+        height
+      } => Some((height)),_ => None,
+    }
+  };
+    ($e:expr,AspectRatio) => {
+    match$e {
+      StyleValue::AspectRatio {
+        ratio
+      } => Some((ratio)),_ => None,
+    }
   };
     ($e:expr,Direction) => {
     match$e {
@@ -350,11 +1499,161 @@ macro_rules! StyleValueAs {
       } => Some((horizontal)),_ => None,
     }
   };
+ ($e:expr,VerticalAlign) => {
+    match$e {
+      StyleValue::VerticalAlign{
+       vertical
+      } => Some((vertical)),_ => None,
+    }
+  };
+ ($e:expr,Display) => {
+    match$e {
+      StyleValue::Display{
+       display
+      } => Some((display)),_ => None,
+    }
+  };
+ ($e:expr,Transform) => {
+    match$e {
+      StyleValue::Transform{
+       transform
+      } => Some((transform)),_ => None,
+    }
+  };
+ ($e:expr,TransformOrigin) => {
+    match$e {
+      StyleValue::TransformOrigin{
+       origin
+      } => Some((origin)),_ => None,
+    }
+  };
+ ($e:expr,BoxShadow) => {
+    match$e {
+      StyleValue::BoxShadow{
+       shadows
+      } => Some((shadows)),_ => None,
+    }
+  };
+ ($e:expr,Transition) => {
+    match$e {
+      StyleValue::Transition{
+       transitions
+      } => Some((transitions)),_ => None,
+    }
+  };
+ ($e:expr,Cursor) => {
+    match$e {
+      StyleValue::Cursor{
+       cursor
+      } => Some((cursor)),_ => None,
+    }
+  };
+ ($e:expr,WhiteSpace) => {
+    match$e {
+      StyleValue::WhiteSpace{
+       white_space
+      } => Some((white_space)),_ => None,
+    }
+  };
+ ($e:expr,WordBreak) => {
+    match$e {
+      StyleValue::WordBreak{
+       word_break
+      } => Some((word_break)),_ => None,
+    }
+  };
+ ($e:expr,TextOverflow) => {
+    match$e {
+      StyleValue::TextOverflow{
+       overflow
+      } => Some((overflow)),_ => None,
+    }
+  };
+ ($e:expr,TextTransform) => {
+    match$e {
+      StyleValue::TextTransform{
+       transform
+      } => Some((transform)),_ => None,
+    }
+  };
+ ($e:expr,Overflow) => {
+    match$e {
+      StyleValue::Overflow{
+       overflow
+      } => Some((overflow)),_ => None,
+    }
+  };
+ ($e:expr,BlendMode) => {
+    match$e {
+      StyleValue::BlendMode{
+       mode
+      } => Some((mode)),_ => None,
+    }
+  };
+ ($e:expr,Visibility) => {
+    match$e {
+      StyleValue::Visibility{
+       visibility
+      } => Some((visibility)),_ => None,
+    }
+  };
+ ($e:expr,Columns) => {
+    match$e {
+      StyleValue::Columns{
+       tracks
+      } => Some((tracks)),_ => None,
+    }
+  };
+ ($e:expr,BorderColors) => {
+    match$e {
+      StyleValue::BorderColors{
+       colors
+      } => Some((colors)),_ => None,
+    }
+  };
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum UnitValue {
     Pixels(f64),
+    /// Of a length of the parent, which one depends on the property
+    Percent(f64),
+}
+
+impl Hash for UnitValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            UnitValue::Pixels(v) | UnitValue::Percent(v) => hash_f64(*v, state),
+        }
+    }
+}
+
+impl UnitValue {
+    /// Blends from `self` at `t = 0` to `to` at `t = 1`
+    pub fn lerp(self, to: UnitValue, t: f64) -> UnitValue {
+        let t = t.clamp(0.0, 1.0);
+        match (self, to) {
+            (UnitValue::Pixels(a), UnitValue::Pixels(b)) => UnitValue::Pixels(a + (b - a) * t),
+            (UnitValue::Percent(a), UnitValue::Percent(b)) => UnitValue::Percent(a + (b - a) * t),
+            // Pixels and percentages can't be blended without a length, so switch at the end
+            (from, to) => {
+                if t < 1.0 {
+                    from
+                } else {
+                    to
+                }
+            }
+        }
+    }
+}
+
+/// The pixels `value` stands for, with percentages taken of `reference`
+pub fn resolve_unit(value: UnitValue, reference: f64) -> f64 {
+    match value {
+        UnitValue::Pixels(p) => p,
+        UnitValue::Percent(p) => reference * p / 100.0,
+    }
 }
 
 impl Default for UnitValue {
@@ -369,15 +1668,135 @@ impl Debug for UnitValue {
     }
 }
 
-impl Display for UnitValue {
+impl fmt::Display for UnitValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             UnitValue::Pixels(u) => write!(f, "{}px", u),
+            UnitValue::Percent(u) => write!(f, "{}%", u),
+        }
+    }
+}
+
+/// A size that may depend on the containing dimension: `pixels + percent%` of it. Percentages
+/// and `calc(...)` expressions are both kept in this form until layout
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Length {
+    pub pixels: f64,
+    pub percent: f64,
+}
+
+impl Hash for Length {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_f64(self.pixels, state);
+        hash_f64(self.percent, state);
+    }
+}
+
+impl Length {
+    pub fn pixels(pixels: f64) -> Length {
+        Length {
+            pixels,
+            percent: 0.0,
+        }
+    }
+
+    pub fn percent(percent: f64) -> Length {
+        Length {
+            pixels: 0.0,
+            percent,
+        }
+    }
+
+    /// `v` in pixels or percent. Other units aren't lengths
+    pub fn of_unit(v: f64, unit: Unit) -> Option<Length> {
+        match unit {
+            Unit::Pixel => Some(Length::pixels(v)),
+            Unit::Percent => Some(Length::percent(v)),
+            Unit::Fraction => None,
+        }
+    }
+
+    /// The size in pixels within a containing dimension of `parent` pixels
+    pub fn resolve(&self, parent: f64) -> f64 {
+        self.pixels + parent * self.percent / 100.0
+    }
+}
+
+impl fmt::Display for Length {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.pixels, self.percent) {
+            (pixels, percent) if percent == 0.0 => write!(f, "{}px", pixels),
+            (pixels, percent) if pixels == 0.0 => write!(f, "{}%", percent),
+            (pixels, percent) => write!(f, "calc({}% + {}px)", percent, pixels),
         }
     }
 }
 
-#[derive(Clone, Copy, Debug, Default)]
+/// A width or height. `min`, `max` and `clamp` pick between lengths once the containing
+/// dimension is known, so `min(100%, 600px)` depends on the parent
+#[derive(Clone, Debug, PartialEq)]
+pub enum LengthExpr {
+    Length(Length),
+    Min(Vec<LengthExpr>),
+    Max(Vec<LengthExpr>),
+    /// `[lo, value, hi]`
+    Clamp(Box<[LengthExpr; 3]>),
+}
+
+impl Hash for LengthExpr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            LengthExpr::Length(length) => length.hash(state),
+            LengthExpr::Min(values) | LengthExpr::Max(values) => values.hash(state),
+            LengthExpr::Clamp(bounds) => bounds.hash(state),
+        }
+    }
+}
+
+impl LengthExpr {
+    /// The size in pixels within a containing dimension of `parent` pixels
+    pub fn resolve(&self, parent: f64) -> f64 {
+        match self {
+            LengthExpr::Length(length) => length.resolve(parent),
+            LengthExpr::Min(values) => values
+                .iter()
+                .map(|value| value.resolve(parent))
+                .fold(f64::INFINITY, f64::min),
+            LengthExpr::Max(values) => values
+                .iter()
+                .map(|value| value.resolve(parent))
+                .fold(f64::NEG_INFINITY, f64::max),
+            LengthExpr::Clamp(bounds) => {
+                let [lo, value, hi] = bounds.as_ref();
+                value
+                    .resolve(parent)
+                    .min(hi.resolve(parent))
+                    .max(lo.resolve(parent))
+            }
+        }
+    }
+}
+
+impl fmt::Display for LengthExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let list = |values: &[LengthExpr]| {
+            values
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self {
+            LengthExpr::Length(length) => write!(f, "{}", length),
+            LengthExpr::Min(values) => write!(f, "min({})", list(values)),
+            LengthExpr::Max(values) => write!(f, "max({})", list(values)),
+            LengthExpr::Clamp(bounds) => write!(f, "clamp({})", list(bounds.as_slice())),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct UnitRect {
     x0: UnitValue,
     y0: UnitValue,
@@ -385,6 +1804,12 @@ pub struct UnitRect {
     y1: UnitValue,
 }
 
+impl Hash for UnitRect {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        [self.x0, self.y0, self.x1, self.y1].hash(state);
+    }
+}
+
 impl UnitRect {
     pub fn new(x0: UnitValue, y0: UnitValue, x1: UnitValue, y1: UnitValue) -> UnitRect {
         UnitRect { x0, y0, x1, y1 }
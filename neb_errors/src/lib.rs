@@ -2,6 +2,7 @@ use std::fmt::Display;
 
 use colored::Colorize;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ErrorKind {
     Info,
     Warning,
@@ -10,19 +11,68 @@ pub enum ErrorKind {
 
 pub enum DocumentErrorType {
     ExpectedTag(String),
+    InvalidColor(String),
+    DuplicateName(String),
+    Syntax(String),
+    UnresolvedReference(String),
+    TypeMismatch {
+        expected: String,
+        found: String,
+    },
+    /// A size written without a unit, which is read as pixels
+    MissingUnit(String),
+    /// A node reaching past the viewport it was laid out in, with its border rect
+    Overflow {
+        node: String,
+        viewport: String,
+        rect: String,
+    },
 }
 
 impl DocumentErrorType {
     pub fn get_message(&self) -> String {
         match self {
             DocumentErrorType::ExpectedTag(tag) => format!("Expected Tag `{}`", tag),
+            DocumentErrorType::InvalidColor(msg) => format!("Invalid color: {}", msg),
+            DocumentErrorType::DuplicateName(name) => {
+                format!("Name `{}` is already used by another element", name)
+            }
+            DocumentErrorType::Syntax(msg) => format!("Syntax error: {}", msg),
+            DocumentErrorType::UnresolvedReference(name) => {
+                format!("Cannot find `{}` in scope", name)
+            }
+            DocumentErrorType::TypeMismatch { expected, found } => {
+                format!("Expected {}, found {}", expected, found)
+            }
+            DocumentErrorType::MissingUnit(key) => {
+                format!("Missing a unit for `{}`, it is read as `px`", key)
+            }
+            DocumentErrorType::Overflow {
+                node,
+                viewport,
+                rect,
+            } => format!(
+                "{} overflows the {} viewport, its border rect is {}",
+                node, viewport, rect
+            ),
         }
     }
 }
 
+/// Where an error points in the source. Zero based, like the lexer's spans
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SourceLocation {
+    pub line: u32,
+    pub column: u32,
+    pub length: u32,
+}
+
 pub struct DocumentError {
     error_kind: ErrorKind,
     error_type: DocumentErrorType,
+
+    /// The location and the text of the line it is on
+    source: Option<(SourceLocation, String)>,
 }
 
 impl DocumentError {
@@ -30,12 +80,70 @@ impl DocumentError {
         DocumentError {
             error_kind: kind,
             error_type: ty,
+            source: None,
         }
     }
 
+    /// Points the error at `location` in `source`, so it renders with the offending line
+    pub fn at(mut self, location: SourceLocation, source: &str) -> DocumentError {
+        let line = source
+            .lines()
+            .nth(location.line as usize)
+            .unwrap_or_default();
+        self.source = Some((location, line.to_string()));
+        self
+    }
+
     pub fn get_message(&self) -> String {
         self.error_type.get_message()
     }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.error_kind
+    }
+
+    pub fn location(&self) -> Option<SourceLocation> {
+        self.source.as_ref().map(|(location, _)| *location)
+    }
+
+    /// The offending line with the location underlined, like rustc does
+    ///
+    /// ```text
+    ///   --> 3:14
+    ///   |
+    /// 3 |     padding: Red
+    ///   |              ^^^
+    /// ```
+    pub fn snippet(&self) -> Option<String> {
+        let (location, line) = self.source.as_ref()?;
+
+        let line_num = (location.line + 1).to_string();
+        let gutter = " ".repeat(line_num.len());
+        let column = line.chars().take(location.column as usize).count();
+
+        Some(format!(
+            "{} --> {}:{}\n{} |\n{} | {}\n{} | {}{}",
+            gutter,
+            location.line + 1,
+            location.column + 1,
+            gutter,
+            line_num,
+            line,
+            gutter,
+            " ".repeat(column),
+            "^".repeat(location.length.max(1) as usize),
+        ))
+    }
+}
+
+/// Orders errors by line then column and drops repeats of the same message at the same spot.
+/// Errors without a location go last, in the order they were reported
+pub fn sort_and_dedup(errors: &mut Vec<DocumentError>) {
+    errors.sort_by_cached_key(|error| match error.location() {
+        Some(location) => (0, location.line, location.column, error.get_message()),
+        None => (1, 0, 0, String::new()),
+    });
+    errors.dedup_by(|a, b| a.location() == b.location() && a.get_message() == b.get_message());
 }
 
 impl Display for DocumentError {
@@ -51,13 +159,72 @@ impl Display for DocumentError {
                 format!("{}{}", "Error: ".red().bold(), self.get_message().bold())
             }
         };
-        write!(f, "{}", msg)
+        write!(f, "{}", msg)?;
+
+        if let Some(snippet) = self.snippet() {
+            write!(f, "\n{}", snippet.blue())?;
+        }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::{sort_and_dedup, DocumentError, DocumentErrorType, ErrorKind, SourceLocation};
 
     #[test]
     fn it_works() {}
+
+    #[test]
+    fn snippet_underlines_the_location() {
+        let source = "style {\n    header {\n        padding: Red\n    }\n}";
+        let location = SourceLocation {
+            line: 2,
+            column: 17,
+            length: 3,
+        };
+        let error = DocumentError::new(
+            DocumentErrorType::TypeMismatch {
+                expected: "a pixel size or rect".into(),
+                found: "`Red`".into(),
+            },
+            ErrorKind::Error,
+        )
+        .at(location, source);
+
+        assert_eq!(error.location(), Some(location));
+        assert_eq!(
+            error.snippet().unwrap(),
+            "  --> 3:18\n  |\n3 |         padding: Red\n  |                  ^^^"
+        );
+    }
+
+    #[test]
+    fn errors_are_deduplicated_and_sorted_by_position() {
+        let source = "view {\n    header { }\n    footer { }\n}";
+        let at = |line, column| SourceLocation {
+            line,
+            column,
+            length: 6,
+        };
+        let duplicate = |name: &str, location| {
+            DocumentError::new(
+                DocumentErrorType::DuplicateName(name.into()),
+                ErrorKind::Error,
+            )
+            .at(location, source)
+        };
+
+        let mut errors = vec![
+            duplicate("footer", at(2, 4)),
+            duplicate("header", at(1, 4)),
+            duplicate("footer", at(2, 4)),
+            duplicate("view", at(0, 0)),
+            duplicate("header", at(1, 2)),
+        ];
+        sort_and_dedup(&mut errors);
+
+        let locations: Vec<_> = errors.iter().map(|e| e.location().unwrap()).collect();
+        assert_eq!(locations, vec![at(0, 0), at(1, 2), at(1, 4), at(2, 4)]);
+    }
 }
@@ -1,10 +1,59 @@
-use vello::{SceneBuilder, kurbo::Size};
-
-use crate::simple_text::SimpleText;
+use vello::{
+    kurbo::{Affine, Point, Size, Vec2},
+    SceneBuilder, SceneFragment,
+};
 
+use crate::{
+    input::{CursorIcon, VirtualKeyCode},
+    simple_text::SimpleText,
+};
 
 pub struct DrawingContext<'a> {
     pub builder: SceneBuilder<'a>,
     pub text: SimpleText,
-    pub size: Size
-}
\ No newline at end of file
+    pub size: Size,
+    /// Applied to everything drawn. Nodes with a `transform` compose theirs onto it for their subtree
+    pub transform: Affine,
+    /// Position of the mouse in the window, if it is inside
+    pub mouse: Option<Point>,
+    /// Keys pressed since the last frame, in the order they were pressed
+    pub keys: Vec<VirtualKeyCode>,
+    /// How far the mouse wheel moved since the last frame, in pixels.
+    /// Positive values move further down and right through the content
+    pub scroll: Vec2,
+    /// Cursor to show after this frame; `None` hides it
+    pub cursor: Option<CursorIcon>,
+}
+
+impl<'a> DrawingContext<'a> {
+    /// Draws a recorded fragment with `transform` applied to it
+    pub fn append_fragment(&mut self, fragment: &SceneFragment, transform: Affine) {
+        self.builder.append(fragment, Some(transform));
+    }
+
+    /// Records what `draw` draws into `fragment` instead of the scene, so it can be appended again
+    /// on later frames. Drawing starts from the identity transform; the transform is given when appending
+    pub fn record(&mut self, fragment: &mut SceneFragment, draw: impl FnOnce(&mut DrawingContext)) {
+        let mut recording = DrawingContext {
+            builder: SceneBuilder::for_fragment(fragment),
+            text: std::mem::replace(&mut self.text, SimpleText::new()),
+            size: self.size,
+            transform: Affine::IDENTITY,
+            mouse: self.mouse,
+            keys: self.keys.clone(),
+            scroll: self.scroll,
+            cursor: self.cursor,
+        };
+        draw(&mut recording);
+
+        let DrawingContext {
+            builder,
+            text,
+            cursor,
+            ..
+        } = recording;
+        builder.finish();
+        self.text = text;
+        self.cursor = cursor;
+    }
+}
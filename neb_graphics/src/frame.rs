@@ -0,0 +1,176 @@
+use vello::{Renderer, Result, Scene};
+
+use crate::{build_scene, drawing_context::DrawingContext, input::CursorIcon, Input};
+
+/// Where a `FrameDriver` renders to
+enum Target {
+    Surface {
+        surface: wgpu::Surface,
+        config: wgpu::SurfaceConfiguration,
+        /// Rendered but not presented yet
+        pending: Option<wgpu::SurfaceTexture>,
+    },
+    Texture {
+        texture: wgpu::Texture,
+        view: wgpu::TextureView,
+    },
+}
+
+/// Renders frames when the caller asks, for hosts that run their own event loop or step frames
+/// by hand, e.g. tests or a game engine. `start_graphics_thread` drives one from winit.
+///
+/// A frame is drawn with `render` and shown with `present`
+pub struct FrameDriver {
+    renderer: Renderer,
+    scene: Scene,
+    target: Target,
+    width: u32,
+    height: u32,
+}
+
+impl FrameDriver {
+    /// Renders to a window's surface, which is configured with `config`
+    pub fn for_surface(
+        device: &wgpu::Device,
+        surface: wgpu::Surface,
+        config: wgpu::SurfaceConfiguration,
+    ) -> Result<FrameDriver> {
+        surface.configure(device, &config);
+
+        Ok(FrameDriver {
+            renderer: Renderer::new(device)?,
+            scene: Scene::default(),
+            width: config.width,
+            height: config.height,
+            target: Target::Surface {
+                surface,
+                config,
+                pending: None,
+            },
+        })
+    }
+
+    /// Renders to an offscreen `Rgba8Unorm` texture, see `texture`
+    pub fn headless(device: &wgpu::Device, width: u32, height: u32) -> Result<FrameDriver> {
+        // wgpu can't make empty textures
+        let (width, height) = (width.max(1), height.max(1));
+        let (texture, view) = allocate(device, width, height);
+
+        Ok(FrameDriver {
+            renderer: Renderer::new(device)?,
+            scene: Scene::default(),
+            target: Target::Texture { texture, view },
+            width,
+            height,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The texture a headless driver renders to. `None` for a surface
+    pub fn texture(&self) -> Option<&wgpu::Texture> {
+        match &self.target {
+            Target::Texture { texture, .. } => Some(texture),
+            Target::Surface { .. } => None,
+        }
+    }
+
+    /// Renders the following frames at a new size. Does nothing if the size is the same
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        if (width, height) == (self.width, self.height) {
+            return;
+        }
+
+        match &mut self.target {
+            Target::Surface {
+                surface, config, ..
+            } => {
+                config.width = width;
+                config.height = height;
+                surface.configure(device, config);
+            }
+            Target::Texture { texture, view } => {
+                (*texture, *view) = allocate(device, width.max(1), height.max(1));
+            }
+        }
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Clears the frame, draws into it with `draw` and renders it. Returns the cursor `draw`
+    /// asked for, `None` meaning it should be hidden.
+    ///
+    /// A surface frame isn't shown until `present`. Rendering again first drops it
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        input: Input,
+        draw: impl Fn(&mut DrawingContext),
+    ) -> Result<Option<CursorIcon>> {
+        let (width, height) = (self.width, self.height);
+
+        self.scene = Scene::default();
+        let cursor = build_scene(&mut self.scene, width, height, input, draw);
+
+        match &mut self.target {
+            Target::Surface {
+                surface, pending, ..
+            } => {
+                let surface_texture = surface.get_current_texture()?;
+                self.renderer.render_to_surface(
+                    device,
+                    queue,
+                    &self.scene,
+                    &surface_texture,
+                    width,
+                    height,
+                )?;
+                *pending = Some(surface_texture);
+            }
+            Target::Texture { view, .. } => {
+                self.renderer
+                    .render_to_texture(device, queue, &self.scene, view, width, height)?;
+            }
+        }
+
+        Ok(cursor)
+    }
+
+    /// Shows the frame from the last `render` and waits for the GPU to finish it
+    pub fn present(&mut self, device: &wgpu::Device) {
+        if let Target::Surface { pending, .. } = &mut self.target {
+            if let Some(surface_texture) = pending.take() {
+                surface_texture.present();
+            }
+        }
+        device.poll(wgpu::Maintain::Wait);
+    }
+}
+
+fn allocate(device: &wgpu::Device, width: u32, height: u32) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("frame"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        // vello writes to it from a compute shader
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    (texture, view)
+}
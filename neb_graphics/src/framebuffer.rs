@@ -0,0 +1,151 @@
+use vello::{Renderer, Result, Scene};
+
+use crate::{build_scene, drawing_context::DrawingContext, Input};
+
+/// Renders frames into a CPU readable buffer for hosts that own their window.
+///
+/// Each frame is drawn into an offscreen texture, copied back and handed to a callback,
+/// which is what software composited hosts or video recording want instead of a surface
+pub struct Framebuffer {
+    renderer: Renderer,
+    scene: Scene,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    stride: u32,
+}
+
+impl Framebuffer {
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Result<Framebuffer> {
+        // wgpu can't make empty textures, a minimized window still gets a pixel
+        let (width, height) = (width.max(1), height.max(1));
+        let (texture, view, buffer, stride) = Self::allocate(device, width, height);
+
+        Ok(Framebuffer {
+            renderer: Renderer::new(device)?,
+            scene: Scene::default(),
+            texture,
+            view,
+            buffer,
+            width,
+            height,
+            stride,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Bytes between the start of two rows. Rows are padded to what wgpu can copy,
+    /// so this is usually more than `width * 4`
+    pub fn stride(&self) -> u32 {
+        self.stride
+    }
+
+    /// Reallocates the texture and readback buffer for a new size. Does nothing if the size is the same
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        let (width, height) = (width.max(1), height.max(1));
+        if (width, height) == (self.width, self.height) {
+            return;
+        }
+
+        let (texture, view, buffer, stride) = Self::allocate(device, width, height);
+        self.texture = texture;
+        self.view = view;
+        self.buffer = buffer;
+        self.width = width;
+        self.height = height;
+        self.stride = stride;
+    }
+
+    /// Draws a frame and calls `frame` with the `Rgba8` pixels, width, height and stride.
+    ///
+    /// The pixels are only borrowed for the call, copy them out to keep them
+    pub fn render(
+        &mut self,
+        draw: impl Fn(&mut DrawingContext),
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        frame: impl FnOnce(&[u8], u32, u32, u32),
+    ) -> Result<()> {
+        let (width, height) = (self.width, self.height);
+
+        self.scene = Scene::default();
+        build_scene(&mut self.scene, width, height, Input::default(), draw);
+
+        self.renderer
+            .render_to_texture(device, queue, &self.scene, &self.view, width, height)?;
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &self.buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(self.stride),
+                    rows_per_image: None,
+                },
+            },
+            extent(width, height),
+        );
+        queue.submit([encoder.finish()]);
+
+        let slice = self.buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |_| ());
+        device.poll(wgpu::Maintain::Wait);
+
+        {
+            let data = slice.get_mapped_range();
+            frame(&data, width, height, self.stride);
+        }
+        self.buffer.unmap();
+
+        Ok(())
+    }
+
+    fn allocate(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+    ) -> (wgpu::Texture, wgpu::TextureView, wgpu::Buffer, u32) {
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let stride = (width * 4 + align - 1) / align * align;
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("framebuffer"),
+            size: extent(width, height),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("framebuffer readback"),
+            size: (stride * height) as _,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        (texture, view, buffer, stride)
+    }
+}
+
+fn extent(width: u32, height: u32) -> wgpu::Extent3d {
+    wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    }
+}
@@ -0,0 +1,24 @@
+//! Types for window input. With the `native` feature these are winit's. Without it there is no
+//! window, and the few that drawing refers to are stand-ins, so nothing pulls in the windowing stack
+
+#[cfg(feature = "native")]
+pub use winit::{event::VirtualKeyCode, window::CursorIcon};
+
+#[cfg(not(feature = "native"))]
+pub use headless::*;
+
+#[cfg(not(feature = "native"))]
+mod headless {
+    /// No keys are pressed without a window
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub enum VirtualKeyCode {}
+
+    /// The cursors documents can ask for, for an embedder to show its own way
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub enum CursorIcon {
+        #[default]
+        Default,
+        Hand,
+        Text,
+    }
+}
@@ -1,40 +1,260 @@
 use drawing_context::DrawingContext;
 use simple_text::SimpleText;
-use vello::kurbo::{Affine, Rect};
+use vello::kurbo::{Affine, Point, Rect, Vec2};
 use vello::peniko::{Brush, Color, Fill};
 use vello::{kurbo::Size, Scene, SceneBuilder};
-use vello::{util::RenderContext, Renderer, Result};
+use vello::{Renderer, Result};
+
+#[cfg(feature = "native")]
+use frame::FrameDriver;
+
+use input::{CursorIcon, VirtualKeyCode};
+#[cfg(feature = "native")]
 use winit::{
-    dpi::LogicalSize,
-    event::{Event, WindowEvent},
+    dpi::{LogicalSize, PhysicalSize},
+    event::{ElementState, Event, KeyboardInput, MouseScrollDelta, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
+    window::{Window, WindowBuilder},
 };
 
 pub use vello;
+#[cfg(feature = "native")]
+pub use winit;
 
 pub mod simple_text;
 
 pub mod drawing_context;
 
-pub async fn start_graphics_thread(draw: impl Fn(&mut DrawingContext) + 'static) -> Result<()> {
-    let event_loop = EventLoop::new();
+pub mod input;
+
+pub mod framebuffer;
 
-    let window = WindowBuilder::new()
-        .with_inner_size(LogicalSize::new(1044, 800))
-        .with_resizable(true)
-        .build(&event_loop)
-        .unwrap();
+pub mod frame;
+
+/// Pixels scrolled for each line a wheel reports
+#[cfg(feature = "native")]
+const WHEEL_LINE_HEIGHT: f64 = 40.0;
+
+/// The color every frame is cleared to before drawing
+pub const BACKGROUND_COLOR: Color = Color::rgb8(30, 30, 30);
+
+/// Settings for the window's render surface
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderOptions {
+    /// Wait for the display's refresh before presenting a frame.
+    /// Turning this off lowers latency at the cost of tearing and more work per second
+    pub vsync: bool,
+    pub adapter: AdapterChoice,
+    /// A fixed inner size for the window in physical pixels, so every frame is drawn at the
+    /// same size. The window can't be resized then. `None` opens a resizable window
+    pub size: Option<(u32, u32)>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            vsync: true,
+            adapter: AdapterChoice::Auto,
+            size: None,
+        }
+    }
+}
+
+impl RenderOptions {
+    /// The present mode the surface is configured with.
+    /// The `Auto` modes fall back to a supported mode, so these never fail to configure
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        if self.vsync {
+            wgpu::PresentMode::AutoVsync
+        } else {
+            wgpu::PresentMode::AutoNoVsync
+        }
+    }
+}
+
+/// Which kind of adapter frames are rendered with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AdapterChoice {
+    /// The best GPU, or a software adapter when there is none
+    #[default]
+    Auto,
+    /// Only a GPU
+    Hardware,
+    /// Only a software adapter, which renders on the CPU. Slow, but works without a GPU
+    Software,
+}
+
+impl AdapterChoice {
+    /// Index of the adapter to render with out of adapters of `types`.
+    /// GPUs are preferred, discrete ones first, so `Auto` only falls back to software
+    pub fn pick(&self, types: impl IntoIterator<Item = wgpu::DeviceType>) -> Option<usize> {
+        let preference = |ty: wgpu::DeviceType| match ty {
+            wgpu::DeviceType::DiscreteGpu => 0,
+            wgpu::DeviceType::IntegratedGpu => 1,
+            wgpu::DeviceType::VirtualGpu => 2,
+            wgpu::DeviceType::Other => 3,
+            wgpu::DeviceType::Cpu => 4,
+        };
+
+        types
+            .into_iter()
+            .enumerate()
+            .filter(|(_, ty)| match self {
+                AdapterChoice::Auto => true,
+                AdapterChoice::Hardware => *ty != wgpu::DeviceType::Cpu,
+                AdapterChoice::Software => *ty == wgpu::DeviceType::Cpu,
+            })
+            .min_by_key(|(_, ty)| preference(*ty))
+            .map(|(index, _)| index)
+    }
+}
+
+/// A device to render with and the adapter it was made from
+#[cfg(feature = "native")]
+pub struct Gpu {
+    pub adapter: wgpu::Adapter,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+}
+
+#[cfg(feature = "native")]
+impl Gpu {
+    /// Makes a device on the adapter `choice` picks out of the ones `instance` finds.
+    /// With a `surface`, only adapters that can present to it are considered
+    pub async fn request(
+        instance: &wgpu::Instance,
+        choice: AdapterChoice,
+        surface: Option<&wgpu::Surface>,
+    ) -> Result<Gpu> {
+        let mut adapters: Vec<_> = instance
+            .enumerate_adapters(wgpu::Backends::all())
+            .filter(|adapter| match surface {
+                Some(surface) => adapter.is_surface_supported(surface),
+                None => true,
+            })
+            .collect();
+
+        let types = adapters
+            .iter()
+            .map(|adapter| adapter.get_info().device_type);
+        let Some(index) = choice.pick(types) else {
+            let missing = match choice {
+                AdapterChoice::Auto => "no adapter, not even a software one",
+                AdapterChoice::Hardware => "no GPU adapter",
+                AdapterChoice::Software => "no software adapter",
+            };
+            return Err(missing.into());
+        };
+        let adapter = adapters.swap_remove(index);
+
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    features: wgpu::Features::empty(),
+                    limits: adapter.limits(),
+                },
+                None,
+            )
+            .await?;
+
+        Ok(Gpu {
+            adapter,
+            device,
+            queue,
+        })
+    }
+
+    /// Whether frames are rendered on the CPU
+    pub fn is_software(&self) -> bool {
+        self.adapter.get_info().device_type == wgpu::DeviceType::Cpu
+    }
+}
+
+/// A device on the adapter `options` asks for and a driver rendering to `window` with it
+#[cfg(feature = "native")]
+async fn open_window_surface(
+    instance: &wgpu::Instance,
+    window: &Window,
+    options: &RenderOptions,
+) -> Result<(Gpu, FrameDriver)> {
+    let surface = unsafe { instance.create_surface(window) }?;
+    let gpu = Gpu::request(instance, options.adapter, Some(&surface)).await?;
 
-    let mut render_cx = RenderContext::new()?;
     let size = window.inner_size();
-    let mut surface = render_cx
-        .create_surface(&window, size.width, size.height)
-        .await;
-    let device_handle = &render_cx.devices[surface.dev_id];
-    let mut renderer = Renderer::new(&device_handle.device)?;
+    let config = wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format: surface_format(&surface, &gpu.adapter)?,
+        width: size.width,
+        height: size.height,
+        present_mode: options.present_mode(),
+        alpha_mode: wgpu::CompositeAlphaMode::Auto,
+        view_formats: vec![],
+    };
+    let driver = FrameDriver::for_surface(&gpu.device, surface, config)?;
 
-    let mut scene = Scene::default();
+    Ok((gpu, driver))
+}
+
+/// The format to present frames to `surface` in, out of the ones `adapter` supports for it.
+/// vello blits its output with a pipeline made for `Bgra8Unorm`, so no other format will do
+#[cfg(feature = "native")]
+fn surface_format(surface: &wgpu::Surface, adapter: &wgpu::Adapter) -> Result<wgpu::TextureFormat> {
+    surface
+        .get_capabilities(adapter)
+        .formats
+        .into_iter()
+        .find(|format| *format == wgpu::TextureFormat::Bgra8Unorm)
+        .ok_or_else(|| "the surface can't present Bgra8Unorm frames".into())
+}
+
+/// Opens a window and draws into it every frame until it's closed
+#[cfg(feature = "native")]
+pub async fn start_graphics_thread(
+    options: RenderOptions,
+    draw: impl Fn(&mut DrawingContext) + 'static,
+) -> Result<()> {
+    let event_loop = EventLoop::new();
+
+    let window = match options.size {
+        Some((width, height)) => WindowBuilder::new()
+            .with_inner_size(PhysicalSize::new(width, height))
+            .with_resizable(false),
+        None => WindowBuilder::new()
+            .with_inner_size(LogicalSize::new(1044, 800))
+            .with_resizable(true),
+    }
+    .build(&event_loop)
+    .unwrap();
+
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::util::backend_bits_from_env().unwrap_or(wgpu::Backends::all()),
+        ..Default::default()
+    });
+
+    // A driver can list an adapter it then can't make a device or compile vello's shaders on
+    let (gpu, mut driver) = match open_window_surface(&instance, &window, &options).await {
+        Ok(opened) => opened,
+        Err(error) if options.adapter != AdapterChoice::Software => {
+            log::warn!("Can't render on the adapter picked ({error}), trying a software one");
+            let options = RenderOptions {
+                adapter: AdapterChoice::Software,
+                ..options
+            };
+            open_window_surface(&instance, &window, &options).await?
+        }
+        Err(error) => return Err(error),
+    };
+    if gpu.is_software() {
+        log::warn!(
+            "Rendering in software on {}, this will be slow",
+            gpu.adapter.get_info().name
+        );
+    }
+
+    let mut mouse = None;
+    let mut keys = Vec::new();
+    let mut scroll = Vec2::ZERO;
 
     event_loop.run(move |event, _, control_flow| match event {
         Event::WindowEvent {
@@ -43,70 +263,385 @@ pub async fn start_graphics_thread(draw: impl Fn(&mut DrawingContext) + 'static)
         } if window_id == window.id() => match event {
             WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
             WindowEvent::Resized(size) => {
-                render_cx.resize_surface(&mut surface, size.width, size.height);
+                driver.resize(&gpu.device, size.width, size.height);
                 window.request_redraw();
             }
+            WindowEvent::CursorMoved { position, .. } => {
+                mouse = Some(Point::new(position.x, position.y));
+            }
+            WindowEvent::CursorLeft { .. } => mouse = None,
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => keys.push(*key),
+            // Wheels report how far the content should move, which is the opposite of the offset
+            WindowEvent::MouseWheel { delta, .. } => {
+                scroll -= match delta {
+                    MouseScrollDelta::LineDelta(x, y) => {
+                        Vec2::new(*x as f64, *y as f64) * WHEEL_LINE_HEIGHT
+                    }
+                    MouseScrollDelta::PixelDelta(position) => Vec2::new(position.x, position.y),
+                };
+            }
             _ => {}
         },
         Event::MainEventsCleared => {
             window.request_redraw();
         }
         Event::RedrawRequested(_) => {
-            let width = surface.config.width;
-            let height = surface.config.height;
-
-            let device_handle = &render_cx.devices[surface.dev_id];
-
-            let mut dctx = DrawingContext {
-                builder: SceneBuilder::for_scene(&mut scene),
-                text: SimpleText::new(),
-                size: Size::new(width as _, height as _),
+            let input = Input {
+                mouse,
+                keys: std::mem::take(&mut keys),
+                scroll: std::mem::take(&mut scroll),
             };
-
-            dctx.builder.fill(
-                Fill::NonZero,
-                Affine::IDENTITY,
-                &Brush::Solid(Color::rgb8(30, 30, 30)),
-                None,
-                &Rect {
-                    x0: 0.0,
-                    y0: 0.0,
-                    x1: width as _,
-                    y1: height as _,
-                },
-            );
-
-            // Call draw callback
-            draw(&mut dctx);
-
-            dctx.builder.finish();
-
-            let surface_texture = surface
-                .surface
-                .get_current_texture()
-                .expect("failed to get surface texture");
-
-            renderer
-                .render_to_surface(
-                    &device_handle.device,
-                    &device_handle.queue,
-                    &scene,
-                    &surface_texture,
-                    width,
-                    height,
-                )
+            let cursor = driver
+                .render(&gpu.device, &gpu.queue, input, &draw)
                 .expect("failed to render to surface");
-            
-            surface_texture.present();
-            device_handle.device.poll(wgpu::Maintain::Wait);
+            match cursor {
+                Some(icon) => {
+                    window.set_cursor_visible(true);
+                    window.set_cursor_icon(icon);
+                }
+                None => window.set_cursor_visible(false),
+            }
+
+            driver.present(&gpu.device);
         }
         _ => {}
     });
 }
 
+/// What happened in the window since the last frame
+#[derive(Default)]
+pub struct Input {
+    pub mouse: Option<Point>,
+    pub keys: Vec<VirtualKeyCode>,
+    /// How far to scroll, the opposite of how far the wheel moved the content
+    pub scroll: Vec2,
+}
+
+/// Clears the scene to the background color and records the draw callback into it.
+/// Returns the cursor the callback asked for
+fn build_scene(
+    scene: &mut Scene,
+    width: u32,
+    height: u32,
+    input: Input,
+    draw: impl Fn(&mut DrawingContext),
+) -> Option<CursorIcon> {
+    let mut dctx = DrawingContext {
+        builder: SceneBuilder::for_scene(scene),
+        text: SimpleText::new(),
+        size: Size::new(width as _, height as _),
+        transform: Affine::IDENTITY,
+        mouse: input.mouse,
+        keys: input.keys,
+        scroll: input.scroll,
+        cursor: Some(CursorIcon::Default),
+    };
+
+    dctx.builder.fill(
+        Fill::NonZero,
+        Affine::IDENTITY,
+        &Brush::Solid(BACKGROUND_COLOR),
+        None,
+        &Rect {
+            x0: 0.0,
+            y0: 0.0,
+            x1: width as _,
+            y1: height as _,
+        },
+    );
+
+    // Call draw callback
+    draw(&mut dctx);
+
+    dctx.builder.finish();
+    dctx.cursor
+}
+
+/// Renders a frame into a caller owned texture instead of a window surface.
+///
+/// This is for embedding the renderer into another wgpu application.
+/// The texture must be `Rgba8Unorm` with `STORAGE_BINDING` usage, as vello writes to it from a compute shader
+pub fn render_to_texture(
+    draw: impl Fn(&mut DrawingContext),
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture_view: &wgpu::TextureView,
+    width: u32,
+    height: u32,
+) -> Result<()> {
+    let mut scene = Scene::default();
+    build_scene(&mut scene, width, height, Input::default(), draw);
+
+    let mut renderer = Renderer::new(device)?;
+    renderer.render_to_texture(device, queue, &scene, texture_view, width, height)?;
+
+    device.poll(wgpu::Maintain::Wait);
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
+    use std::cell::RefCell;
+
+    use vello::{
+        kurbo::{Rect, Size},
+        util::RenderContext,
+    };
+
+    use crate::{
+        drawing_context::DrawingContext,
+        frame::FrameDriver,
+        framebuffer::Framebuffer,
+        input::CursorIcon,
+        render_to_texture,
+        simple_text::{fit_line, FontRef, SimpleText, TextWrap, FONT_DATA},
+        AdapterChoice, Input, RenderOptions, BACKGROUND_COLOR,
+    };
 
     #[test]
     fn it_works() {}
+
+    #[test]
+    fn render_options_present_mode() {
+        assert_eq!(
+            RenderOptions::default().present_mode(),
+            wgpu::PresentMode::AutoVsync
+        );
+        assert_eq!(
+            RenderOptions {
+                vsync: false,
+                ..Default::default()
+            }
+            .present_mode(),
+            wgpu::PresentMode::AutoNoVsync
+        );
+    }
+
+    #[test]
+    fn adapter_choice_falls_back_to_software() {
+        use wgpu::DeviceType::*;
+
+        // Without a GPU, e.g. on CI, `Auto` takes the software adapter rather than failing
+        assert_eq!(AdapterChoice::Auto.pick([Cpu]), Some(0));
+        assert_eq!(AdapterChoice::Hardware.pick([Cpu]), None);
+        assert_eq!(AdapterChoice::Auto.pick([]), None);
+
+        assert_eq!(
+            AdapterChoice::Auto.pick([Cpu, IntegratedGpu, DiscreteGpu]),
+            Some(2)
+        );
+        assert_eq!(AdapterChoice::Hardware.pick([Cpu, IntegratedGpu]), Some(1));
+        assert_eq!(AdapterChoice::Software.pick([DiscreteGpu, Cpu]), Some(1));
+    }
+
+    #[test]
+    fn ellipsis_truncates_to_box_width() {
+        const TEXT: &str = "a long line of text that will never fit in the box";
+        let bounds = Rect::new(0.0, 0.0, 100.0, 100.0);
+        let mut text = SimpleText::new();
+
+        let wrapped = text.layout(None, 16.0, TEXT, &bounds, TextWrap::Wrap);
+        let ellipsis = text.layout(None, 16.0, TEXT, &bounds, TextWrap::Ellipsis);
+        assert!(ellipsis.height() < wrapped.height());
+
+        let font = FontRef {
+            data: FONT_DATA,
+            offset: 0,
+        };
+        let (line, _) = fit_line(&font, 16.0, TEXT, bounds.width(), TextWrap::Ellipsis);
+        assert!(line.ends_with('…'));
+        assert!(line.len() < TEXT.len());
+
+        // Measured as drawn, the truncated line with its ellipsis fits the box
+        let (_, measured) = fit_line(&font, 16.0, &line, f64::INFINITY, TextWrap::NoWrap);
+        assert!(measured > 0.0 && measured <= bounds.width());
+
+        let (line, _) = fit_line(&font, 16.0, "short", bounds.width(), TextWrap::Ellipsis);
+        assert_eq!(line, "short");
+    }
+
+    #[test]
+    fn break_all_wraps_inside_long_words() {
+        let text: String = "0123456789abcdef".chars().cycle().take(200).collect();
+        let bounds = Rect::new(0.0, 0.0, 100.0, 1000.0);
+        let mut simple = SimpleText::new();
+
+        let line = simple.layout(None, 16.0, &text, &bounds, TextWrap::Wrap);
+        let broken = simple.layout(None, 16.0, &text, &bounds, TextWrap::BreakAll);
+
+        // Only spaces break lines normally, so the word stays on one line
+        assert_eq!(line.width(), bounds.width());
+        assert!(broken.width() <= bounds.width());
+
+        // Roboto Mono advances are 0.6em, ten characters to a 100px line at 16px
+        assert_eq!(broken.height(), 20.0 * line.height());
+    }
+
+    #[test]
+    fn line_breaks_start_a_new_line_at_any_width() {
+        let mut simple = SimpleText::new();
+        let line = simple.layout(
+            None,
+            16.0,
+            "line one",
+            &Rect::new(0.0, 0.0, 1000.0, 1000.0),
+            TextWrap::Wrap,
+        );
+
+        for width in [100.0, 1000.0] {
+            let bounds = Rect::new(0.0, 0.0, width, 1000.0);
+            for wrap in [TextWrap::Wrap, TextWrap::BreakAll, TextWrap::NoWrap] {
+                let broken = simple.layout(None, 16.0, "line one\nline two", &bounds, wrap);
+                assert_eq!(broken.height(), 2.0 * line.height());
+                assert_eq!(broken.width(), line.width());
+            }
+        }
+    }
+
+    #[test]
+    fn render_to_texture_clears() {
+        const SIZE: u32 = 64;
+
+        // Headless machines without an adapter can't run this
+        let Ok(mut render_cx) = RenderContext::new() else {
+            return;
+        };
+        let Some(dev_id) = pollster::block_on(render_cx.device(None)) else {
+            return;
+        };
+        let device_handle = &render_cx.devices[dev_id];
+        let (device, queue) = (&device_handle.device, &device_handle.queue);
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: SIZE,
+                height: SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        render_to_texture(|_| (), device, queue, &view, SIZE, SIZE).unwrap();
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (SIZE * SIZE * 4) as _,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(SIZE * 4),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: SIZE,
+                height: SIZE,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit([encoder.finish()]);
+
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |_| ());
+        device.poll(wgpu::Maintain::Wait);
+
+        let data = slice.get_mapped_range();
+        let c = BACKGROUND_COLOR;
+        assert_eq!(&data[..4], &[c.r, c.g, c.b, c.a]);
+    }
+
+    #[test]
+    fn framebuffer_hands_out_a_cleared_frame() {
+        let Ok(mut render_cx) = RenderContext::new() else {
+            return;
+        };
+        let Some(dev_id) = pollster::block_on(render_cx.device(None)) else {
+            return;
+        };
+        let device_handle = &render_cx.devices[dev_id];
+        let (device, queue) = (&device_handle.device, &device_handle.queue);
+
+        let mut framebuffer = Framebuffer::new(device, 32, 32).unwrap();
+        framebuffer.resize(device, 50, 20);
+
+        let mut called = false;
+        framebuffer
+            .render(
+                |_| (),
+                device,
+                queue,
+                |data, width, height, stride| {
+                    called = true;
+                    assert_eq!((width, height), (50, 20));
+                    assert!(stride >= width * 4);
+                    assert_eq!(data.len(), (stride * height) as usize);
+
+                    let c = BACKGROUND_COLOR;
+                    let last_row = ((height - 1) * stride) as usize;
+                    assert_eq!(&data[..4], &[c.r, c.g, c.b, c.a]);
+                    assert_eq!(&data[last_row..last_row + 4], &[c.r, c.g, c.b, c.a]);
+                },
+            )
+            .unwrap();
+        assert!(called);
+    }
+
+    #[test]
+    fn frame_driver_renders_frames_in_sequence() {
+        let Ok(mut render_cx) = RenderContext::new() else {
+            return;
+        };
+        let Some(dev_id) = pollster::block_on(render_cx.device(None)) else {
+            return;
+        };
+        let device_handle = &render_cx.devices[dev_id];
+        let (device, queue) = (&device_handle.device, &device_handle.queue);
+
+        let mut driver = FrameDriver::headless(device, 32, 32).unwrap();
+        let sizes = RefCell::new(Vec::new());
+        let draw = |dctx: &mut DrawingContext| sizes.borrow_mut().push(dctx.size);
+
+        let cursor = driver
+            .render(device, queue, Input::default(), draw)
+            .unwrap();
+        driver.present(device);
+        assert_eq!(cursor, Some(CursorIcon::Default));
+
+        driver.resize(device, 48, 16);
+        driver
+            .render(device, queue, Input::default(), draw)
+            .unwrap();
+        driver.present(device);
+
+        assert_eq!(
+            *sizes.borrow(),
+            [Size::new(32.0, 32.0), Size::new(48.0, 16.0)]
+        );
+        let texture = driver.texture().unwrap().size();
+        assert_eq!((texture.width, texture.height), (48, 16));
+    }
 }
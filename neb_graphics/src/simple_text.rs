@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+
 use vello::glyph::{pinot, pinot::TableProvider, GlyphContext};
 use vello::kurbo::{Affine, Rect};
 use vello::{peniko::Brush, SceneBuilder};
@@ -6,9 +8,11 @@ pub use pinot::FontRef;
 
 // This is very much a hack to get things working.
 // On Windows, can set this to "c:\\Windows\\Fonts\\seguiemj.ttf" to get color emoji
-const FONT_DATA: &[u8] =
+pub(crate) const FONT_DATA: &[u8] =
     include_bytes!("../../resources/Roboto_Mono/static/RobotoMono-Regular.ttf");
 
+const ELLIPSIS: char = '…';
+
 pub struct SimpleText {
     gcx: GlyphContext,
 }
@@ -24,6 +28,90 @@ pub enum TextAlign {
     Center,
 }
 
+/// How text that is wider than its bounds is handled
+#[derive(Debug, Clone, Copy, PartialEq, Hash)]
+pub enum TextWrap {
+    /// Break onto new lines at spaces
+    Wrap,
+    /// Break at spaces, and inside a word that is wider than a whole line
+    BreakAll,
+    /// Keep everything on one line
+    NoWrap,
+    /// Keep everything on one line, replacing what doesn't fit with an ellipsis
+    Ellipsis,
+}
+
+/// Fits `text` on a single line of `width`, truncating with an ellipsis in `TextWrap::Ellipsis` mode.
+/// Returns the text to draw and the width of the text before truncation
+pub(crate) fn fit_line<'a>(
+    font: &FontRef,
+    size: f32,
+    text: &'a str,
+    width: f64,
+    wrap: TextWrap,
+) -> (Cow<'a, str>, f64) {
+    let (Some(cmap), Some(hmtx)) = (font.cmap(), font.hmtx()) else {
+        return (text.into(), 0.0);
+    };
+
+    let upem = font.head().map(|head| head.units_per_em()).unwrap_or(1000) as f64;
+    let scale = size as f64 / upem;
+    let hmetrics = hmtx.hmetrics();
+    let default_advance = hmetrics
+        .get(hmetrics.len().saturating_sub(1))
+        .map(|h| h.advance_width)
+        .unwrap_or(0);
+
+    let advance = |ch: char| {
+        (hmetrics
+            .get(cmap.map(ch as u32).unwrap_or(0) as usize)
+            .map(|h| h.advance_width)
+            .unwrap_or(default_advance) as f64
+            * scale)
+            .ceil()
+    };
+
+    let full: f64 = text.chars().map(advance).sum();
+    if wrap != TextWrap::Ellipsis || full <= width {
+        return (text.into(), full);
+    }
+
+    // Back off characters until the ellipsis fits after them
+    let ellipsis = advance(ELLIPSIS);
+    let mut pen_x = 0.0;
+    let end = text
+        .char_indices()
+        .find_map(|(i, ch)| {
+            let next = pen_x + advance(ch);
+            if next + ellipsis > width {
+                Some(i)
+            } else {
+                pen_x = next;
+                None
+            }
+        })
+        .unwrap_or(text.len());
+
+    (format!("{}{}", &text[..end], ELLIPSIS).into(), full)
+}
+
+/// Whether `ch` goes on a new line in `TextWrap::BreakAll` mode, because the word it is in
+/// can't fit on any line and this line is already full
+fn breaks_word(
+    wrap: TextWrap,
+    ch: char,
+    word: f64,
+    pen_x: f64,
+    advance: f64,
+    bounds: &Rect,
+) -> bool {
+    wrap == TextWrap::BreakAll
+        && ch != ' '
+        && word > bounds.width()
+        && pen_x > 0.0
+        && pen_x + advance.ceil() > bounds.width()
+}
+
 impl SimpleText {
     pub fn new() -> Self {
         Self {
@@ -31,12 +119,39 @@ impl SimpleText {
         }
     }
 
-    pub fn layout(&mut self, font: Option<&FontRef>, size: f32, text: &str, bounds: &Rect) -> Rect {
+    pub fn layout(
+        &mut self,
+        font: Option<&FontRef>,
+        size: f32,
+        text: &str,
+        bounds: &Rect,
+        wrap: TextWrap,
+    ) -> Rect {
         let font = font.unwrap_or(&FontRef {
             data: FONT_DATA,
             offset: 0,
         });
 
+        // A `\n` always starts a new line, whatever the wrap mode
+        if !matches!(wrap, TextWrap::Wrap | TextWrap::BreakAll) {
+            let width = text
+                .split('\n')
+                .map(|line| fit_line(font, size, line, bounds.width(), wrap).1)
+                .fold(0.0, f64::max);
+            let height = if let Some(h) = font.hhea() {
+                let scale = size as f64
+                    / font.head().map(|head| head.units_per_em()).unwrap_or(1000) as f64;
+                h.ascender() as f64 * scale - h.descender() as f64 * scale
+                    + h.line_gap() as f64 * scale
+            } else {
+                size as f64
+            }
+            .ceil();
+            let lines = text.split('\n').count() as f64;
+
+            return Rect::new(0.0, 0.0, width.min(bounds.width()), height * lines);
+        }
+
         if let Some(cmap) = font.cmap() {
             if let Some(hmtx) = font.hmtx() {
                 let upem = font.head().map(|head| head.units_per_em()).unwrap_or(1000) as f64;
@@ -57,7 +172,7 @@ impl SimpleText {
                     .unwrap_or(0);
 
                 let mut words: Vec<_> = text
-                    .split(' ')
+                    .split([' ', '\n'])
                     .map(|f| {
                         f.chars().chain([' '].into_iter()).fold(0.0, |acc, b| {
                             acc + hmetrics
@@ -85,8 +200,17 @@ impl SimpleText {
                         .unwrap_or(default_hadvance) as f64
                         * scale;
 
+                    if ch == '\n' {
+                        pen_x = 0.0;
+                        pen_y += height;
+                        word_index += 1;
+                        continue;
+                    }
+
                     // If overflow, go to next line
-                    if pen_x + words[word_index + 1] > bounds.width() && ch == ' ' {
+                    if pen_x + words[word_index + 1] > bounds.width() && ch == ' '
+                        || breaks_word(wrap, ch, words[word_index], pen_x, advance, bounds)
+                    {
                         // if pen_x + advance > bounds.width() {
                         pen_x = 0.0;
                         pen_y += height;
@@ -118,6 +242,36 @@ impl SimpleText {
         Rect::ZERO
     }
 
+    /// The narrowest `text` can be laid out in `wrap` mode without running past its bounds: its
+    /// longest word when it wraps at spaces, its widest character when words can break too and
+    /// its widest line when it stays on one line
+    pub fn min_content_width(
+        &mut self,
+        font: Option<&FontRef>,
+        size: f32,
+        text: &str,
+        wrap: TextWrap,
+    ) -> f64 {
+        let font = font.unwrap_or(&FontRef {
+            data: FONT_DATA,
+            offset: 0,
+        });
+
+        // Measured the way `layout` places them, a character at a time on one line
+        let width = |run: &str| fit_line(font, size, run, f64::INFINITY, TextWrap::NoWrap).1;
+        match wrap {
+            TextWrap::Wrap => text.split([' ', '\n']).map(width).fold(0.0, f64::max),
+            TextWrap::BreakAll => text
+                .chars()
+                .filter(|ch| !ch.is_whitespace())
+                .map(|ch| width(ch.encode_utf8(&mut [0; 4])))
+                .fold(0.0, f64::max),
+            TextWrap::NoWrap | TextWrap::Ellipsis => {
+                text.split('\n').map(width).fold(0.0, f64::max)
+            }
+        }
+    }
+
     pub fn get_adg(&mut self, font: Option<&FontRef>, size: f32) -> (f64, f64, f64) {
         let font = font.unwrap_or(&FontRef {
             data: FONT_DATA,
@@ -147,12 +301,23 @@ impl SimpleText {
         transform: Affine,
         text: &str,
         bounds: &Rect,
+        wrap: TextWrap,
     ) {
         let font = _font.unwrap_or(&FontRef {
             data: FONT_DATA,
             offset: 0,
         });
 
+        let text = match wrap {
+            TextWrap::Wrap | TextWrap::BreakAll => Cow::Borrowed(text),
+            _ => text
+                .split('\n')
+                .map(|line| fit_line(font, size, line, bounds.width(), wrap).0)
+                .collect::<Vec<_>>()
+                .join("\n")
+                .into(),
+        };
+
         if let Some(cmap) = font.cmap() {
             if let Some(hmtx) = font.hmtx() {
                 let upem = font.head().map(|head| head.units_per_em()).unwrap_or(1000) as f64;
@@ -166,6 +331,14 @@ impl SimpleText {
                     .map(|h| h.advance_width)
                     .unwrap_or(0);
 
+                let line_height = font
+                    .hhea()
+                    .map(|vmtx| {
+                        vmtx.ascender() as f64 * scale - vmtx.descender() as f64 * scale
+                            + vmtx.line_gap() as f64
+                    })
+                    .unwrap_or(size as f64);
+
                 let mut pen_x = 0.0f64;
                 let mut pen_y = 0f64;
 
@@ -175,7 +348,7 @@ impl SimpleText {
                 // }
 
                 let mut words: Vec<_> = text
-                    .split(' ')
+                    .split([' ', '\n'])
                     .map(|f| {
                         f.chars().chain([' '].into_iter()).fold(0.0, |acc, b| {
                             acc + hmetrics
@@ -196,16 +369,21 @@ impl SimpleText {
                         .unwrap_or(default_advance) as f64
                         * scale;
 
+                    if ch == '\n' {
+                        pen_x = 0.0;
+                        pen_y += line_height;
+                        word_index += 1;
+                        continue;
+                    }
+
                     if let Some(glyph) = provider.get(gid, brush) {
-                        if pen_x + words[word_index + 1] > bounds.width() && ch == ' ' {
-                            if let Some(vmtx) = font.hhea() {
-                                let height = (vmtx.ascender() as f64 * scale
-                                    - vmtx.descender() as f64 * scale
-                                    + vmtx.line_gap() as f64);
-
-                                pen_x = 0.0;
-                                pen_y += height;
-                            }
+                        if matches!(wrap, TextWrap::Wrap | TextWrap::BreakAll)
+                            && pen_x + words[word_index + 1] > bounds.width()
+                            && ch == ' '
+                            || breaks_word(wrap, ch, words[word_index], pen_x, advance, bounds)
+                        {
+                            pen_x = 0.0;
+                            pen_y += line_height;
                         }
 
                         if ch == ' ' {
@@ -2,7 +2,7 @@ use std::fmt::Debug;
 
 use neb_util::format::{NodeDisplay, TreeDisplay};
 
-use crate::token::{Range, SpannedToken, Token, Unit};
+use crate::token::{Range, Span, SpannedToken, Token, Unit};
 
 pub trait AstNode: TreeDisplay {
     fn get_range(&self) -> Range;
@@ -65,6 +65,47 @@ macro_rules! switchon {
 //     }
 // }
 
+/// Moves every span in a node by a number of lines, so statements after an edit
+/// still point at their text
+pub trait ShiftLines {
+    fn shift_lines(&mut self, lines: i64);
+}
+
+impl ShiftLines for Span {
+    fn shift_lines(&mut self, lines: i64) {
+        self.line_num = (self.line_num as i64 + lines).max(0) as u32;
+    }
+}
+
+impl ShiftLines for SpannedToken {
+    fn shift_lines(&mut self, lines: i64) {
+        self.0.shift_lines(lines)
+    }
+}
+
+impl ShiftLines for Range {
+    fn shift_lines(&mut self, lines: i64) {
+        self.start.shift_lines(lines);
+        self.end.shift_lines(lines);
+    }
+}
+
+impl<T: ShiftLines> ShiftLines for Option<T> {
+    fn shift_lines(&mut self, lines: i64) {
+        if let Some(node) = self {
+            node.shift_lines(lines)
+        }
+    }
+}
+
+impl<T: ShiftLines> ShiftLines for Vec<T> {
+    fn shift_lines(&mut self, lines: i64) {
+        for node in self {
+            node.shift_lines(lines)
+        }
+    }
+}
+
 impl AstNode for SpannedToken {
     fn get_range(&self) -> Range {
         self.0.into()
@@ -97,11 +138,24 @@ impl<T: AstNode> PunctuationList<T> {
         self.tokens.iter().map(|(v, _)| v)
     }
 
+    pub fn iter_items_mut(&mut self) -> impl Iterator<Item = &mut T> + '_ {
+        self.tokens.iter_mut().map(|(v, _)| v)
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = &(T, Option<SpannedToken>)> + '_ {
         self.tokens.iter()
     }
 }
 
+impl<T: AstNode + ShiftLines> ShiftLines for PunctuationList<T> {
+    fn shift_lines(&mut self, lines: i64) {
+        for (node, separator) in &mut self.tokens {
+            node.shift_lines(lines);
+            separator.shift_lines(lines);
+        }
+    }
+}
+
 impl<T> NodeDisplay for PunctuationList<T>
 where
     T: NodeDisplay + AstNode,
@@ -148,13 +202,29 @@ impl AstNode for ElementArgs {
     }
 }
 
+impl ShiftLines for ElementArgs {
+    fn shift_lines(&mut self, lines: i64) {
+        self.range.shift_lines(lines);
+        self.items.shift_lines(lines);
+    }
+}
+
 impl ElementArgs {
     pub fn iter_items(&self) -> impl Iterator<Item = &Arg> + '_ {
         self.items.iter_items()
     }
 
+    /// The values of the arguments, skipping any without one. Arrays are flattened into their items
     pub fn iter_values(&self) -> impl Iterator<Item = &Value> + '_ {
-        self.items.iter_items().filter_map(|a| a.value.as_ref())
+        self.items
+            .iter_items()
+            .filter_map(|a| a.value.as_ref())
+            .flat_map(|value| -> Box<dyn Iterator<Item = &Value> + '_> {
+                match value {
+                    Value::Array { values, .. } => Box::new(values.iter_items()),
+                    value => Box::new(std::iter::once(value)),
+                }
+            })
     }
 }
 
@@ -196,6 +266,28 @@ impl AstNode for Arg {
     }
 }
 
+impl ShiftLines for Arg {
+    fn shift_lines(&mut self, lines: i64) {
+        self.name.shift_lines(lines);
+        self.colon.shift_lines(lines);
+        self.value.shift_lines(lines);
+    }
+}
+
+/// Arguments are the same if they name the same value, wherever they are in the source
+impl PartialEq for Arg {
+    fn eq(&self, other: &Self) -> bool {
+        self.name.as_ref().map(SpannedToken::tok) == other.name.as_ref().map(SpannedToken::tok)
+            && self.value == other.value
+    }
+}
+
+impl PartialEq for ElementArgs {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter_items().eq(other.iter_items())
+    }
+}
+
 impl Arg {
     pub fn name(&self) -> &String {
         match &self.name {
@@ -265,7 +357,7 @@ impl TreeDisplay for Expression {
 
 #[derive(Clone)]
 pub enum Value {
-    Integer(u64, Option<Unit>, SpannedToken),
+    Integer(i64, Option<Unit>, SpannedToken),
     Float(f64, Option<Unit>, SpannedToken),
     Ident(SpannedToken),
     Function {
@@ -277,6 +369,18 @@ pub enum Value {
         values: PunctuationList<Value>,
         range: Range,
     },
+    /// Arithmetic between two values, e.g. `100% - 20px` in `calc(...)`
+    Binary {
+        left: Box<Value>,
+        op: SpannedToken,
+        right: Box<Value>,
+    },
+    /// Key-value pairs, e.g. `{ at: 0, color: red }`
+    Object {
+        entries: Vec<(String, Value)>,
+        /// From the opening to the closing brace
+        range: Range,
+    },
 }
 
 impl Value {
@@ -291,6 +395,71 @@ impl Value {
     }
 }
 
+/// Compares what the values say, ignoring the spans of their tokens
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Integer(a, au, _), Self::Integer(b, bu, _)) => a == b && au == bu,
+            (Self::Float(a, au, _), Self::Float(b, bu, _)) => a == b && au == bu,
+            (Self::Ident(a), Self::Ident(b)) => a.tok() == b.tok(),
+            (
+                Self::Function { ident, args },
+                Self::Function {
+                    ident: other_ident,
+                    args: other_args,
+                },
+            ) => {
+                ident.as_ref().map(SpannedToken::tok) == other_ident.as_ref().map(SpannedToken::tok)
+                    && args == other_args
+            }
+            (Self::Tuple(a), Self::Tuple(b)) => a == b,
+            (Self::Array { values: a, .. }, Self::Array { values: b, .. }) => {
+                a.iter_items().eq(b.iter_items())
+            }
+            (
+                Self::Binary { left, op, right },
+                Self::Binary {
+                    left: other_left,
+                    op: other_op,
+                    right: other_right,
+                },
+            ) => left == other_left && op.tok() == other_op.tok() && right == other_right,
+            (Self::Object { entries: a, .. }, Self::Object { entries: b, .. }) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl ShiftLines for Value {
+    fn shift_lines(&mut self, lines: i64) {
+        match self {
+            Self::Integer(_, _, token) | Self::Float(_, _, token) | Self::Ident(token) => {
+                token.shift_lines(lines)
+            }
+            Self::Function { ident, args } => {
+                ident.shift_lines(lines);
+                args.shift_lines(lines);
+            }
+            Self::Tuple(values) => values.shift_lines(lines),
+            Self::Array { values, range } => {
+                values.shift_lines(lines);
+                range.shift_lines(lines);
+            }
+            Self::Binary { left, op, right } => {
+                left.shift_lines(lines);
+                op.shift_lines(lines);
+                right.shift_lines(lines);
+            }
+            Self::Object { entries, range } => {
+                for (_, value) in entries {
+                    value.shift_lines(lines);
+                }
+                range.shift_lines(lines);
+            }
+        }
+    }
+}
+
 impl AstNode for Value {
     fn get_range(&self) -> Range {
         match self {
@@ -298,7 +467,7 @@ impl AstNode for Value {
                 (Some(s), Some(e)) => Range::from((&s.get_range(), &e.get_range())),
                 _ => Range::default(),
             },
-            Self::Array { range, .. } => range.clone(),
+            Self::Array { range, .. } => *range,
             Self::Integer(_, _, s) => s.0.into(),
             Self::Float(_, _, s) => s.0.into(),
             Self::Ident(s) => s.0.into(),
@@ -307,6 +476,10 @@ impl AstNode for Value {
                 ident: Some(ident),
                 args,
             } => Range::from((ident, &args.get_range())),
+            Self::Binary { left, right, .. } => {
+                Range::from((&left.get_range(), &right.get_range()))
+            }
+            Self::Object { range, .. } => *range,
         }
     }
 }
@@ -325,6 +498,12 @@ impl NodeDisplay for Value {
             } => write!(f, "Function {}", i),
             Self::Function { ident: None, .. } => write!(f, "Function"),
             Self::Array { .. } => f.write_str("Array"),
+            Self::Tuple(_) => f.write_str("Tuple"),
+            Self::Object { .. } => f.write_str("Object"),
+            Self::Binary {
+                op: SpannedToken(_, Token::Operator(op)),
+                ..
+            } => write!(f, "Binary {}", op.as_str()),
             _ => panic!(),
         }
     }
@@ -341,6 +520,9 @@ impl TreeDisplay for Value {
         match self {
             Self::Function { .. } => 1,
             Self::Array { values, .. } => values.num_children(),
+            Self::Tuple(values) => values.len(),
+            Self::Binary { .. } => 2,
+            Self::Object { entries, .. } => entries.len(),
             _ => 0,
         }
     }
@@ -349,6 +531,12 @@ impl TreeDisplay for Value {
         match self {
             Self::Function { args, .. } => Some(args),
             Self::Array { values, .. } => values.child_at(index),
+            Self::Tuple(values) => Some(&values[index]),
+            Self::Binary { left, right, .. } => match index {
+                0 => Some(left.as_ref()),
+                _ => Some(right.as_ref()),
+            },
+            Self::Object { entries, .. } => Some(&entries[index].1),
             _ => None,
         }
     }
@@ -360,15 +548,23 @@ pub enum StyleStatement {
         key: Option<SpannedToken>,
         colon: Option<SpannedToken>,
         value: Option<Value>,
+        /// The `!` of `!important`
+        bang: Option<SpannedToken>,
+        /// `important` after the `!`, which can be left out
+        important: Option<SpannedToken>,
     },
     Style {
         body: Vec<StyleStatement>,
         body_range: Option<Range>,
         token: Option<SpannedToken>,
+        /// Viewport condition for a variant rule, e.g. `foo@(maxWidth: 600px)`
+        condition: Option<ElementArgs>,
     },
 }
 
 impl StyleStatement {
+    /// The `(key, value)` pairs directly in a style block. Elements missing a key or a value are
+    /// skipped, and a style element on its own yields nothing
     pub fn style_elements(&self) -> impl Iterator<Item = (String, Value)> + '_ {
         let cls = |stmt: &StyleStatement| match stmt {
             StyleStatement::StyleElement {
@@ -384,6 +580,54 @@ impl StyleStatement {
             _ => [].iter().filter_map(cls),
         }
     }
+
+    /// Keys of the style elements marked `!important`
+    pub fn important_keys(&self) -> impl Iterator<Item = String> + '_ {
+        let cls = |stmt: &StyleStatement| match stmt {
+            StyleStatement::StyleElement {
+                key: Some(SpannedToken(_, Token::Ident(key))),
+                bang: Some(_),
+                ..
+            } => Some(key.clone()),
+            _ => None,
+        };
+
+        match self {
+            StyleStatement::Style { body, .. } => body.iter().filter_map(cls),
+            _ => [].iter().filter_map(cls),
+        }
+    }
+}
+
+impl ShiftLines for StyleStatement {
+    fn shift_lines(&mut self, lines: i64) {
+        match self {
+            Self::StyleElement {
+                key,
+                colon,
+                value,
+                bang,
+                important,
+            } => {
+                key.shift_lines(lines);
+                colon.shift_lines(lines);
+                value.shift_lines(lines);
+                bang.shift_lines(lines);
+                important.shift_lines(lines);
+            }
+            Self::Style {
+                body,
+                body_range,
+                token,
+                condition,
+            } => {
+                body.shift_lines(lines);
+                body_range.shift_lines(lines);
+                token.shift_lines(lines);
+                condition.shift_lines(lines);
+            }
+        }
+    }
 }
 
 impl AstNode for StyleStatement {
@@ -403,7 +647,7 @@ impl AstNode for StyleStatement {
                 body_range: Some(body_range),
                 token: None,
                 ..
-            } => body_range.clone(),
+            } => *body_range,
             _ => Range::default(),
         }
     }
@@ -419,35 +663,32 @@ impl TreeDisplay for StyleStatement {
     fn num_children(&self) -> usize {
         match self {
             Self::StyleElement {
-                key,
-                colon: _,
-                value,
-            } => addup!(key, value),
+                key, value, bang, ..
+            } => addup!(key, value, bang),
             Self::Style {
                 body_range,
                 token,
                 body,
-            } => addup!(body_range, token) + body.len(),
+                condition,
+            } => addup!(body_range, token, condition) + body.len(),
         }
     }
 
     fn child_at(&self, index: usize) -> Option<&dyn TreeDisplay> {
         match self {
             Self::StyleElement {
-                key,
-                colon: _,
-                value,
+                key, value, bang, ..
             } => {
-                switchon!(index, key, value);
+                switchon!(index, key, value, bang);
                 None
             }
             Self::Style {
                 body,
                 body_range,
                 token,
-                ..
+                condition,
             } => {
-                let ind = switchon!(index, token, body_range);
+                let ind = switchon!(index, token, condition, body_range);
                 Some(&body[index - ind])
             }
         }
@@ -474,6 +715,38 @@ pub enum Statement {
     Text(SpannedToken),
 }
 
+impl ShiftLines for Statement {
+    fn shift_lines(&mut self, lines: i64) {
+        match self {
+            Self::UseStatement { token, args } => {
+                token.shift_lines(lines);
+                args.shift_lines(lines);
+            }
+            Self::Element {
+                arguments,
+                body,
+                body_range,
+                token,
+            } => {
+                arguments.shift_lines(lines);
+                body.shift_lines(lines);
+                body_range.shift_lines(lines);
+                token.shift_lines(lines);
+            }
+            Self::Style {
+                body,
+                body_range,
+                token,
+            } => {
+                body.shift_lines(lines);
+                body_range.shift_lines(lines);
+                token.shift_lines(lines);
+            }
+            Self::Text(token) => token.shift_lines(lines),
+        }
+    }
+}
+
 impl AstNode for Statement {
     fn get_range(&self) -> Range {
         match self {
@@ -494,7 +767,7 @@ impl AstNode for Statement {
                 arguments: None,
                 token: None,
                 ..
-            } => body_range.clone(),
+            } => *body_range,
             Self::Style {
                 body_range: Some(body_range),
                 token: Some(token),
@@ -509,7 +782,16 @@ impl AstNode for Statement {
                 body_range: Some(body_range),
                 token: None,
                 ..
-            } => body_range.clone(),
+            } => *body_range,
+            Self::UseStatement {
+                token: Some(token),
+                args,
+            } => match args.iter().last() {
+                Some((_, Some(separator))) => Range::from((token.0, separator.0)),
+                Some((segment, None)) => Range::from((token.0, segment.0)),
+                None => Range::from(token.0),
+            },
+            Self::Text(token) => token.get_range(),
             _ => Range::default(),
         }
     }
@@ -582,6 +864,13 @@ impl TreeDisplay for Statement {
                 0 => Box::new(format!("Name: `{}`", name)),
                 _ => panic!(),
             },
+            Self::Element {
+                token: Some(SpannedToken(_, Token::Keyword(keyword))),
+                ..
+            } => match index {
+                0 => Box::new(format!("Name: `{}`", keyword.as_str())),
+                _ => panic!(),
+            },
             _ => panic!(),
         }
     }
@@ -0,0 +1,195 @@
+use crate::{
+    ast::{AstNode, Value},
+    token::{Range, SpannedToken, Token},
+};
+
+/// A color written in a module, with channels from 0 to 255
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+/// Colors that can be written by name, e.g. `color: red`
+const NAMED: [(&str, Color); 9] = [
+    ("black", Color::rgb(0, 0, 0)),
+    ("white", Color::rgb(255, 255, 255)),
+    ("red", Color::rgb(255, 0, 0)),
+    ("green", Color::rgb(0, 128, 0)),
+    ("blue", Color::rgb(0, 0, 255)),
+    ("yellow", Color::rgb(255, 255, 0)),
+    ("gray", Color::rgb(128, 128, 128)),
+    ("orange", Color::rgb(255, 165, 0)),
+    ("transparent", Color::rgba(0, 0, 0, 0)),
+];
+
+impl Color {
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color { r, g, b, a: 255 }
+    }
+
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+
+    pub fn named(name: &str) -> Option<Color> {
+        NAMED
+            .iter()
+            .find(|(named, _)| *named == name)
+            .map(|(_, color)| *color)
+    }
+
+    /// `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`. Hex colors are quoted in a module,
+    /// since `#` isn't part of an identifier
+    pub fn from_hex(hex: &str) -> Option<Color> {
+        let digits = hex.strip_prefix('#')?;
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        let channel = |i: usize, width: usize| {
+            let value = u8::from_str_radix(&digits[i * width..(i + 1) * width], 16).ok()?;
+            // A single digit stands for itself repeated, `f` is `ff`
+            Some(if width == 1 { value * 17 } else { value })
+        };
+
+        match digits.len() {
+            3 => Some(Color::rgb(channel(0, 1)?, channel(1, 1)?, channel(2, 1)?)),
+            4 => Some(Color::rgba(
+                channel(0, 1)?,
+                channel(1, 1)?,
+                channel(2, 1)?,
+                channel(3, 1)?,
+            )),
+            6 => Some(Color::rgb(channel(0, 2)?, channel(1, 2)?, channel(2, 2)?)),
+            8 => Some(Color::rgba(
+                channel(0, 2)?,
+                channel(1, 2)?,
+                channel(2, 2)?,
+                channel(3, 2)?,
+            )),
+            _ => None,
+        }
+    }
+
+    /// Three or four channels. Integer channels are 0 to 255 and float channels 0.0 to 1.0,
+    /// so `rgba(255, 0, 0, 0.5)` is half transparent red
+    pub fn from_channels<'a>(iter: impl Iterator<Item = &'a Value>) -> Result<Color, ColorError> {
+        let channels = iter
+            .map(channel)
+            .collect::<Option<Vec<_>>>()
+            .ok_or(ColorError::InvalidArguments)?;
+
+        let (color, clamped) = match channels.as_slice() {
+            [(r, rc), (g, gc), (b, bc)] => (Color::rgb(*r, *g, *b), *rc || *gc || *bc),
+            [(r, rc), (g, gc), (b, bc), (a, ac)] => {
+                (Color::rgba(*r, *g, *b, *a), *rc || *gc || *bc || *ac)
+            }
+            _ => return Err(ColorError::InvalidArguments),
+        };
+
+        if clamped {
+            Err(ColorError::OutOfRange(color))
+        } else {
+            Ok(color)
+        }
+    }
+
+    /// The color `value` spells out: `rgb` with three channels, `rgba` with four, the channel
+    /// tuple those functions evaluate to in the symbol tree, a hex string or a name.
+    ///
+    /// This is the one place colors are parsed, the renderer, the validator and tooling all
+    /// go through it
+    pub fn from_value(value: &Value) -> Result<Color, ColorError> {
+        match value {
+            Value::Ident(SpannedToken(_, Token::Ident(ident))) => Color::from_hex(ident)
+                .or_else(|| Color::named(ident))
+                .ok_or(ColorError::InvalidArguments),
+            Value::Tuple(channels) => Color::from_channels(channels.iter()),
+            value => match value.as_function() {
+                Some(("rgb", args)) if args.iter_values().count() == 3 => {
+                    Color::from_channels(args.iter_values())
+                }
+                Some(("rgba", args)) if args.iter_values().count() == 4 => {
+                    Color::from_channels(args.iter_values())
+                }
+                _ => Err(ColorError::InvalidArguments),
+            },
+        }
+    }
+
+    /// The channel tuple `rgb` and `rgba` evaluate to in the symbol tree
+    pub fn to_value(&self) -> Value {
+        Value::Tuple(
+            [self.r, self.g, self.b, self.a]
+                .into_iter()
+                .map(|channel| {
+                    let token = Token::Integer(channel as i64, None);
+                    Value::Integer(channel as i64, None, SpannedToken::synthetic(token))
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Why a value isn't a color
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorError {
+    /// Not a color, the wrong number of channels or a channel that isn't a unitless number
+    InvalidArguments,
+    /// A channel was outside of 0-255 (or 0.0-1.0); carries the clamped color
+    OutOfRange(Color),
+}
+
+/// Returns the clamped channel and whether it was out of range
+fn channel(value: &Value) -> Option<(u8, bool)> {
+    match value {
+        Value::Integer(v, None, _) => Some(((*v).clamp(0, 255) as u8, !(0..=255).contains(v))),
+        Value::Float(v, None, _) => Some((
+            (v.clamp(0.0, 1.0) * 255.0).round() as u8,
+            !(0.0..=1.0).contains(v),
+        )),
+        _ => None,
+    }
+}
+
+/// Every color in `value`, including ones nested in arguments, arrays and objects
+pub(crate) fn colors_in(value: &Value, colors: &mut Vec<(Range, Color)>) {
+    // A tuple is only a color once `rgb` is evaluated, as written it's e.g. a padding
+    if !matches!(value, Value::Tuple(_)) {
+        if let Ok(color) = Color::from_value(value) {
+            colors.push((value.get_range(), color));
+            return;
+        }
+    }
+
+    match value {
+        Value::Function { args, .. } => {
+            for value in args.iter_values() {
+                colors_in(value, colors);
+            }
+        }
+        Value::Tuple(values) => {
+            for value in values {
+                colors_in(value, colors);
+            }
+        }
+        Value::Array { values, .. } => {
+            for value in values.iter_items() {
+                colors_in(value, colors);
+            }
+        }
+        Value::Binary { left, right, .. } => {
+            colors_in(left, colors);
+            colors_in(right, colors);
+        }
+        Value::Object { entries, .. } => {
+            for (_, value) in entries {
+                colors_in(value, colors);
+            }
+        }
+        Value::Integer(..) | Value::Float(..) | Value::Ident(_) => (),
+    }
+}
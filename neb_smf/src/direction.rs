@@ -0,0 +1,43 @@
+/// The way a view lays out its children, the value of `direction`. The renderer and the
+/// language server both take the names from here
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Vertical,
+    Horizontal,
+    VerticalReverse,
+    HorizontalReverse,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::Vertical,
+        Direction::Horizontal,
+        Direction::VerticalReverse,
+        Direction::HorizontalReverse,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Direction::Vertical => "Vertical",
+            Direction::Horizontal => "Horizontal",
+            Direction::VerticalReverse => "VerticalReverse",
+            Direction::HorizontalReverse => "HorizontalReverse",
+        }
+    }
+
+    /// Case insensitive, and `-` or `_` between words are ignored,
+    /// so `verticalReverse`, `vertical_reverse` and `vertical-reverse` all match
+    pub fn from_name(name: &str) -> Option<Direction> {
+        let normalized = |name: &'_ str| {
+            name.chars()
+                .filter(|c| !matches!(c, '-' | '_'))
+                .map(|c| c.to_ascii_lowercase())
+                .collect::<String>()
+        };
+        let name = normalized(name);
+
+        Direction::ALL
+            .into_iter()
+            .find(|direction| normalized(direction.name()) == name)
+    }
+}
@@ -16,6 +16,38 @@ impl Display for ParseError {
 
 impl Error for ParseError {}
 
+/// Orders errors by where they start and drops repeats of the same message at the same range,
+/// so a construct the parser revisits doesn't report twice
+pub fn sort_and_dedup(errors: &mut Vec<ParseError>) {
+    fn key(error: &ParseError) -> (u32, u32, u32, u32, String) {
+        let Range { start, end } = error.range;
+        (
+            start.line_num,
+            start.position,
+            end.line_num,
+            end.position,
+            error.kind.to_string(),
+        )
+    }
+
+    errors.sort_by_cached_key(key);
+    errors.dedup_by(|a, b| key(a) == key(b));
+}
+
+/// Returned when the parser gives up on the whole input
+#[derive(Debug, Clone)]
+pub struct ParseFatal {
+    pub errors: Vec<ParseError>,
+}
+
+impl Display for ParseFatal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unable to parse module ({} errors)", self.errors.len())
+    }
+}
+
+impl Error for ParseFatal {}
+
 #[derive(Debug, Clone)]
 pub enum ParseErrorKind {
     InvalidSyntax(String),
@@ -28,3 +60,18 @@ impl Display for ParseErrorKind {
         }
     }
 }
+
+/// Why a value couldn't be evaluated, with where the offending part is written
+#[derive(Debug, Clone)]
+pub struct EvaluationError {
+    pub message: String,
+    pub range: Range,
+}
+
+impl Display for EvaluationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for EvaluationError {}
@@ -0,0 +1,293 @@
+//! Resolves style values to what they stand for, for tooling that wants to show the final value
+
+use neb_util::Rf;
+
+use crate::{
+    ast::{AstNode, Value},
+    color::Color,
+    error::EvaluationError,
+    token::{Operator, SpannedToken, Token, Unit},
+    Module, Symbol, SymbolKind, Type,
+};
+
+/// A style value with its functions called, constants replaced and numbers made floats
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvaluatedValue {
+    Number(f64, Option<Unit>),
+    Color(Color),
+    /// Left, top, right and bottom, the order `rect` takes them in
+    Rect([(f64, Option<Unit>); 4]),
+    /// A keyword like `Center` that isn't a constant or a color
+    Ident(String),
+    /// The items of a tuple or an array
+    List(Vec<EvaluatedValue>),
+    Object(Vec<(String, EvaluatedValue)>),
+}
+
+impl Module {
+    /// Evaluates `value` as written in the `scope` symbol, e.g. the style it's a property of.
+    ///
+    /// This takes what the renderer draws and nothing more. Constants are replaced with
+    /// `substitute_constants`, which the document does too, colors are read with
+    /// `Color::from_value`, and functions are looked up from `scope` like any other symbol and
+    /// called. Arithmetic is only folded inside a function like `calc(...)`, and only when it
+    /// doesn't depend on layout, `calc(100% - 20px)` is an error
+    pub fn evaluate_value(
+        &self,
+        value: &Value,
+        scope: &Rf<Symbol>,
+    ) -> Result<EvaluatedValue, EvaluationError> {
+        let value = substitute_constants(value, scope)?;
+        self.impl_evaluate_value(&value, scope, false)
+    }
+
+    fn impl_evaluate_value(
+        &self,
+        value: &Value,
+        scope: &Rf<Symbol>,
+        in_function: bool,
+    ) -> Result<EvaluatedValue, EvaluationError> {
+        let error = |message: String| EvaluationError {
+            message,
+            range: value.get_range(),
+        };
+
+        match value {
+            Value::Integer(v, unit, _) => Ok(EvaluatedValue::Number(*v as f64, *unit)),
+            Value::Float(v, unit, _) => Ok(EvaluatedValue::Number(*v, *unit)),
+            Value::Ident(SpannedToken(_, Token::Ident(ident))) => Ok(Color::from_value(value)
+                .map(EvaluatedValue::Color)
+                .unwrap_or_else(|_| EvaluatedValue::Ident(ident.clone()))),
+            Value::Ident(_) => Err(error("Expected an identifier".to_string())),
+            Value::Function { .. } => {
+                let Some((name, args)) = value.as_function() else {
+                    return Err(error("Function has no name".to_string()));
+                };
+                let evaluated = args
+                    .iter_values()
+                    .map(|arg| self.impl_evaluate_value(arg, scope, true))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                self.call(name, args.iter_values().zip(evaluated).collect(), scope)
+                    .map_err(|message| error(format!("`{}`: {}", name, message)))
+            }
+            Value::Tuple(values) => values
+                .iter()
+                .map(|value| self.impl_evaluate_value(value, scope, in_function))
+                .collect::<Result<_, _>>()
+                .map(EvaluatedValue::List),
+            Value::Array { values, .. } => values
+                .iter_items()
+                .map(|value| self.impl_evaluate_value(value, scope, in_function))
+                .collect::<Result<_, _>>()
+                .map(EvaluatedValue::List),
+            Value::Object { entries, .. } => entries
+                .iter()
+                .map(|(key, value)| {
+                    Ok((
+                        key.clone(),
+                        self.impl_evaluate_value(value, scope, in_function)?,
+                    ))
+                })
+                .collect::<Result<_, _>>()
+                .map(EvaluatedValue::Object),
+            // The renderer only reads arithmetic in the arguments of `calc`, `min`, `max`
+            // and `clamp`
+            Value::Binary { .. } if !in_function => {
+                Err(error("Arithmetic has to be inside `calc(...)`".to_string()))
+            }
+            Value::Binary { left, op, right } => {
+                let left = self.impl_evaluate_value(left, scope, in_function)?;
+                let right = self.impl_evaluate_value(right, scope, in_function)?;
+                match op {
+                    SpannedToken(_, Token::Operator(op)) => fold_binary(op, left, right)
+                        .ok_or_else(|| error("Can't be evaluated before layout".to_string())),
+                    _ => Err(error("Expected an operator".to_string())),
+                }
+            }
+        }
+    }
+
+    /// Calls the function `name` with each argument as written and evaluated
+    fn call(
+        &self,
+        name: &str,
+        args: Vec<(&Value, EvaluatedValue)>,
+        scope: &Rf<Symbol>,
+    ) -> Result<EvaluatedValue, String> {
+        if name == "calc" {
+            return match <[_; 1]>::try_from(args) {
+                Ok([(_, number @ EvaluatedValue::Number(..))]) => Ok(number),
+                _ => Err("expected one size".to_string()),
+            };
+        }
+
+        let symbol = self
+            .resolve_symbol(scope, name)
+            .ok_or("no function with this name")?;
+        let symbol = symbol.borrow();
+        let SymbolKind::Function {
+            func, args: params, ..
+        } = &symbol.kind
+        else {
+            return Err("not a function".to_string());
+        };
+        let variadic = matches!(params.last(), Some(Type::Variadic(_)));
+        if args.len() != params.len() && !(variadic && args.len() > params.len()) {
+            let plural = if params.len() == 1 { "" } else { "s" };
+            return Err(format!(
+                "takes {}{} argument{}, found {}",
+                if variadic { "at least " } else { "" },
+                params.len(),
+                plural,
+                args.len()
+            ));
+        }
+
+        // A number is passed as written, so `rgb` can tell integer channels from float ones
+        let args = args
+            .into_iter()
+            .map(|(written, evaluated)| match (written, evaluated) {
+                (Value::Integer(..) | Value::Float(..), _) => Ok(written.clone()),
+                (_, EvaluatedValue::Number(v, unit)) => Ok(Value::Float(
+                    v,
+                    unit,
+                    SpannedToken::synthetic(Token::Float(v, unit)),
+                )),
+                _ => Err("arguments are numbers"),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let result = func(args).ok_or("arguments don't fit the function")?;
+
+        let number = |value: &Value| match value {
+            Value::Integer(v, unit, _) => Some((*v as f64, *unit)),
+            Value::Float(v, unit, _) => Some((*v, *unit)),
+            _ => None,
+        };
+        match &result {
+            Value::Tuple(_) if name == "rgb" || name == "rgba" => Color::from_value(&result)
+                .map(EvaluatedValue::Color)
+                .map_err(|_| "doesn't give a color".to_string()),
+            Value::Tuple(values) if name.starts_with("rect") => values
+                .iter()
+                .map(number)
+                .collect::<Option<Vec<_>>>()
+                .and_then(|sides| <[_; 4]>::try_from(sides).ok())
+                .map(EvaluatedValue::Rect)
+                .ok_or_else(|| "expected four sizes".to_string()),
+            value => number(value)
+                .map(|(v, unit)| EvaluatedValue::Number(v, unit))
+                .ok_or_else(|| "doesn't give a number".to_string()),
+        }
+    }
+}
+
+/// `value` with every identifier that names a constant replaced by the constant's value.
+/// A constant is a named argument of a `setup` element in scope, e.g. `accent` in
+/// `setup (accent: rgb(255, 0, 0))`, and can be defined in terms of other constants.
+///
+/// The document substitutes the style values it's built from with this, so the renderer and
+/// `Module::evaluate_value` agree on what a constant stands for
+pub fn substitute_constants(value: &Value, scope: &Rf<Symbol>) -> Result<Value, EvaluationError> {
+    let mut value = value.clone();
+    substitute(&mut value, scope, &mut Vec::new())?;
+    Ok(value)
+}
+
+fn substitute(
+    value: &mut Value,
+    scope: &Rf<Symbol>,
+    constants: &mut Vec<String>,
+) -> Result<(), EvaluationError> {
+    match value {
+        Value::Ident(SpannedToken(_, Token::Ident(ident))) => {
+            let Some(mut constant) = setup_constant(scope, ident) else {
+                return Ok(());
+            };
+            if constants.contains(ident) {
+                return Err(EvaluationError {
+                    message: format!("`{}` is defined in terms of itself", ident),
+                    range: value.get_range(),
+                });
+            }
+
+            constants.push(ident.clone());
+            substitute(&mut constant, scope, constants)?;
+            constants.pop();
+            *value = constant;
+        }
+        Value::Function { args, .. } => {
+            for arg in args.items.iter_items_mut() {
+                if let Some(value) = &mut arg.value {
+                    substitute(value, scope, constants)?;
+                }
+            }
+        }
+        Value::Tuple(values) => {
+            for value in values {
+                substitute(value, scope, constants)?;
+            }
+        }
+        Value::Array { values, .. } => {
+            for value in values.iter_items_mut() {
+                substitute(value, scope, constants)?;
+            }
+        }
+        Value::Binary { left, right, .. } => {
+            substitute(left, scope, constants)?;
+            substitute(right, scope, constants)?;
+        }
+        Value::Object { entries, .. } => {
+            for (_, value) in entries {
+                substitute(value, scope, constants)?;
+            }
+        }
+        Value::Integer(..) | Value::Float(..) | Value::Ident(_) => (),
+    }
+    Ok(())
+}
+
+/// The value of the `name` argument on the closest `setup` element around `scope`,
+/// or on one beside `scope` or any of its ancestors
+fn setup_constant(scope: &Rf<Symbol>, name: &str) -> Option<Value> {
+    let mut node = Some(scope.clone());
+    while let Some(current) = node {
+        let symbol = current.borrow();
+        let setups = (symbol.name == "setup")
+            .then(|| current.clone())
+            .into_iter()
+            .chain(symbol.children.get("setup").cloned());
+
+        for setup in setups {
+            if let SymbolKind::Node { args } = &setup.borrow().kind {
+                if let Some(value) = args.get(name) {
+                    return Some(value.clone());
+                }
+            }
+        }
+
+        node = symbol.parent.clone();
+    }
+    None
+}
+
+/// Arithmetic on numbers of the same unit, or scaling by a unitless number
+fn fold_binary(
+    op: &Operator,
+    left: EvaluatedValue,
+    right: EvaluatedValue,
+) -> Option<EvaluatedValue> {
+    let (EvaluatedValue::Number(a, a_unit), EvaluatedValue::Number(b, b_unit)) = (left, right)
+    else {
+        return None;
+    };
+
+    let (value, unit) = match (op, a_unit, b_unit) {
+        (Operator::Plus, _, _) if a_unit == b_unit => (a + b, a_unit),
+        (Operator::Minus, _, _) if a_unit == b_unit => (a - b, a_unit),
+        (Operator::Star, unit, None) | (Operator::Star, None, unit) => (a * b, unit),
+        (Operator::Slash, unit, None) if b != 0.0 => (a / b, unit),
+        _ => return None,
+    };
+    Some(EvaluatedValue::Number(value, unit))
+}
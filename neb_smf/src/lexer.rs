@@ -1,177 +1,267 @@
-use crate::token::{Operator, Span, SpannedToken, Token, Unit};
+use std::collections::VecDeque;
+
+use crate::{
+    error::{ParseError, ParseErrorKind},
+    token::{Keyword, Operator, Span, SpannedToken, Token, Unit},
+};
 
 pub struct Lexer {}
 
-impl Lexer {
-    pub fn lex(&mut self, input: &str) -> Vec<SpannedToken> {
-        let mut start_index = 0;
-        let mut end_index = 1;
+/// Lazily lexes `input`, yielding the same tokens as `Lexer::lex`
+pub fn tokens(input: &str) -> Tokens<'_> {
+    Tokens::new(input, 0, 0)
+}
 
-        let mut line_num = 0;
-        let mut position = 0;
+/// A stream of tokens over a source. A line starting with `:` can turn into a single text
+/// token once its end is reached, so tokens are yielded a line at a time.
+/// `//` starts a comment that runs to the end of the line
+pub struct Tokens<'a> {
+    lexer: Lexer,
+    input: &'a str,
+
+    // Indices are byte offsets that always sit on char boundaries, so that arbitrary
+    // (multibyte) input can be sliced without panicking
+    start_index: usize,
+    end_index: usize,
+
+    line_num: u32,
+    position: u32,
+
+    /// Tokens of the current line, held back until the line ends
+    line: Vec<SpannedToken>,
+    /// Index in `line` of the colon that may start a text token, and its byte offset
+    text_start: Option<(usize, usize)>,
+    /// Line of the last token produced
+    last_line: Option<u32>,
+    /// Number of tokens produced so far, including the held back ones
+    count: u32,
+
+    ready: VecDeque<SpannedToken>,
+    finished: bool,
+    /// Strings that aren't closed on their line
+    errors: Vec<ParseError>,
+}
 
-        let mut str_index: Option<(usize, usize)> = None;
+impl<'a> Tokens<'a> {
+    /// Lexes `input` as if it started at `line_num` and `position` of a larger source
+    pub fn new(input: &'a str, line_num: u32, position: u32) -> Self {
+        Self {
+            lexer: Lexer {},
+            input,
+            start_index: 0,
+            end_index: char_end(input, 0),
+            line_num,
+            position,
+            line: Vec::new(),
+            text_start: None,
+            last_line: None,
+            count: 0,
+            ready: VecDeque::new(),
+            finished: false,
+            errors: Vec::new(),
+        }
+    }
 
-        let mut tokens: Vec<SpannedToken> = Vec::new();
-        while start_index < input.len() && end_index <= input.len() {
-            let sub_str = &input[start_index..end_index];
-            let next = input.chars().nth(end_index);
+    /// What couldn't be lexed so far, e.g. a string without its closing quote
+    pub fn errors(&self) -> &[ParseError] {
+        &self.errors
+    }
 
-            if let Some(token) = self.try_lex(sub_str, next) {
-                match token {
-                    Token::Whitespace => position += 1,
-                    Token::Newline => {
-                        let ce = end_index - 1;
-                        if &input[start_index..end_index + 1] == "\r\n" {
-                            end_index += 1;
-                            // continue;
-                        }
-                        if let Some(indicies) = str_index {
-                            let st = &input[indicies.1 + 1..ce];
-                            if verify_text(st) {
-                                let token = SpannedToken::new(
-                                    Token::Text(st.to_string()),
-                                    Span {
-                                        line_num: tokens[indicies.0 as usize].span().line_num,
-                                        position: tokens[indicies.0 as usize].span().position,
-                                        length: st.len() as u32,
-                                        token_index: tokens.len() as u32,
-                                    },
-                                );
-
-                                tokens.truncate(indicies.0);
-
-                                tokens.push(token);
-                            }
-                        }
-
-                        line_num += 1;
-                        position = 0;
-
-                        str_index = None
-                    }
-                    Token::Ident(_) => {
-                        let token = SpannedToken::new(
-                            token,
-                            Span {
-                                line_num,
-                                position,
-                                length: (end_index - start_index) as u32,
-                                token_index: tokens.len() as u32,
-                            },
-                        );
+    fn push(&mut self, token: Token, length: u32) {
+        self.line.push(SpannedToken::new(
+            token,
+            Span {
+                line_num: self.line_num,
+                position: self.position,
+                length,
+                token_index: self.count,
+            },
+        ));
+        self.count += 1;
+        self.last_line = Some(self.line_num);
+        self.position += length;
+    }
 
-                        // if str_index.is_none()
-                        //     && tokens
-                        //         .last()
-                        //         .map(|c| c.0.line_num < token.0.line_num)
-                        //         .unwrap_or(false)
-                        // {
-                        //     str_index = Some((tokens.len(), start_index));
-                        // }
-
-                        tokens.push(token);
-                        position += (end_index - start_index) as u32;
-                    }
-                    Token::Operator(Operator::Colon) => {
-                        let token = SpannedToken::new(
-                            token,
-                            Span {
-                                line_num,
-                                position,
-                                length: (end_index - start_index) as u32,
-                                token_index: tokens.len() as u32,
-                            },
-                        );
+    /// Whether a colon came before on this line, so the next token is part of a value
+    fn in_value(&self) -> bool {
+        self.line
+            .iter()
+            .any(|token| matches!(token.tok(), Token::Operator(Operator::Colon)))
+    }
 
-                        // If the token starts at the beginning of a line
-                        if str_index.is_none()
-                            && tokens
-                                .last()
-                                .map(|c| c.0.line_num < token.0.line_num)
-                                .unwrap_or(false)
-                        {
-                            str_index = Some((tokens.len(), start_index));
-                        }
-                        tokens.push(token);
-                        position += (end_index - start_index) as u32;
-                    }
-                    Token::Integer(i, _) => {
-                        let unit = if &input[end_index..end_index + 2] == "px" {
-                            end_index += 2;
-                            Some(Unit::Pixel)
-                        } else {
-                            None
-                        };
+    /// Tries to lex one more token, moving the line to `ready` once it ends
+    fn step(&mut self) {
+        let input = self.input;
+
+        if self.start_index >= input.len() || self.end_index > input.len() {
+            self.ready.extend(self.line.drain(..));
+            self.ready.push_back(SpannedToken::new(
+                Token::Newline,
+                Span {
+                    line_num: self.line_num,
+                    position: self.position,
+                    length: 1,
+                    token_index: self.count,
+                },
+            ));
+            self.finished = true;
+            return;
+        }
 
-                        let token = SpannedToken::new(
-                            Token::Integer(i, unit),
-                            Span {
-                                line_num,
-                                position,
-                                length: (end_index - start_index) as u32,
-                                token_index: tokens.len() as u32,
-                            },
-                        );
+        // Line comments are skipped like whitespace, up to but not including the line break
+        if input[self.start_index..].starts_with("//") {
+            let rest = &input[self.start_index..];
+            let length = rest.find(['\r', '\n']).unwrap_or(rest.len());
+            self.position += length as u32;
+            self.start_index += length;
+            self.end_index = char_end(input, self.start_index);
+            return;
+        }
 
-                        tokens.push(token);
-                        position += (end_index - start_index) as u32;
-                    }
-                    Token::Float(f, _) => {
-                        let unit = if &input[end_index..end_index + 2] == "px" {
-                            end_index += 2;
-                            Some(Unit::Pixel)
-                        } else {
-                            None
-                        };
+        // A string ends with a quote on the same line. Without one the rest of the line is an
+        // error and lexing goes on with the next line, rather than looking through the rest of
+        // the file. A text line is taken as written at its end, so its quotes don't matter
+        if input[self.start_index..].starts_with('"') {
+            let rest = &input[self.start_index..];
+            let line = &rest[..rest.find(['\r', '\n']).unwrap_or(rest.len())];
+            if !line[1..].contains('"') {
+                if self.text_start.is_none() {
+                    let span = Span {
+                        line_num: self.line_num,
+                        position: self.position,
+                        length: line.len() as u32,
+                        token_index: self.count,
+                    };
+                    self.errors.push(ParseError {
+                        kind: ParseErrorKind::InvalidSyntax("Unterminated string".to_string()),
+                        range: span.into(),
+                    });
+                }
+                self.position += line.len() as u32;
+                self.start_index += line.len();
+                self.end_index = char_end(input, self.start_index);
+                return;
+            }
+        }
 
-                        let token = SpannedToken::new(
-                            Token::Float(f, unit),
-                            Span {
-                                line_num,
-                                position,
-                                length: (end_index - start_index) as u32,
-                                token_index: tokens.len() as u32,
-                            },
-                        );
+        let Some(sub_str) = input.get(self.start_index..self.end_index) else {
+            self.end_index = char_end(input, self.end_index);
+            return;
+        };
+        let next = input
+            .get(self.end_index..)
+            .and_then(|rest| rest.chars().next());
 
-                        tokens.push(token);
-                        position += (end_index - start_index) as u32;
-                    }
-                    token => {
+        let Some(token) = self.lexer.try_lex(sub_str, next) else {
+            self.end_index = char_end(input, self.end_index);
+            return;
+        };
+
+        let length = (self.end_index - self.start_index) as u32;
+        match token {
+            Token::Whitespace => self.position += 1,
+            Token::Newline => {
+                let ce = self.end_index - 1;
+                if input.get(self.start_index..self.end_index + 1) == Some("\r\n") {
+                    self.end_index += 1;
+                }
+                let text = self.text_start.and_then(|indicies| {
+                    let colon = self.line.get(indicies.0)?.span();
+                    Some((indicies.0, *colon, input.get(indicies.1 + 1..ce)?))
+                });
+                if let Some((index, colon, st)) = text {
+                    if verify_text(st) {
+                        // The span covers the text after the colon, in the UTF-16 units
+                        // editors count columns in
                         let token = SpannedToken::new(
-                            token,
+                            Token::Text(st.to_string()),
                             Span {
-                                line_num,
-                                position,
-                                length: (end_index - start_index) as u32,
-                                token_index: tokens.len() as u32,
+                                line_num: colon.line_num,
+                                position: colon.position + 1,
+                                length: st.encode_utf16().count() as u32,
+                                token_index: self.count,
                             },
                         );
 
-                        tokens.push(token);
-                        position += (end_index - start_index) as u32;
+                        self.count -= (self.line.len() - index) as u32;
+                        self.line.truncate(index);
+
+                        self.line.push(token);
+                        self.count += 1;
+                        self.last_line = Some(colon.line_num);
                     }
                 }
 
-                start_index = end_index;
-                end_index = start_index + 1;
-            } else {
-                end_index += 1;
+                self.ready.extend(self.line.drain(..));
+
+                self.line_num += 1;
+                self.position = 0;
+
+                self.text_start = None
+            }
+            Token::Operator(Operator::Colon) => {
+                // If the token starts at the beginning of a line
+                if self.text_start.is_none()
+                    && self
+                        .last_line
+                        .map(|line| line < self.line_num)
+                        .unwrap_or(false)
+                {
+                    self.text_start = Some((self.line.len(), self.start_index));
+                }
+                self.push(token, length);
+            }
+            Token::Integer(..) | Token::Float(..) => {
+                let token =
+                    lex_digit_groups(input, self.start_index, &mut self.end_index).unwrap_or(token);
+                let unit = lex_unit(input, &mut self.end_index);
+                let length = (self.end_index - self.start_index) as u32;
+                match token {
+                    Token::Float(f, _) => self.push(Token::Float(f, unit), length),
+                    Token::Integer(i, _) => self.push(Token::Integer(i, unit), length),
+                    _ => unreachable!(),
+                }
             }
+            // Words joined by dashes are one value, e.g. `direction: vertical-reverse`. Keys
+            // come before the colon, so a dash there is still a minus
+            Token::Ident(word) if self.in_value() => {
+                let word = lex_dashed_words(input, word, &mut self.end_index);
+                let length = (self.end_index - self.start_index) as u32;
+                self.push(Token::Ident(word), length)
+            }
+            token => self.push(token, length),
         }
 
-        tokens.push(SpannedToken::new(
-            Token::Newline,
-            Span {
-                line_num,
-                position,
-                length: 1,
-                token_index: tokens.len() as u32,
-            },
-        ));
+        self.start_index = self.end_index;
+        self.end_index = char_end(input, self.start_index);
+    }
+}
 
-        tokens
+impl<'a> Iterator for Tokens<'a> {
+    type Item = SpannedToken;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(token) = self.ready.pop_front() {
+                return Some(token);
+            }
+            if self.finished {
+                return None;
+            }
+            self.step();
+        }
+    }
+}
+
+impl Lexer {
+    pub fn lex(&mut self, input: &str) -> Vec<SpannedToken> {
+        self.lex_at(input, 0, 0)
+    }
+
+    /// Lexes `input` as if it started at `line_num` and `position` of a larger source,
+    /// e.g. to re-lex a single edited statement
+    pub fn lex_at(&mut self, input: &str, line_num: u32, position: u32) -> Vec<SpannedToken> {
+        Tokens::new(input, line_num, position).collect()
     }
 
     pub fn try_lex<'a>(&mut self, input: &'a str, next: Option<char>) -> Option<Token> {
@@ -187,12 +277,29 @@ impl Lexer {
                 Some(':') => return Some(Token::Operator(Operator::Colon)),
                 Some('.') => return Some(Token::Operator(Operator::Dot)),
                 Some(',') => return Some(Token::Operator(Operator::Comma)),
+                Some('@') => return Some(Token::Operator(Operator::At)),
+                Some('+') => return Some(Token::Operator(Operator::Plus)),
+                Some('-') => return Some(Token::Operator(Operator::Minus)),
+                Some('*') => return Some(Token::Operator(Operator::Star)),
+                Some('/') => return Some(Token::Operator(Operator::Slash)),
+                Some('!') => return Some(Token::Operator(Operator::Bang)),
                 Some('\r' | '\n') => return Some(Token::Newline),
                 Some(c) if c.is_whitespace() => return Some(Token::Whitespace),
                 _ => (),
             }
         }
 
+        // Quotes make an identifier out of anything on one line, e.g. a file name or a key with
+        // dashes. Keywords in quotes are identifiers too, so `"style": 1` is an ordinary key
+        if let Some(quoted) = input
+            .strip_prefix('"')
+            .and_then(|rest| rest.strip_suffix('"'))
+        {
+            if !quoted.contains('"') {
+                return Some(Token::Ident(quoted.to_string()));
+            }
+        }
+
         let del = match next.map(|c| !(c.is_numeric() || c == '.')) {
             None => true,
             Some(t) => t,
@@ -212,7 +319,7 @@ impl Lexer {
                 let val = input.parse().unwrap_or(0.0f64);
                 return Some(Token::Float(val, None));
             } else {
-                let val = input.parse().unwrap_or(0u64);
+                let val = input.parse().unwrap_or(0i64);
                 return Some(Token::Integer(val, None));
             }
         }
@@ -230,6 +337,9 @@ impl Lexer {
             .is_none()
             && del
         {
+            if let Some(keyword) = Keyword::from_ident(input) {
+                return Some(Token::Keyword(keyword));
+            }
             return Some(Token::Ident(input.to_string()));
         }
 
@@ -241,6 +351,90 @@ impl Lexer {
     }
 }
 
+/// Continues the identifier `word` ending at `end_index` over `-` and another word, as many
+/// times as they follow. A dash that isn't followed by a letter is left to be a minus, so
+/// `gap-4px` still subtracts
+fn lex_dashed_words(input: &str, mut word: String, end_index: &mut usize) -> String {
+    let is_word = |c: char| c.is_alphabetic() || c == '_';
+    // A quoted identifier ends with its quote
+    if !input[..*end_index].ends_with(is_word) {
+        return word;
+    }
+
+    while let Some(rest) = input[*end_index..].strip_prefix('-') {
+        let length = rest.find(|c| !is_word(c)).unwrap_or(rest.len());
+        if length == 0 {
+            break;
+        }
+        word.push('-');
+        word.push_str(&rest[..length]);
+        *end_index += 1 + length;
+    }
+    word
+}
+
+/// Continues a number ending at `end_index` over `_` digit separators, e.g. `1_000` or `0.000_5`.
+/// A separator has to sit between two digits, so `1_`, `1__0` and `_1` aren't one number.
+///
+/// Returns the number without the separators, or `None` if it doesn't continue with one
+fn lex_digit_groups(input: &str, start_index: usize, end_index: &mut usize) -> Option<Token> {
+    let bytes = input.as_bytes();
+    let digit_at = |index: usize| bytes.get(index).is_some_and(u8::is_ascii_digit);
+
+    if bytes.get(*end_index) != Some(&b'_') || !digit_at(*end_index + 1) {
+        return None;
+    }
+
+    let mut float = input[start_index..*end_index].contains('.');
+    loop {
+        match bytes.get(*end_index) {
+            Some(b'_') if digit_at(*end_index + 1) => *end_index += 1,
+            Some(b'.') if !float && digit_at(*end_index + 1) => {
+                float = true;
+                *end_index += 1;
+            }
+            _ => break,
+        }
+        while digit_at(*end_index) {
+            *end_index += 1;
+        }
+    }
+
+    let digits: String = input[start_index..*end_index]
+        .chars()
+        .filter(|c| *c != '_')
+        .collect();
+    Some(if float {
+        Token::Float(digits.parse().unwrap_or(0.0), None)
+    } else {
+        Token::Integer(digits.parse().unwrap_or(0), None)
+    })
+}
+
+/// Consumes the unit suffix of a number ending at `end_index`, if there is one
+fn lex_unit(input: &str, end_index: &mut usize) -> Option<Unit> {
+    if input.get(*end_index..*end_index + 2) == Some("px") {
+        *end_index += 2;
+        Some(Unit::Pixel)
+    } else if input.get(*end_index..*end_index + 1) == Some("%") {
+        *end_index += 1;
+        Some(Unit::Percent)
+    } else if input.get(*end_index..*end_index + 2) == Some("fr") {
+        *end_index += 2;
+        Some(Unit::Fraction)
+    } else {
+        None
+    }
+}
+
+/// The byte offset just past the char starting at `index`
+fn char_end(input: &str, index: usize) -> usize {
+    input
+        .get(index..)
+        .and_then(|rest| rest.chars().next())
+        .map_or(index + 1, |c| index + c.len_utf8())
+}
+
 fn verify_text(st: &str) -> bool {
     let val = st.chars().find(|c| {
         !(match c {
@@ -251,8 +445,6 @@ fn verify_text(st: &str) -> bool {
         })
     });
 
-    println!("Val: {:?}", val);
-
     val.is_none()
 }
 
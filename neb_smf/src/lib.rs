@@ -1,9 +1,9 @@
 #![feature(trait_upcasting)]
 
-use std::collections::HashMap;
+use std::collections::HashSet;
 
-use ast::{Statement, StyleStatement, Value};
-use lexer::Lexer;
+use ast::{AstNode, ElementArgs, ShiftLines, Statement, StyleStatement, Value};
+use color::{Color, ColorError};
 use linked_hash_map::LinkedHashMap;
 use log::{Log, SetLoggerError};
 use neb_util::{
@@ -13,33 +13,145 @@ use neb_util::{
 use parser::Parser;
 
 pub mod ast;
+pub mod color;
+pub mod direction;
 pub mod error;
+pub mod evaluate;
 pub mod lexer;
+#[cfg(feature = "lsp")]
 pub mod logger;
 pub mod parser;
 pub mod style_parser;
 pub mod token;
 
-use error::ParseError;
+use error::{ParseError, ParseErrorKind, ParseFatal};
 pub use pollster;
-use token::{SpannedToken, Token};
+use token::{Keyword, Operator, Range, Span, SpannedToken, Token, Unit};
 
 impl Module {
-    pub fn parse_str(input: &str) -> (Module, Vec<ParseError>) {
-        let mut lexer = Lexer {};
-        let tokens = lexer.lex(&input);
+    /// Parses a module without printing anything. Recoverable syntax errors are returned
+    /// alongside the module; `Err` means no statements could be parsed at all
+    pub fn parse(input: &str) -> Result<(Module, Vec<ParseError>), ParseFatal> {
+        Module::parse_with(input, |_| ())
+    }
+
+    /// Like `parse`, calling `on_statement` with the top level statements parsed so far as each
+    /// one is done, e.g. to show progress or start rendering a large document early
+    pub fn parse_with(
+        input: &str,
+        on_statement: impl FnMut(&[Statement]),
+    ) -> Result<(Module, Vec<ParseError>), ParseFatal> {
+        let (tokens, lex_errors) = lex(input);
+        #[cfg(feature = "trace")]
         for tok in &tokens {
             println!("{:?}", tok);
         }
 
         let parser = Parser::new(tokens);
-        let parsed = parser.parse().unwrap();
+        let parsed = parser.parse_with(on_statement);
+        let mut errors = parser.get_errors().clone();
+        errors.extend(lex_errors);
+        error::sort_and_dedup(&mut errors);
+        let Some(parsed) = parsed else {
+            return Err(ParseFatal { errors });
+        };
+        #[cfg(feature = "trace")]
         for p in &parsed {
             println!("{}", p.format());
         }
 
-        let er = parser.get_errors().clone();
+        let module = Module::from_statements(input, parsed);
+        #[cfg(feature = "trace")]
+        println!("Mods {}", module.symbol_tree.format());
+
+        Ok((module, errors))
+    }
+
+    /// Like `parse`, but a fatal error yields an empty module holding the errors that
+    /// were collected
+    pub fn parse_str(input: &str) -> (Module, Vec<ParseError>) {
+        match Module::parse(input) {
+            Ok(result) => result,
+            Err(fatal) => (Module::from_statements(input, Vec::new()), fatal.errors),
+        }
+    }
+
+    /// Re-lexes and re-parses only the top level statement at `index`, replacing its source with
+    /// `text`. Later statements are moved to their new lines and the symbol tree is rebuilt from
+    /// the statements. If `text` isn't exactly one statement the module is left unchanged.
+    ///
+    /// Panics if `index` is out of bounds
+    pub fn replace_statement(
+        &mut self,
+        index: usize,
+        text: &str,
+    ) -> Result<Vec<ParseError>, ParseFatal> {
+        let range = self.stmts[index].get_range();
+
+        let extent = byte_offset(&self.content, &range.start).zip(
+            byte_offset(&self.content, &range.end)
+                .map(|end| (end + range.end.length as usize).min(self.content.len())),
+        );
+        let Some((start, end)) = extent else {
+            return Err(ParseFatal {
+                errors: vec![ParseError {
+                    kind: ParseErrorKind::InvalidSyntax(format!(
+                        "Statement {} is not in the module's source",
+                        index
+                    )),
+                    range,
+                }],
+            });
+        };
+
+        let (tokens, lex_errors) = lex_at(text, range.start.line_num, range.start.position);
+
+        let parser = Parser::new(tokens);
+        for error in lex_errors {
+            parser.add_error(error);
+        }
+        parser.ignore_ws();
+        let statement = parser.parse_statement(false);
+        parser.ignore_ws();
+
+        if let Some(SpannedToken(span, tok)) = parser.tokens.peek_spanned() {
+            parser.add_error(ParseError {
+                kind: ParseErrorKind::InvalidSyntax(format!("Unexpected token {:?}", tok)),
+                range: Range {
+                    start: *span,
+                    end: *span,
+                },
+            });
+        }
+
+        let mut errors = parser.get_errors().clone();
+        error::sort_and_dedup(&mut errors);
+        let (Some(statement), None) = (statement, parser.tokens.peek()) else {
+            return Err(ParseFatal { errors });
+        };
+
+        let lines = text.matches('\n').count() as i64
+            - self.content[start..end].matches('\n').count() as i64;
+        for later in &mut self.stmts[index + 1..] {
+            later.shift_lines(lines);
+        }
+
+        self.content.replace_range(start..end, text);
+        self.stmts[index] = statement;
+        self.symbol_tree = Module::symbol_tree(&self.stmts);
 
+        Ok(errors)
+    }
+
+    fn from_statements(input: &str, parsed: Vec<Statement>) -> Module {
+        Module {
+            content: input.to_string(),
+            symbol_tree: Module::symbol_tree(&parsed),
+            stmts: parsed,
+        }
+    }
+
+    fn symbol_tree(parsed: &Vec<Statement>) -> Rf<Symbol> {
         let mods = Symbol::new_root();
         let md = ModuleDescender::new(mods.clone())
             .with_on_statement(|st, ud| {
@@ -47,49 +159,41 @@ impl Module {
                     Statement::Element {
                         token, arguments, ..
                     } => {
-                        let args = if let Some(args) = arguments {
-                            let vals = args.iter_items().filter_map(|arg| {
-                                if let (Some(SpannedToken(_, Token::Ident(name))), Some(value)) =
-                                    (&arg.name, &arg.value)
-                                {
-                                    Some((name.clone(), value.clone()))
-                                } else {
-                                    None
-                                }
-                            });
-                            HashMap::from_iter(vals)
-                        } else {
-                            HashMap::new()
-                        };
-                        let cd = if let Some(SpannedToken(_, Token::Ident(i))) = token {
-                            match i.as_str() {
-                                "setup" | "style" => {
-                                    Some(Symbol::insert(&ud, i, SymbolKind::Node { args }))
-                                }
-                                _ => Symbol::insert_unnamed(&ud, i, SymbolKind::Node { args }),
+                        let args = arguments.as_ref().map(named_args).unwrap_or_default();
+                        let cd = match token {
+                            Some(SpannedToken(
+                                _,
+                                Token::Keyword(k @ (Keyword::Setup | Keyword::Style)),
+                            )) => Some(Symbol::insert(&ud, k.as_str(), SymbolKind::Node { args })),
+                            Some(SpannedToken(_, Token::Keyword(k))) => {
+                                Symbol::insert_unnamed(&ud, k.as_str(), SymbolKind::Node { args })
                             }
-                        } else {
-                            Symbol::insert_unnamed(&ud, "view", SymbolKind::Node { args })
+                            Some(SpannedToken(_, Token::Ident(i))) => {
+                                Symbol::insert_unnamed(&ud, i, SymbolKind::Node { args })
+                            }
+                            _ => Symbol::insert_unnamed(&ud, "view", SymbolKind::Node { args }),
                         };
                         if let Some(cd) = cd {
+                            cd.borrow_mut().range = Some(st.get_range());
                             return (cd, ud);
                         }
                     }
                     Statement::Text(SpannedToken(_, Token::Text(i))) => {
                         let cd = Symbol::insert_unnamed(&ud, "text", SymbolKind::Text(i.clone()));
                         if let Some(cd) = cd {
+                            cd.borrow_mut().range = Some(st.get_range());
                             return (cd, ud);
                         } else {
                             return (ud.clone(), ud);
                         }
                     }
                     Statement::Style { token, .. } => {
-                        let cd = if let Some(SpannedToken(_, Token::Ident(i))) = token {
+                        let cd = if let Some(SpannedToken(_, Token::Keyword(k))) = token {
                             Symbol::insert(
                                 &ud,
-                                &i,
+                                k.as_str(),
                                 SymbolKind::Node {
-                                    args: HashMap::new(),
+                                    args: LinkedHashMap::new(),
                                 },
                             )
                         } else {
@@ -97,7 +201,7 @@ impl Module {
                                 &ud,
                                 &"view",
                                 SymbolKind::Node {
-                                    args: HashMap::new(),
+                                    args: LinkedHashMap::new(),
                                 },
                             )
                         };
@@ -113,6 +217,7 @@ impl Module {
                             .collect();
                         if let Some(res) = res {
                             let cd = Symbol::insert(&ud, &"use", SymbolKind::Use(res));
+                            cd.borrow_mut().range = Some(st.get_range());
                             return (cd, ud);
                         }
                     }
@@ -123,17 +228,61 @@ impl Module {
             .with_on_style_statement(move |st, ud| {
                 match st {
                     StyleStatement::Style {
-                        body: _,
-                        body_range: _,
                         token: Some(SpannedToken(_, Token::Ident(i))),
+                        condition,
+                        ..
                     } => {
-                        let cd = Symbol::insert(
-                            &ud,
-                            &i,
-                            SymbolKind::Style {
-                                properties: HashMap::from_iter(st.style_elements()),
-                            },
-                        );
+                        let properties = LinkedHashMap::from_iter(st.style_elements());
+                        let important = HashSet::from_iter(st.important_keys());
+                        let existing = ud.borrow().children.get(i).cloned();
+
+                        // Variants live on the base style so either may be declared first
+                        let cd = match (existing, condition) {
+                            (Some(existing), Some(condition)) => {
+                                if let SymbolKind::Style { variants, .. } =
+                                    &mut existing.borrow_mut().kind
+                                {
+                                    variants.push(StyleVariant {
+                                        condition: named_args(condition),
+                                        properties,
+                                        important,
+                                    });
+                                }
+                                existing
+                            }
+                            (None, Some(condition)) => Symbol::insert(
+                                &ud,
+                                &i,
+                                SymbolKind::Style {
+                                    properties: LinkedHashMap::new(),
+                                    important: HashSet::new(),
+                                    variants: vec![StyleVariant {
+                                        condition: named_args(condition),
+                                        properties,
+                                        important,
+                                    }],
+                                },
+                            ),
+                            (existing, None) => {
+                                let variants = existing
+                                    .and_then(|existing| match &mut existing.borrow_mut().kind {
+                                        SymbolKind::Style { variants, .. } => {
+                                            Some(std::mem::take(variants))
+                                        }
+                                        _ => None,
+                                    })
+                                    .unwrap_or_default();
+                                Symbol::insert(
+                                    &ud,
+                                    &i,
+                                    SymbolKind::Style {
+                                        properties,
+                                        important,
+                                        variants,
+                                    },
+                                )
+                            }
+                        };
                         return (cd, ud);
                     }
                     _ => (),
@@ -141,7 +290,7 @@ impl Module {
                 (ud.clone(), ud)
             });
 
-        md.descend(&parsed);
+        md.descend(parsed);
 
         {
             // let mods = mods.borrow_mut();
@@ -150,8 +299,13 @@ impl Module {
                 "rgb",
                 SymbolKind::Function {
                     args: vec![Type::Integer, Type::Integer, Type::Integer],
-                    return_type: Type::Tuple(vec![Type::Integer, Type::Integer, Type::Integer]),
-                    func: Box::new(|vals| Some(Value::Tuple(vals))),
+                    return_type: Type::Tuple(vec![
+                        Type::Integer,
+                        Type::Integer,
+                        Type::Integer,
+                        Type::Integer,
+                    ]),
+                    func: Box::new(color_channels),
                 },
             );
 
@@ -166,7 +320,7 @@ impl Module {
                         Type::Integer,
                         Type::Integer,
                     ]),
-                    func: Box::new(|vals| Some(Value::Tuple(vals))),
+                    func: Box::new(color_channels),
                 },
             );
 
@@ -228,19 +382,117 @@ impl Module {
                     }),
                 },
             );
+
+            Symbol::insert(
+                &mods,
+                "min",
+                SymbolKind::Function {
+                    args: vec![Type::Variadic(Box::new(Type::Integer))],
+                    return_type: Type::Integer,
+                    func: Box::new(|vals| pick_number(vals, |a, b| b < a)),
+                },
+            );
+
+            Symbol::insert(
+                &mods,
+                "max",
+                SymbolKind::Function {
+                    args: vec![Type::Variadic(Box::new(Type::Integer))],
+                    return_type: Type::Integer,
+                    func: Box::new(|vals| pick_number(vals, |a, b| b > a)),
+                },
+            );
+
+            Symbol::insert(
+                &mods,
+                "clamp",
+                SymbolKind::Function {
+                    args: vec![Type::Integer, Type::Integer, Type::Integer],
+                    return_type: Type::Integer,
+                    func: Box::new(|vals| {
+                        let [lo, value, hi] = <[Value; 3]>::try_from(vals).ok()?;
+                        let value = pick_number(vec![value, hi], |a, b| b < a)?;
+                        pick_number(vec![lo, value], |a, b| b > a)
+                    }),
+                },
+            );
         }
 
-        println!("Mods {}", mods.format());
+        mods
+    }
+}
+
+/// What `rgb` and `rgba` evaluate to, the four channels of the color `Color::from_value`
+/// reads from `vals`. Out of range channels are clamped, like the renderer draws them
+fn color_channels(vals: Vec<Value>) -> Option<Value> {
+    match Color::from_value(&Value::Tuple(vals)) {
+        Ok(color) | Err(ColorError::OutOfRange(color)) => Some(color.to_value()),
+        Err(ColorError::InvalidArguments) => None,
+    }
+}
+
+/// The value `replace` prefers when going over `vals` in order, for `min` and `max`. Only
+/// sizes in the same unit are compared, see `Unit::of_size`; pixels against percentages wait
+/// for layout
+fn pick_number(vals: Vec<Value>, replace: fn(f64, f64) -> bool) -> Option<Value> {
+    fn number(value: &Value) -> Option<(f64, Unit)> {
+        match value {
+            Value::Integer(v, unit, _) => Some((*v as f64, Unit::of_size(*unit))),
+            Value::Float(v, unit, _) => Some((*v, Unit::of_size(*unit))),
+            _ => None,
+        }
+    }
 
-        (
-            Module {
-                content: input.to_string(),
-                stmts: parsed,
-                symbol_tree: mods,
-            },
-            er,
-        )
+    let mut vals = vals.into_iter();
+    let mut picked = vals.next()?;
+    let (mut best, unit) = number(&picked)?;
+    for value in vals {
+        let (v, value_unit) = number(&value)?;
+        if value_unit != unit {
+            return None;
+        }
+        if replace(best, v) {
+            best = v;
+            picked = value;
+        }
     }
+    Some(picked)
+}
+
+/// Byte offset of the start of `span` in `content`
+fn byte_offset(content: &str, span: &Span) -> Option<usize> {
+    let line_start = match span.line_num {
+        0 => 0,
+        line => content.match_indices('\n').nth(line as usize - 1)?.0 + 1,
+    };
+    let offset = line_start + span.position as usize;
+
+    content.is_char_boundary(offset).then_some(offset)
+}
+
+/// Splits `input` into tokens with their spans, e.g. for a highlighter that doesn't need the
+/// statements. The errors are for what isn't a token, like a string missing its closing quote
+pub fn lex(input: &str) -> (Vec<SpannedToken>, Vec<ParseError>) {
+    lex_at(input, 0, 0)
+}
+
+/// `lex` as if `input` started at `line_num` and `position` of a larger source
+fn lex_at(input: &str, line_num: u32, position: u32) -> (Vec<SpannedToken>, Vec<ParseError>) {
+    let mut tokens = lexer::Tokens::new(input, line_num, position);
+    let lexed = tokens.by_ref().collect();
+    (lexed, tokens.errors().to_vec())
+}
+
+/// Parses `tokens` from `lex` into statements without building a symbol tree, e.g. for a
+/// formatter. The syntax errors are sorted by position; when nothing could be parsed at all there
+/// are no statements, only errors
+pub fn parse(tokens: Vec<SpannedToken>) -> (Vec<Statement>, Vec<ParseError>) {
+    let parser = Parser::new(tokens);
+    let parsed = parser.parse();
+    let mut errors = parser.get_errors().clone();
+    error::sort_and_dedup(&mut errors);
+
+    (parsed.unwrap_or_default(), errors)
 }
 
 pub fn set_logger(logger: Box<dyn Log>) -> Result<(), SetLoggerError> {
@@ -261,13 +513,55 @@ impl Module {
             .collect()
     }
 
+    /// Every color written in the module's styles with where it's written, in source order.
+    /// For color pickers and palettes, see `Color::from_value` for what counts as a color
+    pub fn colors(&self) -> Vec<(Range, Color)> {
+        ModuleDescender::new(Vec::new())
+            .with_on_value(|_, value, mut colors| {
+                color::colors_in(value, &mut colors);
+                colors
+            })
+            .descend(&self.stmts)
+    }
+
+    /// Grafts the top level symbols of `other` under a `namespace` symbol in this module's
+    /// symbol tree, so `use namespace.setup.style` finds styles written in another file.
+    ///
+    /// Merging into a namespace that's already there adds to it. Unnamed elements are numbered
+    /// after the ones already there, elements with the same name have their children merged,
+    /// and any other symbol with a name that's taken replaces the old one, like a later
+    /// declaration in the same file would. Only the symbol tree is merged, so the ranges of the
+    /// grafted symbols point into `other`'s source and `replace_statement` drops them again
+    pub fn merge(&mut self, other: Module, namespace: &str) {
+        let existing = self.symbol_tree.borrow().children.get(namespace).cloned();
+        let target = match existing {
+            Some(existing) if matches!(existing.borrow().kind, SymbolKind::Node { .. }) => existing,
+            _ => Symbol::insert(
+                &self.symbol_tree,
+                namespace,
+                SymbolKind::Node {
+                    args: LinkedHashMap::new(),
+                },
+            ),
+        };
+
+        let children = std::mem::take(&mut other.symbol_tree.borrow_mut().children);
+        for (key, child) in children {
+            // Every module has its own copy of the built in functions
+            if matches!(child.borrow().kind, SymbolKind::Function { .. }) {
+                continue;
+            }
+            Symbol::graft(&target, key, child);
+        }
+    }
+
     pub fn resolve_symbol_in_scope<'a>(
         &self,
         symbol: &str,
         scope: impl Iterator<Item = &'a String>,
     ) -> Option<Rf<Symbol>> {
         let Some(sym) = self.resolve_symbol_chain_string(scope) else {
-            return None
+            return None;
         };
         self.impl_resolve_symbol_in_scope(symbol, &sym)
     }
@@ -301,8 +595,8 @@ impl Module {
             Some(node)
         } else {
             let Some(parent) = ({ &node.borrow().parent }) else {
-                    return None
-                };
+                return None;
+            };
 
             self.resolve_symbol(parent, symbol_name)
         }
@@ -350,6 +644,71 @@ impl Module {
             .ok()
     }
 
+    /// Visits every symbol below the root in tree order. The path holds the child keys
+    /// leading to the symbol and can be resolved with `resolve_symbol_chain_string`
+    pub fn walk_symbols<F: FnMut(&[String], &Symbol)>(&self, mut f: F) {
+        self.impl_walk_symbols(&self.symbol_tree, &mut Vec::new(), &mut |path, symbol| {
+            f(path, &symbol.borrow())
+        });
+    }
+
+    /// Every symbol below the root in tree order, paired with its path
+    pub fn symbols(&self) -> impl Iterator<Item = (Vec<String>, Rf<Symbol>)> {
+        let mut symbols = Vec::new();
+        self.impl_walk_symbols(&self.symbol_tree, &mut Vec::new(), &mut |path, symbol| {
+            symbols.push((path.to_vec(), symbol.clone()))
+        });
+        symbols.into_iter()
+    }
+
+    fn impl_walk_symbols<F: FnMut(&[String], &Rf<Symbol>)>(
+        &self,
+        last: &Rf<Symbol>,
+        path: &mut Vec<String>,
+        f: &mut F,
+    ) {
+        for (key, child) in &last.borrow().children {
+            path.push(key.clone());
+            f(path, child);
+            self.impl_walk_symbols(child, path, f);
+            path.pop();
+        }
+    }
+
+    /// The token under `line` and `column`, and what it is part of. A position just past the end
+    /// of a token still finds it, so a cursor at the end of a word works.
+    /// Punctuation like colons and brackets isn't returned
+    pub fn token_at(&self, line: u32, column: u32) -> Option<(&SpannedToken, TokenContext)> {
+        self.stmts
+            .iter()
+            .find_map(|stmt| statement_token_at(stmt, line, column))
+    }
+
+    /// What a token at `line` and `column` is part of, or would be if one was typed there, e.g.
+    /// a `StyleValue` right after a colon with no value yet. Outside of every statement a new
+    /// top level element fits, so that's an `Element`
+    pub fn context_at(&self, line: u32, column: u32) -> Option<TokenContext> {
+        if let Some((_, context)) = self.token_at(line, column) {
+            return Some(context);
+        }
+
+        let at = Span {
+            line_num: line,
+            position: column,
+            ..Default::default()
+        };
+        // The next segment of a `use` path is typed past the end of the statement
+        let stmt = self.stmts.iter().find(|stmt| {
+            let range = stmt.get_range();
+            range.contains(&at)
+                || matches!(stmt, Statement::UseStatement { .. }) && range.end.before(&at)
+        });
+        match stmt {
+            Some(stmt) => statement_context_at(stmt, &at),
+            None => Some(TokenContext::Element),
+        }
+    }
+
     pub fn iter_symbol<'a, F: FnMut(&SpannedToken, &Rf<Symbol>)>(
         &self,
         iter: impl Iterator<Item = &'a SpannedToken>,
@@ -411,12 +770,274 @@ impl Module {
     }
 }
 
+/// What a token found by `Module::token_at` is part of
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenContext {
+    /// The keyword or name that starts an element, e.g. `view` or `setup`
+    Element,
+    /// The `style` keyword
+    StyleBlock,
+    /// Name of a rule in a style block
+    StyleName,
+    /// A property name in a style rule
+    StyleKey,
+    /// Anywhere in the value of a style property, including function names and their arguments
+    StyleValue {
+        key: String,
+    },
+    /// Name of an element argument or a variant condition, e.g. `class` in `view (class: a)`
+    ArgName,
+    /// Anywhere in the value of an argument. Positional arguments have no name
+    ArgValue {
+        name: Option<String>,
+    },
+    /// The `use` keyword
+    Use,
+    /// A segment of a `use` path, counting from 0
+    UsePath {
+        index: usize,
+    },
+    Text,
+}
+
+fn token_contains(token: &SpannedToken, line: u32, column: u32) -> bool {
+    let span = token.span();
+    !span.is_synthetic()
+        && span.line_num == line
+        && (span.position..=span.position + span.length).contains(&column)
+}
+
+fn found(
+    token: Option<&SpannedToken>,
+    line: u32,
+    column: u32,
+    context: impl FnOnce() -> TokenContext,
+) -> Option<(&SpannedToken, TokenContext)> {
+    token
+        .filter(|token| token_contains(token, line, column))
+        .map(|token| (token, context()))
+}
+
+fn statement_token_at(
+    stmt: &Statement,
+    line: u32,
+    column: u32,
+) -> Option<(&SpannedToken, TokenContext)> {
+    let range = stmt.get_range();
+    if line < range.start.line_num || line > range.end.line_num {
+        return None;
+    }
+
+    match stmt {
+        Statement::UseStatement { token, args } => {
+            found(token.as_ref(), line, column, || TokenContext::Use).or_else(|| {
+                args.iter_items().enumerate().find_map(|(index, segment)| {
+                    found(Some(segment), line, column, || TokenContext::UsePath {
+                        index,
+                    })
+                })
+            })
+        }
+        Statement::Element {
+            arguments,
+            body,
+            token,
+            ..
+        } => found(token.as_ref(), line, column, || TokenContext::Element)
+            .or_else(|| {
+                arguments
+                    .as_ref()
+                    .and_then(|args| args_token_at(args, line, column))
+            })
+            .or_else(|| {
+                body.iter()
+                    .find_map(|stmt| statement_token_at(stmt, line, column))
+            }),
+        Statement::Style { body, token, .. } => {
+            found(token.as_ref(), line, column, || TokenContext::StyleBlock).or_else(|| {
+                body.iter()
+                    .find_map(|stmt| style_token_at(stmt, line, column))
+            })
+        }
+        Statement::Text(token) => found(Some(token), line, column, || TokenContext::Text),
+    }
+}
+
+fn style_token_at(
+    stmt: &StyleStatement,
+    line: u32,
+    column: u32,
+) -> Option<(&SpannedToken, TokenContext)> {
+    match stmt {
+        StyleStatement::StyleElement { key, value, .. } => {
+            found(key.as_ref(), line, column, || TokenContext::StyleKey).or_else(|| {
+                let key = match key {
+                    Some(SpannedToken(_, Token::Ident(key))) => key.clone(),
+                    _ => String::new(),
+                };
+                value.as_ref().and_then(|value| {
+                    value_token_at(value, line, column, &|| TokenContext::StyleValue {
+                        key: key.clone(),
+                    })
+                })
+            })
+        }
+        StyleStatement::Style {
+            body,
+            token,
+            condition,
+            ..
+        } => found(token.as_ref(), line, column, || TokenContext::StyleName)
+            .or_else(|| {
+                condition
+                    .as_ref()
+                    .and_then(|args| args_token_at(args, line, column))
+            })
+            .or_else(|| {
+                body.iter()
+                    .find_map(|stmt| style_token_at(stmt, line, column))
+            }),
+    }
+}
+
+fn args_token_at(
+    args: &ElementArgs,
+    line: u32,
+    column: u32,
+) -> Option<(&SpannedToken, TokenContext)> {
+    args.items.iter_items().find_map(|arg| {
+        found(arg.name.as_ref(), line, column, || TokenContext::ArgName).or_else(|| {
+            let name = match &arg.name {
+                Some(SpannedToken(_, Token::Ident(name))) => Some(name.clone()),
+                _ => None,
+            };
+            arg.value.as_ref().and_then(|value| {
+                value_token_at(value, line, column, &|| TokenContext::ArgValue {
+                    name: name.clone(),
+                })
+            })
+        })
+    })
+}
+
+/// Everything in a value shares the context of the property or argument it belongs to
+fn value_token_at<'a>(
+    value: &'a Value,
+    line: u32,
+    column: u32,
+    context: &dyn Fn() -> TokenContext,
+) -> Option<(&'a SpannedToken, TokenContext)> {
+    match value {
+        Value::Integer(_, _, token) | Value::Float(_, _, token) | Value::Ident(token) => {
+            found(Some(token), line, column, context)
+        }
+        Value::Function { ident, args } => {
+            found(ident.as_ref(), line, column, context).or_else(|| {
+                args.items.iter_items().find_map(|arg| {
+                    arg.value
+                        .as_ref()
+                        .and_then(|value| value_token_at(value, line, column, context))
+                })
+            })
+        }
+        Value::Tuple(values) => values
+            .iter()
+            .find_map(|value| value_token_at(value, line, column, context)),
+        Value::Array { values, .. } => values
+            .iter_items()
+            .find_map(|value| value_token_at(value, line, column, context)),
+        Value::Binary { left, right, .. } => value_token_at(left, line, column, context)
+            .or_else(|| value_token_at(right, line, column, context)),
+        Value::Object { entries, .. } => entries
+            .iter()
+            .find_map(|(_, value)| value_token_at(value, line, column, context)),
+    }
+}
+
+/// `context_at` for a position between the tokens of `stmt`
+fn statement_context_at(stmt: &Statement, at: &Span) -> Option<TokenContext> {
+    match stmt {
+        Statement::Element {
+            arguments,
+            body,
+            body_range,
+            token,
+            ..
+        } => {
+            if let Some(args) = arguments.as_ref().filter(|args| args.range.contains(at)) {
+                // Past an argument's colon and before its comma is where its value goes
+                let value = args.items.iter().find_map(|(arg, comma)| {
+                    let colon = arg.colon.as_ref()?;
+                    let before_comma = comma.as_ref().is_none_or(|comma| comma.0.after(at));
+                    (colon.0.before(at) && before_comma).then(|| TokenContext::ArgValue {
+                        name: match &arg.name {
+                            Some(SpannedToken(_, Token::Ident(name))) => Some(name.clone()),
+                            _ => None,
+                        },
+                    })
+                });
+                return Some(value.unwrap_or(TokenContext::ArgName));
+            }
+            // The body comes first so a child on the same line as this element's name wins
+            if body_range.is_some_and(|range| range.contains(at)) {
+                return body
+                    .iter()
+                    .find(|stmt| stmt.get_range().contains(at))
+                    .and_then(|stmt| statement_context_at(stmt, at))
+                    .or(Some(TokenContext::Element));
+            }
+            token
+                .as_ref()
+                .filter(|token| token.0.before(at))
+                .map(|_| TokenContext::Element)
+        }
+        Statement::Style {
+            body, body_range, ..
+        } => body_range
+            .filter(|range| range.contains(at))
+            .map(|_| style_context_at(body, at)),
+        // A segment goes after a trailing dot
+        Statement::UseStatement { args, .. } => match args.iter().last() {
+            Some((_, Some(dot @ SpannedToken(_, Token::Operator(Operator::Dot)))))
+                if dot.0.before(at) =>
+            {
+                Some(TokenContext::UsePath {
+                    index: args.iter_items().count(),
+                })
+            }
+            _ => None,
+        },
+        Statement::Text(_) => None,
+    }
+}
+
+/// `context_at` for a position in a style body. A property can go anywhere that isn't a value
+fn style_context_at(body: &[StyleStatement], at: &Span) -> TokenContext {
+    body.iter()
+        .find_map(|stmt| match stmt {
+            StyleStatement::Style {
+                body, body_range, ..
+            } => body_range
+                .filter(|range| range.contains(at))
+                .map(|_| style_context_at(body, at)),
+            StyleStatement::StyleElement {
+                key: Some(SpannedToken(_, Token::Ident(key))),
+                colon: Some(colon),
+                ..
+            } if colon.0.before(at) => Some(TokenContext::StyleValue { key: key.clone() }),
+            StyleStatement::StyleElement { .. } => None,
+        })
+        .unwrap_or(TokenContext::StyleKey)
+}
+
 pub enum Type {
     None,
     Float,
     Integer,
     Ident(String),
     Tuple(Vec<Type>),
+    /// One or more of the type, as the last argument
+    Variadic(Box<Type>),
 }
 
 impl Type {
@@ -432,7 +1053,7 @@ impl Type {
 pub enum SymbolKind {
     Text(String),
     Node {
-        args: HashMap<String, Value>,
+        args: LinkedHashMap<String, Value>,
     },
     Function {
         args: Vec<Type>,
@@ -440,17 +1061,30 @@ pub enum SymbolKind {
         func: Box<dyn Fn(Vec<Value>) -> Option<Value> + Send + Sync>,
     },
     Style {
-        properties: HashMap<String, Value>,
+        properties: LinkedHashMap<String, Value>,
+        /// Properties marked `!important`, which win over the same property in other classes
+        important: HashSet<String>,
+        variants: Vec<StyleVariant>,
     },
     Use(Vec<String>),
     Root,
 }
 
+/// A style rule that only applies while its viewport condition holds
+#[derive(Clone, PartialEq)]
+pub struct StyleVariant {
+    pub condition: LinkedHashMap<String, Value>,
+    pub properties: LinkedHashMap<String, Value>,
+    pub important: HashSet<String>,
+}
+
 pub struct Symbol {
     pub name: String,
     pub kind: SymbolKind,
     pub parent: Option<Rf<Symbol>>,
     pub children: LinkedHashMap<String, Rf<Symbol>>,
+    /// Where the element or text was written, `None` for symbols without a statement of their own
+    pub range: Option<Range>,
 }
 
 impl NodeDisplay for Symbol {
@@ -489,27 +1123,12 @@ impl Symbol {
             kind: SymbolKind::Root,
             parent: None,
             children: LinkedHashMap::new(),
+            range: None,
         })
     }
 
     pub fn insert_unnamed(symb: &Rf<Symbol>, name: &str, kind: SymbolKind) -> Option<Rf<Symbol>> {
-        let insert_index = {
-            let symb = symb.borrow();
-
-            // Find free index; max 128
-            [0; 128]
-                .into_iter()
-                .enumerate()
-                .map(|(i, _)| i)
-                .find_map(|v| {
-                    let val = format!("{}", v);
-                    if symb.children.get(&val).is_none() {
-                        Some(val)
-                    } else {
-                        None
-                    }
-                })
-        };
+        let insert_index = symb.borrow().free_index();
 
         if let Some(insert_index) = insert_index {
             let new = Rf::new(Symbol {
@@ -517,6 +1136,7 @@ impl Symbol {
                 kind,
                 parent: Some(symb.clone()),
                 children: LinkedHashMap::new(),
+                range: None,
             });
 
             symb.borrow_mut().children.insert(insert_index, new.clone());
@@ -527,12 +1147,59 @@ impl Symbol {
         }
     }
 
+    /// The first key an unnamed child can take
+    fn free_index(&self) -> Option<String> {
+        // Find free index; max 128
+        [0; 128]
+            .into_iter()
+            .enumerate()
+            .map(|(i, _)| i)
+            .find_map(|v| {
+                let val = format!("{}", v);
+                if self.children.get(&val).is_none() {
+                    Some(val)
+                } else {
+                    None
+                }
+            })
+    }
+
+    /// Moves `child` from another tree under `symb` at `key`, see `Module::merge` for what
+    /// happens when `key` is taken
+    fn graft(symb: &Rf<Symbol>, key: String, child: Rf<Symbol>) {
+        let existing = symb.borrow().children.get(&key).cloned();
+        let key = match existing {
+            None => key,
+            Some(_) if key.parse::<usize>().is_ok() => {
+                let Some(key) = symb.borrow().free_index() else {
+                    return;
+                };
+                key
+            }
+            Some(existing)
+                if matches!(existing.borrow().kind, SymbolKind::Node { .. })
+                    && matches!(child.borrow().kind, SymbolKind::Node { .. }) =>
+            {
+                let children = std::mem::take(&mut child.borrow_mut().children);
+                for (key, child) in children {
+                    Symbol::graft(&existing, key, child);
+                }
+                return;
+            }
+            Some(_) => key,
+        };
+
+        child.borrow_mut().parent = Some(symb.clone());
+        symb.borrow_mut().children.insert(key, child);
+    }
+
     pub fn insert(symb: &Rf<Symbol>, name: &str, kind: SymbolKind) -> Rf<Symbol> {
         let new = Rf::new(Symbol {
             name: name.to_string(),
             kind,
             parent: Some(symb.clone()),
             children: LinkedHashMap::new(),
+            range: None,
         });
 
         symb.borrow_mut()
@@ -543,6 +1210,20 @@ impl Symbol {
     }
 }
 
+fn named_args(args: &ElementArgs) -> LinkedHashMap<String, Value> {
+    args.iter_items()
+        .filter_map(|arg| {
+            if let (Some(SpannedToken(_, Token::Ident(name))), Some(value)) =
+                (&arg.name, &arg.value)
+            {
+                Some((name.clone(), value.clone()))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
 #[derive(Default)]
 pub struct ModuleDescender<U: Clone> {
     user_data: U,
@@ -804,3 +1485,1098 @@ impl<U: Clone> MutModuleDescender<U> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        ast::{Arg, AstNode, ElementArgs, PunctuationList, Statement, StyleStatement, Value},
+        color::{Color, ColorError},
+        error::{ParseError, ParseErrorKind},
+        evaluate::EvaluatedValue,
+        lexer::{tokens, Lexer},
+        parser::Parser,
+        token::{Keyword, Operator, Range, Span, SpannedToken, Token, Unit},
+        Module, SymbolKind, TokenContext,
+    };
+    use neb_util::format::TreeDisplay;
+
+    fn use_paths(input: &str) -> Vec<Vec<String>> {
+        let (module, _) = Module::parse_str(input);
+        module
+            .stmts
+            .iter()
+            .filter_map(|stmt| match stmt {
+                Statement::UseStatement { args, .. } => Some(
+                    args.iter_items()
+                        .filter_map(|tok| match tok {
+                            SpannedToken(_, Token::Ident(i)) => Some(i.clone()),
+                            _ => None,
+                        })
+                        .collect(),
+                ),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn consecutive_use_statements() {
+        assert_eq!(
+            use_paths("use setup.style\nuse other.style"),
+            vec![vec!["setup", "style"], vec!["other", "style"]]
+        );
+    }
+
+    #[test]
+    fn use_statement_stops_at_line_break() {
+        assert_eq!(
+            use_paths("use setup\nuse other.style"),
+            vec![vec!["setup"], vec!["other", "style"]]
+        );
+    }
+
+    #[test]
+    fn reserved_words_lex_as_keywords() {
+        let tokens = Lexer {}.lex("use style view setup styles viewer");
+        let tokens: Vec<_> = tokens.iter().map(|tok| tok.tok().clone()).collect();
+
+        assert!(matches!(
+            tokens.as_slice(),
+            [
+                Token::Keyword(Keyword::Use),
+                Token::Keyword(Keyword::Style),
+                Token::Keyword(Keyword::View),
+                Token::Keyword(Keyword::Setup),
+                Token::Ident(styles),
+                Token::Ident(viewer),
+                Token::Newline,
+            ] if styles == "styles" && viewer == "viewer"
+        ));
+    }
+
+    #[test]
+    fn empty_sources_parse_to_nothing() {
+        for source in [
+            "",
+            "\n\n",
+            "  \r\n\t\n",
+            "// nothing here\n",
+            "// a\n\n    // b",
+        ] {
+            let (module, errors) = Module::parse(source).unwrap();
+            assert!(errors.is_empty(), "{:?}: {:?}", source, errors);
+            assert!(module.stmts.is_empty(), "{:?}", source);
+        }
+    }
+
+    #[test]
+    fn comments_run_to_the_end_of_the_line() {
+        let tokens = Lexer {}.lex("view // a view\n:Text // kept\n");
+        let tokens: Vec<_> = tokens.iter().map(|tok| tok.tok().clone()).collect();
+
+        assert!(matches!(
+            tokens.as_slice(),
+            [Token::Keyword(Keyword::View), Token::Text(text), Token::Newline] if text == "Text // kept"
+        ));
+    }
+
+    #[test]
+    fn text_spans_cover_the_shown_text() {
+        let line = "    :héllo wörld";
+        let tokens = Lexer {}.lex(&format!("view {{\n{}\n}}", line));
+        let Some(SpannedToken(span, Token::Text(text))) = tokens
+            .iter()
+            .find(|tok| matches!(tok.tok(), Token::Text(_)))
+        else {
+            panic!("expected a text token");
+        };
+
+        assert_eq!(text, "héllo wörld");
+        assert_eq!((span.line_num, span.position, span.length), (1, 5, 11));
+
+        let units: Vec<u16> = line.encode_utf16().collect();
+        let start = span.position as usize;
+        let covered = String::from_utf16(&units[start..start + span.length as usize]).unwrap();
+        assert_eq!(&covered, text);
+    }
+
+    #[test]
+    fn a_broken_statement_keeps_the_ones_around_it() {
+        let source = "view (name: before) {
+    :Before
+}
+) oops {
+    :Lost
+}
+view (name: after) {
+    :After
+}";
+        let (module, errors) = Module::parse_str(source);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_eq!(errors[0].range.start.line_num, 3);
+
+        let names: Vec<_> = module
+            .stmts
+            .iter()
+            .map(|stmt| match stmt {
+                Statement::Element {
+                    arguments: Some(args),
+                    ..
+                } => args
+                    .iter_items()
+                    .find_map(|arg| match (&arg.name, &arg.value) {
+                        (
+                            Some(SpannedToken(_, Token::Ident(name))),
+                            Some(Value::Ident(SpannedToken(_, Token::Ident(value)))),
+                        ) if name == "name" => Some(value.as_str()),
+                        _ => None,
+                    })
+                    .unwrap_or_default(),
+                _ => "",
+            })
+            .collect();
+        assert_eq!(names, ["before", "after"]);
+    }
+
+    #[test]
+    fn underscores_separate_digits() {
+        let lex = |input: &str| -> Vec<Token> {
+            Lexer {}
+                .lex(input)
+                .iter()
+                .map(|tok| tok.tok().clone())
+                .filter(|tok| !matches!(tok, Token::Newline))
+                .collect()
+        };
+
+        assert!(matches!(
+            lex("1_000").as_slice(),
+            [Token::Integer(1000, None)]
+        ));
+        assert!(matches!(
+            lex("1_000px").as_slice(),
+            [Token::Integer(1000, Some(Unit::Pixel))]
+        ));
+        assert!(
+            matches!(lex("1_000.000_5").as_slice(), [Token::Float(f, None)] if *f == 1000.0005)
+        );
+        assert!(matches!(lex("padding_top").as_slice(), [Token::Ident(i)] if i == "padding_top"));
+
+        // A separator has to be between digits
+        assert!(matches!(
+            lex("_1").as_slice(),
+            [Token::Ident(i), Token::Integer(1, None)] if i == "_"
+        ));
+        assert!(matches!(
+            lex("1_ ").as_slice(),
+            [Token::Integer(1, None), Token::Ident(i)] if i == "_"
+        ));
+        assert!(matches!(
+            lex("1__0").as_slice(),
+            [Token::Integer(1, None), Token::Ident(i), Token::Integer(0, None)] if i == "__"
+        ));
+    }
+
+    #[test]
+    fn token_at_finds_the_token_and_its_context() {
+        let source = "setup {
+    style {
+        button {
+            backgroundColor: rgb(255, 0, 10)
+        }
+    }
+}
+use setup.style
+view (class: button) {
+}";
+        let (module, _) = Module::parse_str(source);
+
+        let at = |line, column| {
+            module
+                .token_at(line, column)
+                .map(|(SpannedToken(span, token), context)| {
+                    (span.position, format!("{:?}", token), context)
+                })
+        };
+
+        let value = TokenContext::StyleValue {
+            key: "backgroundColor".to_string(),
+        };
+        assert_eq!(
+            at(3, 38),
+            Some((38, "Integer(0, None)".to_string(), value.clone()))
+        );
+        assert_eq!(
+            at(3, 34),
+            Some((33, "Integer(255, None)".to_string(), value.clone()))
+        );
+        assert_eq!(at(3, 30), Some((29, "Ident(\"rgb\")".to_string(), value)));
+        assert_eq!(
+            at(3, 12),
+            Some((
+                12,
+                "Ident(\"backgroundColor\")".to_string(),
+                TokenContext::StyleKey
+            ))
+        );
+        assert_eq!(
+            at(2, 10),
+            Some((8, "Ident(\"button\")".to_string(), TokenContext::StyleName))
+        );
+        assert_eq!(
+            at(7, 11),
+            Some((
+                10,
+                "Ident(\"style\")".to_string(),
+                TokenContext::UsePath { index: 1 }
+            ))
+        );
+        assert_eq!(
+            at(8, 15),
+            Some((
+                13,
+                "Ident(\"button\")".to_string(),
+                TokenContext::ArgValue {
+                    name: Some("class".to_string())
+                }
+            ))
+        );
+        assert_eq!(
+            at(8, 0).map(|(.., context)| context),
+            Some(TokenContext::Element)
+        );
+
+        // Punctuation and empty space find nothing
+        assert_eq!(at(3, 28), None);
+        assert_eq!(at(9, 0), None);
+    }
+
+    #[test]
+    fn context_at_works_between_tokens() {
+        let source = "setup {
+    style {
+        button {
+            backgroundColor: 
+        }
+    }
+}
+use setup.
+view (class: ) {
+}";
+        let (module, _) = Module::parse_str(source);
+
+        assert_eq!(
+            module.context_at(3, 29),
+            Some(TokenContext::StyleValue {
+                key: "backgroundColor".to_string()
+            })
+        );
+        assert_eq!(module.context_at(4, 4), Some(TokenContext::StyleKey));
+        assert_eq!(
+            module.context_at(7, 10),
+            Some(TokenContext::UsePath { index: 1 })
+        );
+        assert_eq!(
+            module.context_at(8, 13),
+            Some(TokenContext::ArgValue {
+                name: Some("class".to_string())
+            })
+        );
+        assert_eq!(module.context_at(8, 6), Some(TokenContext::ArgName));
+        assert_eq!(module.context_at(9, 0), Some(TokenContext::Element));
+        assert_eq!(module.context_at(11, 0), Some(TokenContext::Element));
+    }
+
+    #[test]
+    fn token_stream_matches_lex() {
+        let input = "view (class: a) {\n    :Hello, there\n    width: calc(50% - 10px)\n}\n:tail";
+        let spans = |tokens: &mut dyn Iterator<Item = SpannedToken>| -> Vec<_> {
+            tokens
+                .map(|SpannedToken(span, token)| (span.line_num, span.position, span.length, token))
+                .collect()
+        };
+
+        use Operator::*;
+        let ident = |name: &str| Token::Ident(name.to_string());
+        let expected = vec![
+            (0, 0, 4, Token::Keyword(Keyword::View)),
+            (0, 5, 1, Token::Operator(OpenParen)),
+            (0, 6, 5, ident("class")),
+            (0, 11, 1, Token::Operator(Colon)),
+            (0, 13, 1, ident("a")),
+            (0, 14, 1, Token::Operator(CloseParen)),
+            (0, 16, 1, Token::Operator(OpenBrace)),
+            (1, 5, 12, Token::Text("Hello, there".to_string())),
+            (2, 4, 5, ident("width")),
+            (2, 9, 1, Token::Operator(Colon)),
+            (2, 11, 4, ident("calc")),
+            (2, 15, 1, Token::Operator(OpenParen)),
+            (2, 16, 3, Token::Integer(50, Some(Unit::Percent))),
+            (2, 20, 1, Token::Operator(Minus)),
+            (2, 22, 4, Token::Integer(10, Some(Unit::Pixel))),
+            (2, 26, 1, Token::Operator(CloseParen)),
+            (3, 0, 1, Token::Operator(CloseBrace)),
+            // Text only ends at a line break, so the last line stays tokens
+            (4, 0, 1, Token::Operator(Colon)),
+            (4, 1, 4, ident("tail")),
+            (4, 5, 1, Token::Newline),
+        ];
+        assert_eq!(spans(&mut tokens(input)), expected);
+        assert_eq!(spans(&mut Lexer {}.lex(input).into_iter()), expected);
+
+        // The first line is complete without lexing the rest
+        assert_eq!(spans(&mut tokens(input).take(7)), expected[..7]);
+    }
+
+    #[test]
+    fn square_brackets_parse_into_an_array() {
+        let tokens = Lexer {}.lex("[1, 2]");
+        assert!(matches!(
+            tokens.first().map(|tok| tok.tok()),
+            Some(Token::Operator(Operator::OpenSquare))
+        ));
+        assert_eq!(Operator::OpenSquare.as_str(), "`[`");
+        assert_eq!(Operator::CloseSquare.as_str(), "`]`");
+
+        let parser = Parser::new(tokens);
+        let Some(Value::Array { values, .. }) = parser.parse_value() else {
+            panic!("expected an array");
+        };
+        assert!(parser.get_errors().is_empty());
+
+        let values: Vec<_> = values.iter_items().collect();
+        assert!(matches!(
+            values.as_slice(),
+            [Value::Integer(1, None, _), Value::Integer(2, None, _)]
+        ));
+    }
+
+    #[test]
+    fn colors_are_found_with_their_ranges() {
+        let source = "style {
+    card {
+        backgroundColor: rgb(255, 0, 0)
+        borderColor: \"#00ff80\"
+        foregroundColor: black
+        padding: 4px
+    }
+}";
+        let (module, _) = Module::parse_str(source);
+        let colors = module.colors();
+
+        let place = |range: Range| {
+            (
+                (range.start.line_num, range.start.position),
+                (range.end.line_num, range.end.position + range.end.length),
+            )
+        };
+        let found: Vec<_> = colors
+            .iter()
+            .map(|(range, color)| (place(*range), *color))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (((2, 25), (2, 39)), Color::rgb(255, 0, 0)),
+                (((3, 21), (3, 30)), Color::rgb(0, 255, 128)),
+                (((4, 25), (4, 30)), Color::rgb(0, 0, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn negative_numbers_are_literals() {
+        let source = "style {\n    loud {\n        backgroundColor: rgb(300, -1, 0)\n    }\n}";
+        let (module, errors) = Module::parse_str(source);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let Statement::Style { body, .. } = &module.stmts[0] else {
+            panic!("expected a style block");
+        };
+        let elements: Vec<_> = body[0].style_elements().collect();
+        let [(_, value @ Value::Function { args, .. })] = elements.as_slice() else {
+            panic!("expected an rgb call");
+        };
+        let channels: Vec<_> = args
+            .iter_items()
+            .filter_map(|arg| match &arg.value {
+                Some(Value::Integer(i, None, _)) => Some(*i),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(channels, [300, -1, 0]);
+
+        // The minus is part of the literal's range
+        let Some(Value::Integer(_, _, SpannedToken(span, _))) =
+            &args.iter_items().nth(1).unwrap().value
+        else {
+            panic!("expected a literal");
+        };
+        assert_eq!((span.position, span.length), (34, 2));
+
+        assert_eq!(
+            Color::from_value(value),
+            Err(ColorError::OutOfRange(Color::rgb(255, 0, 0)))
+        );
+    }
+
+    #[test]
+    fn objects_parse_in_value_positions() {
+        let source = "style {\n    bar {\n        stops: [{ at: 0, color: red }, { at: 1, color: blue }]\n    }\n}\nview (class: bar) {\n    :Hi\n}";
+        let (module, errors) = Module::parse_str(source);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(module.stmts.len(), 2);
+
+        let Statement::Style { body, .. } = &module.stmts[0] else {
+            panic!("expected a style block");
+        };
+        let elements: Vec<_> = body[0].style_elements().collect();
+        let [(key, Value::Array { values, .. })] = elements.as_slice() else {
+            panic!("expected an array of stops");
+        };
+        assert_eq!(key, "stops");
+
+        let stops: Vec<_> = values
+            .iter_items()
+            .filter_map(|stop| match stop {
+                Value::Object { entries, range } => Some((entries, range)),
+                _ => None,
+            })
+            .collect();
+        let [(first, range), (second, _)] = stops.as_slice() else {
+            panic!("expected two objects");
+        };
+        // From brace to brace
+        assert_eq!((range.start.position, range.end.position), (16, 36));
+        let keys: Vec<_> = first.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, ["at", "color"]);
+        assert!(matches!(first[0].1, Value::Integer(0, None, _)));
+        assert!(matches!(second[0].1, Value::Integer(1, None, _)));
+        assert!(matches!(
+            &second[1].1,
+            Value::Ident(SpannedToken(_, Token::Ident(color))) if color == "blue"
+        ));
+    }
+
+    #[test]
+    fn module_format_is_stable() {
+        let source = "style {\n    bar {\n        padding: 4px 8px\n    }\n}";
+        let (module, _) = Module::parse_str(source);
+        assert_eq!(
+            module.format(),
+            "Statement
+├──Token: style
+│   └──Span line: 0, character: 0, 5 long, token: 0
+├──Range
+│   ├──Span line: 0, character: 6, 1 long, token: 1
+│   └──Span line: 4, character: 0, 1 long, token: 9
+└──Style Statement
+    ├──Token: bar
+    │   └──Span line: 1, character: 4, 3 long, token: 2
+    ├──Range
+    │   ├──Span line: 1, character: 8, 1 long, token: 3
+    │   └──Span line: 3, character: 4, 1 long, token: 8
+    └──Style Statement
+        ├──Token: padding
+        │   └──Span line: 2, character: 8, 7 long, token: 4
+        └──Tuple
+            ├──4px
+            └──8px
+
+"
+        );
+    }
+
+    #[test]
+    fn style_elements_yields_complete_pairs() {
+        let source = "style {\n    bar {\n        padding: 4px\n        gap:\n        foregroundColor: red\n    }\n}";
+        let (module, _) = Module::parse_str(source);
+
+        let Statement::Style { body, .. } = &module.stmts[0] else {
+            panic!("expected a style block");
+        };
+        let elements: Vec<_> = body[0].style_elements().collect();
+        assert_eq!(
+            elements.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>(),
+            ["padding", "foregroundColor"]
+        );
+        assert!(matches!(
+            elements[0].1,
+            Value::Integer(4, Some(Unit::Pixel), _)
+        ));
+        assert!(matches!(
+            &elements[1].1,
+            Value::Ident(SpannedToken(_, Token::Ident(color))) if color == "red"
+        ));
+
+        let StyleStatement::Style { body: elements, .. } = &body[0] else {
+            panic!("expected a style statement");
+        };
+        assert!(elements[0].style_elements().next().is_none());
+    }
+
+    #[test]
+    fn iter_values_skips_args_without_a_value() {
+        let int = |v| {
+            let token = SpannedToken::new(Token::Integer(v, None), Default::default());
+            Value::Integer(v, None, token)
+        };
+        let arg = |value| Arg {
+            name: None,
+            colon: None,
+            value,
+        };
+
+        let mut items = PunctuationList::new();
+        items.push(arg(Some(int(1))), None);
+        items.push(arg(None), None);
+        items.push(arg(Some(int(2))), None);
+        let args = ElementArgs {
+            range: Range::default(),
+            items,
+        };
+
+        let values: Vec<_> = args.iter_values().collect();
+        assert!(matches!(
+            values.as_slice(),
+            [Value::Integer(1, None, _), Value::Integer(2, None, _)]
+        ));
+    }
+
+    #[test]
+    fn walk_symbols_in_tree_order() {
+        let (module, _) = Module::parse_str(
+            "style {\n    header {\n        padding: 4\n    }\n    footer {\n        gap: 2\n    }\n}\nview {\n    use style\n}",
+        );
+
+        let mut visited = Vec::new();
+        module.walk_symbols(|path, symbol| {
+            if !matches!(symbol.kind, SymbolKind::Function { .. }) {
+                visited.push((path.join("."), symbol.name.clone()));
+            }
+        });
+
+        let expected = [
+            ("style", "style"),
+            ("style.header", "header"),
+            ("style.footer", "footer"),
+            ("0", "view"),
+            ("0.use", "use"),
+        ];
+        assert_eq!(
+            visited,
+            expected
+                .iter()
+                .map(|(path, name)| (path.to_string(), name.to_string()))
+                .collect::<Vec<_>>()
+        );
+
+        let names: Vec<_> = module
+            .symbols()
+            .map(|(_, symbol)| symbol.borrow().name.clone())
+            .collect();
+        assert_eq!(names[..5], ["style", "header", "footer", "view", "use"]);
+    }
+
+    #[test]
+    fn garbage_reports_errors_instead_of_panicking() {
+        for input in [
+            "view(: ; ) {",
+            "view(a: 1 b: 2) {}",
+            ") ( } {",
+            "view(a: 1 }",
+        ] {
+            match Module::parse(input) {
+                Ok((_, errors)) => assert!(!errors.is_empty(), "no errors for {:?}", input),
+                Err(fatal) => assert!(!fatal.errors.is_empty()),
+            }
+        }
+    }
+
+    #[cfg(feature = "lsp")]
+    #[test]
+    fn ranges_convert_to_lsp() {
+        use tower_lsp::lsp_types;
+
+        use crate::token::{Range, Span};
+
+        let span = |line_num, position, length| Span {
+            line_num,
+            position,
+            length,
+            ..Default::default()
+        };
+
+        // A single token covers its own length
+        let token = span(2, 4, 5);
+        assert_eq!(
+            lsp_types::Range::from(&Range::from(token)),
+            lsp_types::Range::new(
+                lsp_types::Position::new(2, 4),
+                lsp_types::Position::new(2, 9)
+            )
+        );
+
+        // Multi-line ranges end after the last token
+        let range = Range::new(span(1, 5, 1), span(3, 0, 1));
+        assert_eq!(
+            lsp_types::Range::from(&range),
+            lsp_types::Range::new(
+                lsp_types::Position::new(1, 5),
+                lsp_types::Position::new(3, 1)
+            )
+        );
+
+        let cursor = Span::from(lsp_types::Position::new(7, 3));
+        assert_eq!((cursor.line_num, cursor.position, cursor.length), (7, 3, 0));
+    }
+
+    #[test]
+    fn lexer_never_panics() {
+        const PIECES: &[&str] = &[
+            "view", "style", "use", ":", ".", ",", "(", ")", "{", "}", "[", "]", "@", " ", "\n",
+            "\r\n", "\r", "\t", "1", "2.5", "1.2.3", "px", "p", "_", "#", "é", "日本", "🦀",
+            "\u{3000}", "\0",
+        ];
+
+        // xorshift, so the inputs are the same on every run
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for _ in 0..5000 {
+            let len = random() % 24;
+            let input: String = (0..len)
+                .map(|_| match random() % 4 {
+                    0 => char::from_u32((random() % 0x11_0000) as u32)
+                        .unwrap_or('?')
+                        .to_string(),
+                    _ => PIECES[(random() % PIECES.len() as u64) as usize].to_string(),
+                })
+                .collect();
+
+            let tokens = Lexer {}.lex(&input);
+            assert!(matches!(
+                tokens.last().map(|t| t.tok()),
+                Some(Token::Newline)
+            ));
+        }
+    }
+
+    #[test]
+    fn parse_with_reports_each_top_level_statement() {
+        let input = "setup {
+    style {
+        card {
+            padding: 4px
+        }
+    }
+}
+
+use setup.style
+
+view (class: card) {
+    :Hello
+}
+";
+        let mut seen = Vec::new();
+        let (module, _) = Module::parse_with(input, |statements| {
+            let last = match statements.last() {
+                Some(Statement::Element { .. }) => "element",
+                Some(Statement::UseStatement { .. }) => "use",
+                _ => "other",
+            };
+            seen.push((statements.len(), last));
+        })
+        .unwrap();
+
+        // Nested statements aren't reported on their own
+        assert_eq!(seen, [(1, "element"), (2, "use"), (3, "element")]);
+        assert_eq!(module.stmts.len(), 3);
+    }
+
+    #[test]
+    fn merged_modules_resolve_under_their_namespace() {
+        let (mut module, _) =
+            Module::parse_str("use widgets.setup.style\n\nview (class: card) {\n    :Hi\n}");
+        let (widgets, _) = Module::parse_str(
+            "setup {\n    style {\n        card {\n            padding: 4px\n        }\n    }\n}\n\nview {\n    :Preview\n}",
+        );
+        let (more, _) = Module::parse_str("view {\n    :Another\n}");
+
+        module.merge(widgets, "widgets");
+        module.merge(more, "widgets");
+
+        let Some(Statement::UseStatement { args, .. }) = module.stmts.first() else {
+            panic!("Expected a use statement");
+        };
+        let style = module.resolve_symbol_chain(args.iter_items()).unwrap();
+        let card = style.borrow().children.get("card").cloned().unwrap();
+        assert!(matches!(card.borrow().kind, SymbolKind::Style { .. }));
+
+        // The grafted symbols point back up to the namespace, which hangs off the root
+        let setup = style.borrow().parent.clone().unwrap();
+        let namespace = setup.borrow().parent.clone().unwrap();
+        assert_eq!(namespace.borrow().name, "widgets");
+        assert!(matches!(
+            namespace.borrow().parent.as_ref().unwrap().borrow().kind,
+            SymbolKind::Root
+        ));
+
+        // The second module's view is numbered after the first one's, and the built in
+        // functions aren't copied into the namespace
+        let keys: Vec<_> = namespace.borrow().children.keys().cloned().collect();
+        assert_eq!(keys, ["setup", "0", "1"]);
+        assert!(module
+            .resolve_symbol_chain_string(["0".to_string()].iter())
+            .is_some());
+    }
+
+    #[test]
+    fn properties_and_args_keep_source_order() {
+        let (module, _) = Module::parse_str(
+            "style {\n    card {\n        width: 1px\n        color: red\n        height: 2px\n        align: Center\n        color: blue\n    }\n}\nview (name: card, class: card, id: 2) {\n}",
+        );
+
+        let card = module
+            .resolve_symbol_chain_string(["style", "card"].map(String::from).iter())
+            .unwrap();
+        let SymbolKind::Style { properties, .. } = &card.borrow().kind else {
+            panic!("Expected a style");
+        };
+        // A repeated property wins with the value and place it's given last
+        let keys: Vec<_> = properties.keys().map(String::as_str).collect();
+        assert_eq!(keys, ["width", "height", "align", "color"]);
+        assert_eq!(
+            Color::from_value(&properties["color"]),
+            Ok(Color::rgb(0, 0, 255))
+        );
+
+        let view = module
+            .resolve_symbol_chain_string(["0"].map(String::from).iter())
+            .unwrap();
+        let SymbolKind::Node { args } = &view.borrow().kind else {
+            panic!("Expected an element");
+        };
+        let keys: Vec<_> = args.keys().map(String::as_str).collect();
+        assert_eq!(keys, ["name", "class", "id"]);
+    }
+
+    #[test]
+    fn quoted_identifiers_work_as_keys_and_use_segments() {
+        let (module, errors) = Module::parse_str(
+            "\"my-widgets\" {\n    style {\n        card {\n            \"background-color\": rgb(255, 0, 0)\n            \"style\": 1\n        }\n    }\n}\nuse \"my-widgets\".style\n",
+        );
+        assert!(errors.is_empty());
+
+        let Some(Statement::UseStatement { args, .. }) = module.stmts.last() else {
+            panic!("Expected a use statement");
+        };
+        let segments: Vec<_> = args
+            .iter_items()
+            .map(|token| match token {
+                SpannedToken(_, Token::Ident(i)) => i.as_str(),
+                _ => panic!("Expected an identifier"),
+            })
+            .collect();
+        assert_eq!(segments, ["my-widgets", "style"]);
+
+        // Quoted keywords are plain keys, and the quotes are part of the key's span
+        let card = module
+            .resolve_symbol_chain_string(["0", "style", "card"].map(String::from).iter())
+            .unwrap();
+        let SymbolKind::Style { properties, .. } = &card.borrow().kind else {
+            panic!("Expected a style");
+        };
+        let keys: Vec<_> = properties.keys().cloned().collect();
+        assert_eq!(keys, ["background-color", "style"]);
+        assert_eq!(args.iter_items().next().unwrap().span().length, 12);
+    }
+
+    #[test]
+    fn values_evaluate_to_their_final_form() {
+        let (module, errors) = Module::parse_str(
+            "setup (accent: rgb(255, 0, 0), highlight: accent) {\n    style {\n        card {\n            borderColor: highlight\n            backgroundColor: rgb(0, 0, 255)\n            padding: rect_all(4px)\n            width: calc(100% - 20px)\n            height: 10px + 4px\n            gap: calc(10px + 4px)\n        }\n    }\n}",
+        );
+        assert!(errors.is_empty());
+
+        let card = module
+            .resolve_symbol_chain_string(["setup", "style", "card"].map(String::from).iter())
+            .unwrap();
+        let property = |name: &str| match &card.borrow().kind {
+            SymbolKind::Style { properties, .. } => properties[name].clone(),
+            _ => panic!("Expected a style"),
+        };
+
+        assert_eq!(
+            module
+                .evaluate_value(&property("backgroundColor"), &card)
+                .unwrap(),
+            EvaluatedValue::Color(Color::rgb(0, 0, 255))
+        );
+        assert_eq!(
+            module.evaluate_value(&property("padding"), &card).unwrap(),
+            EvaluatedValue::Rect([(4.0, Some(Unit::Pixel)); 4])
+        );
+        // Constants can refer to other constants
+        assert_eq!(
+            module
+                .evaluate_value(&property("borderColor"), &card)
+                .unwrap(),
+            EvaluatedValue::Color(Color::rgb(255, 0, 0))
+        );
+        // Only what the renderer reads: arithmetic in `calc`, and only if layout doesn't matter
+        assert_eq!(
+            module.evaluate_value(&property("gap"), &card).unwrap(),
+            EvaluatedValue::Number(14.0, Some(Unit::Pixel))
+        );
+        assert!(module.evaluate_value(&property("width"), &card).is_err());
+        assert!(module.evaluate_value(&property("height"), &card).is_err());
+    }
+
+    #[test]
+    fn functions_called_with_the_wrong_arity_are_errors() {
+        let (module, errors) = Module::parse_str(
+            "style {\n    card {\n        padding: rect_xy(4px)\n        radius: rect_all()\n        width: min()\n        height: max(4px, 10, 8px)\n    }\n}",
+        );
+        assert!(errors.is_empty());
+
+        let card = module
+            .resolve_symbol_chain_string(["style", "card"].map(String::from).iter())
+            .unwrap();
+        let property = |name: &str| match &card.borrow().kind {
+            SymbolKind::Style { properties, .. } => properties[name].clone(),
+            _ => panic!("Expected a style"),
+        };
+
+        let error = module
+            .evaluate_value(&property("padding"), &card)
+            .unwrap_err();
+        assert_eq!(error.message, "`rect_xy`: takes 2 arguments, found 1");
+        let error = module
+            .evaluate_value(&property("radius"), &card)
+            .unwrap_err();
+        assert_eq!(error.message, "`rect_all`: takes 1 argument, found 0");
+
+        // `min` and `max` take any number of sizes
+        let error = module
+            .evaluate_value(&property("width"), &card)
+            .unwrap_err();
+        assert_eq!(error.message, "`min`: takes at least 1 argument, found 0");
+        assert_eq!(
+            module.evaluate_value(&property("height"), &card).unwrap(),
+            EvaluatedValue::Number(10.0, None)
+        );
+    }
+
+    #[test]
+    fn replace_statement_reparses_one_statement() {
+        let (mut module, _) = Module::parse_str(
+            "style {\n    header {\n        padding: 4px\n    }\n}\nview {\n    :Hi\n}",
+        );
+
+        let errors = module
+            .replace_statement(
+                0,
+                "style {\n    header {\n        padding: 8px\n        gap: 2px\n    }\n}",
+            )
+            .unwrap();
+        assert!(errors.is_empty());
+
+        let expected =
+            "style {\n    header {\n        padding: 8px\n        gap: 2px\n    }\n}\nview {\n    :Hi\n}";
+        assert_eq!(module.content, expected);
+
+        let path = ["style".to_string(), "header".to_string()];
+        let header = module.resolve_symbol_chain_string(path.iter()).unwrap();
+        let SymbolKind::Style { properties, .. } = &header.borrow().kind else {
+            panic!("header is not a style");
+        };
+        assert!(matches!(
+            properties["padding"],
+            Value::Integer(8, Some(Unit::Pixel), _)
+        ));
+        assert!(properties.contains_key("gap"));
+
+        // The view was only moved down a line, so it matches a fresh parse of the new source
+        let (fresh, _) = Module::parse_str(expected);
+        for (edited, fresh) in module.stmts.iter().zip(fresh.stmts.iter()) {
+            let place = |range: Range| {
+                [range.start, range.end].map(|span| (span.line_num, span.position, span.length))
+            };
+            assert_eq!(place(edited.get_range()), place(fresh.get_range()));
+        }
+
+        // More than one statement can't replace one
+        assert!(module.replace_statement(1, "view {\n}\nview {\n}").is_err());
+        assert_eq!(module.content, expected);
+    }
+
+    #[test]
+    fn lex_then_parse_matches_parse_str() {
+        let input = "style {
+    header {
+        padding: 8px
+    }
+}
+) oops {
+}
+view (class: header) {
+    :Hi
+}";
+        let (tokens, lex_errors) = crate::lex(input);
+        assert!(lex_errors.is_empty());
+        assert!(tokens
+            .iter()
+            .any(|tok| matches!(tok.tok(), Token::Integer(8, Some(Unit::Pixel)))));
+
+        let (stmts, errors) = crate::parse(tokens);
+        let (module, module_errors) = Module::parse_str(input);
+
+        let formatted = |stmts: &[Statement]| -> Vec<String> {
+            stmts.iter().map(|stmt| stmt.format()).collect()
+        };
+        assert_eq!(formatted(&stmts), formatted(&module.stmts));
+        assert_eq!(stmts.len(), 2);
+        assert_eq!(format!("{:?}", errors), format!("{:?}", module_errors));
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn unterminated_strings_end_at_the_line() {
+        let (tokens, errors) = crate::lex("a: \"abc\nb: \"d\"\n");
+
+        assert_eq!(errors.len(), 1);
+        assert!(
+            matches!(&errors[0].kind, ParseErrorKind::InvalidSyntax(msg) if msg == "Unterminated string")
+        );
+        assert_eq!(errors[0].range.start.line_num, 0);
+        assert_eq!(errors[0].range.start.position, 3);
+        assert_eq!(errors[0].range.start.length, 4);
+
+        // The next line is lexed as usual
+        let second: Vec<_> = tokens
+            .iter()
+            .filter(|tok| tok.span().line_num == 1)
+            .map(|tok| tok.tok().clone())
+            .collect();
+        assert!(matches!(
+            second.as_slice(),
+            [Token::Ident(b), Token::Operator(Operator::Colon), Token::Ident(d)] if b == "b" && d == "d"
+        ));
+
+        // The error reaches the module. A quote in a text line is just text
+        let (_, errors) = Module::parse_str("view {\n    :\"abc\n}\nstyle s {\n    a: \"b }\n}\n");
+        let unterminated: Vec<_> = errors
+            .iter()
+            .filter(|err| {
+                matches!(&err.kind, ParseErrorKind::InvalidSyntax(msg) if msg == "Unterminated string")
+            })
+            .collect();
+        assert_eq!(unterminated.len(), 1);
+        assert_eq!(unterminated[0].range.start.line_num, 4);
+    }
+
+    #[test]
+    fn parse_errors_are_sorted_and_deduplicated() {
+        let error = |line_num, position, message: &str| ParseError {
+            kind: ParseErrorKind::InvalidSyntax(message.to_string()),
+            range: Span {
+                line_num,
+                position,
+                length: 1,
+                token_index: 0,
+            }
+            .into(),
+        };
+        let mut errors = vec![
+            error(2, 0, "Expected value"),
+            error(0, 4, "Expected comma"),
+            error(2, 0, "Expected value"),
+            error(0, 4, "Expected value"),
+        ];
+        crate::error::sort_and_dedup(&mut errors);
+
+        let found: Vec<_> = errors
+            .iter()
+            .map(|error| (error.range.start.line_num, error.kind.to_string()))
+            .collect();
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[2].0, 2);
+        // Errors at the same place are kept if their messages differ
+        assert_eq!(found[0].0, 0);
+        assert_eq!(found[1].0, 0);
+        assert_ne!(found[0].1, found[1].1);
+    }
+
+    #[test]
+    fn dashed_words_in_values_are_one_identifier() {
+        let (tokens, errors) = crate::lex("direction: vertical-reverse\nwidth: gap-4px\na-b: c");
+        assert!(errors.is_empty());
+
+        let line = |line_num| -> Vec<_> {
+            tokens
+                .iter()
+                .filter(|tok| tok.span().line_num == line_num)
+                .map(|tok| tok.tok().clone())
+                .collect()
+        };
+        assert!(matches!(
+            line(0).as_slice(),
+            [_, Token::Operator(Operator::Colon), Token::Ident(value)] if value == "vertical-reverse"
+        ));
+        assert_eq!(tokens[2].span().length, 16);
+
+        // A dash before a number is still a minus, and so is one in a key
+        assert!(matches!(
+            line(1).as_slice(),
+            [
+                _,
+                _,
+                Token::Ident(_),
+                Token::Operator(Operator::Minus),
+                Token::Integer(4, _)
+            ]
+        ));
+        assert!(matches!(
+            line(2).as_slice(),
+            [
+                Token::Ident(_),
+                Token::Operator(Operator::Minus),
+                Token::Ident(_),
+                ..
+            ]
+        ));
+    }
+
+    #[test]
+    fn enclosing_range_spans_first_to_last_token() {
+        let (tokens, _) = crate::lex("gap: 10px + 2px");
+        let value = &tokens[2..5];
+        assert!(matches!(
+            value[0].tok(),
+            Token::Integer(10, Some(Unit::Pixel))
+        ));
+        assert!(matches!(
+            value[2].tok(),
+            Token::Integer(2, Some(Unit::Pixel))
+        ));
+
+        let range = Range::enclosing(value);
+        assert_eq!(range.start, *value[0].span());
+        assert_eq!(range.end, *value[2].span());
+
+        // Out of order tokens and ones made in code don't change the range
+        let mut shuffled = vec![value[2].clone(), SpannedToken::synthetic(Token::Newline)];
+        shuffled.extend_from_slice(&value[..2]);
+        let shuffled = Range::enclosing(&shuffled);
+        assert_eq!((shuffled.start, shuffled.end), (range.start, range.end));
+
+        let merged = value[2].span().merge(value[0].span());
+        assert_eq!((merged.start, merged.end), (range.start, range.end));
+    }
+}
@@ -1,9 +1,9 @@
 use std::sync::{RwLock, RwLockReadGuard};
 
 use crate::{
-    ast::{Arg, ElementArgs, PunctuationList, Statement},
+    ast::{Arg, ElementArgs, PunctuationList, Statement, Value},
     error::{ParseError, ParseErrorKind},
-    token::{Operator, Range, SpannedToken, Token, TokenStream},
+    token::{Keyword, Operator, Range, Span, SpannedToken, Token, TokenStream},
 };
 
 pub struct Parser {
@@ -29,30 +29,70 @@ impl Parser {
     }
 
     pub fn parse(&self) -> Option<Vec<Statement>> {
-        let mut statements = Vec::new();
-        self.ignore_ws();
-        while let Some(stmt) = self.parse_statement(false) {
-            statements.push(stmt);
+        self.parse_with(|_| ())
+    }
 
-            if let Some(Token::Newline) = self.tokens.peek() {
-                self.tokens.next();
-            }
+    /// Like `parse`, calling `on_statement` with the statements parsed so far each time
+    /// another top level statement is done
+    pub fn parse_with(&self, mut on_statement: impl FnMut(&[Statement])) -> Option<Vec<Statement>> {
+        let mut statements = Vec::new();
+        loop {
             self.ignore_ws();
+            if let Some(stmt) = self.parse_statement(false) {
+                statements.push(stmt);
+                on_statement(&statements);
+                continue;
+            }
+
+            let Some(SpannedToken(span, tok)) = self.tokens.peek_spanned() else {
+                break;
+            };
+            self.add_error(ParseError {
+                kind: ParseErrorKind::InvalidSyntax(format!("Unexpected token {:?}", tok)),
+                range: Range {
+                    start: *span,
+                    end: *span,
+                },
+            });
+            self.recover();
         }
 
         Some(statements)
     }
 
+    /// Skips a statement that couldn't be parsed: the rest of its line, and everything up to the
+    /// matching brace of any brace opened on it, so parsing can pick up at the next statement.
+    /// Always moves past at least one token
+    fn recover(&self) {
+        let Some(SpannedToken(span, _)) = self.tokens.peek_spanned() else {
+            return;
+        };
+        let mut line = span.line_num;
+        let mut depth = 0usize;
+
+        while let Some(SpannedToken(span, tok)) = self.tokens.peek_spanned() {
+            if depth == 0 && span.line_num > line {
+                break;
+            }
+            match tok {
+                Token::Operator(Operator::OpenBrace) => depth += 1,
+                Token::Operator(Operator::CloseBrace) => depth = depth.saturating_sub(1),
+                _ => (),
+            }
+            line = span.line_num;
+            self.tokens.next();
+        }
+    }
+
     pub fn parse_statement(&self, in_view: bool) -> Option<Statement> {
         let tok = match self.tokens.peek() {
-            Some(Token::Ident(s)) if s == "use" => {
+            Some(Token::Keyword(Keyword::Use)) => {
                 if let Some(us) = self.parse_use() {
                     return Some(us);
                 } else {
                     None
                 }
             }
-            Some(Token::Ident(_)) => self.tokens.next(),
             Some(Token::Text(_)) if in_view => {
                 let Some(tok) = self.tokens.next() else {
                     return None;
@@ -60,40 +100,119 @@ impl Parser {
 
                 return Some(Statement::Text(tok.clone()));
             }
+            // Anything in a view that doesn't start an element is text
+            Some(tok) if in_view && !self.at_element_start() => {
+                return match tok {
+                    Token::Operator(Operator::CloseBrace) | Token::Newline => None,
+                    _ => self.parse_text_run(),
+                };
+            }
+            Some(Token::Ident(_) | Token::Keyword(_)) => self.tokens.next(),
             Some(Token::Text(_)) => self.tokens.next(),
             _ => None,
         };
 
         match self.tokens.peek() {
-            Some(_) => return self.parse_element(tok),
+            // An unnamed element has to open with its arguments or body, otherwise a stray
+            // token would be parsed as an element forever without being consumed
+            Some(Token::Operator(Operator::OpenParen | Operator::OpenBrace)) if tok.is_none() => {
+                return self.parse_element(tok)
+            }
+            Some(_) if tok.is_some() => return self.parse_element(tok),
             _ => return None,
         }
     }
 
+    /// Whether the next tokens are a name followed by arguments or a body, or an unnamed element
+    fn at_element_start(&self) -> bool {
+        let opens = |tok: Option<&SpannedToken>| {
+            matches!(
+                tok,
+                Some(SpannedToken(
+                    _,
+                    Token::Operator(Operator::OpenParen | Operator::OpenBrace)
+                ))
+            )
+        };
+
+        match self.tokens.peek_spanned() {
+            Some(SpannedToken(_, Token::Ident(_) | Token::Keyword(_))) => {
+                opens(self.tokens.peek_nth(1))
+            }
+            tok => opens(tok),
+        }
+    }
+
+    /// Collects the rest of the line up to the next element or closing brace into a single text token
+    fn parse_text_run(&self) -> Option<Statement> {
+        // Like a `:Text` line, a leading colon only marks the start of the text
+        if let (Some(SpannedToken(colon, Token::Operator(Operator::Colon))), Some(next)) =
+            (self.tokens.peek_spanned(), self.tokens.peek_nth(1))
+        {
+            let closes = matches!(
+                next.tok(),
+                Token::Operator(Operator::OpenBrace | Operator::CloseBrace)
+            );
+            if next.span().line_num == colon.line_num && !closes {
+                self.tokens.next();
+                if self.at_element_start() {
+                    self.tokens.back();
+                }
+            }
+        }
+
+        let first = *self.tokens.peek_spanned()?.span();
+        let mut text = String::new();
+        let mut end = first.position;
+
+        while let Some(SpannedToken(span, tok)) = self.tokens.peek_spanned() {
+            let stop = span.line_num != first.line_num
+                || matches!(
+                    tok,
+                    Token::Operator(Operator::OpenBrace | Operator::CloseBrace) | Token::Newline
+                );
+            if stop || (!text.is_empty() && self.at_element_start()) {
+                break;
+            }
+
+            // Whitespace isn't tokenized, so the gaps between tokens are put back from their spans
+            if !text.is_empty() {
+                text.extend((end..span.position).map(|_| ' '));
+            }
+            text.push_str(&token_source(tok));
+            end = span.position + span.length;
+
+            self.tokens.next();
+        }
+
+        if text.is_empty() {
+            return None;
+        }
+
+        Some(Statement::Text(SpannedToken::new(
+            Token::Text(text),
+            Span {
+                length: end - first.position,
+                ..first
+            },
+        )))
+    }
+
     pub fn parse_use(&self) -> Option<Statement> {
         let token = self.tokens.next();
         let mut args = PunctuationList::new();
-        let mut last_line = token.map(|l| l.span().line_num);
-        while let Some(Token::Ident(_)) = self.tokens.peek() {
-            let tok = self.tokens.next();
-
-            match (self.tokens.peek(), tok) {
-                (Some(Token::Operator(Operator::Dot)), Some(id)) => {
-                    let dot = self.tokens.next();
-                    args.push(id.clone(), dot.cloned());
-                }
-                (_, Some(id)) => {
-                    let lline = *last_line.get_or_insert(id.span().line_num);
-                    if lline == id.span().line_num {
-                        args.push(id.clone(), None);
-                    } else {
-                        self.tokens.back();
-                    }
-                    break;
-                }
-                _ => break,
+
+        // A use path is newline terminated, so none of its segments may skip lines
+        while let Some(id) = self.expect_path_segment_inline() {
+            let dot = self.expect_operator_inline(Operator::Dot);
+            let done = dot.is_none();
+
+            args.push(id, dot.cloned());
+            if done {
+                break;
             }
         }
+
         Some(Statement::UseStatement {
             token: token.cloned(),
             args,
@@ -107,12 +226,34 @@ impl Parser {
             None
         };
 
-        let open_brace = self.expect_operator(Operator::OpenBrace);
+        // Elements with nothing inside, e.g. `image (src: "a.png")`, can leave out the body
+        let Some(open_brace) = self.expect_operator(Operator::OpenBrace) else {
+            return Some(Statement::Element {
+                arguments: args,
+                body: vec![],
+                body_range: None,
+                token: ident.cloned(),
+            });
+        };
+        let open_brace = Some(open_brace);
         let statements = if let Some(Token::Operator(Operator::CloseBrace)) = self.tokens.peek() {
+            // An empty style block is still a style block rather than an element
+            if let Some(SpannedToken(_, Token::Keyword(Keyword::Style))) = ident {
+                let close_brace = self.tokens.next();
+
+                return Some(Statement::Style {
+                    body: vec![],
+                    body_range: open_brace.zip(close_brace).map(|(o, c)| Range {
+                        start: *o.span(),
+                        end: *c.span(),
+                    }),
+                    token: ident.cloned(),
+                });
+            }
             vec![]
         } else {
             match ident {
-                Some(SpannedToken(_, Token::Ident(i))) if &i == &"style" => {
+                Some(SpannedToken(_, Token::Keyword(Keyword::Style))) => {
                     let mut statements = Vec::new();
                     while let Some(stmt) = self.parse_style_statement() {
                         statements.push(stmt);
@@ -122,8 +263,8 @@ impl Parser {
                             return Some(Statement::Style {
                                 body: statements,
                                 body_range: open_brace.zip(close_brace).map(|(o, c)| Range {
-                                    start: o.span().clone(),
-                                    end: c.span().clone(),
+                                    start: *o.span(),
+                                    end: *c.span(),
                                 }),
                                 token: ident.cloned(),
                             });
@@ -132,11 +273,8 @@ impl Parser {
                     vec![]
                 }
                 _ => {
-                    let view = if let Some(SpannedToken(_, Token::Ident(s))) = ident {
-                        s == "view"
-                    } else {
-                        false
-                    };
+                    let view =
+                        matches!(ident, Some(SpannedToken(_, Token::Keyword(Keyword::View))));
                     let mut statements = Vec::new();
                     while let Some(stmt) = self.parse_statement(view) {
                         statements.push(stmt);
@@ -155,8 +293,8 @@ impl Parser {
             arguments: args,
             body: statements,
             body_range: open_brace.zip(close_brace).map(|(o, c)| Range {
-                start: o.span().clone(),
-                end: c.span().clone(),
+                start: *o.span(),
+                end: *c.span(),
             }),
             token: ident.cloned(),
         })
@@ -184,7 +322,6 @@ impl Parser {
         // }
     }
 
-
     pub fn parse_args(&self) -> Option<ElementArgs> {
         let open = self.expect_operator(Operator::OpenParen);
 
@@ -203,15 +340,17 @@ impl Parser {
                         args.push(arg, comma);
                         break;
                     }
-                    if comma.is_none() {
+                    let Some(comma) = comma else {
                         self.add_error(ParseError {
                             kind: ParseErrorKind::InvalidSyntax(format!(
                                 "Expected comma in arguments!"
                             )),
-                            range: Range::default(),
+                            range: self.error_range(),
                         });
-                    }
-                    args.push_sep(arg, comma.unwrap());
+                        args.push(arg, None);
+                        break;
+                    };
+                    args.push_sep(arg, comma);
                 }
                 args
             }
@@ -230,7 +369,7 @@ impl Parser {
         } else {
             self.add_error(ParseError {
                 kind: ParseErrorKind::InvalidSyntax(format!("Unable to parse arg brackets!")),
-                range: Range::default(),
+                range: self.error_range(),
             });
             Some(ElementArgs {
                 items: args,
@@ -242,7 +381,22 @@ impl Parser {
     fn parse_arg(&self) -> Option<Arg> {
         let ident = self.expect(Token::Ident("".into()));
         let colon = self.expect_operator(Operator::Colon);
-        let expression = self.parse_value();
+
+        // A positional function call, e.g. the color in `shadow(0, 2px, 8px, rgb(0, 0, 0))`
+        if let (Some(ident), None, Some(Token::Operator(Operator::OpenParen))) =
+            (ident, colon, self.tokens.peek())
+        {
+            return Some(Arg {
+                name: None,
+                colon: None,
+                value: Some(Value::Function {
+                    ident: Some(ident.clone()),
+                    args: self.parse_args()?,
+                }),
+            });
+        }
+
+        let expression = self.parse_expression();
 
         match (ident, colon, expression) {
             (Some(ident), Some(colon), Some(expr)) => Some(Arg {
@@ -250,10 +404,16 @@ impl Parser {
                 colon: Some(colon.clone()),
                 value: Some(expr),
             }),
+            // Positional values, e.g. the channels in `rgb(0, 0, 0)`
+            (None, None, Some(expr)) => Some(Arg {
+                name: None,
+                colon: None,
+                value: Some(expr),
+            }),
             (ident, colon, expression) => {
                 self.add_error(ParseError {
                     kind: ParseErrorKind::InvalidSyntax(format!("Unable to parse arg fields!")),
-                    range: Range::default(),
+                    range: self.error_range(),
                 });
                 Some(Arg {
                     name: ident.cloned(),
@@ -288,12 +448,59 @@ impl Parser {
         None
     }
 
+    /// Where to point an error: the next token, or the last one at the end of the input
+    pub(crate) fn error_range(&self) -> Range {
+        self.tokens
+            .peek_spanned()
+            .or_else(|| self.tokens.last())
+            .map(|tok| Range::from(tok.0))
+            .unwrap_or_default()
+    }
+
     pub fn ignore_ws(&self) {
         while let Some(Token::Newline) = self.tokens.peek() {
             self.tokens.next();
         }
     }
 
+    /// Whether the line of the last consumed token has no tokens left.
+    ///
+    /// The lexer doesn't emit newlines between lines, so this compares line numbers instead
+    pub fn at_line_end(&self) -> bool {
+        match (self.tokens.last(), self.tokens.peek_spanned()) {
+            (_, None | Some(SpannedToken(_, Token::Newline))) => true,
+            (Some(last), Some(next)) => next.span().line_num > last.span().line_num,
+            (None, Some(_)) => false,
+        }
+    }
+
+    /// Same as `expect_operator` but newline significant; fails instead of moving onto the next line
+    pub(crate) fn expect_operator_inline(&self, operator: Operator) -> Option<&SpannedToken> {
+        if self.at_line_end() {
+            return None;
+        }
+        self.expect_operator(operator)
+    }
+
+    /// Same as `expect` but newline significant; fails instead of moving onto the next line
+    /// Path segments may name reserved blocks (`use setup.style`), so keywords are accepted as identifiers
+    pub(crate) fn expect_path_segment_inline(&self) -> Option<SpannedToken> {
+        if self.at_line_end() {
+            return None;
+        }
+        match self.tokens.peek() {
+            Some(Token::Ident(_)) => self.tokens.next().cloned(),
+            Some(Token::Keyword(_)) => {
+                if let Some(SpannedToken(span, Token::Keyword(k))) = self.tokens.next() {
+                    Some(SpannedToken::new(Token::Ident(k.as_str().into()), *span))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
     pub(crate) fn expect(&self, token_type: Token) -> Option<&SpannedToken> {
         self.ignore_ws();
         let Some(tok) = self.tokens.peek() else {
@@ -306,3 +513,17 @@ impl Parser {
         None
     }
 }
+
+/// The text a token was lexed from
+fn token_source(tok: &Token) -> String {
+    match tok {
+        Token::Ident(s) | Token::Text(s) => s.clone(),
+        Token::Keyword(k) => k.as_str().to_string(),
+        Token::Operator(o) => o.as_str().trim_matches('`').to_string(),
+        Token::Integer(i, Some(u)) => format!("{}{}", i, u),
+        Token::Float(f, Some(u)) => format!("{}{}", f, u),
+        Token::Integer(i, None) => i.to_string(),
+        Token::Float(f, None) => f.to_string(),
+        Token::Newline | Token::Whitespace => String::new(),
+    }
+}
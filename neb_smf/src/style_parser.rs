@@ -1,8 +1,8 @@
 use crate::{
-    ast::{PunctuationList, StyleStatement, Value},
+    ast::{AstNode, PunctuationList, StyleStatement, Value},
     error::{ParseError, ParseErrorKind},
     parser::Parser,
-    token::{Operator, Range, SpannedToken, Token},
+    token::{Operator, Range, Span, SpannedToken, Token},
 };
 
 impl Parser {
@@ -12,7 +12,19 @@ impl Parser {
             _ => None,
         };
 
+        let condition = match self.tokens.peek() {
+            Some(Token::Operator(Operator::At)) => {
+                self.tokens.next();
+                self.parse_args()
+            }
+            _ => None,
+        };
+
         let open_brace = self.expect_operator(Operator::OpenBrace);
+        if ident.is_none() && condition.is_none() && open_brace.is_none() {
+            return None;
+        }
+
         let mut statements = Vec::new();
 
         while let Some(statement) = self.parse_style_element() {
@@ -27,10 +39,11 @@ impl Parser {
         Some(StyleStatement::Style {
             body: statements,
             body_range: open_brace.zip(close_brace).map(|(o, c)| Range {
-                start: o.span().clone(),
-                end: c.span().clone(),
+                start: *o.span(),
+                end: *c.span(),
             }),
             token: ident.cloned(),
+            condition,
         })
     }
 
@@ -39,7 +52,7 @@ impl Parser {
             Some(Token::Ident(_)) => self.tokens.next().cloned(),
             Some(Token::Text(_)) => {
                 if let Some(SpannedToken(span, Token::Text(i))) = self.tokens.next() {
-                    Some(SpannedToken::new(Token::Ident(i.clone()), span.clone()))
+                    Some(SpannedToken::new(Token::Ident(i.clone()), *span))
                 } else {
                     None
                 }
@@ -47,14 +60,38 @@ impl Parser {
             _ => None,
         };
 
-        let colon = self.expect_operator(Operator::Colon);
+        // Style elements are newline terminated; don't let a missing colon pull in the next line
+        let colon = self.expect_operator_inline(Operator::Colon);
+
+        // Parsed as an expression so arithmetic like `10px + 4px` stays in one value. A brace
+        // without a colon before it opens a block rather than an object value, and a missing
+        // value mustn't take the next line's key
+        let value = match (colon, self.tokens.peek()) {
+            (None, Some(Token::Operator(Operator::OpenBrace))) => None,
+            _ if (key.is_some() || colon.is_some()) && self.at_line_end() => None,
+            _ => self.parse_expression(),
+        };
+
+        // `!important`, or just `!`, makes the property win over the ones in other classes
+        let bang = self.expect_operator_inline(Operator::Bang).cloned();
+        let important = match self.tokens.peek() {
+            Some(Token::Ident(i)) if bang.is_some() && i == "important" && !self.at_line_end() => {
+                self.tokens.next().cloned()
+            }
+            _ => None,
+        };
 
-        let value = self.parse_value();
+        // Nothing here is part of a style element, so stop instead of looping on the same token
+        if key.is_none() && colon.is_none() && value.is_none() {
+            return None;
+        }
 
         Some(StyleStatement::StyleElement {
             key: key,
             colon: colon.cloned(),
             value,
+            bang,
+            important,
         })
     }
 
@@ -76,15 +113,17 @@ impl Parser {
                         args.push(arg, comma);
                         break;
                     }
-                    if comma.is_none() {
+                    let Some(comma) = comma else {
                         self.add_error(ParseError {
                             kind: ParseErrorKind::InvalidSyntax(format!(
                                 "Expected comma in arguments!"
                             )),
-                            range: Range::default(),
+                            range: self.error_range(),
                         });
-                    }
-                    args.push_sep(arg, comma.unwrap());
+                        args.push(arg, None);
+                        break;
+                    };
+                    args.push_sep(arg, comma);
                 }
                 args
             }
@@ -100,7 +139,7 @@ impl Parser {
         } else {
             self.add_error(ParseError {
                 kind: ParseErrorKind::InvalidSyntax(format!("Unable to parse arg brackets!")),
-                range: Range::default(),
+                range: self.error_range(),
             });
             Some(Value::Array {
                 values: args,
@@ -109,15 +148,148 @@ impl Parser {
         }
     }
 
+    /// `{ key: value, ... }` in a value position
+    pub fn parse_object(&self) -> Option<Value> {
+        let open = self.expect_operator(Operator::OpenBrace)?;
+
+        let mut entries = Vec::new();
+        while let Some(Token::Ident(_)) = self.tokens.peek() {
+            let Some(SpannedToken(_, Token::Ident(key))) = self.tokens.next().cloned() else {
+                break;
+            };
+            let value = match self.expect_operator(Operator::Colon) {
+                Some(_) => self.parse_expression(),
+                None => None,
+            };
+            let Some(value) = value else {
+                self.add_error(ParseError {
+                    kind: ParseErrorKind::InvalidSyntax(format!(
+                        "Expected value for `{}` in object!",
+                        key
+                    )),
+                    range: self.error_range(),
+                });
+                break;
+            };
+            entries.push((key, value));
+
+            if self.expect_operator(Operator::Comma).is_none() {
+                break;
+            }
+        }
+
+        // An object that isn't closed runs to the end of its last value
+        let end = match self.expect_operator(Operator::CloseBrace) {
+            Some(close) => close.0,
+            None => {
+                self.add_error(ParseError {
+                    kind: ParseErrorKind::InvalidSyntax(
+                        "Expected `}` to close object!".to_string(),
+                    ),
+                    range: self.error_range(),
+                });
+                entries
+                    .last()
+                    .map_or(open.0, |(_, value)| value.get_range().end)
+            }
+        };
+
+        Some(Value::Object {
+            entries,
+            range: Range::from((open.0, end)),
+        })
+    }
+
     pub fn parse_value(&self) -> Option<Value> {
+        match self.parse_single_value()? {
+            value @ (Value::Integer(..) | Value::Float(..)) => self.parse_shorthand(value),
+            value => Some(value),
+        }
+    }
+
+    /// A value followed by any number of `+ - * /` operations, folded into `Value::Binary` with
+    /// `*` and `/` binding tighter
+    pub fn parse_expression(&self) -> Option<Value> {
+        let left = self.parse_value()?;
+        Some(self.parse_binary(left, 1))
+    }
+
+    fn parse_binary(&self, mut left: Value, min_precedence: u8) -> Value {
+        while let Some(Token::Operator(op)) = self.tokens.peek() {
+            let Some(precedence) = op.precedence().filter(|p| *p >= min_precedence) else {
+                break;
+            };
+            let symbol = op.as_str();
+            let op = self.tokens.next().unwrap().clone();
+
+            let Some(mut right) = self.parse_single_value() else {
+                self.add_error(ParseError {
+                    kind: ParseErrorKind::InvalidSyntax(format!(
+                        "Expected value after {}!",
+                        symbol
+                    )),
+                    range: self.error_range(),
+                });
+                break;
+            };
+
+            while let Some(Token::Operator(next)) = self.tokens.peek() {
+                match next.precedence() {
+                    Some(next) if next > precedence => right = self.parse_binary(right, next),
+                    _ => break,
+                }
+            }
+
+            left = Value::Binary {
+                left: Box::new(left),
+                op,
+                right: Box::new(right),
+            };
+        }
+        left
+    }
+
+    /// Collects adjacent unit literals that aren't separated by commas
+    /// (e.g. `padding: 4px 8px`) into a single `Value::Tuple`.
+    ///
+    /// Only whitespace may separate the values; any operator or line break between them ends the list
+    fn parse_shorthand(&self, first: Value) -> Option<Value> {
+        let mut values = vec![first];
+
+        while let Some(Token::Integer(..) | Token::Float(..)) = self.tokens.peek() {
+            if self.at_line_end() {
+                break;
+            }
+            let Some(value) = self.parse_single_value() else {
+                break;
+            };
+            values.push(value);
+        }
+
+        if values.len() == 1 {
+            values.pop()
+        } else {
+            Some(Value::Tuple(values))
+        }
+    }
+
+    fn parse_single_value(&self) -> Option<Value> {
         match self.tokens.peek() {
             Some(Token::Operator(Operator::OpenSquare)) => self.parse_array(),
+            Some(Token::Operator(Operator::OpenBrace)) => self.parse_object(),
             Some(Token::Integer(i, u)) => {
                 Some(Value::Integer(*i, *u, self.tokens.next().cloned().unwrap()))
             }
             Some(Token::Float(i, u)) => {
                 Some(Value::Float(*i, *u, self.tokens.next().cloned().unwrap()))
             }
+            // A minus in front of a number negates it, e.g. `rgb(300, -1, 0)`
+            Some(Token::Operator(Operator::Minus)) => match self.tokens.peek_nth(1) {
+                Some(SpannedToken(_, Token::Integer(..) | Token::Float(..))) => {
+                    self.parse_negated()
+                }
+                _ => None,
+            },
             Some(Token::Ident(_)) => {
                 let ident = self.tokens.next().unwrap();
 
@@ -134,6 +306,30 @@ impl Parser {
         }
     }
 
+    /// `-` and the number after it, as one literal spanning both
+    fn parse_negated(&self) -> Option<Value> {
+        let minus = self.tokens.next()?.0;
+        let SpannedToken(span, token) = self.tokens.next()?;
+        let span = Span {
+            length: span.position + span.length - minus.position,
+            ..minus
+        };
+
+        match *token {
+            Token::Integer(i, u) => Some(Value::Integer(
+                -i,
+                u,
+                SpannedToken::new(Token::Integer(-i, u), span),
+            )),
+            Token::Float(f, u) => Some(Value::Float(
+                -f,
+                u,
+                SpannedToken::new(Token::Float(-f, u), span),
+            )),
+            _ => None,
+        }
+    }
+
     // fn parse_style_args(&self) -> Vec<Value> {
     //     let open_paren= self.expect_operator(Operator::OpenParen);
 
@@ -1,8 +1,7 @@
-use std::{sync::RwLock, fmt::Display};
+use std::{fmt::Display, sync::RwLock};
 
 use neb_util::format::{NodeDisplay, TreeDisplay};
 
-
 #[derive(Debug, PartialEq, Clone)]
 pub enum Operator {
     OpenSquare,
@@ -14,6 +13,13 @@ pub enum Operator {
     Dot,
     Colon,
     Comma,
+    At,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    /// Marks a style property `!important`
+    Bang,
 }
 
 impl Operator {
@@ -28,38 +34,89 @@ impl Operator {
             Self::Dot => "`.`",
             Self::Colon => "`:`",
             Self::Comma => "`,`",
+            Self::At => "`@`",
+            Self::Plus => "`+`",
+            Self::Minus => "`-`",
+            Self::Star => "`*`",
+            Self::Slash => "`/`",
+            Self::Bang => "`!`",
+        }
+    }
+
+    /// How tightly an arithmetic operator binds, `None` for everything else
+    pub fn precedence(&self) -> Option<u8> {
+        match self {
+            Self::Plus | Self::Minus => Some(1),
+            Self::Star | Self::Slash => Some(2),
+            _ => None,
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Keyword {
-    // Output,
+    Use,
+    Style,
+    View,
+    Setup,
 }
 
+impl Keyword {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Use => "use",
+            Self::Style => "style",
+            Self::View => "view",
+            Self::Setup => "setup",
+        }
+    }
+
+    pub fn from_ident(ident: &str) -> Option<Keyword> {
+        match ident {
+            "use" => Some(Self::Use),
+            "style" => Some(Self::Style),
+            "view" => Some(Self::View),
+            "setup" => Some(Self::Setup),
+            _ => None,
+        }
+    }
+}
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Unit {
-    Pixel
+    Pixel,
+    Percent,
+    /// A share of the space left over, for grid tracks
+    Fraction,
+}
+
+impl Unit {
+    /// The unit of a size written with `unit`. A number without one is in pixels, like `gap: 4`,
+    /// so `min(100%, 600)` picks between 100% and 600px. Evaluating a module and drawing a
+    /// document both read sizes with this
+    pub fn of_size(unit: Option<Unit>) -> Unit {
+        unit.unwrap_or(Unit::Pixel)
+    }
 }
 
 impl Display for Unit {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-       match self {
-            Unit::Pixel => f.write_str("px") 
-       } 
+        match self {
+            Unit::Pixel => f.write_str("px"),
+            Unit::Percent => f.write_str("%"),
+            Unit::Fraction => f.write_str("fr"),
+        }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     Ident(String),
     Text(String),
-    Integer(u64, Option<Unit>),
+    Integer(i64, Option<Unit>),
     Float(f64, Option<Unit>),
     Operator(Operator),
-
-    // Keyword(Keyword),
+    Keyword(Keyword),
     Newline,
     Whitespace,
 }
@@ -70,6 +127,7 @@ impl NodeDisplay for Token {
             Self::Ident(s) => f.write_str(s),
             Self::Text(s) => f.write_str(s),
             Self::Operator(o) => f.write_str(o.as_str()),
+            Self::Keyword(k) => f.write_str(k.as_str()),
             Self::Integer(i, Some(u)) => write!(f, "{}{}", i, u),
             Self::Float(fl, Some(u)) => write!(f, "{}{}", fl, u),
             Self::Integer(i, _) => write!(f, "{}", i),
@@ -106,6 +164,23 @@ impl<'a> TokenStream {
         Some(&self.tokens[next_index].tok())
     }
 
+    pub fn peek_spanned(&'a self) -> Option<&'a SpannedToken> {
+        let next_index = *self.next_index.read().unwrap();
+        self.tokens.get(next_index)
+    }
+
+    /// Looks `offset` tokens past the next one without consuming anything
+    pub fn peek_nth(&'a self, offset: usize) -> Option<&'a SpannedToken> {
+        let next_index = *self.next_index.read().unwrap();
+        self.tokens.get(next_index + offset)
+    }
+
+    /// The most recently consumed token
+    pub fn last(&'a self) -> Option<&'a SpannedToken> {
+        let next_index = *self.next_index.read().unwrap();
+        next_index.checked_sub(1).and_then(|i| self.tokens.get(i))
+    }
+
     pub fn back(&'a self) {
         let mut s = self.next_index.write().unwrap();
         *s -= 1;
@@ -138,6 +213,12 @@ impl SpannedToken {
         Self(span, token)
     }
 
+    /// A token that wasn't parsed from a file, e.g. in a default style or a substituted
+    /// argument. Its span is `Span::SYNTHETIC`, which tooling skips
+    pub fn synthetic(token: Token) -> Self {
+        Self(Span::SYNTHETIC, token)
+    }
+
     pub fn tok(&self) -> &Token {
         &self.1
     }
@@ -173,6 +254,19 @@ pub struct Span {
 }
 
 impl Span {
+    /// The span of tokens made in code. It's on a line past the end of any file, so it never
+    /// matches a position in one
+    pub const SYNTHETIC: Span = Span {
+        line_num: u32::MAX,
+        position: 0,
+        length: 0,
+        token_index: u32::MAX,
+    };
+
+    pub fn is_synthetic(&self) -> bool {
+        *self == Span::SYNTHETIC
+    }
+
     pub fn contains(&self, other: &Span) -> bool {
         if self.line_num == other.line_num {
             if other.position < self.position + self.length {
@@ -217,6 +311,19 @@ impl Span {
         }
         false
     }
+
+    /// The range from whichever span starts first to whichever ends last, in either order
+    pub fn merge(&self, other: &Span) -> Range {
+        let end = |span: &Span| (span.line_num, span.position + span.length);
+        Range {
+            start: *self.min(other),
+            end: if end(other) > end(self) {
+                *other
+            } else {
+                *self
+            },
+        }
+    }
 }
 
 impl From<SpannedToken> for Span {
@@ -281,6 +388,23 @@ impl Range {
     pub fn contains(&self, span: &Span) -> bool {
         span >= &self.start && span <= &self.end
     }
+
+    /// The range covering all of `tokens`, e.g. a whole argument list or a value written over
+    /// several tokens. Synthetic tokens aren't in the source, so they are left out
+    pub fn enclosing(tokens: &[SpannedToken]) -> Range {
+        let mut spans = tokens
+            .iter()
+            .map(SpannedToken::span)
+            .filter(|span| !span.is_synthetic());
+        let Some(first) = spans.next() else {
+            return Range::default();
+        };
+
+        spans.fold(Range::from(*first), |range, span| Range {
+            start: range.start.merge(span).start,
+            end: range.end.merge(span).end,
+        })
+    }
 }
 
 impl From<(&Range, &Range)> for Range {
@@ -357,3 +481,63 @@ impl TreeDisplay for Range {
         }
     }
 }
+
+/// Conversions to and from the language server protocol's positions.
+/// LSP ranges are end exclusive, so the end of a range is placed after its last token
+#[cfg(feature = "lsp")]
+mod lsp {
+    use tower_lsp::lsp_types;
+
+    use super::{Range, Span};
+
+    impl From<&Span> for lsp_types::Position {
+        fn from(value: &Span) -> Self {
+            lsp_types::Position::new(value.line_num, value.position)
+        }
+    }
+
+    impl From<lsp_types::Position> for Span {
+        fn from(value: lsp_types::Position) -> Self {
+            Span {
+                line_num: value.line,
+                position: value.character,
+                ..Default::default()
+            }
+        }
+    }
+
+    impl From<&Span> for lsp_types::Range {
+        fn from(value: &Span) -> Self {
+            lsp_types::Range::new(
+                value.into(),
+                lsp_types::Position::new(value.line_num, value.position + value.length),
+            )
+        }
+    }
+
+    impl From<&Range> for lsp_types::Range {
+        fn from(value: &Range) -> Self {
+            // A range on a single token is just that token; otherwise it runs to the end of the last one
+            if value.start == value.end {
+                (&value.start).into()
+            } else {
+                lsp_types::Range::new(
+                    (&value.start).into(),
+                    lsp_types::Position::new(
+                        value.end.line_num,
+                        value.end.position + value.end.length,
+                    ),
+                )
+            }
+        }
+    }
+
+    impl From<lsp_types::Range> for Range {
+        fn from(value: lsp_types::Range) -> Self {
+            Range {
+                start: value.start.into(),
+                end: value.end.into(),
+            }
+        }
+    }
+}
@@ -1,4 +1,76 @@
 mod refs;
 pub use refs::*;
 
-pub mod format;
\ No newline at end of file
+pub mod format;
+#[cfg(test)]
+mod tests {
+    use crate::Rf;
+
+    #[test]
+    fn upgraded_weak_reads_the_same_value() {
+        let strong = Rf::new(5);
+        let weak = strong.downgrade();
+
+        let upgraded = weak.upgrade().unwrap();
+        *upgraded.borrow_mut() += 1;
+        assert_eq!(*strong.borrow(), 6);
+    }
+
+    #[test]
+    fn weak_is_gone_after_the_last_rf_drops() {
+        let strong = Rf::new(String::from("node"));
+        let weak = strong.downgrade();
+
+        let other = strong.clone();
+        drop(strong);
+        assert!(weak.upgrade().is_some());
+
+        drop(other);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn poisoned_lock_is_recovered() {
+        let value = Rf::new(vec![1, 2]);
+
+        let other = value.clone();
+        let _ = std::thread::spawn(move || {
+            let mut held = other.borrow_mut();
+            held.push(3);
+            panic!("poison the lock");
+        })
+        .join();
+        assert!(value.is_poisoned());
+
+        assert_eq!(*value.try_borrow().unwrap(), [1, 2, 3]);
+        assert!(!value.is_poisoned());
+
+        // Plain borrows work again too
+        value.borrow_mut().push(4);
+        assert_eq!(*value.borrow(), [1, 2, 3, 4]);
+
+        let held = value.borrow();
+        assert!(value.try_borrow_mut().is_none());
+        assert!(value.try_borrow().is_some());
+        drop(held);
+        assert!(value.try_borrow_mut().is_some());
+    }
+
+    #[test]
+    fn waiting_borrows_recover_a_poisoned_lock() {
+        let value = Rf::new(1);
+
+        let other = value.clone();
+        let _ = std::thread::spawn(move || {
+            *other.borrow_mut() += 1;
+            let _held = other.borrow_mut();
+            panic!("poison the lock");
+        })
+        .join();
+        assert!(value.is_poisoned());
+
+        *value.borrow_mut_or_recover() += 1;
+        assert!(!value.is_poisoned());
+        assert_eq!(*value.borrow_or_recover(), 3);
+    }
+}
@@ -1,6 +1,6 @@
 use std::{
     ops::Deref,
-    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError, Weak},
 };
 
 pub struct Rf<T: ?Sized>(pub Arc<RwLock<T>>);
@@ -38,6 +38,56 @@ impl<T> Rf<T> {
         self.read().unwrap()
         // self.().unwrap()
     }
+
+    /// Like `borrow`, but recovers a poisoned lock the same way as `try_borrow`
+    pub fn borrow_or_recover(&self) -> RwLockReadGuard<'_, T> {
+        self.read().unwrap_or_else(|poisoned| {
+            self.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+
+    /// Like `borrow_mut`, but recovers a poisoned lock the same way as `try_borrow`
+    pub fn borrow_mut_or_recover(&self) -> RwLockWriteGuard<'_, T> {
+        self.write().unwrap_or_else(|poisoned| {
+            self.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+
+    /// Like `borrow`, but `None` instead of waiting while the value is borrowed mutably.
+    /// A lock poisoned by a thread that panicked while holding it is recovered rather than
+    /// panicking, so one failed request doesn't take every later one down with it
+    pub fn try_borrow(&self) -> Option<RwLockReadGuard<'_, T>> {
+        match self.try_read() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(poisoned)) => {
+                self.clear_poison();
+                Some(poisoned.into_inner())
+            }
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+
+    /// Like `borrow_mut`, but `None` instead of waiting while the value is borrowed.
+    /// Recovers a poisoned lock the same way as `try_borrow`
+    pub fn try_borrow_mut(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        match self.try_write() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(poisoned)) => {
+                self.clear_poison();
+                Some(poisoned.into_inner())
+            }
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+}
+
+impl<T: ?Sized> Rf<T> {
+    /// A reference that doesn't keep the value alive, e.g. for pointing back at a parent
+    pub fn downgrade(&self) -> WeakRf<T> {
+        WeakRf(Arc::downgrade(&self.0))
+    }
 }
 
 impl<T> From<T> for Rf<T> {
@@ -45,3 +95,19 @@ impl<T> From<T> for Rf<T> {
         Rf::new(t)
     }
 }
+
+/// The weak counterpart of `Rf`, made with `Rf::downgrade`
+pub struct WeakRf<T: ?Sized>(pub Weak<RwLock<T>>);
+
+impl<T: ?Sized> Clone for WeakRf<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: ?Sized> WeakRf<T> {
+    /// `None` once every `Rf` to the value has been dropped
+    pub fn upgrade(&self) -> Option<Rf<T>> {
+        self.0.upgrade().map(Rf)
+    }
+}
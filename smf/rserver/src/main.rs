@@ -1,11 +1,15 @@
 #![feature(box_patterns)]
 
 use std::collections::{HashMap, HashSet};
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
 
 use neb_smf::ast::{AstNode, ElementArgs, Statement, StyleStatement, Value};
+use neb_smf::direction::Direction;
+use neb_smf::lexer::tokens;
 use neb_smf::token::{Operator, Span, SpannedToken, Token};
-use neb_smf::{Module, ModuleDescender, MutModuleDescender, SymbolKind};
+use neb_smf::{Module, ModuleDescender, MutModuleDescender, SymbolKind, TokenContext};
+use neb_util::Rf;
 use tokio::net::TcpListener;
 use tower_lsp::jsonrpc::Result;
 use tower_lsp::lsp_types::request::Request;
@@ -51,6 +55,8 @@ pub struct SemanticTokenBuilder {
     tokens: Vec<SemanticToken>,
     last_line: u32,
     last_pos: u32,
+    /// Tokens outside of this are dropped
+    range: Option<Range>,
 }
 
 impl SemanticTokenBuilder {
@@ -59,10 +65,33 @@ impl SemanticTokenBuilder {
             tokens: Vec::new(),
             last_line: 0,
             last_pos: 0,
+            range: None,
+        }
+    }
+
+    /// Only keeps tokens that overlap `range`. Dropped tokens don't move the delta encoding,
+    /// so the first kept token is relative to the start of the file like in a full response
+    pub fn with_range(range: Range) -> SemanticTokenBuilder {
+        SemanticTokenBuilder {
+            range: Some(range),
+            ..SemanticTokenBuilder::new()
         }
     }
 
     pub fn push(&mut self, line: u32, position: u32, length: u32, token: u32, modifier: u32) {
+        // Values made in code aren't anywhere in the file
+        if line == Span::SYNTHETIC.line_num {
+            return;
+        }
+
+        if let Some(range) = &self.range {
+            let start = Position::new(line, position);
+            let end = Position::new(line, position + length);
+            if end <= range.start || start >= range.end {
+                return;
+            }
+        }
+
         if self.last_line == line {
             let delta_pos = position - self.last_pos;
             self.last_pos = position;
@@ -92,13 +121,18 @@ impl SemanticTokenBuilder {
     }
 }
 
-const PROPERTY_COMPLETES: &[&str] = &["class"];
+const PROPERTY_COMPLETES: &[&str] = &["class", "name"];
 
 struct Backend {
     element_names: HashSet<String>,
     style_enum: HashMap<String, CompletionType>,
 
-    documents: RwLock<HashMap<Url, Module>>,
+    /// Parsed documents by uri. Requests wait for an edit being applied, so they answer for the
+    /// text the client has. A map poisoned by a panicking handler is recovered
+    documents: Rf<HashMap<Url, Module>>,
+    /// The last semantic tokens sent for each document and their result id, for delta requests
+    semantic_results: RwLock<HashMap<Url, (String, Vec<SemanticToken>)>>,
+    next_result_id: AtomicUsize,
     client: Arc<Client>,
 }
 
@@ -250,8 +284,10 @@ impl Backend {
             }
             StyleStatement::StyleElement {
                 key,
-                colon: _,
                 value,
+                bang,
+                important,
+                ..
             } => {
                 if let Some(key @ SpannedToken(_, Token::Ident(_key_str))) = key {
                     builder.push(
@@ -266,6 +302,16 @@ impl Backend {
                 if let Some(value) = value {
                     self.recurse_value(value, module, key, scope_index, builder)
                 }
+
+                for token in [bang, important].into_iter().flatten() {
+                    builder.push(
+                        token.span().line_num,
+                        token.span().position,
+                        token.span().length,
+                        get_stype_index(SemanticTokenType::MODIFIER),
+                        0,
+                    );
+                }
             }
         }
     }
@@ -308,14 +354,22 @@ impl Backend {
                 token,
                 ..
             } => {
-                if let Some(token @ SpannedToken(_, Token::Ident(i))) = token {
-                    builder.push(
+                match token {
+                    Some(token @ SpannedToken(_, Token::Keyword(_))) => builder.push(
+                        token.span().line_num,
+                        token.span().position,
+                        token.span().length,
+                        get_stype_index(SemanticTokenType::KEYWORD),
+                        0,
+                    ),
+                    Some(token @ SpannedToken(_, Token::Ident(i))) => builder.push(
                         token.span().line_num,
                         token.span().position,
                         token.span().length,
                         get_stype_index(i.clone().into()),
                         0,
-                    );
+                    ),
+                    _ => (),
                 }
 
                 if let Some(args) = arguments {
@@ -330,12 +384,12 @@ impl Backend {
                 }
             }
             Statement::Style { body, token, .. } => {
-                if let Some(token @ SpannedToken(_, Token::Ident(i))) = token {
+                if let Some(token @ SpannedToken(_, Token::Keyword(_))) = token {
                     builder.push(
                         token.span().line_num,
                         token.span().position,
                         token.span().length,
-                        get_stype_index_from_str(&i),
+                        get_stype_index(SemanticTokenType::KEYWORD),
                         0,
                     );
                 }
@@ -377,10 +431,9 @@ impl Backend {
                 });
             }
             Statement::Text(txt) => {
-                println!("text {:?}", txt.span());
                 builder.push(
                     txt.span().line_num,
-                    txt.span().position + 1,
+                    txt.span().position,
                     txt.span().length,
                     get_stype_index_from_str("string"),
                     0,
@@ -389,301 +442,408 @@ impl Backend {
         }
     }
 
-    fn bsearch_value_with_key(
+    /// Semantic tokens for the whole module, or only the statements overlapping `range`
+    fn semantic_tokens(&self, module: &Module, range: Option<Range>) -> Vec<SemanticToken> {
+        let mut builder = match range {
+            Some(range) => SemanticTokenBuilder::with_range(range),
+            None => SemanticTokenBuilder::new(),
+        };
+        let mut scope = Vec::with_capacity(50);
+        scope.push(0);
+        for (i, stmt) in module.stmts.iter().enumerate() {
+            if let Some(range) = &range {
+                let lines = stmt.get_range();
+                if lines.end.line_num < range.start.line || lines.start.line_num > range.end.line {
+                    continue;
+                }
+            }
+
+            scope[0] = i;
+            self.recurse(module, stmt, &mut scope, &mut builder);
+        }
+        builder.build()
+    }
+
+    /// Stores `tokens` as the latest result for `uri` and returns its id
+    fn remember_semantic_tokens(&self, uri: &Url, tokens: Vec<SemanticToken>) -> String {
+        let id = self
+            .next_result_id
+            .fetch_add(1, Ordering::Relaxed)
+            .to_string();
+        self.semantic_results
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(uri.clone(), (id.clone(), tokens));
+        id
+    }
+
+    fn element_completions(&self, module: &Module, span: &Span) -> Vec<CompletionItem> {
+        let range = word_range(&module.content, span);
+        self.element_names
+            .iter()
+            .map(|name| element_completion(name, range))
+            .chain([use_completion(range)])
+            .collect()
+    }
+
+    /// What can be typed in the value of the style property or argument `key`
+    fn value_completions(
         &self,
-        key: &SpannedToken,
+        module: &Module,
+        key: &str,
         span: &Span,
     ) -> Option<Vec<CompletionItem>> {
-        if let SpannedToken(_, Token::Ident(key_str)) = key {
-            let member = self.style_enum.get(key_str);
-            match member {
-                Some(CompletionType::Enum(members)) => {
-                    let res = members
-                        .iter()
+        let member = self.style_enum.get(key);
+        match member {
+            Some(CompletionType::Enum(members)) => {
+                let res = members
+                    .iter()
+                    .map(|v| CompletionItem {
+                        label: v.clone(),
+                        kind: Some(CompletionItemKind::ENUM_MEMBER),
+                        ..Default::default()
+                    })
+                    .collect();
+                return Some(res);
+            }
+            Some(CompletionType::Boolean) => {
+                return Some(
+                    ["true", "false"]
+                        .into_iter()
                         .map(|v| CompletionItem {
-                            label: v.clone(),
-                            kind: Some(CompletionItemKind::ENUM_MEMBER),
+                            label: v.to_string(),
+                            kind: Some(CompletionItemKind::KEYWORD),
                             ..Default::default()
                         })
-                        .collect();
-                    return Some(res);
-                }
-                Some(CompletionType::Boolean) => {
-                    return Some(
-                        ["true", "false"]
-                            .into_iter()
-                            .map(|v| CompletionItem {
-                                label: v.to_string(),
-                                kind: Some(CompletionItemKind::KEYWORD),
-                                ..Default::default()
-                            })
-                            .collect(),
-                    );
-                }
-                Some(CompletionType::Color) => {
-                    let spn = Range {
-                        start: Position {
-                            line: span.line_num,
-                            character: span.position,
-                        },
-                        end: Position {
-                            line: span.line_num,
-                            character: span.position + 1,
-                        },
-                    };
-                    let items = [
-                        CompletionItem {
-                            label: "rgb".to_string(),
-                            kind: Some(CompletionItemKind::FUNCTION),
-                            insert_text_format: Some(InsertTextFormat::SNIPPET),
-                            text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
-                                spn,
-                                "rgb(${1:255}, ${2:0}, ${3:0})$0".to_string(),
-                            ))),
-                            ..Default::default()
-                        },
-                        CompletionItem {
-                            label: "rgba".to_string(),
-                            kind: Some(CompletionItemKind::FUNCTION),
-                            insert_text_format: Some(InsertTextFormat::SNIPPET),
-                            text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
-                                spn,
-                                "rgba(${1:255}, ${2:0}, ${3:0}, ${4:255})$0".to_string(),
-                            ))),
-                            ..Default::default()
-                        },
-                    ]
-                    .to_vec();
-
-                    return Some(items);
-                }
-                Some(CompletionType::Rect) => {
-                    let spn = Range {
-                        start: Position {
-                            line: span.line_num,
-                            character: span.position,
-                        },
-                        end: Position {
-                            line: span.line_num,
-                            character: span.position + 1,
-                        },
-                    };
-                    let items = [
-                        CompletionItem {
-                            label: "rect".to_string(),
-                            kind: Some(CompletionItemKind::FUNCTION),
-                            insert_text_format: Some(InsertTextFormat::SNIPPET),
-                            text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
-                                spn,
-                                "rect(${1}, ${2}, ${3}, ${4})".to_string(),
-                            ))),
-                            ..Default::default()
-                        },
-                        CompletionItem {
-                            label: "rect_xy".to_string(),
-                            kind: Some(CompletionItemKind::FUNCTION),
-                            insert_text_format: Some(InsertTextFormat::SNIPPET),
-                            text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
-                                spn,
-                                "rect_xy(${1}, ${2})$0".to_string(),
-                            ))),
-                            ..Default::default()
-                        },
-                        CompletionItem {
-                            label: "rect_all".to_string(),
-                            kind: Some(CompletionItemKind::FUNCTION),
-                            insert_text_format: Some(InsertTextFormat::SNIPPET),
-                            text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
-                                spn,
-                                "rect_all(${1})$0".to_string(),
-                            ))),
-                            ..Default::default()
-                        },
-                    ]
-                    .to_vec();
-
-                    return Some(items);
-                }
-                _ => (),
+                        .collect(),
+                );
             }
-        } else {
+            Some(CompletionType::Color) => {
+                let spn = Range {
+                    start: Position {
+                        line: span.line_num,
+                        character: span.position,
+                    },
+                    end: Position {
+                        line: span.line_num,
+                        character: span.position + 1,
+                    },
+                };
+                let items = [
+                    CompletionItem {
+                        label: "rgb".to_string(),
+                        kind: Some(CompletionItemKind::FUNCTION),
+                        insert_text_format: Some(InsertTextFormat::SNIPPET),
+                        text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                            spn,
+                            "rgb(${1:255}, ${2:0}, ${3:0})$0".to_string(),
+                        ))),
+                        ..Default::default()
+                    },
+                    CompletionItem {
+                        label: "rgba".to_string(),
+                        kind: Some(CompletionItemKind::FUNCTION),
+                        insert_text_format: Some(InsertTextFormat::SNIPPET),
+                        text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                            spn,
+                            "rgba(${1:255}, ${2:0}, ${3:0}, ${4:255})$0".to_string(),
+                        ))),
+                        ..Default::default()
+                    },
+                ]
+                .to_vec();
+
+                return Some(items);
+            }
+            Some(CompletionType::Rect) => {
+                let spn = Range {
+                    start: Position {
+                        line: span.line_num,
+                        character: span.position,
+                    },
+                    end: Position {
+                        line: span.line_num,
+                        character: span.position + 1,
+                    },
+                };
+                let items = [
+                    CompletionItem {
+                        label: "rect".to_string(),
+                        kind: Some(CompletionItemKind::FUNCTION),
+                        insert_text_format: Some(InsertTextFormat::SNIPPET),
+                        text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                            spn,
+                            "rect(${1}, ${2}, ${3}, ${4})".to_string(),
+                        ))),
+                        ..Default::default()
+                    },
+                    CompletionItem {
+                        label: "rect_xy".to_string(),
+                        kind: Some(CompletionItemKind::FUNCTION),
+                        insert_text_format: Some(InsertTextFormat::SNIPPET),
+                        text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                            spn,
+                            "rect_xy(${1}, ${2})$0".to_string(),
+                        ))),
+                        ..Default::default()
+                    },
+                    CompletionItem {
+                        label: "rect_all".to_string(),
+                        kind: Some(CompletionItemKind::FUNCTION),
+                        insert_text_format: Some(InsertTextFormat::SNIPPET),
+                        text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                            spn,
+                            "rect_all(${1})$0".to_string(),
+                        ))),
+                        ..Default::default()
+                    },
+                ]
+                .to_vec();
+
+                return Some(items);
+            }
+            Some(CompletionType::Symbol(item)) => return self.symbol_completions(module, item),
+            _ => (),
         }
         None
     }
 
-    fn bsearch_style(&self, item: &StyleStatement, span: &Span) -> Option<Vec<CompletionItem>> {
-        println!("Style");
-        match item {
-            StyleStatement::Style {
-                body, body_range, ..
-            } => {
-                if let Some(body_range) = body_range {
-                    if body_range.contains(span) {
-                        for stmt in body {
-                            if let Some(v) = self.bsearch_style(stmt, span) {
-                                return Some(v);
-                            }
-                        }
+    /// The names of the symbols of the module a `Symbol` key can refer to
+    fn symbol_completions(
+        &self,
+        module: &Module,
+        item: &CompletionType,
+    ) -> Option<Vec<CompletionItem>> {
+        let CompletionType::Style = item else {
+            return None;
+        };
 
-                        return Some(
-                            self.style_enum
-                                .keys()
-                                .map(|k| CompletionItem {
-                                    label: k.clone(),
-                                    kind: Some(CompletionItemKind::PROPERTY),
-                                    insert_text: Some(format!("{}: ", k)),
-                                    ..Default::default()
-                                })
-                                .collect(),
-                        );
-                    }
-                }
-            }
-            StyleStatement::StyleElement {
-                key,
-                colon,
-                value: _,
-            } => {
-                if let Some(colon) = colon {
-                    if colon.0.before(span) {
-                        if let Some(key) = key {
-                            return self.bsearch_value_with_key(key, span);
-                        }
-                    }
+        let mut names: Vec<String> = Vec::new();
+        for (_, symbol) in module.symbols() {
+            let symbol = symbol.borrow();
+            if let SymbolKind::Style { .. } = symbol.kind {
+                if !names.contains(&symbol.name) {
+                    names.push(symbol.name.clone());
                 }
             }
         }
-        None
+
+        Some(
+            names
+                .into_iter()
+                .map(|name| CompletionItem {
+                    label: name,
+                    kind: Some(CompletionItemKind::STRUCT),
+                    ..Default::default()
+                })
+                .collect(),
+        )
+    }
+
+    fn style_property_completions(&self) -> Vec<CompletionItem> {
+        self.style_enum
+            .keys()
+            .map(|k| CompletionItem {
+                label: k.clone(),
+                kind: Some(CompletionItemKind::PROPERTY),
+                insert_text: Some(format!("{}: ", k)),
+                ..Default::default()
+            })
+            .collect()
     }
 
-    fn bsearch_statement(
+    /// The children of the symbol a `use` path written so far leads to
+    fn use_completions(
         &self,
         module: &Module,
-        item: &Statement,
         span: &Span,
+        index: usize,
     ) -> Option<Vec<CompletionItem>> {
-        match item {
-            Statement::Element {
-                arguments,
-                body,
-                body_range,
-                token,
-                ..
-            } => {
-                if let Some(args) = arguments {
-                    if args.range.contains(span) {
-                        for (item, cm) in args.items.iter() {
-                            match (&item.colon, cm) {
-                                (Some(colon), Some(cm)) => {
-                                    if colon.0.before(span) && cm.0.after(span) {
-                                        println!("Betwween");
-                                        return None;
-                                    }
-                                }
-                                (Some(colon), None) => {
-                                    if colon.0.before(span) {
-                                        if let Some(key) = &item.name {
-                                            return self.bsearch_value_with_key(key, span);
-                                        } else {
-                                            return None;
-                                        }
-                                    }
-                                }
-                                _ => (),
-                            }
-                        }
-                        return Some(
-                            PROPERTY_COMPLETES
-                                .iter()
-                                .map(|f| CompletionItem {
-                                    label: f.to_string(),
-                                    commit_characters: Some(vec![":".to_string()]),
-                                    ..Default::default()
-                                })
-                                .collect(),
-                        );
-                    }
-                }
-                if let Some(token) = token {
-                    if token.span().before(span) {
-                        return Some(
-                            self.element_names
-                                .iter()
-                                .map(|name| CompletionItem {
-                                    label: name.into(),
-                                    kind: Some(CompletionItemKind::PROPERTY),
-                                    ..Default::default()
-                                })
-                                .collect(),
-                        );
-                    }
-                }
-                if let Some(body_range) = body_range {
-                    if body_range.contains(span) {
-                        for stmt in body {
-                            if let Some(s) = self.bsearch_statement(module, stmt, span) {
-                                return Some(s);
-                            } else {
-                                return Some(
-                                    self.element_names
-                                        .iter()
-                                        .map(|name| CompletionItem {
-                                            label: name.into(),
-                                            kind: Some(CompletionItemKind::PROPERTY),
-                                            ..Default::default()
-                                        })
-                                        .collect(),
-                                );
-                            }
-                        }
-                    }
-                }
+        let args = module.stmts.iter().find_map(|stmt| match stmt {
+            Statement::UseStatement { args, .. }
+                if stmt.get_range().start.line_num == span.line_num =>
+            {
+                Some(args)
             }
-            Statement::Style {
-                body,
-                body_range,
-                token,
-            } => {
-                if let Some(_token) = token {}
-                if let Some(body_range) = body_range {
-                    if body_range.contains(span) {
-                        for stmt in body {
-                            if let Some(v) = self.bsearch_style(stmt, span) {
-                                return Some(v);
-                            }
-                        }
-                    }
-                }
+            _ => None,
+        })?;
+        let sym = module.resolve_symbol_chain(args.iter_items().take(index))?;
+
+        let mut comp = Vec::new();
+        for (name, sym) in &sym.borrow().children {
+            match &sym.borrow().kind {
+                SymbolKind::Node { .. } => comp.push(CompletionItem {
+                    label: name.clone(),
+                    kind: Some(CompletionItemKind::MODULE),
+                    ..Default::default()
+                }),
+                SymbolKind::Style { .. } => comp.push(CompletionItem {
+                    label: name.clone(),
+                    kind: Some(CompletionItemKind::STRUCT),
+                    ..Default::default()
+                }),
+                _ => (),
             }
-            Statement::UseStatement { args, .. } => {
-                if let Some((_, Some(SpannedToken(_, Token::Operator(Operator::Dot))))) =
-                    args.iter().last()
-                {
-                    if let Some(sym) = module.resolve_symbol_chain(args.iter_items()) {
-                        println!("Use {}", sym.borrow().name);
-                        let mut comp = Vec::new();
-                        for (name, sym) in &sym.borrow().children {
-                            match &sym.borrow().kind {
-                                SymbolKind::Node { .. } => comp.push(CompletionItem {
-                                    label: name.clone(),
-                                    kind: Some(CompletionItemKind::MODULE),
-                                    ..Default::default()
-                                }),
-                                SymbolKind::Style { .. } => comp.push(CompletionItem {
-                                    label: name.clone(),
-                                    kind: Some(CompletionItemKind::STRUCT),
-                                    ..Default::default()
-                                }),
-                                _ => (),
-                            }
-                        }
-                        return Some(comp);
-                    }
-                }
+        }
+        Some(comp)
+    }
+
+    fn completions_at(&self, module: &Module, span: &Span) -> Option<Vec<CompletionItem>> {
+        match module.context_at(span.line_num, span.position)? {
+            TokenContext::Element | TokenContext::Text => {
+                Some(self.element_completions(module, span))
             }
-            Statement::Text(_) => {}
+            TokenContext::StyleName | TokenContext::StyleKey => {
+                Some(self.style_property_completions())
+            }
+            TokenContext::StyleValue { key } | TokenContext::ArgValue { name: Some(key) } => {
+                self.value_completions(module, &key, span)
+            }
+            TokenContext::ArgName => {
+                let range = word_range(&module.content, span);
+                Some(
+                    PROPERTY_COMPLETES
+                        .iter()
+                        .map(|f| property_completion(f, range))
+                        .collect(),
+                )
+            }
+            TokenContext::UsePath { index } => self.use_completions(module, span, index),
+            TokenContext::StyleBlock
+            | TokenContext::Use
+            | TokenContext::ArgValue { name: None } => None,
+        }
+    }
+}
+
+impl Backend {
+    fn new(client: Arc<Client>) -> Backend {
+        Backend {
+            element_names: HashSet::from_iter([
+                "style".into(),
+                "view".into(),
+                "setup".into(),
+                "image".into(),
+                "spacer".into(),
+            ]),
+            style_enum: HashMap::from([
+                (
+                    "direction".to_string(),
+                    CompletionType::Enum(
+                        Direction::ALL
+                            .iter()
+                            .map(|direction| direction.name().to_string())
+                            .collect(),
+                    ),
+                ),
+                (
+                    "cursor".to_string(),
+                    CompletionType::Enum(vec![
+                        "Default".to_string(),
+                        "Pointer".to_string(),
+                        "Text".to_string(),
+                        "Hidden".to_string(),
+                    ]),
+                ),
+                (
+                    "whiteSpace".to_string(),
+                    CompletionType::Enum(vec!["Normal".to_string(), "NoWrap".to_string()]),
+                ),
+                (
+                    "wordBreak".to_string(),
+                    CompletionType::Enum(vec!["Normal".to_string(), "BreakAll".to_string()]),
+                ),
+                (
+                    "textOverflow".to_string(),
+                    CompletionType::Enum(vec!["Clip".to_string(), "Ellipsis".to_string()]),
+                ),
+                (
+                    "textTransform".to_string(),
+                    CompletionType::Enum(vec![
+                        "None".to_string(),
+                        "Uppercase".to_string(),
+                        "Lowercase".to_string(),
+                        "Capitalize".to_string(),
+                    ]),
+                ),
+                (
+                    "overflow".to_string(),
+                    CompletionType::Enum(vec![
+                        "Visible".to_string(),
+                        "Hidden".to_string(),
+                        "Scroll".to_string(),
+                    ]),
+                ),
+                (
+                    "display".to_string(),
+                    CompletionType::Enum(vec!["Block".to_string(), "Inline".to_string()]),
+                ),
+                (
+                    "visibility".to_string(),
+                    CompletionType::Enum(vec!["Visible".to_string(), "Hidden".to_string()]),
+                ),
+                (
+                    "verticalAlign".to_string(),
+                    CompletionType::Enum(vec![
+                        "Top".to_string(),
+                        "Center".to_string(),
+                        "Bottom".to_string(),
+                    ]),
+                ),
+                (
+                    "transformOrigin".to_string(),
+                    CompletionType::Enum(vec![
+                        "Center".to_string(),
+                        "TopLeft".to_string(),
+                        "TopRight".to_string(),
+                        "BottomLeft".to_string(),
+                        "BottomRight".to_string(),
+                    ]),
+                ),
+                (
+                    "blendMode".to_string(),
+                    CompletionType::Enum(vec![
+                        "Normal".to_string(),
+                        "Multiply".to_string(),
+                        "Screen".to_string(),
+                        "Overlay".to_string(),
+                        "Darken".to_string(),
+                        "Lighten".to_string(),
+                        "ColorDodge".to_string(),
+                        "ColorBurn".to_string(),
+                        "HardLight".to_string(),
+                        "SoftLight".to_string(),
+                        "Difference".to_string(),
+                        "Exclusion".to_string(),
+                        "Hue".to_string(),
+                        "Saturation".to_string(),
+                        "Color".to_string(),
+                        "Luminosity".to_string(),
+                    ]),
+                ),
+                ("visible".to_string(), CompletionType::Boolean),
+                (
+                    "class".to_string(),
+                    CompletionType::Symbol(Box::new(CompletionType::Style)),
+                ),
+                ("backgroundColor".to_string(), CompletionType::Color),
+                ("foregroundColor".to_string(), CompletionType::Color),
+                ("borderColor".to_string(), CompletionType::Color),
+                ("borderWidth".to_string(), CompletionType::Rect),
+                ("padding".to_string(), CompletionType::Rect),
+                ("radius".to_string(), CompletionType::Rect),
+                ("gap".to_string(), CompletionType::Unknown),
+                ("width".to_string(), CompletionType::Unknown),
+                ("height".to_string(), CompletionType::Unknown),
+                ("aspectRatio".to_string(), CompletionType::Unknown),
+                ("boxShadow".to_string(), CompletionType::Unknown),
+                ("transition".to_string(), CompletionType::Unknown),
+            ]),
+            documents: Rf::new(HashMap::new()),
+            semantic_results: RwLock::new(HashMap::new()),
+            next_result_id: AtomicUsize::new(0),
+            client,
         }
-        None
     }
 }
 
@@ -693,10 +853,9 @@ impl LanguageServer for Backend {
         Ok(InitializeResult {
             capabilities: ServerCapabilities {
                 text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    // TextDocumentSyncKind::INCREMENTAL,
-                    TextDocumentSyncKind::FULL,
+                    TextDocumentSyncKind::INCREMENTAL,
                 )),
-                // color_provider: Some(ColorProviderCapability::Simple(true)),
+                color_provider: Some(ColorProviderCapability::Simple(true)),
                 semantic_tokens_provider: Some(
                     SemanticTokensServerCapabilities::SemanticTokensOptions(
                         SemanticTokensOptions {
@@ -707,8 +866,8 @@ impl LanguageServer for Backend {
                                 token_types: STOKEN_TYPES.into(),
                                 token_modifiers: vec![],
                             },
-                            range: Some(false),
-                            full: Some(SemanticTokensFullOptions::Bool(true)),
+                            range: Some(true),
+                            full: Some(SemanticTokensFullOptions::Delta { delta: Some(true) }),
                         },
                     ),
                 ),
@@ -717,6 +876,7 @@ impl LanguageServer for Backend {
                     trigger_characters: Some(vec![":".to_string(), ".".to_string()]),
                     ..Default::default()
                 }),
+                document_formatting_provider: Some(OneOf::Left(true)),
                 workspace: Some(WorkspaceServerCapabilities {
                     workspace_folders: Some(WorkspaceFoldersServerCapabilities {
                         supported: Some(true),
@@ -735,23 +895,73 @@ impl LanguageServer for Backend {
         params: SemanticTokensParams,
     ) -> Result<Option<SemanticTokensResult>> {
         let toks = {
-            let map = &*self.documents.read().unwrap();
+            let map = self.documents.borrow_or_recover();
+            let Some(mods) = map.get(&params.text_document.uri) else {
+                return Ok(None);
+            };
+
+            self.semantic_tokens(mods, None)
+        };
+        let result_id = self.remember_semantic_tokens(&params.text_document.uri, toks.clone());
 
+        Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
+            data: toks,
+            result_id: Some(result_id),
+        })))
+    }
+
+    async fn semantic_tokens_full_delta(
+        &self,
+        params: SemanticTokensDeltaParams,
+    ) -> Result<Option<SemanticTokensFullDeltaResult>> {
+        let toks = {
+            let map = self.documents.borrow_or_recover();
             let Some(mods) = map.get(&params.text_document.uri) else {
-                return Ok(None)
+                return Ok(None);
             };
 
-            let mut builder = SemanticTokenBuilder::new();
-            let mut scope = Vec::with_capacity(50);
-            scope.push(0);
-            for (i, tok) in mods.stmts.iter().enumerate() {
-                scope[0] = i;
-                self.recurse(mods, tok, &mut scope, &mut builder);
+            self.semantic_tokens(mods, None)
+        };
+
+        let edits = match self
+            .semantic_results
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&params.text_document.uri)
+        {
+            Some((id, previous)) if *id == params.previous_result_id => {
+                Some(semantic_tokens_edits(previous, &toks))
             }
-            builder.build()
+            _ => None,
         };
+        let result_id = self.remember_semantic_tokens(&params.text_document.uri, toks.clone());
+
+        Ok(Some(match edits {
+            Some(edits) => SemanticTokensFullDeltaResult::TokensDelta(SemanticTokensDelta {
+                result_id: Some(result_id),
+                edits,
+            }),
+            None => SemanticTokensFullDeltaResult::Tokens(SemanticTokens {
+                data: toks,
+                result_id: Some(result_id),
+            }),
+        }))
+    }
 
-        Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
+    async fn semantic_tokens_range(
+        &self,
+        params: SemanticTokensRangeParams,
+    ) -> Result<Option<SemanticTokensRangeResult>> {
+        let toks = {
+            let map = self.documents.borrow_or_recover();
+            let Some(mods) = map.get(&params.text_document.uri) else {
+                return Ok(None);
+            };
+
+            self.semantic_tokens(mods, Some(params.range))
+        };
+
+        Ok(Some(SemanticTokensRangeResult::Tokens(SemanticTokens {
             data: toks,
             result_id: None,
         })))
@@ -765,44 +975,13 @@ impl LanguageServer for Backend {
             )
             .await;
         let res = {
-            let map = &*self.documents.read().unwrap();
+            let map = self.documents.borrow_or_recover();
             let Some(mods) = map.get(&params.text_document_position.text_document.uri) else {
-                return Ok(None)
+                return Ok(None);
             };
-            let sp = Span {
-                line_num: params.text_document_position.position.line,
-                position: params.text_document_position.position.character,
-                ..Default::default()
-            };
-
-            let items = mods
-                .stmts
-                .iter()
-                .find_map(|f| self.bsearch_statement(mods, f, &sp));
+            let sp = Span::from(params.text_document_position.position);
 
-            if let None = items {
-                if mods
-                    .stmts
-                    .iter()
-                    .find(|f| f.get_range().contains(&sp))
-                    .is_none()
-                {
-                    Some(
-                        self.element_names
-                            .iter()
-                            .map(|name| CompletionItem {
-                                label: name.into(),
-                                kind: Some(CompletionItemKind::PROPERTY),
-                                ..Default::default()
-                            })
-                            .collect(),
-                    )
-                } else {
-                    items
-                }
-            } else {
-                items
-            }
+            self.completions_at(mods, &sp)
         };
         self.client
             .log_message(MessageType::INFO, format!("completino {:?}", res))
@@ -819,147 +998,76 @@ impl LanguageServer for Backend {
         }
     }
 
+    async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        let content = match self
+            .documents
+            .borrow_or_recover()
+            .get(&params.text_document.uri)
+        {
+            Some(module) => module.content.clone(),
+            None => return Ok(None),
+        };
+
+        let formatted = format_source(&content, &params.options);
+        if formatted == content {
+            return Ok(Some(Vec::new()));
+        }
+        Ok(Some(vec![TextEdit::new(
+            Range::new(Position::new(0, 0), end_position(&content)),
+            formatted,
+        )]))
+    }
+
     async fn completion_resolve(&self, params: CompletionItem) -> Result<CompletionItem> {
         Ok(params)
     }
 
-    // async fn document_color(&self, params: DocumentColorParams) -> Result<Vec<ColorInformation>> {
-    //     println!("Params: {:?}", params);
-
-    //     let res = {
-    //         let map = &*self.documents.read().unwrap();
-    //         let Some(mods) = map.get(&params.text_document.uri) else {
-    //             return Ok(vec![])
-    //         };
-
-    //         let color_info = Vec::new();
-    //         let md = ModuleDescender::new(color_info).with_on_value(|key, val, ud| {
-    //             match val {
-    //                 Value::Function {
-    //                     ident: Some(SpannedToken(spn, Token::Ident(id))),
-    //                     args,
-    //                 } => match id.as_str() {
-    //                     "rgb" => {
-    //                         let args: Option<Vec<&Value>> = args.iter_items().map(|val| val.value.as_ref()).collect();
-    //                         let Some(args) = args else {
-    //                             return ud;
-    //                         };
-    //                         let [Value::Integer(r, _, _), Value::Integer(g, _), Value::Integer(b, _)] = &args[..] else {
-    //                             return ud;
-    //                         };
-    //                         return ud.into_iter().chain([
-    //                             ColorInformation {
-    //                                 color: Color { red: *r as f32 / 255.0, green: *g as f32 / 255.0, blue: *b as f32 / 255.0, alpha: 1.0 },
-    //                                 range: Range::new(Position { line: spn.line_num, character: spn.position }, Position { line: spn.line_num, character: spn.position + 1 })
-    //                             }
-    //                         ].into_iter()).collect();
-    //                     }
-    //                     _ => (),
-    //                 },
-    //                 _ => (),
-    //             }
-    //             ud
-    //         });
-
-    //         let color_info = md.descend(&mods.stmts);
-
-    //         return Ok(color_info);
-    //     };
-    // }
-
-    // async fn color_presentation(
-    //     &self,
-    //     params: ColorPresentationParams,
-    // ) -> Result<Vec<ColorPresentation>> {
-    //     println!("Params: {:?}", params);
-
-    //     let map = &*self.documents.read().unwrap();
-    //     let Some(mods) = map.get(&params.text_document.uri) else {
-    //             return Ok(vec![])
-    //         };
-
-    //     let Color {
-    //         red,
-    //         green,
-    //         blue,
-    //         alpha,
-    //     } = params.color;
-
-    //     let color_info = Vec::new();
-    //     let md = ModuleDescender::new(color_info).with_on_value(move |key, val, ud| {
-    //         match val {
-    //             Value::Function {
-    //                 ident: Some(SpannedToken(spn, Token::Ident(id))),
-    //                 args,
-    //             } => match id.as_str() {
-    //                 "rgb" => {
-    //                     let Position {
-    //                         line: sl,
-    //                         character: sc,
-    //                     } = params.range.start;
-    //                     let Position {
-    //                         line: el,
-    //                         character: ec,
-    //                     } = params.range.end;
-
-    //                     let text_edit = if sl == spn.line_num
-    //                         && sc == spn.position
-    //                         && el == spn.line_num
-    //                         && ec == spn.position + 1
-    //                     {
-    //                         let rng = args.get_range();
-    //                         Some(TextEdit {
-    //                             range: Range {
-    //                                 start: Position {
-    //                                     line: rng.start.line_num,
-    //                                     character: rng.start.position,
-    //                                 },
-    //                                 end: Position {
-    //                                     line: rng.end.line_num,
-    //                                     character: rng.end.position + rng.end.length,
-    //                                 },
-    //                             },
-    //                             new_text: format!(
-    //                                 "({}, {}, {})",
-    //                                 (red * 255.0) as u32,
-    //                                 (green * 255.0) as u32,
-    //                                 (blue * 255.0) as u32
-    //                             ),
-    //                         })
-    //                     } else {
-    //                         None
-    //                     };
-
-    //                     return ud
-    //                         .into_iter()
-    //                         .chain(
-    //                             [ColorPresentation {
-    //                                 label: id.clone(),
-    //                                 text_edit,
-    //                                 additional_text_edits: None,
-    //                             }]
-    //                             .into_iter(),
-    //                         )
-    //                         .collect();
-    //                 }
-    //                 _ => (),
-    //             },
-    //             _ => (),
-    //         }
-    //         ud
-    //     });
-
-    //     let color_info = md.descend(&mods.stmts);
-    //     println!("{:?}", color_info);
-
-    //     return Ok(color_info);
-
-    //     Ok(vec![ColorPresentation {
-    //         label: "fsdlkf".to_string(),
-    //         text_edit: None,
-    //         additional_text_edits: None,
-    //     }])
-    // }
+    async fn document_color(&self, params: DocumentColorParams) -> Result<Vec<ColorInformation>> {
+        let map = self.documents.borrow_or_recover();
+        let Some(mods) = map.get(&params.text_document.uri) else {
+            return Ok(vec![]);
+        };
+
+        Ok(mods
+            .colors()
+            .into_iter()
+            .map(|(range, color)| ColorInformation {
+                range: (&range).into(),
+                color: Color {
+                    red: color.r as f32 / 255.0,
+                    green: color.g as f32 / 255.0,
+                    blue: color.b as f32 / 255.0,
+                    alpha: color.a as f32 / 255.0,
+                },
+            })
+            .collect())
+    }
+
+    async fn color_presentation(
+        &self,
+        params: ColorPresentationParams,
+    ) -> Result<Vec<ColorPresentation>> {
+        let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let Color {
+            red,
+            green,
+            blue,
+            alpha,
+        } = params.color;
+        let (r, g, b, a) = (channel(red), channel(green), channel(blue), channel(alpha));
+
+        let label = if a == 255 {
+            format!("rgb({}, {}, {})", r, g, b)
+        } else {
+            format!("rgba({}, {}, {}, {})", r, g, b, a)
+        };
+
+        Ok(vec![ColorPresentation {
+            text_edit: Some(TextEdit::new(params.range, label.clone())),
+            label,
+            additional_text_edits: None,
+        }])
+    }
 
     async fn initialized(&self, _p: InitializedParams) {
         self.client
@@ -975,81 +1083,61 @@ impl LanguageServer for Backend {
             self.client.log_message(MessageType::ERROR, err).await;
         }
 
-        (*(self.documents.write().unwrap())).insert(params.text_document.uri, out.0);
+        self.documents
+            .borrow_mut_or_recover()
+            .insert(params.text_document.uri, out.0);
 
         // self.client.semantic_tokens_refresh().await.unwrap();
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
-        println!("Change {:?}", params);
-
         let doc = params.text_document;
-        for change in params.content_changes {
-            // if let Some(range) = change.range {
-            //     let map = &mut *self.documents.write().unwrap();
-            //     let Some(mods) = map.get_mut(&doc.uri) else {
-            //         return;
-            //     };
-
-            //     let md = MutModuleDescender::new(false)
-            //         .with_callback_first(false)
-            //         .with_on_value(move |key, val, ud| {
-            //             let rng = val.get_range();
-            //             let rng = to_rng(&rng);
-
-            //             // if rng == range {}
-            //             if range_contains(&range, &rng) {
-            //                 println!("Contains");
-            //             }
-            //             println!("Value: {:?}", val);
-            //             println!("Content: {:?} {:?}", rng, range);
-
-            //             ud
-            //         })
-            //         .with_on_style_statement(move |stmt, ud| {
-            //             let rng = stmt.get_range();
-            //             let rng = to_rng(&rng);
-
-            //             if range_contains(&range, &rng) {
-            //                 println!("Contains");
-            //             }
-            //             // println!("Statent: {:?}", val);
-            //             println!("Statemnt : {:?} {:?}", rng, range);
-
-            //             (ud, ud)
-            //         });
-
-            //     let _ = md.descend(&mut mods.stmts);
-            // } else {
-            let text = change.text;
-
-            let out = neb_smf::Module::parse_str(&text);
-            println!("{}", out.0.format());
-
-            for err in out.1 {
-                self.client.log_message(MessageType::ERROR, err).await;
-            }
-
-            (*(self.documents.write().unwrap())).insert(doc.uri.clone(), out.0);
 
-            self.client.semantic_tokens_refresh().await.unwrap();
-            // }
-        }
-
-        // let mut p = params.content_changes;
-        // let text = p.remove(0);
-        // let text = text.text;
+        // The whole batch is applied under one guard, so no request sees it half done and no
+        // other change starts from text that is about to be replaced
+        let (errors, refresh) = {
+            let mut documents = self.documents.borrow_mut_or_recover();
+            let mut errors = Vec::new();
+            for change in params.content_changes {
+                let content = match documents.get(&doc.uri) {
+                    Some(module) => module.content.clone(),
+                    None => String::new(),
+                };
+                let text = apply_change(&content, &change);
 
-        // let out = neb_smf::parse_str(text).await;
-        // println!("{}", out.0.format());
+                let (module, module_errors) = neb_smf::Module::parse_str(&text);
+                documents.insert(doc.uri.clone(), module);
 
-        // for err in out.1 {
-        //     self.client.log_message(MessageType::ERROR, err).await;
-        // }
+                // Comments and whitespace leave the same errors behind
+                if !only_trivia_changed(&content, &text) {
+                    errors.extend(module_errors);
+                }
+            }
 
-        // (*(self.documents.write().unwrap())).insert(params.text_document.uri, out.0);
+            // Even a blank line moves the tokens after it, so compare against what the client
+            // was last sent rather than the text
+            let tokens = documents
+                .get(&doc.uri)
+                .map(|module| self.semantic_tokens(module, None))
+                .unwrap_or_default();
+            let refresh = match self
+                .semantic_results
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(&doc.uri)
+            {
+                Some((_, previous)) => !semantic_tokens_edits(previous, &tokens).is_empty(),
+                None => true,
+            };
+            (errors, refresh)
+        };
 
-        // self.client.semantic_tokens_refresh().await.unwrap();
+        for err in errors {
+            self.client.log_message(MessageType::ERROR, err).await;
+        }
+        if refresh {
+            self.client.semantic_tokens_refresh().await.unwrap();
+        }
     }
 
     async fn shutdown(&self) -> Result<()> {
@@ -1086,73 +1174,481 @@ async fn main() {
     #[cfg(feature = "runtime-agnostic")]
     let (read, write) = (read.compat(), write.compat_write());
 
-    let (service, socket) = LspService::new(|client| {
-        let client = Arc::new(client);
-        let res = Backend {
-            element_names: HashSet::from_iter(["style".into(), "view".into(), "setup".into()]),
-            style_enum: HashMap::from([
-                (
-                    "direction".to_string(),
-                    CompletionType::Enum(vec![
-                        "Vertical".to_string(),
-                        "Horizontal".to_string(),
-                        "VerticalReverse".to_string(),
-                        "HorizontalReverse".to_string(),
-                    ]),
-                ),
-                ("visible".to_string(), CompletionType::Boolean),
-                (
-                    "class".to_string(),
-                    CompletionType::Symbol(Box::new(CompletionType::Style)),
-                ),
-                ("backgroundColor".to_string(), CompletionType::Color),
-                ("foregroundColor".to_string(), CompletionType::Color),
-                ("borderColor".to_string(), CompletionType::Color),
-                ("borderWidth".to_string(), CompletionType::Rect),
-                ("padding".to_string(), CompletionType::Rect),
-                ("radius".to_string(), CompletionType::Rect),
-                ("gap".to_string(), CompletionType::Unknown),
-            ]),
-            documents: RwLock::new(HashMap::new()),
-            client: client.clone(),
-        };
-
-        res
-    });
+    let (service, socket) = LspService::new(|client| Backend::new(Arc::new(client)));
     Server::new(read, write, socket).serve(service).await;
 }
 
-#[inline]
-fn to_rng(range: &neb_smf::token::Range) -> Range {
-    if range.start == range.end {
-        Range::new(
-            Position {
-                line: range.start.line_num,
-                character: range.start.position,
-            },
-            Position {
-                line: range.start.line_num,
-                character: range.start.position + range.start.length,
-            },
-        )
+/// Byte offset of an LSP position, whose character counts UTF-16 code units along the line
+fn position_offset(content: &str, position: Position) -> usize {
+    let mut offset = 0;
+    for (line_num, line) in content.split_inclusive('\n').enumerate() {
+        if line_num as u32 == position.line {
+            let mut units = 0;
+            for (index, c) in line.char_indices() {
+                if units >= position.character || c == '\r' || c == '\n' {
+                    return offset + index;
+                }
+                units += c.len_utf16() as u32;
+            }
+            return offset + line.len();
+        }
+        offset += line.len();
+    }
+    content.len()
+}
+
+/// Applies an edit from `did_change`. A change without a range replaces the whole text
+fn apply_change(content: &str, change: &TextDocumentContentChangeEvent) -> String {
+    let Some(range) = change.range else {
+        return change.text.clone();
+    };
+
+    let start = position_offset(content, range.start);
+    let end = position_offset(content, range.end).max(start);
+
+    let mut text = content.to_string();
+    text.replace_range(start..end, &change.text);
+    text
+}
+
+/// Re-indents every line by how many brackets are open at its start, using the editor's
+/// indentation. A line starting with a closing bracket goes back a level
+fn format_source(content: &str, options: &FormattingOptions) -> String {
+    let unit = if options.insert_spaces {
+        " ".repeat(options.tab_size as usize)
     } else {
-        Range::new(
-            Position {
-                line: range.start.line_num,
-                character: range.start.position,
-            },
-            Position {
-                line: range.end.line_num,
-                character: range.end.position + range.end.length,
-            },
-        )
+        "\t".to_string()
+    };
+
+    let mut depths = Vec::new();
+    let mut depth = 0usize;
+    for tok in tokens(content) {
+        let line_num = tok.span().line_num as usize;
+        match tok.tok() {
+            Token::Newline => continue,
+            Token::Operator(Operator::OpenBrace | Operator::OpenParen | Operator::OpenSquare) => {
+                depths.resize(depths.len().max(line_num + 1), depth);
+                depth += 1;
+            }
+            Token::Operator(
+                Operator::CloseBrace | Operator::CloseParen | Operator::CloseSquare,
+            ) => {
+                depth = depth.saturating_sub(1);
+                depths.resize(depths.len().max(line_num + 1), depth);
+            }
+            _ => depths.resize(depths.len().max(line_num + 1), depth),
+        }
+    }
+
+    content
+        .split('\n')
+        .enumerate()
+        .map(|(line_num, line)| {
+            let line = line.trim_start();
+            if line.trim_end().is_empty() {
+                return line.to_string();
+            }
+            let depth = depths.get(line_num).copied().unwrap_or(depth);
+            format!("{}{}", unit.repeat(depth), line)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Where `content` ends, in LSP terms
+fn end_position(content: &str) -> Position {
+    let (line, last) = content.split('\n').enumerate().last().unwrap_or_default();
+    Position::new(line as u32, last.encode_utf16().count() as u32)
+}
+
+/// Whether the two sources lex to the same tokens, wherever they are.
+/// If so, the edit between them only touched comments, whitespace or blank lines
+fn only_trivia_changed(old: &str, new: &str) -> bool {
+    tokens(old)
+        .map(|tok| format!("{:?}", tok.tok()))
+        .eq(tokens(new).map(|tok| format!("{:?}", tok.tok())))
+}
+
+/// A single edit replacing whatever differs between the common start and end of the two results.
+/// Empty if they are the same
+fn semantic_tokens_edits(old: &[SemanticToken], new: &[SemanticToken]) -> Vec<SemanticTokensEdit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    if prefix == old.len() && prefix == new.len() {
+        return Vec::new();
     }
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    // Edits index the flattened data, which has five numbers per token
+    vec![SemanticTokensEdit {
+        start: (prefix * 5) as u32,
+        delete_count: ((old.len() - prefix - suffix) * 5) as u32,
+        data: Some(new[prefix..new.len() - suffix].to_vec()),
+    }]
+}
+
+/// Range of the partially typed word that ends at `span`
+fn word_range(content: &str, span: &Span) -> Range {
+    let line = content.lines().nth(span.line_num as usize).unwrap_or("");
+    let typed: Vec<char> = line.chars().take(span.position as usize).collect();
+    let start = typed
+        .iter()
+        .rposition(|c| !(c.is_alphanumeric() || *c == '_'))
+        .map_or(0, |i| i + 1);
+
+    Range::new(
+        Position::new(span.line_num, start as u32),
+        Position::new(span.line_num, typed.len() as u32),
+    )
+}
+
+fn snippet_completion(label: &str, range: Range, snippet: String) -> CompletionItem {
+    CompletionItem {
+        label: label.to_string(),
+        kind: Some(CompletionItemKind::SNIPPET),
+        insert_text_format: Some(InsertTextFormat::SNIPPET),
+        text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(range, snippet))),
+        ..Default::default()
+    }
+}
+
+fn element_completion(name: &str, range: Range) -> CompletionItem {
+    let snippet = match name {
+        "style" => "style {\n\t${1:name} {\n\t\t$0\n\t}\n}".to_string(),
+        _ => format!("{} {{\n\t$0\n}}", name),
+    };
+    snippet_completion(name, range, snippet)
+}
+
+fn use_completion(range: Range) -> CompletionItem {
+    snippet_completion("use", range, "use ${1:setup}.${2:style}$0".to_string())
 }
 
-#[inline]
-fn range_contains(inner: &Range, outer: &Range) -> bool {
-    inner.start.line >= outer.start.line
-        && inner.end.line <= outer.end.line
-        && inner.start.character >= outer.start.character
-        && inner.end.character <= outer.end.character
+fn property_completion(name: &str, range: Range) -> CompletionItem {
+    snippet_completion(name, range, format!("{}: ${{1}}$0", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{
+        CompletionItem, CompletionTextEdit, FormattingOptions, Position, Range, SemanticToken,
+        TextDocumentContentChangeEvent,
+    };
+
+    use std::sync::Arc;
+
+    use neb_smf::{
+        ast::{Statement, StyleStatement, Value},
+        evaluate::EvaluatedValue,
+        token::{Span, SpannedToken, Token, Unit},
+        Module, SymbolKind,
+    };
+    use tower_lsp::LspService;
+
+    use crate::{
+        apply_change, element_completion, format_source, only_trivia_changed, property_completion,
+        semantic_tokens_edits, use_completion, word_range, Backend,
+    };
+
+    fn snippet(item: &CompletionItem) -> (&str, Range) {
+        match &item.text_edit {
+            Some(CompletionTextEdit::Edit(edit)) => (edit.new_text.as_str(), edit.range),
+            _ => panic!("completion has no text edit"),
+        }
+    }
+
+    #[test]
+    fn word_range_covers_typed_prefix() {
+        let span = Span {
+            line_num: 1,
+            position: 6,
+            ..Default::default()
+        };
+        assert_eq!(
+            word_range("view {\n    vi\n}", &span),
+            Range::new(Position::new(1, 4), Position::new(1, 6))
+        );
+    }
+
+    #[test]
+    fn element_snippets() {
+        let range = Range::new(Position::new(0, 0), Position::new(0, 2));
+
+        assert_eq!(
+            snippet(&element_completion("view", range)),
+            ("view {\n\t$0\n}", range)
+        );
+        assert_eq!(
+            snippet(&element_completion("style", range)).0,
+            "style {\n\t${1:name} {\n\t\t$0\n\t}\n}"
+        );
+        assert_eq!(
+            snippet(&use_completion(range)).0,
+            "use ${1:setup}.${2:style}$0"
+        );
+        assert_eq!(
+            snippet(&property_completion("class", range)).0,
+            "class: ${1}$0"
+        );
+    }
+
+    #[test]
+    fn formatting_follows_the_editor_indentation() {
+        let source = "view {\n:Hi\n        view (\nclass: a) {\n:Inner\n}\n\n    }\n";
+        let options = |tab_size, insert_spaces| FormattingOptions {
+            tab_size,
+            insert_spaces,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            format_source(source, &options(2, true)),
+            "view {\n  :Hi\n  view (\n    class: a) {\n    :Inner\n  }\n\n}\n"
+        );
+        assert_eq!(
+            format_source(source, &options(4, false)),
+            "view {\n\t:Hi\n\tview (\n\t\tclass: a) {\n\t\t:Inner\n\t}\n\n}\n"
+        );
+    }
+
+    #[test]
+    fn empty_style_body_offers_style_properties() {
+        let (service, _) = LspService::new(|client| Backend::new(Arc::new(client)));
+        let backend = service.inner();
+
+        for (source, line_num, position) in [("style {\n    \n}", 1, 4), ("style { }", 0, 8)] {
+            let (module, _) = Module::parse_str(source);
+            let span = Span {
+                line_num,
+                position,
+                ..Default::default()
+            };
+
+            let items = backend.completions_at(&module, &span).unwrap();
+            let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+            assert!(labels.contains(&"padding"), "{:?} in {:?}", labels, source);
+            assert!(!labels.contains(&"view"), "{:?} in {:?}", labels, source);
+        }
+    }
+
+    #[test]
+    fn class_lists_complete_style_names() {
+        let (service, _) = LspService::new(|client| Backend::new(Arc::new(client)));
+        let backend = service.inner();
+
+        let source = "setup {
+    style {
+        header {
+            padding: 4px
+        }
+        card {
+            gap: 2px
+        }
+    }
+}
+
+use setup.style
+
+view (class: [], name: main) {
+}
+";
+        let (module, _) = Module::parse_str(source);
+        let span = Span {
+            line_num: 13,
+            position: 14,
+            ..Default::default()
+        };
+
+        let items = backend.completions_at(&module, &span).unwrap();
+        let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, ["header", "card"]);
+    }
+
+    #[test]
+    fn use_paths_complete_the_children_so_far() {
+        let (service, _) = LspService::new(|client| Backend::new(Arc::new(client)));
+        let backend = service.inner();
+
+        let source = "setup {
+    style {
+        header {
+            padding: 4px
+        }
+    }
+}
+
+use setup.
+";
+        let (module, _) = Module::parse_str(source);
+        let span = Span {
+            line_num: 8,
+            position: 10,
+            ..Default::default()
+        };
+
+        let items = backend.completions_at(&module, &span).unwrap();
+        let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, ["style"]);
+    }
+
+    /// Undoes the delta encoding into `(line, start, length)`
+    fn absolute(tokens: &[SemanticToken]) -> Vec<(u32, u32, u32)> {
+        let (mut line, mut start) = (0, 0);
+        tokens
+            .iter()
+            .map(|token| {
+                if token.delta_line > 0 {
+                    line += token.delta_line;
+                    start = token.delta_start;
+                } else {
+                    start += token.delta_start;
+                }
+                (line, start, token.length)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn range_tokens_are_the_in_range_part_of_the_full_tokens() {
+        let (service, _) = LspService::new(|client| Backend::new(Arc::new(client)));
+        let backend = service.inner();
+
+        let source = "setup {
+    style {
+        a {
+            padding: 4px
+        }
+    }
+}
+
+use setup.style
+
+view (class: a) {
+    view {
+    }
+}
+";
+        let (module, _) = Module::parse_str(source);
+
+        let full = absolute(&backend.semantic_tokens(&module, None));
+        let range = Range::new(Position::new(8, 0), Position::new(11, 0));
+        let ranged = backend.semantic_tokens(&module, Some(range));
+
+        let expected: Vec<_> = full
+            .iter()
+            .copied()
+            .filter(|(line, _, _)| (8..11).contains(line))
+            .collect();
+        assert!(!expected.is_empty());
+        assert!(expected.len() < full.len());
+        assert_eq!(absolute(&ranged), expected);
+
+        // The first token is still relative to the start of the file
+        assert_eq!(ranged[0].delta_line, 8);
+    }
+
+    #[test]
+    fn synthetic_values_resolve_but_are_not_highlighted() {
+        let (service, _) = LspService::new(|client| Backend::new(Arc::new(client)));
+        let backend = service.inner();
+
+        let (mut module, _) =
+            Module::parse_str("style {\n    card {\n        padding: 4px\n    }\n}\n");
+        let margin = Value::Integer(
+            8,
+            Some(Unit::Pixel),
+            SpannedToken::synthetic(Token::Integer(8, Some(Unit::Pixel))),
+        );
+
+        // Added the way a default style would be, to the statements and the symbol tree
+        let Statement::Style { body, .. } = &mut module.stmts[0] else {
+            panic!("Expected a style block");
+        };
+        let StyleStatement::Style { body, .. } = &mut body[0] else {
+            panic!("Expected a style");
+        };
+        body.push(StyleStatement::StyleElement {
+            key: Some(SpannedToken::synthetic(Token::Ident("margin".into()))),
+            colon: None,
+            value: Some(margin.clone()),
+            bang: None,
+            important: None,
+        });
+        let card = module
+            .resolve_symbol_chain_string(["style", "card"].map(String::from).iter())
+            .unwrap();
+        if let SymbolKind::Style { properties, .. } = &mut card.borrow_mut().kind {
+            properties.insert("margin".into(), margin);
+        }
+
+        let properties = match &card.borrow().kind {
+            SymbolKind::Style { properties, .. } => properties.clone(),
+            _ => panic!("Expected a style"),
+        };
+        assert_eq!(
+            module.evaluate_value(&properties["margin"], &card).unwrap(),
+            EvaluatedValue::Number(8.0, Some(Unit::Pixel))
+        );
+
+        // Only `style`, `card`, `padding` and `4px` are in the file
+        let tokens = absolute(&backend.semantic_tokens(&module, None));
+        assert_eq!(tokens.len(), 4);
+        assert!(tokens.iter().all(|(line, _, _)| *line < 3));
+        assert!(module.token_at(Span::SYNTHETIC.line_num, 0).is_none());
+    }
+
+    #[test]
+    fn typing_in_a_comment_changes_nothing() {
+        let (service, _) = LspService::new(|client| Backend::new(Arc::new(client)));
+        let backend = service.inner();
+
+        let source = "// the é header
+view {
+    :Hello
+    view (class: missing {
+    }
+}
+";
+        let typed = |content: &str, line, character, text: &str| {
+            let position = Position::new(line, character);
+            apply_change(
+                content,
+                &TextDocumentContentChangeEvent {
+                    range: Some(Range::new(position, position)),
+                    range_length: None,
+                    text: text.to_string(),
+                },
+            )
+        };
+
+        let edited = typed(source, 0, 8, "s");
+        assert!(edited.starts_with("// the és header\n"));
+        assert!(only_trivia_changed(source, &edited));
+
+        let (before, before_errors) = Module::parse_str(source);
+        let (after, after_errors) = Module::parse_str(&edited);
+        assert_eq!(
+            format!("{:?}", after_errors),
+            format!("{:?}", before_errors)
+        );
+
+        let old = backend.semantic_tokens(&before, None);
+        let new = backend.semantic_tokens(&after, None);
+        assert!(semantic_tokens_edits(&old, &new).is_empty());
+
+        // A blank line moves what comes after it, which is a single small edit
+        let spaced = typed(source, 2, 0, "\n");
+        assert!(only_trivia_changed(source, &spaced));
+        let (spaced, _) = Module::parse_str(&spaced);
+        let edits = semantic_tokens_edits(&old, &backend.semantic_tokens(&spaced, None));
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].data.as_ref().unwrap().len(), 1);
+
+        // Anything else is a real change
+        assert!(!only_trivia_changed(source, &typed(source, 1, 4, "x")));
+    }
 }